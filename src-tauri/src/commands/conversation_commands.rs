@@ -0,0 +1,260 @@
+use crate::commands::proxy_commands::resolve_provider;
+use crate::services::proxy::titles::generate_title;
+use crate::services::proxy::{ChatMessage, ProxyError, TitleGenerator, TitleSettings};
+use crate::services::store::import::{self, EVT_IMPORT_PROGRESS};
+use crate::services::store::{export, ImportSource, ImportSummary};
+use crate::services::store::{
+    BulkExportSummary, Conversation, ConversationStore, ExportFormat, NewMessage, SearchFilters,
+    SearchHit, StoreError, StoredMessage, EVT_CONVERSATION_UPDATED,
+};
+use log::{debug, info, warn};
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use tauri::{AppHandle, Emitter, Manager, State, Window};
+
+pub(crate) type StoreState<'a> = State<'a, Arc<Mutex<ConversationStore>>>;
+type TitleState<'a> = State<'a, Arc<Mutex<TitleGenerator>>>;
+
+/// Run `f` on the blocking thread pool, for store work that takes a while (copying
+/// a database, a bulk export or import) and would otherwise hold up the async
+/// workers. `label` names the task in the error if it panics.
+pub(crate) async fn run_blocking<T, F>(label: &str, f: F) -> Result<T, StoreError>
+where
+    F: FnOnce() -> Result<T, StoreError> + Send + 'static,
+    T: Send + 'static,
+{
+    tauri::async_runtime::spawn_blocking(f)
+        .await
+        .map_err(|e| StoreError::Io(format!("{} task failed: {}", label, e)))?
+}
+
+#[tauri::command]
+pub fn create_conversation(
+    store_state: StoreState<'_>,
+    name: String,
+) -> Result<Conversation, StoreError> {
+    info!("Creating conversation: {}", name);
+    store_state.lock()?.create_conversation(&name)
+}
+
+#[tauri::command]
+pub fn list_conversations(store_state: StoreState<'_>) -> Result<Vec<Conversation>, StoreError> {
+    store_state.lock()?.list_conversations()
+}
+
+#[tauri::command]
+pub fn rename_conversation(
+    store_state: StoreState<'_>,
+    conversation_id: i64,
+    name: String,
+) -> Result<Conversation, StoreError> {
+    info!("Renaming conversation {} to {}", conversation_id, name);
+    store_state
+        .lock()?
+        .rename_conversation(conversation_id, &name)
+}
+
+#[tauri::command]
+pub fn delete_conversation(
+    store_state: StoreState<'_>,
+    conversation_id: i64,
+) -> Result<(), StoreError> {
+    info!("Deleting conversation {}", conversation_id);
+    store_state.lock()?.delete_conversation(conversation_id)
+}
+
+/// Append a message; the first reply in a conversation also starts titling it,
+/// announced by a `conversation-updated` event once done
+#[tauri::command]
+pub fn append_message(
+    app: AppHandle,
+    store_state: StoreState<'_>,
+    conversation_id: i64,
+    message: NewMessage,
+) -> Result<StoredMessage, StoreError> {
+    let (stored, to_title) = {
+        let mut store = store_state.lock()?;
+        let stored = store.append_message(conversation_id, message)?;
+        let to_title = store.needs_title(&stored)?;
+        (stored, to_title)
+    };
+    if let Some(branch) = to_title {
+        spawn_title(app, conversation_id, branch);
+    }
+    Ok(stored)
+}
+
+/// Name a conversation after its first exchange in the background, so the reply
+/// that triggered it is not held up. Failures only cost the title, so they are logged.
+fn spawn_title(app: AppHandle, conversation_id: i64, branch: Vec<StoredMessage>) {
+    tauri::async_runtime::spawn(async move {
+        let settings = match app.state::<Arc<Mutex<TitleGenerator>>>().lock() {
+            Ok(titles) => titles.settings(),
+            Err(e) => {
+                warn!("Failed to read title settings: {}", e);
+                return;
+            }
+        };
+        if !settings.enabled {
+            return;
+        }
+
+        let messages: Vec<ChatMessage> = branch
+            .into_iter()
+            .map(|message| ChatMessage {
+                role: message.role,
+                content: message.parts,
+            })
+            .collect();
+        let title = match title_with(&app, &settings, &messages).await {
+            Ok(title) => title,
+            Err(e) => {
+                warn!("Failed to title conversation {}: {}", conversation_id, e);
+                return;
+            }
+        };
+
+        let updated = app
+            .state::<Arc<Mutex<ConversationStore>>>()
+            .lock()
+            .map_err(StoreError::from)
+            .and_then(|mut store| store.set_generated_name(conversation_id, &title));
+        match updated {
+            Ok(Some(conversation)) => {
+                if let Err(e) = app.emit(EVT_CONVERSATION_UPDATED, &conversation) {
+                    warn!("Failed to emit {}: {}", EVT_CONVERSATION_UPDATED, e);
+                }
+            }
+            Ok(None) => debug!(
+                "Conversation {} was renamed or deleted while titling",
+                conversation_id
+            ),
+            Err(e) => warn!(
+                "Failed to save title of conversation {}: {}",
+                conversation_id, e
+            ),
+        }
+    });
+}
+
+async fn title_with(
+    app: &AppHandle,
+    settings: &TitleSettings,
+    messages: &[ChatMessage],
+) -> Result<String, ProxyError> {
+    let provider = resolve_provider(&settings.provider, &app.state(), &app.state(), &app.state())?;
+    generate_title(provider.as_ref(), &settings.model, messages).await
+}
+
+#[tauri::command]
+pub fn get_messages(
+    store_state: StoreState<'_>,
+    conversation_id: i64,
+) -> Result<Vec<StoredMessage>, StoreError> {
+    store_state.lock()?.get_messages(conversation_id)
+}
+
+/// Add an edited or regenerated alternative to a message and switch to it
+#[tauri::command]
+pub fn fork_message(
+    store_state: StoreState<'_>,
+    message_id: i64,
+    message: NewMessage,
+) -> Result<StoredMessage, StoreError> {
+    info!("Forking message {}", message_id);
+    store_state.lock()?.fork_message(message_id, message)
+}
+
+#[tauri::command]
+pub fn switch_branch(
+    store_state: StoreState<'_>,
+    message_id: i64,
+) -> Result<Vec<StoredMessage>, StoreError> {
+    store_state.lock()?.switch_branch(message_id)
+}
+
+#[tauri::command]
+pub fn list_siblings(
+    store_state: StoreState<'_>,
+    message_id: i64,
+) -> Result<Vec<StoredMessage>, StoreError> {
+    store_state.lock()?.list_siblings(message_id)
+}
+
+#[tauri::command]
+pub fn search_messages(
+    store_state: StoreState<'_>,
+    query: String,
+    filters: Option<SearchFilters>,
+) -> Result<Vec<SearchHit>, StoreError> {
+    store_state
+        .lock()?
+        .search_messages(&query, &filters.unwrap_or_default())
+}
+
+/// Render a conversation as Markdown, lossless JSON or fine-tuning JSONL
+#[tauri::command]
+pub fn export_conversation(
+    store_state: StoreState<'_>,
+    conversation_id: i64,
+    format: ExportFormat,
+) -> Result<String, StoreError> {
+    let store = store_state.lock()?;
+    export::export_conversation(&store, conversation_id, format)
+}
+
+/// Export every conversation, one file each, into a directory or a `.zip` archive
+#[tauri::command]
+pub async fn export_conversations(
+    store_state: StoreState<'_>,
+    format: ExportFormat,
+    path: String,
+) -> Result<BulkExportSummary, StoreError> {
+    info!("Exporting all conversations as {:?} to {}", format, path);
+    let store = store_state.inner().clone();
+    run_blocking("Export", move || {
+        // Render under the lock, write after releasing it
+        let files = {
+            let store = store.lock()?;
+            export::export_all(&store, format)?
+        };
+        export::write_bulk_export(&PathBuf::from(path), &files)
+    })
+    .await
+}
+
+/// Import a ChatGPT or Claude export (`conversations.json` or the zip it came in),
+/// emitting `import-progress` after each conversation. Conversations imported
+/// before are left alone; what could not be imported is listed in the summary.
+#[tauri::command]
+pub async fn import_conversations(
+    window: Window,
+    store_state: StoreState<'_>,
+    source: ImportSource,
+    path: String,
+) -> Result<ImportSummary, StoreError> {
+    info!("Importing {} export from {}", source.as_str(), path);
+    let store = store_state.inner().clone();
+    run_blocking("Import", move || {
+        import::import_export(&store, source, &PathBuf::from(path), |progress| {
+            if let Err(e) = window.emit(EVT_IMPORT_PROGRESS, progress) {
+                warn!("Failed to emit {}: {}", EVT_IMPORT_PROGRESS, e);
+            }
+        })
+    })
+    .await
+}
+
+#[tauri::command]
+pub fn get_title_settings(title_state: TitleState<'_>) -> Result<TitleSettings, ProxyError> {
+    Ok(title_state.lock()?.settings())
+}
+
+#[tauri::command]
+pub fn save_title_settings(
+    title_state: TitleState<'_>,
+    settings: TitleSettings,
+) -> Result<(), ProxyError> {
+    info!("Saving title settings: {:?}", settings);
+    title_state.lock()?.update(settings)
+}
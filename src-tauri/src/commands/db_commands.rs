@@ -1,11 +1,214 @@
-use dirs;
+use crate::commands::conversation_commands::{run_blocking, StoreState};
+use crate::services::store::backup;
+use crate::services::store::paths::{env_data_dir, DATA_DIR_ENV};
+use crate::services::store::{
+    BackupConfig, BackupInfo, BackupKind, BackupSettings, ConversationStore, DataDir,
+    ExistingDatabase, IntegrityReport, RestoreSummary, StorageConfig, StorageSettings, StoreError,
+};
+use log::{debug, info, warn};
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tauri::{AppHandle, Manager, State};
+
+type StorageState<'a> = State<'a, Arc<Mutex<StorageConfig>>>;
+type BackupState<'a> = State<'a, Arc<Mutex<BackupConfig>>>;
+
+/// How often the automatic backup schedule is checked
+const BACKUP_CHECK_INTERVAL: Duration = Duration::from_secs(15 * 60);
+
+#[tauri::command]
+pub fn get_db_path(store_state: StoreState<'_>) -> Result<String, StoreError> {
+    let store = store_state.lock()?;
+    Ok(store.path().to_string_lossy().to_string())
+}
+
+/// The directory of the open database and where that choice came from
+#[tauri::command]
+pub fn get_data_dir(
+    store_state: StoreState<'_>,
+    storage_state: StorageState<'_>,
+) -> Result<DataDir, StoreError> {
+    let storage = storage_state.lock()?;
+    let store = store_state.lock()?;
+    storage.data_dir(store.path())
+}
+
+/// Move the data directory, taking the database and the default backup directory
+/// along. `None` (or an empty path) goes back to the platform default. If the new
+/// directory already holds a database, `existing` says whether to use or replace
+/// it; without it the error is `DatabaseExists`, so the user can be asked.
+#[tauri::command]
+pub async fn set_data_dir(
+    store_state: StoreState<'_>,
+    storage_state: StorageState<'_>,
+    backup_state: BackupState<'_>,
+    path: Option<String>,
+    existing: Option<ExistingDatabase>,
+) -> Result<DataDir, StoreError> {
+    if env_data_dir().is_some() {
+        return Err(StoreError::Validation(format!(
+            "the data directory is set by {} and cannot be changed here",
+            DATA_DIR_ENV
+        )));
+    }
+    let settings = StorageSettings {
+        data_dir: path
+            .map(|p| p.trim().to_string())
+            .filter(|p| !p.is_empty())
+            .map(PathBuf::from),
+        skip_legacy: true,
+    };
+    info!("Changing data directory to {:?}", settings.data_dir);
+
+    let store = store_state.inner().clone();
+    let storage = storage_state.inner().clone();
+    let backup_settings = backup_state.lock()?.settings();
+    run_blocking("Data directory", move || {
+        let mut storage = storage.lock()?;
+        let previous = storage.settings();
+        storage.update(settings)?;
+        let moved = storage.resolve().and_then(|data_dir| {
+            let mut store = store.lock()?;
+            let old_path = store.path().to_path_buf();
+            if old_path != data_dir.database_path() {
+                store.relocate(data_dir.database_path(), existing)?;
+                if backup_settings.directory.is_none() {
+                    let from = backup_settings.directory_for(&old_path);
+                    let to = backup_settings.directory_for(store.path());
+                    if let Err(e) = backup::move_backups(&from, &to) {
+                        warn!("Failed to move the backups to {}: {}", to.display(), e);
+                    }
+                }
+            }
+            Ok(data_dir)
+        });
+        if let Ok(data_dir) = &moved {
+            storage.set_source(data_dir.source);
+        }
+        if moved.is_err() {
+            // Keep the saved setting pointing at where the database still is
+            if let Err(e) = storage.update(previous) {
+                warn!("Failed to restore the previous storage settings: {}", e);
+            }
+        }
+        moved
+    })
+    .await
+}
+
+/// Back up the database to `path`, or into the backup directory when `None`
+#[tauri::command]
+pub async fn backup_database(
+    store_state: StoreState<'_>,
+    backup_state: BackupState<'_>,
+    path: Option<String>,
+) -> Result<BackupInfo, StoreError> {
+    let settings = backup_state.lock()?.settings();
+    let database = store_state.lock()?.path().to_path_buf();
+    // The copy reads through its own connection, so the store stays unlocked
+    // while it blocks this thread
+    run_blocking("Backup", move || {
+        match path.map(|p| p.trim().to_string()).filter(|p| !p.is_empty()) {
+            Some(path) => {
+                let path = PathBuf::from(path);
+                info!("Backing up the database to {}", path.display());
+                backup::copy_database(&database, &path)?;
+                BackupInfo::read(&path)
+            }
+            None => {
+                let dir = settings.directory_for(&database);
+                backup::create_backup(&database, &dir, BackupKind::Manual)
+            }
+        }
+    })
+    .await
+}
+
+/// Backups in the backup directory, newest first
+#[tauri::command]
+pub fn list_backups(
+    store_state: StoreState<'_>,
+    backup_state: BackupState<'_>,
+) -> Result<Vec<BackupInfo>, StoreError> {
+    let settings = backup_state.lock()?.settings();
+    let dir = settings.directory_for(store_state.lock()?.path());
+    backup::list_backups(&dir)
+}
+
+/// Replace the database with a backup, after checking it is intact and not from a
+/// newer version. The replaced database is kept as a `pre-restore` backup.
+#[tauri::command]
+pub async fn restore_database(
+    store_state: StoreState<'_>,
+    backup_state: BackupState<'_>,
+    path: String,
+) -> Result<RestoreSummary, StoreError> {
+    info!("Restoring the database from {}", path);
+    let settings = backup_state.lock()?.settings();
+    let store = store_state.inner().clone();
+    run_blocking("Restore", move || {
+        let mut store = store.lock()?;
+        let dir = settings.directory_for(store.path());
+        backup::restore(&mut store, &PathBuf::from(path), &dir)
+    })
+    .await
+}
 
 #[tauri::command]
-pub fn get_db_path() -> String {
-    let home_dir = dirs::home_dir().expect("Could not find home directory");
-    let documents_dir = home_dir.join("Documents");
-    let db_dir = documents_dir.join("pqp_data");
-    let db_path = db_dir.join("pqp_chats.db");
-    
-    db_path.to_string_lossy().to_string()
-} 
\ No newline at end of file
+pub async fn check_database_integrity(
+    store_state: StoreState<'_>,
+) -> Result<IntegrityReport, StoreError> {
+    let database = store_state.lock()?.path().to_path_buf();
+    let report = run_blocking("Integrity check", move || {
+        backup::check_integrity(&database)
+    })
+    .await?;
+    if !report.ok {
+        warn!("Database integrity check failed: {:?}", report.problems);
+    }
+    Ok(report)
+}
+
+#[tauri::command]
+pub fn get_backup_settings(backup_state: BackupState<'_>) -> Result<BackupSettings, StoreError> {
+    Ok(backup_state.lock()?.settings())
+}
+
+#[tauri::command]
+pub fn save_backup_settings(
+    backup_state: BackupState<'_>,
+    settings: BackupSettings,
+) -> Result<(), StoreError> {
+    info!("Saving backup settings: {:?}", settings);
+    backup_state.lock()?.update(settings)
+}
+
+/// Take automatic backups for as long as the app runs. Each check backs up only if
+/// the newest automatic backup is older than the configured interval, so
+/// restarting the app does not reset the schedule.
+pub(crate) fn spawn_backup_schedule(app: AppHandle) {
+    tauri::async_runtime::spawn(async move {
+        loop {
+            tokio::time::sleep(BACKUP_CHECK_INTERVAL).await;
+            let app = app.clone();
+            // VACUUM INTO blocks for as long as the copy takes. It reads through
+            // its own connection, so the store is only locked to look up its path.
+            let result = tauri::async_runtime::spawn_blocking(move || {
+                let settings = app.state::<Arc<Mutex<BackupConfig>>>().lock()?.settings();
+                let store = app.state::<Arc<Mutex<ConversationStore>>>();
+                let database = store.lock()?.path().to_path_buf();
+                backup::run_scheduled(&database, &settings)
+            })
+            .await;
+            match result {
+                Ok(Ok(Some(backup))) => {
+                    debug!("Automatic backup saved to {}", backup.path.display())
+                }
+                Ok(Ok(None)) => {}
+                Ok(Err(e)) => warn!("Automatic backup failed: {}", e),
+                Err(e) => warn!("Automatic backup task failed: {}", e),
+            }
+        }
+    });
+}
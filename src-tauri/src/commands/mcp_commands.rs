@@ -1,4 +1,9 @@
-use rmcp::{model::CallToolRequestParam, transport::TokioChildProcess, ServiceExt};
+use log::{debug, info};
+use rmcp::{
+    model::CallToolRequestParam,
+    transport::{SseTransport, TokioChildProcess},
+    ServiceExt,
+};
 use std::borrow::Cow;
 use std::sync::{Arc, Mutex};
 use tauri::{Manager, Runtime, State};
@@ -7,6 +12,7 @@ use tokio::process::Command;
 use crate::services::mcp::{
     McpError, ServiceManager, ServiceResponse, ToolCallResponse, ToolsResponse,
 };
+use crate::services::proxy::HttpClient;
 
 type ServiceState<'a> = State<'a, Arc<Mutex<ServiceManager>>>;
 
@@ -16,60 +22,81 @@ pub async fn start_service<R: Runtime>(
     service_name: String,
     executable: String,
     args: Vec<String>,
-) -> Result<ServiceResponse, String> {
-    let result = async {
-        let child_process =
-            TokioChildProcess::new(Command::new(executable).args(args)).map_err(McpError::from)?;
+) -> Result<ServiceResponse, McpError> {
+    let child_process =
+        TokioChildProcess::new(Command::new(executable).args(args)).map_err(McpError::from)?;
 
-        let service = ().serve(child_process).await.map_err(McpError::from)?;
+    let service = ().serve(child_process).await.map_err(McpError::from)?;
 
-        let server_info = service.peer_info();
-        println!("Server info for {}: {:?}", service_name, server_info);
+    let server_info = service.peer_info();
+    info!("Server info for {}: {:?}", service_name, server_info);
 
-        let service_manager = app.state::<Arc<Mutex<ServiceManager>>>();
-        {
-            let mut state = service_manager.lock()?;
-            state.add_service(service_name.clone(), service);
-        }
+    let service_manager = app.state::<Arc<Mutex<ServiceManager>>>();
+    {
+        let mut state = service_manager.lock()?;
+        state.add_service(service_name.clone(), service);
+    }
 
-        Ok(ServiceResponse {
-            success: true,
-            message: format!("Service {} started successfully", service_name),
-        })
+    Ok(ServiceResponse {
+        success: true,
+        message: format!("Service {} started successfully", service_name),
+    })
+}
+
+/// Connect to a remote MCP server over SSE, using the configured proxy and
+/// trusted certificates
+#[tauri::command]
+pub async fn start_remote_service(
+    service_state: ServiceState<'_>,
+    http_state: State<'_, Arc<Mutex<HttpClient>>>,
+    service_name: String,
+    url: String,
+) -> Result<ServiceResponse, McpError> {
+    let client = http_state.lock()?.mcp_client();
+    let transport = SseTransport::start_with_client(url.as_str(), client).await?;
+
+    let service = ().serve(transport).await.map_err(McpError::from)?;
+
+    let server_info = service.peer_info();
+    info!(
+        "Server info for {} ({}): {:?}",
+        service_name, url, server_info
+    );
+
+    {
+        let mut state = service_state.lock()?;
+        state.add_service(service_name.clone(), service);
     }
-    .await;
 
-    result.map_err(|e: McpError| e.to_string())
+    Ok(ServiceResponse {
+        success: true,
+        message: format!("Service {} connected to {}", service_name, url),
+    })
 }
 
 #[tauri::command]
 pub async fn list_tools(
     service_state: ServiceState<'_>,
     service_name: String,
-) -> Result<ToolsResponse, String> {
-    let result = async {
-        let peer = {
-            let state = service_state.lock()?;
-            let server = state
-                .get_service(&service_name)
-                .ok_or_else(|| McpError::ServiceNotFound(service_name.clone()))?;
-            server.peer().clone()
-        };
-
-        let tools = peer.list_all_tools().await.map_err(McpError::from)?;
-
-        let tools_count = tools.len();
-        println!("Found {} tools for {}", tools_count, service_name);
-
-        Ok(ToolsResponse {
-            success: true,
-            tools,
-            message: format!("Found {} tools", tools_count),
-        })
-    }
-    .await;
+) -> Result<ToolsResponse, McpError> {
+    let peer = {
+        let state = service_state.lock()?;
+        let server = state
+            .get_service(&service_name)
+            .ok_or_else(|| McpError::ServiceNotFound(service_name.clone()))?;
+        server.peer().clone()
+    };
+
+    let tools = peer.list_all_tools().await.map_err(McpError::from)?;
 
-    result.map_err(|e: McpError| e.to_string())
+    let tools_count = tools.len();
+    info!("Found {} tools for {}", tools_count, service_name);
+
+    Ok(ToolsResponse {
+        success: true,
+        tools,
+        message: format!("Found {} tools", tools_count),
+    })
 }
 
 #[tauri::command]
@@ -78,65 +105,54 @@ pub async fn call_tool(
     service_name: String,
     tool_name: String,
     arguments: serde_json::Value,
-) -> Result<ToolCallResponse, String> {
-    let result = async {
-        let args = match arguments {
-            serde_json::Value::Object(map) => Some(map),
-            _ => {
-                return Err(McpError::InvalidArguments(
-                    "Arguments must be a valid JSON object".to_string(),
-                ))
-            }
-        };
-
-        let peer = {
-            let state = service_state.lock()?;
-            let server = state
-                .get_service(&service_name)
-                .ok_or_else(|| McpError::ServiceNotFound(service_name.clone()))?;
-            server.peer().clone()
-        };
-
-        let tool_result = peer
-            .call_tool(CallToolRequestParam {
-                name: Cow::Owned(tool_name.clone()),
-                arguments: args,
-            })
-            .await
-            .map_err(McpError::from)?;
-
-        println!("Tool {} called successfully.", tool_name);
-
-        Ok(ToolCallResponse {
-            success: true,
-            result: Some(tool_result),
-            message: format!("Tool {} called successfully", tool_name),
+) -> Result<ToolCallResponse, McpError> {
+    let args = match arguments {
+        serde_json::Value::Object(map) => Some(map),
+        _ => {
+            return Err(McpError::InvalidArguments(
+                "Arguments must be a valid JSON object".to_string(),
+            ))
+        }
+    };
+
+    let peer = {
+        let state = service_state.lock()?;
+        let server = state
+            .get_service(&service_name)
+            .ok_or_else(|| McpError::ServiceNotFound(service_name.clone()))?;
+        server.peer().clone()
+    };
+
+    let tool_result = peer
+        .call_tool(CallToolRequestParam {
+            name: Cow::Owned(tool_name.clone()),
+            arguments: args,
         })
-    }
-    .await;
+        .await
+        .map_err(McpError::from)?;
 
-    result.map_err(|e: McpError| e.to_string())
+    debug!("Tool {} called successfully", tool_name);
+
+    Ok(ToolCallResponse {
+        success: true,
+        result: Some(tool_result),
+        message: format!("Tool {} called successfully", tool_name),
+    })
 }
 
 #[tauri::command]
-pub fn get_services(service_state: ServiceState<'_>) -> Result<Vec<String>, String> {
-    let result = (|| {
-        let state = service_state.lock()?;
-        Ok(state.list_services())
-    })();
-
-    result.map_err(|e: McpError| e.to_string())
+pub fn get_services(service_state: ServiceState<'_>) -> Result<Vec<String>, McpError> {
+    let state = service_state.lock()?;
+    Ok(state.list_services())
 }
 
 #[tauri::command]
 pub async fn stop_service(
     service_state: ServiceState<'_>,
     service_name: String,
-) -> Result<ServiceResponse, String> {
+) -> Result<ServiceResponse, McpError> {
     let maybe_service = {
-        let mut service_manager = service_state
-            .lock()
-            .map_err(|e| McpError::LockError(e.to_string()))?;
+        let mut service_manager = service_state.lock()?;
         service_manager.remove_service(&service_name)
     };
 
@@ -146,7 +162,7 @@ pub async fn stop_service(
                 success: true,
                 message: format!("Service {} stopped successfully", service_name),
             }),
-            Err(e) => Err(McpError::from(e).to_string()),
+            Err(e) => Err(McpError::from(e)),
         }
     } else {
         Ok(ServiceResponse {
@@ -1,3 +1,4 @@
+pub mod conversation_commands;
 pub mod mcp_commands;
 pub mod proxy_commands;
 pub mod db_commands;
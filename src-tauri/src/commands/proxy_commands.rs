@@ -1,31 +1,215 @@
-use crate::services::proxy::get_provider;
+use crate::services::proxy::{
+    self, get_provider, ChatRequest, ConnectionTestResult, HttpClient, HttpSettings, KeyStore,
+    ModelCache, ModelInfo, OllamaProvider, OpenAISettings, ProfileStore, ProviderProfile,
+    ProxyError, ProxyProvider,
+};
 use log::info;
-use serde_json::Value;
-use tauri::Window;
+use std::sync::{Arc, Mutex};
+use tauri::{State, Window};
 
+type ProfileState<'a> = State<'a, Arc<Mutex<ProfileStore>>>;
+type ModelCacheState<'a> = State<'a, Arc<Mutex<ModelCache>>>;
+type KeyState<'a> = State<'a, Arc<Mutex<KeyStore>>>;
+type HttpState<'a> = State<'a, Arc<Mutex<HttpClient>>>;
+
+/// Resolve a provider while holding the profile, key and client locks only briefly
+pub(crate) fn resolve_provider(
+    provider: &str,
+    profile_state: &ProfileState<'_>,
+    key_state: &KeyState<'_>,
+    http_state: &HttpState<'_>,
+) -> Result<Box<dyn ProxyProvider + Send + Sync>, ProxyError> {
+    let client = http_state.lock()?.client();
+    let profiles = profile_state.lock()?;
+    let keys = key_state.lock()?;
+    get_provider(provider, &profiles, &keys, client)
+}
+
+/// Stream a chat completion; provider failures are returned (and emitted) with
+/// their parsed error details
 #[tauri::command]
 pub async fn stream_api_request(
     window: Window,
+    profile_state: ProfileState<'_>,
+    key_state: KeyState<'_>,
+    http_state: HttpState<'_>,
     provider: String,
-    payload: String,
-) -> Result<(), String> {
+    request: ChatRequest,
+) -> Result<(), ProxyError> {
     info!("Received stream request for provider: {}", provider);
 
-    let body_json: Value = match serde_json::from_str(&payload) {
-        Ok(json) => json,
-        Err(e) => {
-            let err_msg = format!("Failed to parse payload into JSON: {}", e);
-            return Err(err_msg);
+    let provider_impl = resolve_provider(&provider, &profile_state, &key_state, &http_state)?;
+
+    // Reject malformed requests before any HTTP call is made
+    request.validate()?;
+    let body = provider_impl.build_request(&request)?;
+
+    provider_impl.stream(window, body).await
+}
+
+#[tauri::command]
+pub fn list_provider_profiles(
+    profile_state: ProfileState<'_>,
+) -> Result<Vec<ProviderProfile>, ProxyError> {
+    let profiles = profile_state.lock()?;
+    Ok(profiles.list())
+}
+
+#[tauri::command]
+pub fn save_provider_profile(
+    profile_state: ProfileState<'_>,
+    cache_state: ModelCacheState<'_>,
+    profile: ProviderProfile,
+) -> Result<(), ProxyError> {
+    info!("Saving provider profile: {}", profile.name);
+    let name = profile.name.clone();
+    let mut profiles = profile_state.lock()?;
+    profiles.upsert(profile)?;
+    cache_state.lock()?.invalidate(&name);
+    Ok(())
+}
+
+/// Remove a profile along with its API key, so a later profile of the same name
+/// does not inherit it
+#[tauri::command]
+pub fn delete_provider_profile(
+    profile_state: ProfileState<'_>,
+    key_state: KeyState<'_>,
+    cache_state: ModelCacheState<'_>,
+    name: String,
+) -> Result<bool, ProxyError> {
+    info!("Deleting provider profile: {}", name);
+    let mut profiles = profile_state.lock()?;
+    let removed = profiles.remove(&name)?;
+    if removed {
+        key_state.lock()?.delete(&name)?;
+    }
+    cache_state.lock()?.invalidate(&name);
+    Ok(removed)
+}
+
+#[tauri::command]
+pub fn get_openai_settings(profile_state: ProfileState<'_>) -> Result<OpenAISettings, ProxyError> {
+    Ok(profile_state.lock()?.openai_settings())
+}
+
+/// Choose per model whether the built-in OpenAI provider uses Chat Completions or
+/// the Responses API
+#[tauri::command]
+pub fn save_openai_settings(
+    profile_state: ProfileState<'_>,
+    settings: OpenAISettings,
+) -> Result<(), ProxyError> {
+    info!("Saving OpenAI provider settings");
+    profile_state.lock()?.set_openai_settings(settings)
+}
+
+/// List models for a provider, served from cache unless `refresh` is set
+#[tauri::command]
+pub async fn list_models(
+    profile_state: ProfileState<'_>,
+    key_state: KeyState<'_>,
+    http_state: HttpState<'_>,
+    cache_state: ModelCacheState<'_>,
+    provider: String,
+    refresh: Option<bool>,
+) -> Result<Vec<ModelInfo>, ProxyError> {
+    if !refresh.unwrap_or(false) {
+        let cache = cache_state.lock()?;
+        if let Some(models) = cache.get(&provider) {
+            return Ok(models);
         }
-    };
+    }
 
-    let provider_impl = match get_provider(&provider) {
-        Ok(p) => p,
-        Err(e) => return Err(e.to_string()),
-    };
+    info!("Listing models for provider: {}", provider);
+    let provider_impl = resolve_provider(&provider, &profile_state, &key_state, &http_state)?;
+    let models = provider_impl.list_models().await?;
 
-    match provider_impl.stream(window, body_json).await {
-        Ok(_) => Ok(()),
-        Err(e) => Err(e.to_string()),
+    cache_state.lock()?.insert(&provider, models.clone());
+    Ok(models)
+}
+
+/// Download an Ollama model, emitting `ollama-pull-progress` events as it goes
+#[tauri::command]
+pub async fn pull_ollama_model(
+    window: Window,
+    http_state: HttpState<'_>,
+    cache_state: ModelCacheState<'_>,
+    model: String,
+) -> Result<(), ProxyError> {
+    info!("Pulling Ollama model: {}", model);
+    let client = http_state.lock()?.client();
+    OllamaProvider::from_env(client)
+        .pull(&window, &model)
+        .await?;
+    cache_state.lock()?.invalidate("ollama");
+    Ok(())
+}
+
+#[tauri::command]
+pub fn set_api_key(
+    key_state: KeyState<'_>,
+    cache_state: ModelCacheState<'_>,
+    provider: String,
+    api_key: String,
+) -> Result<(), ProxyError> {
+    info!("Saving API key for provider: {}", provider);
+    let mut keys = key_state.lock()?;
+    keys.set(&provider, &api_key)?;
+    cache_state.lock()?.invalidate(&provider);
+    Ok(())
+}
+
+#[tauri::command]
+pub fn delete_api_key(
+    key_state: KeyState<'_>,
+    cache_state: ModelCacheState<'_>,
+    provider: String,
+) -> Result<bool, ProxyError> {
+    info!("Deleting API key for provider: {}", provider);
+    let mut keys = key_state.lock()?;
+    let removed = keys.delete(&provider)?;
+    cache_state.lock()?.invalidate(&provider);
+    Ok(removed)
+}
+
+/// Whether a key is available for the provider, either saved or from the environment
+#[tauri::command]
+pub fn has_api_key(key_state: KeyState<'_>, provider: String) -> Result<bool, ProxyError> {
+    let keys = key_state.lock()?;
+    proxy::has_api_key(&provider, &keys)
+}
+
+/// Validate a provider's key and base URL with a cheap authenticated request.
+///
+/// Failures are reported in the result rather than as an error so the settings
+/// screen can show why the connection failed.
+#[tauri::command]
+pub async fn test_provider_connection(
+    profile_state: ProfileState<'_>,
+    key_state: KeyState<'_>,
+    http_state: HttpState<'_>,
+    provider: String,
+) -> Result<ConnectionTestResult, ProxyError> {
+    let resolved = resolve_provider(&provider, &profile_state, &key_state, &http_state);
+
+    match resolved {
+        Ok(provider_impl) => Ok(proxy::test_connection(&provider, provider_impl.as_ref()).await),
+        Err(e) => Ok(ConnectionTestResult::from_error(&provider, &e, 0)),
     }
 }
+
+#[tauri::command]
+pub fn get_http_settings(http_state: HttpState<'_>) -> Result<HttpSettings, ProxyError> {
+    Ok(http_state.lock()?.settings())
+}
+
+/// Apply new HTTP client settings; later requests use the rebuilt client
+#[tauri::command]
+pub fn save_http_settings(
+    http_state: HttpState<'_>,
+    settings: HttpSettings,
+) -> Result<(), ProxyError> {
+    info!("Saving HTTP client settings");
+    http_state.lock()?.update(settings)
+}
@@ -1,16 +1,33 @@
 // Learn more about Tauri commands at https://tauri.app/develop/calling-rust/
-use log::LevelFilter;
+use log::{warn, LevelFilter};
 use std::sync::{Arc, Mutex};
 use tauri::Manager;
-use tauri_plugin_sql::{Migration, MigrationKind};
 
 pub mod commands;
 pub mod services;
 
-use commands::mcp_commands::{call_tool, get_services, list_tools, start_service, stop_service};
-use commands::proxy_commands::stream_api_request;
-use commands::db_commands::get_db_path;
+use commands::mcp_commands::{
+    call_tool, get_services, list_tools, start_remote_service, start_service, stop_service,
+};
+use commands::proxy_commands::{
+    delete_api_key, delete_provider_profile, get_http_settings, get_openai_settings, has_api_key,
+    list_models, list_provider_profiles, pull_ollama_model, save_http_settings,
+    save_openai_settings, save_provider_profile, set_api_key, stream_api_request,
+    test_provider_connection,
+};
+use commands::conversation_commands::{
+    append_message, create_conversation, delete_conversation, export_conversation,
+    export_conversations, fork_message, get_messages, get_title_settings, import_conversations,
+    list_conversations, list_siblings, rename_conversation, save_title_settings, search_messages,
+    switch_branch,
+};
+use commands::db_commands::{
+    backup_database, check_database_integrity, get_backup_settings, get_data_dir, get_db_path,
+    list_backups, restore_database, save_backup_settings, set_data_dir, spawn_backup_schedule,
+};
 use services::mcp::ServiceManager;
+use services::proxy::{HttpClient, KeyStore, ModelCache, ProfileStore, TitleGenerator};
+use services::store::{BackupConfig, ConversationStore, StorageConfig};
 
 #[cfg_attr(mobile, tauri::mobile_entry_point)]
 pub fn run() {
@@ -23,63 +40,63 @@ pub fn run() {
     // Simple logger setup - can be replaced with a more sophisticated logger if needed
     env_logger::Builder::new().filter_level(log_level).init();
 
-    // Define migrations for chat database
-    let migrations = vec![
-        Migration {
-            version: 1,
-            description: "create_conversations_table",
-            sql: "CREATE TABLE IF NOT EXISTS conversations (
-                id INTEGER PRIMARY KEY AUTOINCREMENT,
-                name TEXT NOT NULL,
-                created_at TEXT NOT NULL,
-                updated_at TEXT NOT NULL
-            )",
-            kind: MigrationKind::Up,
-        },
-        Migration {
-            version: 2,
-            description: "create_messages_table",
-            sql: "CREATE TABLE IF NOT EXISTS messages (
-                id INTEGER PRIMARY KEY AUTOINCREMENT,
-                conversation_id INTEGER NOT NULL,
-                content TEXT NOT NULL,
-                sender TEXT NOT NULL,
-                timestamp TEXT NOT NULL,
-                FOREIGN KEY (conversation_id) REFERENCES conversations(id)
-            )",
-            kind: MigrationKind::Up,
-        }
-    ];
-
-    // Get user's home directory and construct path to Documents
-    let home_dir = dirs::home_dir().expect("Could not find home directory");
-    let documents_dir = home_dir.join("Documents");
-    let db_dir = documents_dir.join("pqp_data");
-    
-    // Create the directory if it doesn't exist
-    std::fs::create_dir_all(&db_dir).expect("Failed to create database directory");
-    
-    let db_path = db_dir.join("pqp_chats.db");
-    let db_url = format!("sqlite:{}", db_path.to_string_lossy());
+    // Environment variables (optionally from a .env file) are only a fallback for
+    // keys saved through the key store, so load them once at startup
+    dotenv::dotenv().ok();
 
     tauri::Builder::default()
-        .plugin(tauri_plugin_sql::Builder::default()
-            .add_migrations(&db_url, migrations)
-            .build())
         .plugin(tauri_plugin_http::init())
         .plugin(tauri_plugin_shell::init())
         .plugin(tauri_plugin_opener::init())
         .manage(Arc::new(Mutex::new(ServiceManager::default())))
+        .manage(Arc::new(Mutex::new(ModelCache::default())))
         .invoke_handler(tauri::generate_handler![
             start_service,
+            start_remote_service,
             list_tools,
             call_tool,
             get_services,
             stop_service,
             stream_api_request,
+            list_provider_profiles,
+            save_provider_profile,
+            delete_provider_profile,
+            get_openai_settings,
+            save_openai_settings,
+            list_models,
+            pull_ollama_model,
+            set_api_key,
+            delete_api_key,
+            has_api_key,
+            test_provider_connection,
+            get_http_settings,
+            save_http_settings,
+            create_conversation,
+            list_conversations,
+            rename_conversation,
+            delete_conversation,
+            append_message,
+            get_messages,
+            fork_message,
+            switch_branch,
+            list_siblings,
+            search_messages,
+            get_title_settings,
+            save_title_settings,
+            export_conversation,
+            export_conversations,
+            import_conversations,
             get_db_path,
+            get_data_dir,
+            set_data_dir,
+            backup_database,
+            list_backups,
+            restore_database,
+            check_database_integrity,
+            get_backup_settings,
+            save_backup_settings,
         ])
-        .setup(move |app| {
+        .setup(|app| {
             #[cfg(debug_assertions)]
             {
                 if let Some(window) = app.get_webview_window("main") {
@@ -87,12 +104,47 @@ pub fn run() {
                 }
             }
             
-            // Make the database path available to the frontend
-            #[cfg(desktop)]
-            {
-                let db_path_str = db_path.to_string_lossy().to_string();
-                app.manage(db_path_str);
+            // Load OpenAI-compatible provider profiles from the app config directory
+            let profiles_path = app.path().app_config_dir()?.join("providers.json");
+            let mut profile_store = ProfileStore::load(profiles_path)?;
+
+            // API keys live encrypted in the app data directory (or the OS keyring),
+            // including those older versions kept in the profiles file
+            let mut key_store = KeyStore::open_default(&app.path().app_data_dir()?)?;
+            if let Err(e) = profile_store.migrate_keys(&mut key_store) {
+                warn!("Failed to move profile API keys into the key store: {}", e);
             }
+            app.manage(Arc::new(Mutex::new(profile_store)));
+            app.manage(Arc::new(Mutex::new(key_store)));
+
+            // One pooled HTTP client shared by every provider request
+            let http_client = HttpClient::load(app.path().app_config_dir()?.join("http.json"))?;
+            app.manage(Arc::new(Mutex::new(http_client)));
+
+            // Model used to title new conversations
+            let title_generator =
+                TitleGenerator::load(app.path().app_config_dir()?.join("titles.json"));
+            app.manage(Arc::new(Mutex::new(title_generator)));
+
+            // The data directory defaults to the app data dir; PQP_DATA_DIR or the
+            // storage settings can point it elsewhere
+            let mut storage_config = StorageConfig::load(
+                app.path().app_config_dir()?.join("storage.json"),
+                app.path().app_data_dir()?,
+            );
+            let data_dir = storage_config.resolve()?;
+
+            // Conversations are read and written only through the store's commands
+            let conversation_store = ConversationStore::open(data_dir.database_path())?;
+            storage_config.set_source(data_dir.source);
+            app.manage(Arc::new(Mutex::new(storage_config)));
+            app.manage(Arc::new(Mutex::new(conversation_store)));
+
+            // Rolling automatic backups of the database
+            let backup_config =
+                BackupConfig::load(app.path().app_config_dir()?.join("backups.json"));
+            app.manage(Arc::new(Mutex::new(backup_config)));
+            spawn_backup_schedule(app.handle().clone());
             
             Ok(())
         })
@@ -1,4 +1,5 @@
-use rmcp::{model::JsonRpcError, ServiceError};
+use rmcp::{model::JsonRpcError, transport::sse::SseTransportError, ServiceError};
+use serde::{ser::SerializeMap, Serialize, Serializer};
 use std::error::Error;
 use std::fmt;
 use std::io;
@@ -14,6 +15,7 @@ pub enum McpError {
     InvalidArguments(String),
     JsonRpcError(JsonRpcError),
     TaskJoinError(String),
+    TransportError(String),
 }
 
 impl fmt::Display for McpError {
@@ -27,12 +29,56 @@ impl fmt::Display for McpError {
             McpError::InvalidArguments(msg) => write!(f, "Invalid arguments: {}", msg),
             McpError::JsonRpcError(err) => write!(f, "JSON-RPC error: {:?}", err),
             McpError::TaskJoinError(msg) => write!(f, "Task join/cancellation error: {}", msg),
+            McpError::TransportError(msg) => write!(f, "Transport error: {}", msg),
         }
     }
 }
 
 impl Error for McpError {}
 
+impl McpError {
+    /// Stable discriminator for the frontend, matching the variant name
+    pub fn kind(&self) -> &'static str {
+        match self {
+            McpError::ServiceNotFound(_) => "ServiceNotFound",
+            McpError::LockError(_) => "LockError",
+            McpError::IoError(_) => "IoError",
+            McpError::RmcpError(_) => "RmcpError",
+            McpError::SerializationError(_) => "SerializationError",
+            McpError::InvalidArguments(_) => "InvalidArguments",
+            McpError::JsonRpcError(_) => "JsonRpcError",
+            McpError::TaskJoinError(_) => "TaskJoinError",
+            McpError::TransportError(_) => "TransportError",
+        }
+    }
+}
+
+/// Serialized as `{kind, message, ...details}`, e.g.
+/// `{"kind": "ServiceNotFound", "service": "filesystem", "message": "..."}`
+impl Serialize for McpError {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut map = serializer.serialize_map(None)?;
+        map.serialize_entry("kind", self.kind())?;
+        match self {
+            McpError::ServiceNotFound(service) => map.serialize_entry("service", service)?,
+            McpError::JsonRpcError(err) => {
+                map.serialize_entry("code", &err.error.code)?;
+                map.serialize_entry("data", &err.error.data)?;
+            }
+            McpError::RmcpError(ServiceError::McpError(data)) => {
+                map.serialize_entry("code", &data.code)?;
+                map.serialize_entry("data", &data.data)?;
+            }
+            McpError::RmcpError(ServiceError::Timeout { timeout }) => {
+                map.serialize_entry("timeoutMs", &(timeout.as_millis() as u64))?;
+            }
+            _ => {}
+        }
+        map.serialize_entry("message", &self.to_string())?;
+        map.end()
+    }
+}
+
 impl From<JsonRpcError> for McpError {
     fn from(err: JsonRpcError) -> Self {
         McpError::JsonRpcError(err)
@@ -69,8 +115,8 @@ impl From<JoinError> for McpError {
     }
 }
 
-impl From<McpError> for String {
-    fn from(err: McpError) -> Self {
-        err.to_string()
+impl From<SseTransportError> for McpError {
+    fn from(err: SseTransportError) -> Self {
+        McpError::TransportError(err.to_string())
     }
 }
@@ -1,2 +1,4 @@
 pub mod mcp;
 pub mod proxy;
+pub(crate) mod settings;
+pub mod store;
@@ -1,10 +1,19 @@
-use crate::services::proxy::{emit_chunk, emit_end, emit_error};
-use crate::services::proxy::{ProxyError, ProxyProvider, ProxyResult};
+use crate::services::proxy::attachments::{self, AttachmentKind, AttachmentSupport};
+use crate::services::proxy::errors::read_error;
+use crate::services::proxy::request::{invalid, tool_result_text};
+use crate::services::proxy::retry::{retry_wait, send_with_retry, RetryPolicy};
+use crate::services::proxy::sse::SseBuffer;
+use crate::services::proxy::{emit_end, emit_error, emit_part, emit_provider_error};
+use crate::services::proxy::{
+    ChatRequest, ChatRole, ContentPart, ModelInfo, ProviderError, ProxyError, ProxyProvider,
+    ProxyResult,
+};
 use async_trait::async_trait;
 use futures_util::StreamExt;
 use log::{debug, error, info, warn};
 use serde::Deserialize;
-use serde_json::Value;
+use serde_json::{json, Map, Value};
+use std::collections::HashMap;
 use tauri::Window;
 use tauri_plugin_http::reqwest::{
     self,
@@ -12,13 +21,59 @@ use tauri_plugin_http::reqwest::{
 };
 
 pub struct AnthropicProvider {
+    client: reqwest::Client,
     api_key: String,
 }
 
+const ANTHROPIC_BASE_URL: &str = "https://api.anthropic.com/v1";
+/// Anthropic requires `max_tokens`; used when the request leaves it unset
+const ANTHROPIC_DEFAULT_MAX_TOKENS: u32 = 4096;
+
+/// Mark a block as the end of a cached prefix. Anthropic allows four breakpoints;
+/// the system prompt, tool definitions and latest turn use three of them.
+fn add_cache_breakpoint(block: &mut Value) {
+    block["cache_control"] = json!({ "type": "ephemeral" });
+}
+
+/// Map Anthropic stop reasons onto AI SDK finish reasons
+fn map_stop_reason(reason: &str) -> &'static str {
+    match reason {
+        "end_turn" | "stop_sequence" | "pause_turn" => "stop",
+        "max_tokens" => "length",
+        "tool_use" => "tool-calls",
+        "refusal" => "content-filter",
+        _ => "other",
+    }
+}
+
 impl AnthropicProvider {
-    pub fn new(api_key: String) -> Self {
-        Self { api_key }
+    pub fn new(client: reqwest::Client, api_key: String) -> Self {
+        Self { client, api_key }
     }
+
+    fn headers(&self) -> ProxyResult<HeaderMap> {
+        let mut headers = HeaderMap::new();
+        headers.insert(CONTENT_TYPE, HeaderValue::from_static("application/json"));
+        headers.insert("anthropic-version", HeaderValue::from_static("2023-06-01"));
+        headers.insert(
+            "x-api-key",
+            HeaderValue::from_str(&self.api_key).map_err(|e| {
+                ProxyError::ApiKey(format!("Invalid Anthropic API key format: {}", e))
+            })?,
+        );
+        Ok(headers)
+    }
+}
+
+#[derive(Deserialize, Debug)]
+struct AnthropicModelList {
+    data: Vec<AnthropicModel>,
+}
+
+#[derive(Deserialize, Debug)]
+struct AnthropicModel {
+    id: String,
+    display_name: Option<String>,
 }
 
 #[derive(Deserialize, Debug)]
@@ -26,11 +81,11 @@ struct AnthropicEvent {
     #[serde(rename = "type")]
     event_type: String,
     delta: Option<AnthropicDelta>,
-    #[allow(dead_code)]
-    message: Option<Value>,
-    usage: Option<Value>,
+    message: Option<AnthropicMessage>,
+    usage: Option<AnthropicUsage>,
+    content_block: Option<AnthropicContentBlock>,
     error: Option<AnthropicError>,
-    #[allow(dead_code)]
+    /// The content block a `content_block_*` event belongs to
     index: Option<u32>,
 }
 
@@ -39,6 +94,41 @@ struct AnthropicDelta {
     #[serde(rename = "type")]
     delta_type: Option<String>,
     text: Option<String>,
+    thinking: Option<String>,
+    signature: Option<String>,
+    /// A piece of a tool call's JSON input
+    partial_json: Option<String>,
+    /// Set on `message_delta`
+    stop_reason: Option<String>,
+}
+
+#[derive(Deserialize, Debug)]
+struct AnthropicMessage {
+    usage: Option<AnthropicUsage>,
+}
+
+/// Token counts, reported in full on `message_start` and as output updates on `message_delta`
+#[derive(Deserialize, Debug, Default, Clone, Copy)]
+struct AnthropicUsage {
+    #[serde(default)]
+    input_tokens: u64,
+    #[serde(default)]
+    output_tokens: u64,
+    #[serde(default)]
+    cache_creation_input_tokens: u64,
+    #[serde(default)]
+    cache_read_input_tokens: u64,
+}
+
+#[derive(Deserialize, Debug)]
+struct AnthropicContentBlock {
+    #[serde(rename = "type")]
+    block_type: String,
+    /// Encrypted reasoning of a `redacted_thinking` block
+    data: Option<String>,
+    /// Set on `tool_use` blocks
+    id: Option<String>,
+    name: Option<String>,
 }
 
 #[derive(Deserialize, Debug)]
@@ -48,149 +138,859 @@ struct AnthropicError {
     message: String,
 }
 
+/// A tool call whose input is still streaming in
+#[derive(Debug)]
+struct PendingToolUse {
+    id: String,
+    name: String,
+    input: String,
+}
+
+/// Turns Messages stream events into data stream parts. Error events are left to
+/// the caller, which decides whether to retry.
+#[derive(Debug, Default)]
+struct AnthropicStream {
+    usage: AnthropicUsage,
+    stop_reason: Option<String>,
+    /// Client tool calls by content block index
+    tool_uses: HashMap<u32, PendingToolUse>,
+    /// Whether any output has gone out, after which a retry would repeat it
+    emitted: bool,
+}
+
+impl AnthropicStream {
+    /// The parts to emit for one event
+    fn parts(&mut self, event: AnthropicEvent) -> ProxyResult<Vec<(char, Value)>> {
+        let mut parts = Vec::new();
+        match event.event_type.as_str() {
+            "message_start" => {
+                debug!("Processing message_start event");
+                if let Some(start_usage) = event.message.and_then(|m| m.usage) {
+                    self.usage = start_usage;
+                }
+            }
+            "content_block_start" => match event.content_block {
+                Some(AnthropicContentBlock {
+                    block_type,
+                    data: Some(data),
+                    ..
+                }) if block_type == "redacted_thinking" => {
+                    parts.push(('i', json!({ "data": data })));
+                }
+                // Server tools such as web search run on Anthropic's side and are not
+                // the client's to call
+                Some(AnthropicContentBlock {
+                    block_type,
+                    id: Some(id),
+                    name: Some(name),
+                    ..
+                }) if block_type == "tool_use" => {
+                    parts.push(('b', json!({ "toolCallId": id, "toolName": name })));
+                    self.tool_uses.insert(
+                        event.index.unwrap_or_default(),
+                        PendingToolUse {
+                            id,
+                            name,
+                            input: String::new(),
+                        },
+                    );
+                }
+                _ => {}
+            },
+            "content_block_delta" => {
+                let Some(delta) = event.delta else {
+                    return Ok(parts);
+                };
+                match delta.delta_type.as_deref() {
+                    Some("text_delta") => {
+                        if let Some(text) = delta.text {
+                            parts.push(('0', json!(text)));
+                        }
+                    }
+                    // Reasoning goes out separately from the answer text
+                    Some("thinking_delta") => {
+                        if let Some(thinking) = delta.thinking {
+                            parts.push(('g', json!(thinking)));
+                        }
+                    }
+                    Some("signature_delta") => {
+                        if let Some(signature) = delta.signature {
+                            parts.push(('j', json!({ "signature": signature })));
+                        }
+                    }
+                    Some("input_json_delta") => {
+                        let tool_use = event.index.and_then(|index| self.tool_uses.get_mut(&index));
+                        if let (Some(tool_use), Some(partial)) = (tool_use, delta.partial_json) {
+                            if !partial.is_empty() {
+                                parts.push((
+                                    'c',
+                                    json!({ "toolCallId": tool_use.id, "argsTextDelta": partial }),
+                                ));
+                                tool_use.input.push_str(&partial);
+                            }
+                        }
+                    }
+                    other => debug!("Ignoring content_block_delta of type {:?}", other),
+                }
+            }
+            "content_block_stop" => {
+                let tool_use = event.index.and_then(|index| self.tool_uses.remove(&index));
+                if let Some(tool_use) = tool_use {
+                    let args: Value = if tool_use.input.trim().is_empty() {
+                        json!({})
+                    } else {
+                        serde_json::from_str(&tool_use.input)?
+                    };
+                    parts.push((
+                        '9',
+                        json!({
+                            "toolCallId": tool_use.id,
+                            "toolName": tool_use.name,
+                            "args": args,
+                        }),
+                    ));
+                }
+            }
+            "message_delta" => {
+                if let Some(delta_usage) = event.usage {
+                    self.usage.output_tokens = delta_usage.output_tokens;
+                }
+                if let Some(reason) = event.delta.and_then(|d| d.stop_reason) {
+                    self.stop_reason = Some(reason);
+                }
+            }
+            "message_stop" => {
+                debug!("Message_stop event received");
+                let usage = self.usage;
+                info!(
+                    "Anthropic usage: {} input ({} cache write, {} cache read), {} output",
+                    usage.input_tokens,
+                    usage.cache_creation_input_tokens,
+                    usage.cache_read_input_tokens,
+                    usage.output_tokens
+                );
+                // Cache counts ride along as a message annotation,
+                // since the finish part only carries prompt/completion tokens
+                parts.push((
+                    '8',
+                    json!([{
+                        "type": "usage",
+                        "cacheCreationInputTokens": usage.cache_creation_input_tokens,
+                        "cacheReadInputTokens": usage.cache_read_input_tokens,
+                    }]),
+                ));
+                parts.push((
+                    'd',
+                    json!({
+                        "finishReason": self
+                            .stop_reason
+                            .as_deref()
+                            .map_or("stop", map_stop_reason),
+                        "usage": {
+                            "promptTokens": usage.input_tokens
+                                + usage.cache_creation_input_tokens
+                                + usage.cache_read_input_tokens,
+                            "completionTokens": usage.output_tokens,
+                            "cacheCreationInputTokens": usage.cache_creation_input_tokens,
+                            "cacheReadInputTokens": usage.cache_read_input_tokens,
+                        },
+                    }),
+                ));
+            }
+            "ping" => {
+                debug!("Ping event ignored");
+            }
+            "error" => warn!("Error event without details"),
+            _ => warn!("Unknown event type: {}", event.event_type),
+        }
+        self.emitted |= !parts.is_empty();
+        Ok(parts)
+    }
+}
+
 #[async_trait]
 impl ProxyProvider for AnthropicProvider {
-    async fn stream(&self, window: Window, body: Value) -> ProxyResult<()> {
-        info!("Starting Anthropic stream request");
-        let client = reqwest::Client::new();
-        let mut headers = HeaderMap::new();
-        headers.insert(CONTENT_TYPE, HeaderValue::from_static("application/json"));
-        headers.insert("anthropic-version", HeaderValue::from_static("2023-06-01"));
-        headers.insert(
-            "x-api-key",
-            HeaderValue::from_str(&self.api_key).map_err(|e| {
-                ProxyError::ApiKey(format!("Invalid Anthropic API key format: {}", e))
-            })?,
-        );
-
+    /// List available models via `/v1/models`
+    async fn list_models(&self) -> ProxyResult<Vec<ModelInfo>> {
+        let client = &self.client;
         let response = client
-            .post("https://api.anthropic.com/v1/messages")
-            .headers(headers)
-            .json(&body)
+            .get(format!("{}/models?limit=1000", ANTHROPIC_BASE_URL))
+            .headers(self.headers()?)
             .send()
             .await?;
 
-        let status = response.status();
-        if !status.is_success() {
-            let error_body = response
-                .text()
-                .await
-                .unwrap_or_else(|_| "Failed to read error body".to_string());
-            let error_msg = format!(
-                "Anthropic API request failed with status {}: {}",
-                status, error_body
+        if !response.status().is_success() {
+            return Err(ProxyError::Provider(
+                read_error("anthropic", response).await,
+            ));
+        }
+
+        let list: AnthropicModelList = response.json().await?;
+        Ok(list
+            .data
+            .into_iter()
+            .map(|model| {
+                ModelInfo::with_known_capabilities(model.id, model.display_name, "anthropic")
+            })
+            .collect())
+    }
+
+    fn build_request(&self, request: &ChatRequest) -> ProxyResult<Value> {
+        if request.temperature.is_some_and(|t| t > 1.0) {
+            return Err(invalid("Anthropic temperature must be between 0 and 1"));
+        }
+        if request.thinking.is_some() && request.temperature.is_some_and(|t| t != 1.0) {
+            return Err(invalid(
+                "Anthropic extended thinking requires temperature to be unset or 1",
+            ));
+        }
+        attachments::check_support(
+            request,
+            &AttachmentSupport {
+                provider: "Anthropic",
+                images: true,
+                documents: true,
+                vision_models: &[],
+                allow_unknown_models: true,
+            },
+        )?;
+
+        let mut messages = Vec::new();
+        for message in request.conversation() {
+            let mut blocks = Vec::new();
+            for part in &message.content {
+                blocks.push(match part {
+                    ContentPart::Text { text } => json!({ "type": "text", "text": text }),
+                    ContentPart::ToolCall {
+                        id,
+                        name,
+                        arguments,
+                    } => json!({
+                        "type": "tool_use",
+                        "id": id,
+                        "name": name,
+                        "input": if arguments.is_null() { json!({}) } else { arguments.clone() },
+                    }),
+                    ContentPart::ToolResult {
+                        tool_call_id,
+                        content,
+                        is_error,
+                        ..
+                    } => json!({
+                        "type": "tool_result",
+                        "tool_use_id": tool_call_id,
+                        "content": tool_result_text(content),
+                        "is_error": is_error,
+                    }),
+                    ContentPart::Reasoning {
+                        text,
+                        signature: Some(signature),
+                    } => json!({
+                        "type": "thinking",
+                        "thinking": text,
+                        "signature": signature,
+                    }),
+                    // Unsigned reasoning (e.g. from another provider) would be rejected
+                    ContentPart::Reasoning {
+                        signature: None, ..
+                    } => continue,
+                    ContentPart::RedactedReasoning { data } => json!({
+                        "type": "redacted_thinking",
+                        "data": data,
+                    }),
+                    ContentPart::Attachment { path, media_type } => {
+                        let attachment = attachments::load(path, media_type.as_deref())?;
+                        let block_type = match attachment.kind {
+                            AttachmentKind::Image => "image",
+                            AttachmentKind::Document => "document",
+                        };
+                        json!({
+                            "type": block_type,
+                            "source": {
+                                "type": "base64",
+                                "media_type": attachment.media_type,
+                                "data": attachment.data,
+                            },
+                        })
+                    }
+                });
+            }
+            // Tool results travel back to Anthropic inside user turns
+            let role = match message.role {
+                ChatRole::Assistant => "assistant",
+                _ => "user",
+            };
+            messages.push(json!({ "role": role, "content": blocks }));
+        }
+
+        let caching = request.prompt_caching != Some(false);
+        if caching {
+            // The latest turn is resent unchanged next time, so cache everything up to it.
+            // Thinking blocks and empty text cannot carry a breakpoint.
+            let cacheable = messages
+                .last_mut()
+                .and_then(|m| m["content"].as_array_mut())
+                .and_then(|blocks| {
+                    blocks.iter_mut().rev().find(|b| {
+                        !matches!(b["type"].as_str(), Some("thinking" | "redacted_thinking"))
+                            && b["text"].as_str() != Some("")
+                    })
+                });
+            if let Some(block) = cacheable {
+                add_cache_breakpoint(block);
+            }
+        }
+
+        let mut body = Map::new();
+        body.insert("model".to_string(), json!(request.model));
+        body.insert("messages".to_string(), Value::Array(messages));
+        // The thinking budget counts towards max_tokens, so leave room for the answer
+        let default_max_tokens = request
+            .thinking
+            .as_ref()
+            .map_or(0, |thinking| thinking.budget_tokens)
+            + ANTHROPIC_DEFAULT_MAX_TOKENS;
+        body.insert(
+            "max_tokens".to_string(),
+            json!(request.max_tokens.unwrap_or(default_max_tokens)),
+        );
+        body.insert("stream".to_string(), Value::Bool(true));
+        if let Some(system) = request.system_prompt() {
+            let mut block = json!({ "type": "text", "text": system });
+            if caching {
+                add_cache_breakpoint(&mut block);
+            }
+            body.insert("system".to_string(), json!([block]));
+        }
+        if let Some(temperature) = request.temperature {
+            body.insert("temperature".to_string(), json!(temperature));
+        }
+        if !request.stop.is_empty() {
+            body.insert("stop_sequences".to_string(), json!(request.stop));
+        }
+        if let Some(thinking) = &request.thinking {
+            body.insert(
+                "thinking".to_string(),
+                json!({ "type": "enabled", "budget_tokens": thinking.budget_tokens }),
             );
-            emit_error(&window, &error_msg)?;
-            return Err(ProxyError::Status(status.as_u16()));
         }
-        info!("Anthropic API request successful (status: {})", status);
-
-        let mut stream = response.bytes_stream();
-        let mut buffer = String::new();
-
-        debug!("Starting to process Anthropic stream");
-        while let Some(item) = stream.next().await {
-            match item {
-                Ok(chunk) => {
-                    debug!("Received raw bytes chunk: {} bytes", chunk.len());
-                    match String::from_utf8(chunk.to_vec()) {
-                        Ok(chunk_string) => {
-                            buffer.push_str(&chunk_string);
-
-                            while let Some(pos) = buffer.find("\n\n") {
-                                let event_data = buffer[..pos].trim().to_string();
-                                buffer = buffer[pos + 2..].to_string(); // Skip "\n\n"
-
-                                let mut data_line = "";
-                                for line in event_data.lines() {
-                                    if let Some(stripped) = line.strip_prefix("data: ") {
-                                        data_line = stripped;
-                                    }
-                                }
-
-                                if data_line.is_empty() {
-                                    debug!("Skipping event block - no data line found");
-                                    continue;
-                                }
-
-                                match serde_json::from_str::<AnthropicEvent>(data_line) {
-                                    Ok(event) => {
-                                        debug!("Parsed event type: {}", event.event_type);
-                                        match event.event_type.as_str() {
-                                            "message_start" => {
-                                                debug!("Processing message_start event");
-                                            }
-                                            "content_block_delta" => {
-                                                if let Some(delta) = event.delta {
-                                                    if delta.delta_type.as_deref()
-                                                        == Some("text_delta")
-                                                    {
-                                                        if let Some(text) = delta.text {
-                                                            let text_json =
-                                                                serde_json::to_string(&text)
-                                                                    .map_err(ProxyError::Parse)?;
-                                                            emit_chunk(
-                                                                &window,
-                                                                format!("0:{}\n", text_json),
-                                                            )?;
-                                                        }
-                                                    }
-                                                }
-                                            }
-                                            "message_delta" => {
-                                                if let Some(_usage) = event.usage {
-                                                    debug!(
-                                                        "Message_delta with usage metrics received"
-                                                    );
-                                                }
-                                            }
-                                            "message_stop" => {
-                                                debug!("Message_stop event received");
-                                                if let Some(_usage) = event.usage {
-                                                    debug!("Final usage data received");
-                                                }
-                                            }
-                                            "error" => {
-                                                if let Some(error_details) = event.error {
-                                                    let err_msg = format!(
-                                                        "API Error Event: [{}] {}",
-                                                        error_details.error_type,
-                                                        error_details.message
-                                                    );
-                                                    error!("{}", err_msg);
-                                                    emit_error(&window, &err_msg)?;
-                                                }
-                                            }
-                                            "ping" => {
-                                                debug!("Ping event ignored");
-                                            }
-                                            _ => warn!("Unknown event type: {}", event.event_type),
-                                        }
-                                    }
-                                    Err(e) => {
-                                        warn!("Failed to parse data as JSON event: {}", e);
-                                    }
-                                }
-                            }
-                        }
+        let mut tools: Vec<Value> = request
+            .tools
+            .iter()
+            .map(|tool| {
+                let mut definition = json!({
+                    "name": tool.name,
+                    "input_schema": tool.parameters,
+                });
+                if let Some(description) = &tool.description {
+                    definition["description"] = json!(description);
+                }
+                definition
+            })
+            .collect();
+        // Server tools such as web search sit alongside client tools
+        tools.extend(request.builtin_tools.iter().cloned());
+        if caching {
+            // Tools precede the system prompt in the cached prefix; one breakpoint covers them all
+            if let Some(last) = tools.last_mut() {
+                add_cache_breakpoint(last);
+            }
+        }
+        if !tools.is_empty() {
+            body.insert("tools".to_string(), Value::Array(tools));
+        }
+
+        Ok(Value::Object(body))
+    }
+
+    async fn complete(&self, mut body: Value) -> ProxyResult<String> {
+        body["stream"] = Value::Bool(false);
+        let response = self
+            .client
+            .post(format!("{}/messages", ANTHROPIC_BASE_URL))
+            .headers(self.headers()?)
+            .json(&body)
+            .send()
+            .await?;
+        if !response.status().is_success() {
+            return Err(ProxyError::Provider(
+                read_error("anthropic", response).await,
+            ));
+        }
+
+        let message: Value = response.json().await?;
+        Ok(message["content"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .filter(|block| block["type"] == "text")
+            .filter_map(|block| block["text"].as_str())
+            .collect())
+    }
+
+    async fn stream(&self, window: Window, body: Value) -> ProxyResult<()> {
+        info!("Starting Anthropic stream request");
+        let client = &self.client;
+        let headers = self.headers()?;
+
+        let url = format!("{}/messages", ANTHROPIC_BASE_URL);
+        let policy = RetryPolicy::default();
+        // Shared with `send_with_retry`, so overloads before and after the
+        // response draw on the same budget
+        let mut attempt = 0;
+
+        'attempts: loop {
+            let response = send_with_retry(&window, "anthropic", &policy, &mut attempt, || {
+                client.post(&url).headers(headers.clone()).json(&body)
+            })
+            .await?;
+
+            let status = response.status();
+            if !status.is_success() {
+                let err = read_error("anthropic", response).await;
+                emit_provider_error(&window, &err)?;
+                return Err(ProxyError::Provider(err));
+            }
+            info!("Anthropic API request successful (status: {})", status);
+
+            let mut stream = response.bytes_stream();
+            let mut sse = SseBuffer::default();
+            let mut state = AnthropicStream::default();
+
+            debug!("Starting to process Anthropic stream");
+            while let Some(item) = stream.next().await {
+                let chunk = match item {
+                    Ok(chunk) => chunk,
+                    Err(e) => {
+                        let error_msg = format!("Error reading stream chunk: {}", e);
+                        error!("{}", error_msg);
+                        emit_error(&window, &error_msg)?;
+                        return Err(ProxyError::Http(e));
+                    }
+                };
+                debug!("Received raw bytes chunk: {} bytes", chunk.len());
+                sse.push(&chunk);
+
+                while let Some(data) = sse.next_data() {
+                    let mut event = match serde_json::from_str::<AnthropicEvent>(&data) {
+                        Ok(event) => event,
                         Err(e) => {
-                            let error_msg = format!("Failed to decode chunk as UTF-8: {}", e);
-                            error!("{}", error_msg);
-                            emit_error(&window, &error_msg)?;
+                            warn!("Failed to parse data as JSON event: {}", e);
+                            continue;
+                        }
+                    };
+                    debug!("Parsed event type: {}", event.event_type);
+                    if let Some(error_details) = event.error.take() {
+                        // Anthropic can report overload mid-stream; until output is
+                        // emitted it is safe to start over
+                        let retry =
+                            if error_details.error_type == "overloaded_error" && !state.emitted {
+                                policy.next_delay(attempt, None)
+                            } else {
+                                None
+                            };
+                        if let Some(delay) = retry {
+                            warn!(
+                                "Anthropic overloaded before output, retrying in {:?}",
+                                delay
+                            );
+                            retry_wait(
+                                &window,
+                                "anthropic",
+                                &policy,
+                                attempt,
+                                delay,
+                                "overloaded".to_string(),
+                                None,
+                            )
+                            .await?;
+                            continue 'attempts;
                         }
+                        let err = ProviderError::in_stream(
+                            "anthropic",
+                            error_details.error_type,
+                            error_details.message,
+                        );
+                        // Nothing after an error event is usable
+                        emit_provider_error(&window, &err)?;
+                        return Err(ProxyError::Provider(err));
+                    }
+                    for (code, value) in state.parts(event)? {
+                        emit_part(&window, code, &value)?;
                     }
-                }
-                Err(e) => {
-                    let error_msg = format!("Error reading stream chunk: {}", e);
-                    error!("{}", error_msg);
-                    emit_error(&window, &error_msg)?;
-                    return Err(ProxyError::Http(e));
                 }
             }
+
+            info!("Anthropic stream completed");
+            emit_end(&window)?;
+            return Ok(());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn provider() -> AnthropicProvider {
+        AnthropicProvider::new(reqwest::Client::new(), "sk-ant".to_string())
+    }
+
+    fn chat_request(request: Value) -> ChatRequest {
+        serde_json::from_value(request).unwrap()
+    }
+
+    #[test]
+    fn builds_messages_with_the_system_prompt_apart() {
+        let request = chat_request(json!({
+            "model": "claude-sonnet-4-5",
+            "system": "Be brief.",
+            "messages": [
+                { "role": "system", "content": "Use metric units." },
+                { "role": "user", "content": "Weather in Oslo?" },
+                { "role": "assistant", "content": [
+                    { "type": "text", "text": "Checking." },
+                    { "type": "tool_call", "id": "toolu_1", "name": "weather" },
+                ] },
+                { "role": "tool", "content": [
+                    { "type": "tool_result", "tool_call_id": "toolu_1",
+                      "content": { "temp": 4 } },
+                ] },
+            ],
+            "tools": [{
+                "name": "weather",
+                "description": "Current weather",
+                "parameters": { "type": "object" },
+            }],
+            "stop": ["END"],
+            "temperature": 0.5,
+            "prompt_caching": false,
+        }));
+
+        let body = provider().build_request(&request).unwrap();
+        assert_eq!(
+            body,
+            json!({
+                "model": "claude-sonnet-4-5",
+                "max_tokens": ANTHROPIC_DEFAULT_MAX_TOKENS,
+                "stream": true,
+                "system": [{ "type": "text", "text": "Be brief.\n\nUse metric units." }],
+                "messages": [
+                    { "role": "user", "content": [
+                        { "type": "text", "text": "Weather in Oslo?" },
+                    ] },
+                    { "role": "assistant", "content": [
+                        { "type": "text", "text": "Checking." },
+                        { "type": "tool_use", "id": "toolu_1", "name": "weather", "input": {} },
+                    ] },
+                    // Tool results go back in a user turn
+                    { "role": "user", "content": [{
+                        "type": "tool_result",
+                        "tool_use_id": "toolu_1",
+                        "content": "{\"temp\":4}",
+                        "is_error": false,
+                    }] },
+                ],
+                "tools": [{
+                    "name": "weather",
+                    "description": "Current weather",
+                    "input_schema": { "type": "object" },
+                }],
+                "stop_sequences": ["END"],
+                "temperature": 0.5,
+            })
+        );
+    }
+
+    #[test]
+    fn places_cache_breakpoints() {
+        let request = chat_request(json!({
+            "model": "claude-sonnet-4-5",
+            "system": "Be brief.",
+            "messages": [
+                { "role": "user", "content": "Hi" },
+                { "role": "assistant", "content": [
+                    { "type": "text", "text": "Hello" },
+                    { "type": "reasoning", "text": "Greeting.", "signature": "sig" },
+                ] },
+            ],
+            "tools": [
+                { "name": "a", "parameters": { "type": "object" } },
+                { "name": "b", "parameters": { "type": "object" } },
+            ],
+            "builtin_tools": [{ "type": "web_search_20250305", "name": "web_search" }],
+        }));
+
+        let body = provider().build_request(&request).unwrap();
+        let ephemeral = json!({ "type": "ephemeral" });
+        assert_eq!(body["system"][0]["cache_control"], ephemeral);
+        // One breakpoint after the last tool, built-in ones included
+        let tools = body["tools"].as_array().unwrap();
+        assert_eq!(tools[2]["type"], json!("web_search_20250305"));
+        assert_eq!(tools[2]["cache_control"], ephemeral);
+        assert!(tools[..2].iter().all(|t| t.get("cache_control").is_none()));
+        // Thinking blocks cannot carry one, so it goes on the text before
+        let last_turn = &body["messages"][1]["content"];
+        assert_eq!(last_turn[0]["cache_control"], ephemeral);
+        assert!(last_turn[1].get("cache_control").is_none());
+        assert!(body["messages"][0]["content"][0]
+            .get("cache_control")
+            .is_none());
+    }
+
+    #[test]
+    fn passes_thinking_and_signed_reasoning_back() {
+        let request = chat_request(json!({
+            "model": "claude-sonnet-4-5",
+            "thinking": { "budget_tokens": 2048 },
+            "prompt_caching": false,
+            "messages": [
+                { "role": "user", "content": "Hi" },
+                { "role": "assistant", "content": [
+                    { "type": "reasoning", "text": "Signed.", "signature": "sig" },
+                    { "type": "reasoning", "text": "From another provider." },
+                    { "type": "redacted_reasoning", "data": "EmwK" },
+                    { "type": "text", "text": "Hello" },
+                ] },
+                { "role": "user", "content": "Again" },
+            ],
+        }));
+
+        let body = provider().build_request(&request).unwrap();
+        assert_eq!(
+            body["thinking"],
+            json!({ "type": "enabled", "budget_tokens": 2048 })
+        );
+        // The budget comes out of max_tokens, so the default leaves room on top
+        assert_eq!(
+            body["max_tokens"],
+            json!(2048 + ANTHROPIC_DEFAULT_MAX_TOKENS)
+        );
+        assert_eq!(
+            body["messages"][1]["content"],
+            json!([
+                { "type": "thinking", "thinking": "Signed.", "signature": "sig" },
+                { "type": "redacted_thinking", "data": "EmwK" },
+                { "type": "text", "text": "Hello" },
+            ])
+        );
+    }
+
+    #[test]
+    fn rejects_unsupported_temperatures() {
+        let too_hot = chat_request(json!({
+            "model": "claude-sonnet-4-5",
+            "messages": [{ "role": "user", "content": "Hi" }],
+            "temperature": 1.5,
+        }));
+        assert!(matches!(
+            provider().build_request(&too_hot),
+            Err(ProxyError::Validation(_))
+        ));
+
+        let thinking = chat_request(json!({
+            "model": "claude-sonnet-4-5",
+            "messages": [{ "role": "user", "content": "Hi" }],
+            "temperature": 0.5,
+            "thinking": { "budget_tokens": 2048 },
+        }));
+        assert!(matches!(
+            provider().build_request(&thinking),
+            Err(ProxyError::Validation(_))
+        ));
+    }
+
+    /// Run a recorded stream through the parser, as `stream` does
+    fn stream_parts(recorded: &str) -> Vec<(char, Value)> {
+        let mut sse = SseBuffer::default();
+        sse.push(recorded.as_bytes());
+        let mut state = AnthropicStream::default();
+        let mut parts = Vec::new();
+        while let Some(data) = sse.next_data() {
+            let event: AnthropicEvent = serde_json::from_str(&data).unwrap();
+            parts.extend(state.parts(event).unwrap());
         }
+        parts
+    }
+
+    #[test]
+    fn streams_thinking_text_and_usage() {
+        let parts = stream_parts(concat!(
+            "event: message_start\n",
+            r#"data: {"type":"message_start","message":{"id":"msg_1","usage":{"input_tokens":12,"#,
+            r#""cache_creation_input_tokens":100,"cache_read_input_tokens":0,"output_tokens":1}}}"#,
+            "\n\n",
+            "event: content_block_start\n",
+            r#"data: {"type":"content_block_start","index":0,"#,
+            r#""content_block":{"type":"thinking","thinking":""}}"#,
+            "\n\n",
+            "event: content_block_delta\n",
+            r#"data: {"type":"content_block_delta","index":0,"#,
+            r#""delta":{"type":"thinking_delta","thinking":"Short answer."}}"#,
+            "\n\n",
+            "event: content_block_delta\n",
+            r#"data: {"type":"content_block_delta","index":0,"#,
+            r#""delta":{"type":"signature_delta","signature":"sig=="}}"#,
+            "\n\n",
+            "event: content_block_stop\n",
+            r#"data: {"type":"content_block_stop","index":0}"#,
+            "\n\n",
+            "event: ping\n",
+            r#"data: {"type": "ping"}"#,
+            "\n\n",
+            "event: content_block_start\n",
+            r#"data: {"type":"content_block_start","index":1,"#,
+            r#""content_block":{"type":"redacted_thinking","data":"EmwKAhgB"}}"#,
+            "\n\n",
+            "event: content_block_start\n",
+            r#"data: {"type":"content_block_start","index":2,"#,
+            r#""content_block":{"type":"text","text":""}}"#,
+            "\n\n",
+            "event: content_block_delta\n",
+            r#"data: {"type":"content_block_delta","index":2,"#,
+            r#""delta":{"type":"text_delta","text":"Hi"}}"#,
+            "\n\n",
+            "event: message_delta\n",
+            r#"data: {"type":"message_delta","delta":{"stop_reason":"max_tokens"},"#,
+            r#""usage":{"output_tokens":7}}"#,
+            "\n\n",
+            "event: message_stop\n",
+            r#"data: {"type":"message_stop"}"#,
+            "\n\n",
+        ));
+
+        assert_eq!(
+            parts,
+            [
+                ('g', json!("Short answer.")),
+                ('j', json!({ "signature": "sig==" })),
+                ('i', json!({ "data": "EmwKAhgB" })),
+                ('0', json!("Hi")),
+                (
+                    '8',
+                    json!([{
+                        "type": "usage",
+                        "cacheCreationInputTokens": 100,
+                        "cacheReadInputTokens": 0,
+                    }])
+                ),
+                (
+                    'd',
+                    json!({
+                        "finishReason": "length",
+                        "usage": {
+                            "promptTokens": 112,
+                            "completionTokens": 7,
+                            "cacheCreationInputTokens": 100,
+                            "cacheReadInputTokens": 0,
+                        },
+                    })
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn streams_tool_use_blocks() {
+        let parts = stream_parts(concat!(
+            "event: message_start\n",
+            r#"data: {"type":"message_start","#,
+            r#""message":{"usage":{"input_tokens":30,"output_tokens":1}}}"#,
+            "\n\n",
+            "event: content_block_start\n",
+            r#"data: {"type":"content_block_start","index":0,"#,
+            r#""content_block":{"type":"text","text":""}}"#,
+            "\n\n",
+            "event: content_block_delta\n",
+            r#"data: {"type":"content_block_delta","index":0,"#,
+            r#""delta":{"type":"text_delta","text":"Checking."}}"#,
+            "\n\n",
+            "event: content_block_stop\n",
+            r#"data: {"type":"content_block_stop","index":0}"#,
+            "\n\n",
+            "event: content_block_start\n",
+            r#"data: {"type":"content_block_start","index":1,"content_block":{"type":"tool_use","#,
+            r#""id":"toolu_01","name":"weather","input":{}}}"#,
+            "\n\n",
+            "event: content_block_delta\n",
+            r#"data: {"type":"content_block_delta","index":1,"#,
+            r#""delta":{"type":"input_json_delta","partial_json":""}}"#,
+            "\n\n",
+            "event: content_block_delta\n",
+            r#"data: {"type":"content_block_delta","index":1,"#,
+            r#""delta":{"type":"input_json_delta","partial_json":"{\"city\": "}}"#,
+            "\n\n",
+            "event: content_block_delta\n",
+            r#"data: {"type":"content_block_delta","index":1,"#,
+            r#""delta":{"type":"input_json_delta","partial_json":"\"Oslo\"}"}}"#,
+            "\n\n",
+            "event: content_block_stop\n",
+            r#"data: {"type":"content_block_stop","index":1}"#,
+            "\n\n",
+            "event: content_block_start\n",
+            r#"data: {"type":"content_block_start","index":2,"content_block":{"type":"tool_use","#,
+            r#""id":"toolu_02","name":"time","input":{}}}"#,
+            "\n\n",
+            "event: content_block_stop\n",
+            r#"data: {"type":"content_block_stop","index":2}"#,
+            "\n\n",
+            "event: message_delta\n",
+            r#"data: {"type":"message_delta","delta":{"stop_reason":"tool_use"},"#,
+            r#""usage":{"output_tokens":40}}"#,
+            "\n\n",
+            "event: message_stop\n",
+            r#"data: {"type":"message_stop"}"#,
+            "\n\n",
+        ));
+
+        assert_eq!(
+            parts[..7],
+            [
+                ('0', json!("Checking.")),
+                (
+                    'b',
+                    json!({ "toolCallId": "toolu_01", "toolName": "weather" })
+                ),
+                (
+                    'c',
+                    json!({ "toolCallId": "toolu_01", "argsTextDelta": "{\"city\": " })
+                ),
+                (
+                    'c',
+                    json!({ "toolCallId": "toolu_01", "argsTextDelta": "\"Oslo\"}" })
+                ),
+                (
+                    '9',
+                    json!({
+                        "toolCallId": "toolu_01",
+                        "toolName": "weather",
+                        "args": { "city": "Oslo" },
+                    })
+                ),
+                ('b', json!({ "toolCallId": "toolu_02", "toolName": "time" })),
+                (
+                    '9',
+                    json!({ "toolCallId": "toolu_02", "toolName": "time", "args": {} })
+                ),
+            ]
+        );
+        assert_eq!(parts[8].0, 'd');
+        assert_eq!(parts[8].1["finishReason"], json!("tool-calls"));
+    }
+
+    #[test]
+    fn server_tool_use_is_not_a_client_tool_call() {
+        let parts = stream_parts(concat!(
+            r#"data: {"type":"content_block_start","index":0,"content_block":{"#,
+            r#""type":"server_tool_use","id":"srvtoolu_01","name":"web_search","input":{}}}"#,
+            "\n\n",
+            r#"data: {"type":"content_block_delta","index":0,"#,
+            r#""delta":{"type":"input_json_delta","partial_json":"{\"query\":\"rust\"}"}}"#,
+            "\n\n",
+            r#"data: {"type":"content_block_stop","index":0}"#,
+            "\n\n",
+        ));
 
-        info!("Anthropic stream completed");
-        emit_end(&window)?;
-        Ok(())
+        assert!(parts.is_empty());
     }
 }
@@ -0,0 +1,368 @@
+use crate::services::proxy::models::supports_vision;
+use crate::services::proxy::request::invalid;
+#[cfg(test)]
+use crate::services::proxy::ProxyError;
+use crate::services::proxy::{ChatRequest, ContentPart, ProxyResult};
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+use image::{codecs::jpeg::JpegEncoder, imageops::FilterType, ImageFormat};
+use log::debug;
+use std::fs;
+use std::io::Cursor;
+use std::path::Path;
+
+/// Largest image file accepted before downscaling
+const MAX_IMAGE_FILE_BYTES: u64 = 20 * 1024 * 1024;
+/// Largest PDF accepted; PDFs are sent as-is, so this is also the request cost
+const MAX_DOCUMENT_BYTES: u64 = 32 * 1024 * 1024;
+/// Images are downscaled so their longest side fits, which is also the largest size
+/// providers process without resizing server-side
+const MAX_IMAGE_DIMENSION: u32 = 1568;
+/// Images still larger than this once encoded are re-encoded as JPEG
+const MAX_ENCODED_IMAGE_BYTES: usize = 5 * 1024 * 1024;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum AttachmentKind {
+    Image,
+    Document,
+}
+
+/// A local file read, checked and encoded for sending to a provider
+#[derive(Debug, Clone)]
+pub(crate) struct Attachment {
+    pub kind: AttachmentKind,
+    pub media_type: String,
+    /// Base64-encoded file contents
+    pub data: String,
+}
+
+impl Attachment {
+    /// `data:` URL form used by OpenAI
+    pub fn data_url(&self) -> String {
+        format!("data:{};base64,{}", self.media_type, self.data)
+    }
+}
+
+/// Which attachment kinds a provider path can send, and for which models
+pub(crate) struct AttachmentSupport<'a> {
+    pub provider: &'static str,
+    pub images: bool,
+    pub documents: bool,
+    /// Models a profile declares as taking images and PDFs, whatever the
+    /// capability table says
+    pub vision_models: &'a [String],
+    /// Whether models neither in the capability table nor in `vision_models` may
+    /// be sent attachments. Official APIs reject what a model cannot take; a
+    /// compatible server may instead drop the attachment without a word.
+    pub allow_unknown_models: bool,
+}
+
+/// Resolve the media type from the explicit value or the file extension
+fn media_type(path: &Path, declared: Option<&str>) -> ProxyResult<&'static str> {
+    let declared = declared.map(str::to_ascii_lowercase);
+    let extension = path
+        .extension()
+        .and_then(|e| e.to_str())
+        .map(str::to_ascii_lowercase);
+    let media_type = match (declared.as_deref(), extension.as_deref()) {
+        (Some("image/png"), _) | (None, Some("png")) => "image/png",
+        (Some("image/jpeg" | "image/jpg"), _) | (None, Some("jpg" | "jpeg")) => "image/jpeg",
+        (Some("image/webp"), _) | (None, Some("webp")) => "image/webp",
+        (Some("image/gif"), _) | (None, Some("gif")) => "image/gif",
+        (Some("application/pdf"), _) | (None, Some("pdf")) => "application/pdf",
+        (Some(other), _) => {
+            return Err(invalid(format!(
+                "unsupported attachment type '{}' for {}; expected PNG, JPEG, WebP, GIF or PDF",
+                other,
+                path.display()
+            )))
+        }
+        (None, _) => {
+            return Err(invalid(format!(
+                "cannot tell the type of attachment {}; expected a .png, .jpg, .webp, .gif or .pdf file",
+                path.display()
+            )))
+        }
+    };
+    Ok(media_type)
+}
+
+/// Read an attachment from disk, downscaling images that exceed provider limits
+pub(crate) fn load(path: &str, declared_media_type: Option<&str>) -> ProxyResult<Attachment> {
+    let path = Path::new(path);
+    let media_type = media_type(path, declared_media_type)?;
+    let kind = if media_type == "application/pdf" {
+        AttachmentKind::Document
+    } else {
+        AttachmentKind::Image
+    };
+
+    let size = fs::metadata(path)
+        .map_err(|e| invalid(format!("cannot read attachment {}: {}", path.display(), e)))?
+        .len();
+    let limit = match kind {
+        AttachmentKind::Image => MAX_IMAGE_FILE_BYTES,
+        AttachmentKind::Document => MAX_DOCUMENT_BYTES,
+    };
+    if size > limit {
+        return Err(invalid(format!(
+            "attachment {} is {} MB, larger than the {} MB limit",
+            path.display(),
+            size / (1024 * 1024),
+            limit / (1024 * 1024)
+        )));
+    }
+
+    let bytes = fs::read(path)
+        .map_err(|e| invalid(format!("cannot read attachment {}: {}", path.display(), e)))?;
+    let (media_type, bytes) = match kind {
+        AttachmentKind::Image => fit_image(path, media_type, bytes)?,
+        AttachmentKind::Document => {
+            if !bytes.starts_with(b"%PDF") {
+                return Err(invalid(format!("{} is not a PDF file", path.display())));
+            }
+            (media_type, bytes)
+        }
+    };
+
+    debug!(
+        "Loaded attachment {} ({}, {} bytes)",
+        path.display(),
+        media_type,
+        bytes.len()
+    );
+    Ok(Attachment {
+        kind,
+        media_type: media_type.to_string(),
+        data: BASE64.encode(bytes),
+    })
+}
+
+/// Downscale an image whose dimensions or size exceed the limits, returning the
+/// (possibly changed) media type and bytes
+fn fit_image(
+    path: &Path,
+    media_type: &'static str,
+    bytes: Vec<u8>,
+) -> ProxyResult<(&'static str, Vec<u8>)> {
+    let format = ImageFormat::from_mime_type(media_type).unwrap_or(ImageFormat::Png);
+    let image = image::load_from_memory_with_format(&bytes, format).map_err(|e| {
+        invalid(format!(
+            "attachment {} is not a valid {} image: {}",
+            path.display(),
+            media_type,
+            e
+        ))
+    })?;
+
+    let oversized = image.width().max(image.height()) > MAX_IMAGE_DIMENSION;
+    if !oversized && bytes.len() <= MAX_ENCODED_IMAGE_BYTES {
+        return Ok((media_type, bytes));
+    }
+
+    let resized = if oversized {
+        debug!(
+            "Downscaling {} from {}x{}",
+            path.display(),
+            image.width(),
+            image.height()
+        );
+        image.resize(
+            MAX_IMAGE_DIMENSION,
+            MAX_IMAGE_DIMENSION,
+            FilterType::Lanczos3,
+        )
+    } else {
+        image
+    };
+
+    let encode_error = |e: image::ImageError| {
+        invalid(format!(
+            "failed to re-encode attachment {}: {}",
+            path.display(),
+            e
+        ))
+    };
+    // Keep PNG for graphics with transparency unless it is still too large
+    if media_type == "image/png" {
+        let mut png = Vec::new();
+        resized
+            .write_to(&mut Cursor::new(&mut png), ImageFormat::Png)
+            .map_err(encode_error)?;
+        if png.len() <= MAX_ENCODED_IMAGE_BYTES {
+            return Ok(("image/png", png));
+        }
+    }
+    let mut jpeg = Vec::new();
+    resized
+        .to_rgb8()
+        .write_with_encoder(JpegEncoder::new_with_quality(&mut jpeg, 85))
+        .map_err(encode_error)?;
+    if jpeg.len() > MAX_ENCODED_IMAGE_BYTES {
+        return Err(invalid(format!(
+            "attachment {} is still larger than 5 MB after downscaling",
+            path.display()
+        )));
+    }
+    Ok(("image/jpeg", jpeg))
+}
+
+/// Reject attachments the provider path or model cannot take, before any file is read.
+/// PDFs are read as page images by every provider, so they need a vision model too.
+pub(crate) fn check_support(request: &ChatRequest, support: &AttachmentSupport) -> ProxyResult<()> {
+    let vision = if support.vision_models.contains(&request.model) {
+        Some(true)
+    } else {
+        supports_vision(&request.model)
+    };
+    for message in request.conversation() {
+        for part in &message.content {
+            let ContentPart::Attachment { path, media_type } = part else {
+                continue;
+            };
+            let (kind, supported) = match media_type_kind(Path::new(path), media_type.as_deref())? {
+                AttachmentKind::Image => ("image", support.images),
+                AttachmentKind::Document => ("PDF", support.documents),
+            };
+            if !supported {
+                return Err(invalid(format!(
+                    "{} does not accept {} attachments",
+                    support.provider, kind
+                )));
+            }
+            match vision {
+                Some(false) => {
+                    return Err(invalid(format!(
+                        "model {} does not accept {} attachments",
+                        request.model, kind
+                    )))
+                }
+                None if !support.allow_unknown_models => {
+                    return Err(invalid(format!(
+                        "model {} is not known to accept {} attachments; \
+                         add it to the profile's vision_models if it does",
+                        request.model, kind
+                    )))
+                }
+                _ => {}
+            }
+        }
+    }
+    Ok(())
+}
+
+fn media_type_kind(path: &Path, declared: Option<&str>) -> ProxyResult<AttachmentKind> {
+    Ok(match media_type(path, declared)? {
+        "application/pdf" => AttachmentKind::Document,
+        _ => AttachmentKind::Image,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn with_attachment(model: &str, path: &str) -> ChatRequest {
+        serde_json::from_value(json!({
+            "model": model,
+            "messages": [{ "role": "user", "content": [
+                { "type": "text", "text": "What is this?" },
+                { "type": "attachment", "path": path },
+            ] }],
+        }))
+        .unwrap()
+    }
+
+    fn support(vision_models: &[String], allow_unknown_models: bool) -> AttachmentSupport<'_> {
+        AttachmentSupport {
+            provider: "Chat Completions",
+            images: true,
+            documents: false,
+            vision_models,
+            allow_unknown_models,
+        }
+    }
+
+    fn rejection(request: &ChatRequest, support: &AttachmentSupport) -> Option<String> {
+        match check_support(request, support) {
+            Ok(()) => None,
+            Err(ProxyError::Validation(message)) => Some(message),
+            Err(other) => panic!("expected a validation error, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn known_models_follow_the_capability_table() {
+        let support = support(&[], true);
+        assert_eq!(
+            rejection(&with_attachment("gpt-4o", "cat.png"), &support),
+            None
+        );
+        assert_eq!(
+            rejection(&with_attachment("gpt-3.5-turbo", "cat.png"), &support).as_deref(),
+            Some("model gpt-3.5-turbo does not accept image attachments")
+        );
+        // Unknown models are left to an official API to judge
+        assert_eq!(
+            rejection(&with_attachment("llava", "cat.png"), &support),
+            None
+        );
+    }
+
+    #[test]
+    fn paths_without_documents_reject_pdfs() {
+        let support = support(&[], true);
+        assert_eq!(
+            rejection(&with_attachment("gpt-4o", "paper.pdf"), &support).as_deref(),
+            Some("Chat Completions does not accept PDF attachments")
+        );
+    }
+
+    #[test]
+    fn profiles_declare_their_vision_models() {
+        let declared = vec!["llava".to_string(), "gpt-3.5-turbo".to_string()];
+        let support = support(&declared, false);
+
+        assert_eq!(
+            rejection(&with_attachment("llava", "cat.png"), &support),
+            None
+        );
+        // A declaration wins over the table, for servers hosting fine-tunes
+        assert_eq!(
+            rejection(&with_attachment("gpt-3.5-turbo", "cat.PNG"), &support),
+            None
+        );
+        let message = rejection(&with_attachment("llama3", "cat.png"), &support).unwrap();
+        assert!(message.contains("vision_models"), "{}", message);
+    }
+
+    #[test]
+    fn documents_need_a_vision_model() {
+        let support = AttachmentSupport {
+            provider: "Anthropic",
+            images: true,
+            documents: true,
+            vision_models: &[],
+            allow_unknown_models: true,
+        };
+        assert_eq!(
+            rejection(&with_attachment("claude-sonnet-4-5", "paper.pdf"), &support),
+            None
+        );
+        assert_eq!(
+            rejection(
+                &with_attachment("claude-3-5-haiku-latest", "paper.pdf"),
+                &support
+            )
+            .as_deref(),
+            Some("model claude-3-5-haiku-latest does not accept PDF attachments")
+        );
+    }
+
+    #[test]
+    fn unknown_file_types_are_rejected() {
+        let support = support(&[], true);
+        let message = rejection(&with_attachment("gpt-4o", "notes.txt"), &support).unwrap();
+        assert!(message.contains("cannot tell the type"), "{}", message);
+    }
+}
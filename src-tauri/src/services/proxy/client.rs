@@ -0,0 +1,287 @@
+use crate::services::proxy::{ProxyError, ProxyResult};
+use crate::services::settings;
+use log::{debug, info};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use tauri_plugin_http::reqwest;
+
+/// Settings for the HTTP client shared by all providers
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(default)]
+pub struct HttpSettings {
+    pub connect_timeout_secs: u64,
+    /// Longest gap allowed between bytes of a response. There is no overall
+    /// timeout, since a long generation can stream for minutes.
+    pub read_timeout_secs: u64,
+    /// Negotiate HTTP/2 where the server supports it; `false` forces HTTP/1.1
+    pub http2: bool,
+    pub user_agent: String,
+    /// Proxy URL for all outgoing traffic, e.g. `http://proxy.internal:3128`.
+    /// When unset, the `HTTPS_PROXY`/`HTTP_PROXY`/`NO_PROXY` environment variables apply.
+    pub proxy: Option<String>,
+    /// Comma-separated hosts, domains and CIDR ranges that bypass `proxy`, in
+    /// `NO_PROXY` syntax, e.g. `localhost,127.0.0.1,.corp.example.com`. Only
+    /// valid together with `proxy`.
+    pub no_proxy: Option<String>,
+    /// PEM files with extra root certificates to trust, e.g. an intercepting
+    /// proxy's private CA. Bundles with several certificates are accepted.
+    pub ca_certificates: Vec<PathBuf>,
+}
+
+impl Default for HttpSettings {
+    fn default() -> Self {
+        Self {
+            connect_timeout_secs: 10,
+            read_timeout_secs: 120,
+            http2: true,
+            user_agent: format!("pqp/{}", env!("CARGO_PKG_VERSION")),
+            proxy: None,
+            no_proxy: None,
+            ca_certificates: Vec::new(),
+        }
+    }
+}
+
+impl HttpSettings {
+    /// Check the settings, reporting the offending value in the error
+    pub fn validate(&self) -> ProxyResult<()> {
+        if self.connect_timeout_secs == 0 || self.read_timeout_secs == 0 {
+            return Err(ProxyError::Config(
+                "HTTP timeouts must be greater than 0 seconds".to_string(),
+            ));
+        }
+        self.proxy()?;
+        for path in &self.ca_certificates {
+            load_certificates(path)?;
+        }
+        Ok(())
+    }
+
+    fn proxy(&self) -> ProxyResult<Option<reqwest::Proxy>> {
+        let Some(url) = self
+            .proxy
+            .as_deref()
+            .map(str::trim)
+            .filter(|p| !p.is_empty())
+        else {
+            // The environment's NO_PROXY goes with its proxy variables, not this setting
+            if self
+                .no_proxy
+                .as_deref()
+                .is_some_and(|n| !n.trim().is_empty())
+            {
+                return Err(ProxyError::Config(
+                    "no_proxy is set without a proxy; set proxy as well or clear no_proxy"
+                        .to_string(),
+                ));
+            }
+            return Ok(None);
+        };
+        // reqwest is built without SOCKS support
+        if !(url.starts_with("http://") || url.starts_with("https://")) {
+            return Err(ProxyError::Config(format!(
+                "Invalid proxy URL '{}': expected an http:// or https:// URL",
+                url
+            )));
+        }
+        let proxy = reqwest::Proxy::all(url)
+            .map_err(|e| ProxyError::Config(format!("Invalid proxy URL '{}': {}", url, e)))?;
+        let no_proxy = self
+            .no_proxy
+            .as_deref()
+            .and_then(reqwest::NoProxy::from_string);
+        Ok(Some(proxy.no_proxy(no_proxy)))
+    }
+
+    /// Builder with the network settings every client shares: proxy, trusted
+    /// certificates, connect timeout, HTTP version and user agent
+    fn client_builder(&self) -> ProxyResult<reqwest::ClientBuilder> {
+        self.validate()?;
+
+        let mut builder = reqwest::Client::builder()
+            .connect_timeout(Duration::from_secs(self.connect_timeout_secs))
+            .pool_idle_timeout(Duration::from_secs(90))
+            .user_agent(&self.user_agent);
+        if !self.http2 {
+            builder = builder.http1_only();
+        }
+        if let Some(proxy) = self.proxy()? {
+            builder = builder.proxy(proxy);
+        }
+        for path in &self.ca_certificates {
+            for cert in load_certificates(path)? {
+                builder = builder.add_root_certificate(cert);
+            }
+        }
+        Ok(builder)
+    }
+
+    /// Client for provider requests, which also bounds the wait between bytes
+    fn build_client(&self) -> ProxyResult<reqwest::Client> {
+        self.client_builder()?
+            .read_timeout(Duration::from_secs(self.read_timeout_secs))
+            .build()
+            .map_err(|e| ProxyError::Config(format!("Failed to build HTTP client: {}", e)))
+    }
+
+    /// Client for remote MCP transports, whose event streams stay open and idle
+    /// between tool calls, so no read timeout applies
+    fn build_mcp_client(&self) -> ProxyResult<reqwest::Client> {
+        self.client_builder()?
+            .build()
+            .map_err(|e| ProxyError::Config(format!("Failed to build MCP HTTP client: {}", e)))
+    }
+}
+
+/// Read the certificates in a PEM file, failing if it holds none
+fn load_certificates(path: &Path) -> ProxyResult<Vec<reqwest::Certificate>> {
+    let pem = fs::read(path).map_err(|e| {
+        ProxyError::Config(format!(
+            "Failed to read CA certificate {}: {}",
+            path.display(),
+            e
+        ))
+    })?;
+    let certs = reqwest::Certificate::from_pem_bundle(&pem).map_err(|e| {
+        ProxyError::Config(format!("Invalid CA certificate {}: {}", path.display(), e))
+    })?;
+    if certs.is_empty() {
+        return Err(ProxyError::Config(format!(
+            "No PEM certificates found in {}",
+            path.display()
+        )));
+    }
+    debug!(
+        "Trusting {} certificate(s) from {}",
+        certs.len(),
+        path.display()
+    );
+    Ok(certs)
+}
+
+/// The pooled client handed to providers, rebuilt whenever its settings change.
+///
+/// `reqwest::Client` is reference counted, so providers clone it cheaply and keep
+/// sharing one connection pool and TLS session cache.
+pub struct HttpClient {
+    path: PathBuf,
+    settings: HttpSettings,
+    client: reqwest::Client,
+    mcp_client: reqwest::Client,
+}
+
+impl HttpClient {
+    /// Load settings from `path`. Settings the client cannot be built from, such
+    /// as a malformed proxy URL, are an error naming the file: falling back to the
+    /// defaults would quietly send traffic around a required proxy.
+    pub fn load(path: PathBuf) -> ProxyResult<Self> {
+        let settings: HttpSettings = settings::load(&path);
+        let client = settings.build_client().map_err(|e| in_file(&path, e))?;
+        let mcp_client = settings.build_mcp_client().map_err(|e| in_file(&path, e))?;
+        info!("HTTP client ready ({:?})", settings);
+        Ok(Self {
+            path,
+            settings,
+            client,
+            mcp_client,
+        })
+    }
+
+    pub fn client(&self) -> reqwest::Client {
+        self.client.clone()
+    }
+
+    /// Client for remote MCP transports, sharing the proxy and certificate settings
+    pub fn mcp_client(&self) -> reqwest::Client {
+        self.mcp_client.clone()
+    }
+
+    pub fn settings(&self) -> HttpSettings {
+        self.settings.clone()
+    }
+
+    /// Replace the settings, rebuilding the client, and persist them
+    pub fn update(&mut self, settings: HttpSettings) -> ProxyResult<()> {
+        let client = settings.build_client()?;
+        let mcp_client = settings.build_mcp_client()?;
+        settings::save(&self.path, &settings).map_err(|e| {
+            ProxyError::Config(format!("Failed to write {}: {}", self.path.display(), e))
+        })?;
+        self.client = client;
+        self.mcp_client = mcp_client;
+        self.settings = settings;
+        Ok(())
+    }
+}
+
+/// Point a settings error at the file it came from, so a bad proxy URL or
+/// certificate path at startup says where to fix it
+fn in_file(path: &Path, err: ProxyError) -> ProxyError {
+    match err {
+        ProxyError::Config(msg) => ProxyError::Config(format!("{} (in {})", msg, path.display())),
+        other => other,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+    use std::process;
+
+    fn with_proxy(proxy: Option<&str>, no_proxy: Option<&str>) -> HttpSettings {
+        HttpSettings {
+            proxy: proxy.map(str::to_string),
+            no_proxy: no_proxy.map(str::to_string),
+            ..HttpSettings::default()
+        }
+    }
+
+    #[test]
+    fn validates_proxy_settings() {
+        assert!(with_proxy(None, None).validate().is_ok());
+        assert!(
+            with_proxy(Some("http://proxy.internal:3128"), Some("localhost"))
+                .validate()
+                .is_ok()
+        );
+        assert!(with_proxy(Some("  "), None).validate().is_ok());
+
+        for settings in [
+            with_proxy(Some("socks5://127.0.0.1:1080"), None),
+            with_proxy(Some("proxy.internal:3128"), None),
+            with_proxy(None, Some("localhost,.corp.example.com")),
+        ] {
+            assert!(
+                matches!(settings.validate(), Err(ProxyError::Config(_))),
+                "{:?}",
+                settings
+            );
+        }
+    }
+
+    #[test]
+    fn load_reports_settings_it_cannot_use() {
+        let dir = env::temp_dir().join(format!("pqp-http-load-{}", process::id()));
+        let path = dir.join("http.json");
+        let settings = HttpSettings {
+            ca_certificates: vec![dir.join("missing.pem")],
+            ..HttpSettings::default()
+        };
+        settings::save(&path, &settings).unwrap();
+
+        match HttpClient::load(path.clone()) {
+            Err(ProxyError::Config(message)) => {
+                assert!(message.contains("missing.pem"), "{}", message);
+                assert!(message.contains("http.json"), "{}", message);
+            }
+            other => panic!("expected a config error, got {:?}", other.map(|_| ())),
+        }
+
+        settings::save(&path, &HttpSettings::default()).unwrap();
+        assert!(HttpClient::load(path).is_ok());
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
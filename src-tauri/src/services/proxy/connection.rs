@@ -0,0 +1,154 @@
+use crate::services::proxy::{ProxyError, ProxyProvider};
+use log::{info, warn};
+use serde::Serialize;
+use std::time::Instant;
+
+/// Outcome of probing a provider with an authenticated request
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ConnectionStatus {
+    Ok,
+    InvalidKey,
+    NoNetwork,
+    RateLimited,
+    BadBaseUrl,
+    Error,
+}
+
+#[derive(Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct ConnectionTestResult {
+    pub provider: String,
+    pub status: ConnectionStatus,
+    pub message: String,
+    pub latency_ms: u64,
+    pub model_count: Option<usize>,
+}
+
+impl ConnectionTestResult {
+    /// Build a failed result from the error that ended the probe
+    pub fn from_error(provider: &str, err: &ProxyError, latency_ms: u64) -> Self {
+        Self {
+            provider: provider.to_string(),
+            status: classify(err),
+            message: err.to_string(),
+            latency_ms,
+            model_count: None,
+        }
+    }
+}
+
+/// Map a probe failure onto the status a settings screen can act on
+fn classify(err: &ProxyError) -> ConnectionStatus {
+    match err {
+        ProxyError::ApiKey(_) => ConnectionStatus::InvalidKey,
+        ProxyError::Provider(e) => match e.status {
+            Some(401 | 403) => ConnectionStatus::InvalidKey,
+            Some(429) => ConnectionStatus::RateLimited,
+            Some(404 | 405) => ConnectionStatus::BadBaseUrl,
+            _ => ConnectionStatus::Error,
+        },
+        ProxyError::Config(_) => ConnectionStatus::BadBaseUrl,
+        ProxyError::Http(e) if e.is_builder() || e.is_decode() => ConnectionStatus::BadBaseUrl,
+        ProxyError::Http(e) if e.is_connect() || e.is_timeout() => ConnectionStatus::NoNetwork,
+        // A non-JSON body usually means the URL points at something other than the API
+        ProxyError::Parse(_) => ConnectionStatus::BadBaseUrl,
+        _ => ConnectionStatus::Error,
+    }
+}
+
+/// Probe a provider with its model listing endpoint, the cheapest authenticated call
+pub async fn test_connection(
+    provider_name: &str,
+    provider: &(dyn ProxyProvider + Send + Sync),
+) -> ConnectionTestResult {
+    info!("Testing connection to provider: {}", provider_name);
+    let started = Instant::now();
+    let result = provider.probe().await;
+    let latency_ms = started.elapsed().as_millis() as u64;
+
+    match result {
+        Ok(model_count) => ConnectionTestResult {
+            provider: provider_name.to_string(),
+            status: ConnectionStatus::Ok,
+            message: format!("Connected, {} models available", model_count),
+            latency_ms,
+            model_count: Some(model_count),
+        },
+        Err(e) => {
+            warn!("Connection test for {} failed: {}", provider_name, e);
+            ConnectionTestResult::from_error(provider_name, &e, latency_ms)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::services::proxy::ProviderError;
+    use serde_json::json;
+    use tauri_plugin_http::reqwest::{self, header::HeaderMap};
+
+    fn provider_error(status: u16) -> ProxyError {
+        ProxyError::Provider(ProviderError::parse(
+            "openai",
+            Some(status),
+            &HeaderMap::new(),
+            "{}",
+        ))
+    }
+
+    #[test]
+    fn classifies_probe_failures() {
+        let client = reqwest::Client::new();
+        let builder_error = client.get("http://[::1").build().unwrap_err();
+        // Nothing listens on port 1, so the connection is refused
+        let connect_error =
+            tauri::async_runtime::block_on(client.get("http://127.0.0.1:1").send()).unwrap_err();
+        let parse_error = serde_json::from_str::<serde_json::Value>("<html>").unwrap_err();
+
+        let cases = [
+            (
+                ProxyError::ApiKey("missing".into()),
+                ConnectionStatus::InvalidKey,
+            ),
+            (provider_error(401), ConnectionStatus::InvalidKey),
+            (provider_error(403), ConnectionStatus::InvalidKey),
+            (provider_error(429), ConnectionStatus::RateLimited),
+            (provider_error(404), ConnectionStatus::BadBaseUrl),
+            (provider_error(405), ConnectionStatus::BadBaseUrl),
+            (provider_error(500), ConnectionStatus::Error),
+            (
+                ProxyError::Config("bad header".into()),
+                ConnectionStatus::BadBaseUrl,
+            ),
+            (
+                ProxyError::Http(builder_error),
+                ConnectionStatus::BadBaseUrl,
+            ),
+            (ProxyError::Http(connect_error), ConnectionStatus::NoNetwork),
+            (ProxyError::Parse(parse_error), ConnectionStatus::BadBaseUrl),
+            // A broken keyring is not the key's fault
+            (
+                ProxyError::KeyStore("locked".into()),
+                ConnectionStatus::Error,
+            ),
+            (
+                ProxyError::Validation("bad".into()),
+                ConnectionStatus::Error,
+            ),
+        ];
+        for (err, status) in cases {
+            assert_eq!(classify(&err), status, "{}", err);
+        }
+    }
+
+    #[test]
+    fn results_serialize_as_camel_case() {
+        let result = ConnectionTestResult::from_error("groq", &provider_error(429), 120);
+        let value = serde_json::to_value(&result).unwrap();
+        assert_eq!(value["status"], json!("rate_limited"));
+        assert_eq!(value["latencyMs"], json!(120));
+        assert_eq!(value["modelCount"], json!(null));
+    }
+}
@@ -0,0 +1,225 @@
+use crate::services::proxy::retry::requested_delay;
+use serde::Serialize;
+use serde_json::Value;
+use std::fmt;
+use tauri_plugin_http::reqwest::{header::HeaderMap, Response};
+
+/// A provider-reported failure, parsed from the error body so the frontend can
+/// react per error type instead of matching on English messages
+#[derive(Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct ProviderError {
+    pub provider: String,
+    /// HTTP status, absent for errors reported inside an already-open stream
+    pub status: Option<u16>,
+    /// Machine-readable type, e.g. `rate_limit_error`, `invalid_request_error`,
+    /// `context_length_exceeded`
+    pub error_type: String,
+    pub message: String,
+    pub request_id: Option<String>,
+    pub retry_after_ms: Option<u64>,
+}
+
+impl fmt::Display for ProviderError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.status {
+            Some(status) => write!(
+                f,
+                "{} API request failed with status {}: [{}] {}",
+                self.provider, status, self.error_type, self.message
+            ),
+            None => write!(
+                f,
+                "{} API error: [{}] {}",
+                self.provider, self.error_type, self.message
+            ),
+        }
+    }
+}
+
+impl ProviderError {
+    /// Build an error for a failure reported inside the stream body
+    pub fn in_stream<S: Into<String>>(provider: &str, error_type: S, message: S) -> Self {
+        let message = message.into();
+        Self {
+            provider: provider.to_string(),
+            status: None,
+            error_type: normalize_type(error_type.into(), &message),
+            message,
+            request_id: None,
+            retry_after_ms: None,
+        }
+    }
+
+    /// Parse an error response body in any of the shapes the providers use:
+    /// Anthropic `{"error": {"type", "message"}}`, OpenAI `{"error": {"code", "type", "message"}}`,
+    /// Gemini `{"error": {"status", "message"}}` and Ollama `{"error": "..."}`
+    pub fn parse(provider: &str, status: Option<u16>, headers: &HeaderMap, body: &str) -> Self {
+        let json: Value = serde_json::from_str(body).unwrap_or(Value::Null);
+        let error = &json["error"];
+
+        let (error_type, message) = match error {
+            Value::Object(details) => {
+                let error_type = ["code", "type", "status"]
+                    .iter()
+                    .find_map(|field| details.get(*field).and_then(Value::as_str))
+                    .map(str::to_string);
+                let message = details
+                    .get("message")
+                    .and_then(Value::as_str)
+                    .map(str::to_string);
+                (error_type, message)
+            }
+            Value::String(message) => (None, Some(message.clone())),
+            _ => (None, None),
+        };
+
+        let message = message.unwrap_or_else(|| {
+            if body.trim().is_empty() {
+                "No error details returned".to_string()
+            } else {
+                body.trim().to_string()
+            }
+        });
+        let error_type = error_type.unwrap_or_else(|| type_for_status(status).to_string());
+
+        let request_id = ["request-id", "x-request-id", "x-goog-request-id"]
+            .iter()
+            .find_map(|name| headers.get(*name).and_then(|v| v.to_str().ok()))
+            .map(str::to_string);
+
+        Self {
+            provider: provider.to_string(),
+            status,
+            error_type: normalize_type(error_type, &message),
+            message,
+            request_id,
+            retry_after_ms: requested_delay(headers).map(|d| d.as_millis() as u64),
+        }
+    }
+}
+
+/// Read a failed response into a structured error
+pub(crate) async fn read_error(provider: &str, response: Response) -> ProviderError {
+    let status = response.status().as_u16();
+    let headers = response.headers().clone();
+    let body = response
+        .text()
+        .await
+        .unwrap_or_else(|_| "Failed to read error body".to_string());
+    ProviderError::parse(provider, Some(status), &headers, &body)
+}
+
+/// Fallback type when the body carries none
+fn type_for_status(status: Option<u16>) -> &'static str {
+    match status {
+        Some(400) => "invalid_request_error",
+        Some(401) => "authentication_error",
+        Some(403) => "permission_error",
+        Some(404) => "not_found_error",
+        Some(413) => "request_too_large",
+        Some(429) => "rate_limit_error",
+        Some(529) => "overloaded_error",
+        Some(500..=599) => "api_error",
+        _ => "unknown_error",
+    }
+}
+
+/// Providers report an over-long prompt differently; give it one type
+fn normalize_type(error_type: String, message: &str) -> String {
+    let lower = message.to_lowercase();
+    if lower.contains("prompt is too long")
+        || lower.contains("maximum context length")
+        || lower.contains("context window")
+        || lower.contains("exceeds the maximum number of tokens")
+    {
+        "context_length_exceeded".to_string()
+    } else {
+        error_type
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tauri_plugin_http::reqwest::header::HeaderValue;
+
+    #[test]
+    fn parses_anthropic_errors() {
+        let mut headers = HeaderMap::new();
+        headers.insert("request-id", HeaderValue::from_static("req_011"));
+        headers.insert("retry-after", HeaderValue::from_static("7"));
+        let err = ProviderError::parse(
+            "anthropic",
+            Some(429),
+            &headers,
+            r#"{"type":"error","error":{"type":"rate_limit_error","message":"Number of requests has exceeded your rate limit"}}"#,
+        );
+        assert_eq!(err.error_type, "rate_limit_error");
+        assert_eq!(
+            err.message,
+            "Number of requests has exceeded your rate limit"
+        );
+        assert_eq!(err.request_id.as_deref(), Some("req_011"));
+        assert_eq!(err.retry_after_ms, Some(7000));
+    }
+
+    #[test]
+    fn prefers_openai_codes_over_types() {
+        let err = ProviderError::parse(
+            "openai",
+            Some(400),
+            &HeaderMap::new(),
+            r#"{"error":{"message":"This model's maximum context length is 128000 tokens.","type":"invalid_request_error","param":"messages","code":"context_length_exceeded"}}"#,
+        );
+        assert_eq!(err.error_type, "context_length_exceeded");
+        assert_eq!(err.status, Some(400));
+    }
+
+    #[test]
+    fn parses_gemini_and_ollama_errors() {
+        let gemini = ProviderError::parse(
+            "gemini",
+            Some(403),
+            &HeaderMap::new(),
+            r#"{"error":{"code":403,"message":"API key not valid.","status":"PERMISSION_DENIED"}}"#,
+        );
+        // Gemini's numeric code is not a type, so its status names the error
+        assert_eq!(gemini.error_type, "PERMISSION_DENIED");
+        assert_eq!(gemini.message, "API key not valid.");
+
+        let ollama = ProviderError::parse(
+            "ollama",
+            Some(404),
+            &HeaderMap::new(),
+            r#"{"error":"model \"llama9\" not found, try pulling it first"}"#,
+        );
+        assert_eq!(ollama.error_type, "not_found_error");
+        assert_eq!(
+            ollama.message,
+            "model \"llama9\" not found, try pulling it first"
+        );
+    }
+
+    #[test]
+    fn falls_back_to_the_body_and_status() {
+        let err = ProviderError::parse("openai", Some(502), &HeaderMap::new(), "Bad Gateway\n");
+        assert_eq!(err.error_type, "api_error");
+        assert_eq!(err.message, "Bad Gateway");
+
+        let empty = ProviderError::parse("openai", None, &HeaderMap::new(), "  ");
+        assert_eq!(empty.error_type, "unknown_error");
+        assert_eq!(empty.message, "No error details returned");
+    }
+
+    #[test]
+    fn gives_over_long_prompts_one_type() {
+        let err = ProviderError::in_stream(
+            "anthropic",
+            "invalid_request_error",
+            "prompt is too long: 210000 tokens > 200000 maximum",
+        );
+        assert_eq!(err.error_type, "context_length_exceeded");
+        assert_eq!(err.status, None);
+    }
+}
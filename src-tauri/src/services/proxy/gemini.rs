@@ -0,0 +1,578 @@
+use crate::services::proxy::attachments::{self, AttachmentSupport};
+use crate::services::proxy::errors::read_error;
+use crate::services::proxy::request::invalid;
+use crate::services::proxy::retry::{send_with_retry, RetryPolicy};
+use crate::services::proxy::sse::SseBuffer;
+use crate::services::proxy::{
+    emit_end, emit_error, emit_part, emit_provider_error, generated_tool_call_id,
+};
+use crate::services::proxy::{
+    ChatRequest, ChatRole, ContentPart, ModelInfo, ProviderError, ProxyError, ProxyProvider,
+    ProxyResult,
+};
+use async_trait::async_trait;
+use futures_util::StreamExt;
+use log::{debug, error, info, warn};
+use serde::Deserialize;
+use serde_json::{json, Map, Value};
+use std::collections::HashMap;
+use tauri::Window;
+use tauri_plugin_http::reqwest::{
+    self,
+    header::{HeaderMap, HeaderValue, CONTENT_TYPE},
+};
+
+const GEMINI_BASE_URL: &str = "https://generativelanguage.googleapis.com/v1beta";
+
+pub struct GeminiProvider {
+    client: reqwest::Client,
+    api_key: String,
+}
+
+/// The model is part of the Gemini URL rather than the body
+fn split_model(mut body: Value) -> ProxyResult<(String, Value)> {
+    let model = body
+        .as_object_mut()
+        .and_then(|body| body.remove("model"))
+        .and_then(|model| {
+            model
+                .as_str()
+                .map(|m| m.trim_start_matches("models/").to_string())
+        })
+        .ok_or_else(|| invalid("Gemini request is missing a model"))?;
+    Ok((model, body))
+}
+
+/// URL of a model method such as `generateContent`. The model id is a single
+/// path segment, so a `/`, `?` or `#` in it is percent-encoded.
+fn model_url(model: &str, method: &str) -> ProxyResult<reqwest::Url> {
+    let mut url = reqwest::Url::parse(GEMINI_BASE_URL)
+        .map_err(|e| ProxyError::Config(format!("Invalid Gemini base URL: {}", e)))?;
+    url.path_segments_mut()
+        .map_err(|_| ProxyError::Config("Invalid Gemini base URL".to_string()))?
+        .push("models")
+        .push(&format!("{}:{}", model, method));
+    Ok(url)
+}
+
+/// Schema keywords function declarations accept, a subset of OpenAPI 3.0
+const SCHEMA_KEYWORDS: &[&str] = &[
+    "type",
+    "format",
+    "title",
+    "description",
+    "nullable",
+    "enum",
+    "default",
+    "items",
+    "minItems",
+    "maxItems",
+    "properties",
+    "required",
+    "minProperties",
+    "maxProperties",
+    "propertyOrdering",
+    "minLength",
+    "maxLength",
+    "pattern",
+    "minimum",
+    "maximum",
+    "anyOf",
+];
+
+/// Reduce a JSON Schema from an MCP server to what Gemini accepts, which rejects
+/// the whole request over keywords like `$schema` or `additionalProperties`.
+/// A `["string", "null"]` type becomes a nullable string.
+fn gemini_schema(schema: &Value) -> Value {
+    let Value::Object(schema) = schema else {
+        return schema.clone();
+    };
+    let mut converted = Map::new();
+    for (keyword, value) in schema {
+        if !SCHEMA_KEYWORDS.contains(&keyword.as_str()) {
+            continue;
+        }
+        let value = match (keyword.as_str(), value) {
+            // Property names are not keywords, so only their schemas are reduced
+            ("properties", Value::Object(properties)) => Value::Object(
+                properties
+                    .iter()
+                    .map(|(name, property)| (name.clone(), gemini_schema(property)))
+                    .collect(),
+            ),
+            ("items", items) => gemini_schema(items),
+            ("anyOf", Value::Array(options)) => {
+                Value::Array(options.iter().map(gemini_schema).collect())
+            }
+            ("type", Value::Array(types)) => {
+                if types.iter().any(|t| t == "null") {
+                    converted.insert("nullable".to_string(), Value::Bool(true));
+                }
+                match types.iter().find(|t| *t != "null") {
+                    Some(first) => first.clone(),
+                    None => continue,
+                }
+            }
+            _ => value.clone(),
+        };
+        converted.insert(keyword.clone(), value);
+    }
+    Value::Object(converted)
+}
+
+impl GeminiProvider {
+    pub fn new(client: reqwest::Client, api_key: String) -> Self {
+        Self { client, api_key }
+    }
+
+    fn headers(&self) -> ProxyResult<HeaderMap> {
+        let mut headers = HeaderMap::new();
+        headers.insert(CONTENT_TYPE, HeaderValue::from_static("application/json"));
+        headers.insert(
+            "x-goog-api-key",
+            HeaderValue::from_str(&self.api_key)
+                .map_err(|e| ProxyError::ApiKey(format!("Invalid Gemini API key format: {}", e)))?,
+        );
+        Ok(headers)
+    }
+}
+
+/// Map Gemini finish reasons onto AI SDK finish reasons
+fn map_finish_reason(reason: &str) -> &'static str {
+    match reason {
+        "STOP" => "stop",
+        "MAX_TOKENS" => "length",
+        "SAFETY" | "RECITATION" | "BLOCKLIST" | "PROHIBITED_CONTENT" | "SPII" => "content-filter",
+        _ => "other",
+    }
+}
+
+#[derive(Deserialize, Debug)]
+struct GeminiModelList {
+    #[serde(default)]
+    models: Vec<GeminiModel>,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct GeminiModel {
+    name: String,
+    display_name: Option<String>,
+    input_token_limit: Option<u32>,
+    #[serde(default)]
+    supported_generation_methods: Vec<String>,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct GeminiResponseChunk {
+    #[serde(default)]
+    candidates: Vec<GeminiCandidate>,
+    usage_metadata: Option<GeminiUsage>,
+    error: Option<GeminiError>,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct GeminiCandidate {
+    content: Option<GeminiContent>,
+    finish_reason: Option<String>,
+}
+
+#[derive(Deserialize, Debug)]
+struct GeminiContent {
+    #[serde(default)]
+    parts: Vec<GeminiPart>,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct GeminiPart {
+    text: Option<String>,
+    #[serde(default)]
+    thought: bool,
+    function_call: Option<GeminiFunctionCall>,
+}
+
+#[derive(Deserialize, Debug)]
+struct GeminiFunctionCall {
+    name: String,
+    #[serde(default)]
+    args: Value,
+}
+
+#[derive(Deserialize, Debug, Default)]
+#[serde(rename_all = "camelCase")]
+struct GeminiUsage {
+    #[serde(default)]
+    prompt_token_count: u64,
+    #[serde(default)]
+    candidates_token_count: u64,
+}
+
+#[derive(Deserialize, Debug)]
+struct GeminiError {
+    #[serde(default)]
+    status: String,
+    message: String,
+}
+
+#[async_trait]
+impl ProxyProvider for GeminiProvider {
+    /// List models that support `generateContent` via `/models`
+    async fn list_models(&self) -> ProxyResult<Vec<ModelInfo>> {
+        let client = &self.client;
+        let response = client
+            .get(format!("{}/models?pageSize=1000", GEMINI_BASE_URL))
+            .headers(self.headers()?)
+            .send()
+            .await?;
+
+        if !response.status().is_success() {
+            return Err(ProxyError::Provider(read_error("gemini", response).await));
+        }
+
+        let list: GeminiModelList = response.json().await?;
+        Ok(list
+            .models
+            .into_iter()
+            .filter(|model| {
+                model
+                    .supported_generation_methods
+                    .iter()
+                    .any(|m| m == "generateContent")
+            })
+            .map(|model| {
+                let id = model.name.trim_start_matches("models/").to_string();
+                let mut info = ModelInfo::with_known_capabilities(id, model.display_name, "gemini");
+                info.context_window = model.input_token_limit.or(info.context_window);
+                info
+            })
+            .collect())
+    }
+
+    /// Build a `generateContent` request. Gemini takes the model in the URL, so it is
+    /// carried in a `model` field that `stream` strips before sending.
+    fn build_request(&self, request: &ChatRequest) -> ProxyResult<Value> {
+        attachments::check_support(
+            request,
+            &AttachmentSupport {
+                provider: "Gemini",
+                images: true,
+                documents: true,
+                vision_models: &[],
+                allow_unknown_models: true,
+            },
+        )?;
+
+        let mut contents = Vec::new();
+        // Gemini function responses are keyed by name rather than call id
+        let mut tool_names: HashMap<&str, &str> = HashMap::new();
+
+        for message in request.conversation() {
+            let mut parts = Vec::new();
+            for part in &message.content {
+                match part {
+                    ContentPart::Text { text } if !text.is_empty() => {
+                        parts.push(json!({ "text": text }))
+                    }
+                    ContentPart::Text { .. } => {}
+                    ContentPart::ToolCall {
+                        id,
+                        name,
+                        arguments,
+                    } => {
+                        tool_names.insert(id, name);
+                        parts.push(json!({ "functionCall": { "name": name, "args": arguments } }));
+                    }
+                    ContentPart::ToolResult {
+                        tool_call_id,
+                        name,
+                        content,
+                        ..
+                    } => {
+                        let name = name
+                            .as_deref()
+                            .or_else(|| tool_names.get(tool_call_id.as_str()).copied())
+                            .unwrap_or_default();
+                        let response = match content {
+                            Value::Object(_) => content.clone(),
+                            other => json!({ "content": other }),
+                        };
+                        parts.push(json!({
+                            "functionResponse": { "name": name, "response": response },
+                        }));
+                    }
+                    // Reasoning from another provider's turn cannot be replayed to Gemini
+                    ContentPart::Reasoning { .. } | ContentPart::RedactedReasoning { .. } => {}
+                    ContentPart::Attachment { path, media_type } => {
+                        let attachment = attachments::load(path, media_type.as_deref())?;
+                        parts.push(json!({
+                            "inlineData": {
+                                "mimeType": attachment.media_type,
+                                "data": attachment.data,
+                            },
+                        }));
+                    }
+                }
+            }
+            let role = match message.role {
+                ChatRole::Assistant => "model",
+                _ => "user",
+            };
+            contents.push(json!({ "role": role, "parts": parts }));
+        }
+
+        let mut body = Map::new();
+        body.insert("model".to_string(), json!(request.model));
+        body.insert("contents".to_string(), Value::Array(contents));
+        if let Some(system) = request.system_prompt() {
+            body.insert(
+                "systemInstruction".to_string(),
+                json!({ "parts": [{ "text": system }] }),
+            );
+        }
+
+        let mut generation_config = Map::new();
+        if let Some(max_tokens) = request.max_tokens {
+            generation_config.insert("maxOutputTokens".to_string(), json!(max_tokens));
+        }
+        if let Some(temperature) = request.temperature {
+            generation_config.insert("temperature".to_string(), json!(temperature));
+        }
+        if !request.stop.is_empty() {
+            generation_config.insert("stopSequences".to_string(), json!(request.stop));
+        }
+        if !generation_config.is_empty() {
+            body.insert(
+                "generationConfig".to_string(),
+                Value::Object(generation_config),
+            );
+        }
+
+        let mut tools = Vec::new();
+        if !request.tools.is_empty() {
+            let declarations: Vec<Value> = request
+                .tools
+                .iter()
+                .map(|tool| {
+                    let mut declaration = json!({ "name": tool.name });
+                    // Gemini rejects an object schema without properties, which
+                    // is how MCP describes a tool that takes no arguments
+                    let has_properties = tool.parameters["properties"]
+                        .as_object()
+                        .is_some_and(|properties| !properties.is_empty());
+                    if has_properties {
+                        declaration["parameters"] = gemini_schema(&tool.parameters);
+                    }
+                    if let Some(description) = &tool.description {
+                        declaration["description"] = json!(description);
+                    }
+                    declaration
+                })
+                .collect();
+            tools.push(json!({ "functionDeclarations": declarations }));
+        }
+        // Built-in tools such as `{"googleSearch": {}}` are tool entries of their own
+        tools.extend(request.builtin_tools.iter().cloned());
+        if !tools.is_empty() {
+            body.insert("tools".to_string(), Value::Array(tools));
+        }
+
+        Ok(Value::Object(body))
+    }
+
+    async fn complete(&self, body: Value) -> ProxyResult<String> {
+        let (model, request) = split_model(body)?;
+        let response = self
+            .client
+            .post(model_url(&model, "generateContent")?)
+            .headers(self.headers()?)
+            .json(&request)
+            .send()
+            .await?;
+        if !response.status().is_success() {
+            return Err(ProxyError::Provider(read_error("gemini", response).await));
+        }
+
+        let completion: Value = response.json().await?;
+        // Thought summaries come back as parts flagged `thought`
+        Ok(completion["candidates"][0]["content"]["parts"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .filter(|part| part["thought"] != Value::Bool(true))
+            .filter_map(|part| part["text"].as_str())
+            .collect())
+    }
+
+    async fn stream(&self, window: Window, body: Value) -> ProxyResult<()> {
+        info!("Starting Gemini stream request");
+        let (model, request) = split_model(body)?;
+
+        let client = &self.client;
+        let headers = self.headers()?;
+
+        let mut url = model_url(&model, "streamGenerateContent")?;
+        url.set_query(Some("alt=sse"));
+        let response = send_with_retry(&window, "gemini", &RetryPolicy::default(), &mut 0, || {
+            client
+                .post(url.clone())
+                .headers(headers.clone())
+                .json(&request)
+        })
+        .await?;
+
+        let status = response.status();
+        if !status.is_success() {
+            let err = read_error("gemini", response).await;
+            emit_provider_error(&window, &err)?;
+            return Err(ProxyError::Provider(err));
+        }
+        info!("Gemini API request successful (status: {})", status);
+
+        let mut stream = response.bytes_stream();
+        // Gemini separates events with CRLF pairs
+        let mut sse = SseBuffer::default();
+        let mut tool_call_count = 0usize;
+        let mut finish_reason: Option<String> = None;
+        let mut usage = GeminiUsage::default();
+
+        debug!("Starting to process Gemini stream");
+        while let Some(item) = stream.next().await {
+            let chunk = match item {
+                Ok(chunk) => chunk,
+                Err(e) => {
+                    let error_msg = format!("Error reading stream chunk: {}", e);
+                    error!("{}", error_msg);
+                    emit_error(&window, &error_msg)?;
+                    return Err(ProxyError::Http(e));
+                }
+            };
+            debug!("Received raw bytes chunk: {} bytes", chunk.len());
+            sse.push(&chunk);
+
+            while let Some(data) = sse.next_data() {
+                let chunk_event = match serde_json::from_str::<GeminiResponseChunk>(&data) {
+                    Ok(event) => event,
+                    Err(e) => {
+                        warn!("Failed to parse Gemini event: {}", e);
+                        continue;
+                    }
+                };
+
+                if let Some(details) = chunk_event.error {
+                    let err = ProviderError::in_stream("gemini", details.status, details.message);
+                    emit_provider_error(&window, &err)?;
+                    return Err(ProxyError::Provider(err));
+                }
+
+                for candidate in chunk_event.candidates {
+                    for part in candidate.content.map(|c| c.parts).unwrap_or_default() {
+                        if let Some(text) = part.text.filter(|t| !t.is_empty()) {
+                            let code = if part.thought { 'g' } else { '0' };
+                            emit_part(&window, code, &text)?;
+                        }
+                        if let Some(call) = part.function_call {
+                            // Gemini does not assign tool call ids
+                            tool_call_count += 1;
+                            emit_part(
+                                &window,
+                                '9',
+                                &json!({
+                                    "toolCallId": generated_tool_call_id("gemini"),
+                                    "toolName": call.name,
+                                    "args": call.args,
+                                }),
+                            )?;
+                        }
+                    }
+                    if let Some(reason) = candidate.finish_reason {
+                        debug!("Candidate finished with reason: {}", reason);
+                        finish_reason = Some(reason);
+                    }
+                }
+
+                // Usage metadata is cumulative, so the last one seen wins
+                if let Some(chunk_usage) = chunk_event.usage_metadata {
+                    usage = chunk_usage;
+                }
+            }
+        }
+
+        if let Some(reason) = finish_reason {
+            let reason = if tool_call_count > 0 {
+                "tool-calls"
+            } else {
+                map_finish_reason(&reason)
+            };
+            emit_part(
+                &window,
+                'd',
+                &json!({
+                    "finishReason": reason,
+                    "usage": {
+                        "promptTokens": usage.prompt_token_count,
+                        "completionTokens": usage.candidates_token_count,
+                    },
+                }),
+            )?;
+        }
+
+        info!("Gemini stream completed");
+        emit_end(&window)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn model_ids_are_a_single_path_segment() {
+        assert_eq!(
+            model_url("gemini-2.5-flash", "generateContent")
+                .unwrap()
+                .as_str(),
+            "https://generativelanguage.googleapis.com/v1beta/models/gemini-2.5-flash:generateContent"
+        );
+        assert_eq!(
+            model_url("tuned/model?x#y", "generateContent")
+                .unwrap()
+                .as_str(),
+            "https://generativelanguage.googleapis.com/v1beta/models/tuned%2Fmodel%3Fx%23y:generateContent"
+        );
+    }
+
+    #[test]
+    fn schemas_lose_keywords_gemini_rejects() {
+        let schema = json!({
+            "$schema": "http://json-schema.org/draft-07/schema#",
+            "type": "object",
+            "additionalProperties": false,
+            "properties": {
+                // A property may be named like a keyword
+                "additionalProperties": { "type": "string", "examples": ["a"] },
+                "tags": {
+                    "type": "array",
+                    "items": { "type": "object", "additionalProperties": { "type": "string" } },
+                },
+                "limit": { "type": ["integer", "null"], "exclusiveMinimum": 0 },
+            },
+            "required": ["tags"],
+        });
+        assert_eq!(
+            gemini_schema(&schema),
+            json!({
+                "type": "object",
+                "properties": {
+                    "additionalProperties": { "type": "string" },
+                    "tags": { "type": "array", "items": { "type": "object" } },
+                    "limit": { "type": "integer", "nullable": true },
+                },
+                "required": ["tags"],
+            })
+        );
+    }
+}
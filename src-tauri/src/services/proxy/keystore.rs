@@ -0,0 +1,431 @@
+use crate::services::proxy::{ProxyError, ProxyResult};
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+use chacha20poly1305::{
+    aead::{Aead, AeadCore, KeyInit, OsRng},
+    ChaCha20Poly1305, Key, Nonce,
+};
+use log::{debug, info};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+/// Where API keys are persisted
+pub trait SecretBackend: Send {
+    fn get(&self, name: &str) -> ProxyResult<Option<String>>;
+    fn set(&mut self, name: &str, secret: &str) -> ProxyResult<()>;
+    fn delete(&mut self, name: &str) -> ProxyResult<bool>;
+}
+
+/// API key storage used by the providers, with environment variables as a fallback
+pub struct KeyStore {
+    backend: Box<dyn SecretBackend>,
+}
+
+impl KeyStore {
+    pub fn new(backend: Box<dyn SecretBackend>) -> Self {
+        Self { backend }
+    }
+
+    /// Open the default backend: the OS keyring when this build has the `keyring`
+    /// feature (on by default) and a keyring answers, otherwise the encrypted file
+    /// store in `data_dir`. `PQP_SECRET_BACKEND=file` forces the file store.
+    pub fn open_default(data_dir: &Path) -> ProxyResult<Self> {
+        #[cfg(feature = "keyring")]
+        {
+            if std::env::var("PQP_SECRET_BACKEND").as_deref() == Ok("file") {
+                info!("PQP_SECRET_BACKEND=file, not using the OS keyring");
+            } else {
+                // Headless Linux sessions often have no Secret Service running
+                match KeyringBackend::probe() {
+                    Ok(()) => {
+                        info!("Using OS keyring for API keys");
+                        let mut keyring = KeyringBackend;
+                        migrate_file_secrets(data_dir, &mut keyring)?;
+                        return Ok(Self::new(Box::new(keyring)));
+                    }
+                    Err(e) => log::warn!(
+                        "OS keyring unavailable, storing API keys in the data directory: {}",
+                        e
+                    ),
+                }
+            }
+        }
+
+        info!("Using encrypted file store for API keys");
+        Ok(Self::new(Box::new(FileBackend::open(data_dir)?)))
+    }
+
+    pub fn get(&self, provider: &str) -> ProxyResult<Option<String>> {
+        self.backend.get(provider)
+    }
+
+    pub fn set(&mut self, provider: &str, api_key: &str) -> ProxyResult<()> {
+        if provider.trim().is_empty() {
+            return Err(ProxyError::KeyStore(
+                "Provider name cannot be empty".to_string(),
+            ));
+        }
+        if api_key.trim().is_empty() {
+            return Err(ProxyError::KeyStore("API key cannot be empty".to_string()));
+        }
+        self.backend.set(provider, api_key.trim())
+    }
+
+    pub fn delete(&mut self, provider: &str) -> ProxyResult<bool> {
+        self.backend.delete(provider)
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+struct EncryptedSecret {
+    nonce: String,
+    ciphertext: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Default)]
+struct SecretsFile {
+    #[serde(default)]
+    secrets: HashMap<String, EncryptedSecret>,
+}
+
+/// Secrets encrypted with ChaCha20-Poly1305 in `credentials.json`, keyed by a random
+/// master key in `credentials.key` that is readable only by the current user.
+///
+/// The master key sits next to the ciphertext, so this is no real protection at
+/// rest: anyone who can read the data directory can decrypt the keys. It only
+/// keeps them out of a copy of `credentials.json` taken on its own. It is the
+/// fallback for builds without the `keyring` feature and for sessions where no
+/// keyring answers.
+pub struct FileBackend {
+    path: PathBuf,
+    cipher: ChaCha20Poly1305,
+    secrets: HashMap<String, EncryptedSecret>,
+}
+
+impl FileBackend {
+    pub fn open(dir: &Path) -> ProxyResult<Self> {
+        fs::create_dir_all(dir).map_err(|e| store_error("create", dir, e))?;
+
+        let key_path = dir.join("credentials.key");
+        let key = if key_path.exists() {
+            let bytes = fs::read(&key_path).map_err(|e| store_error("read", &key_path, e))?;
+            if bytes.len() != 32 {
+                return Err(ProxyError::KeyStore(format!(
+                    "{} is corrupt (expected 32 bytes, found {})",
+                    key_path.display(),
+                    bytes.len()
+                )));
+            }
+            *Key::from_slice(&bytes)
+        } else {
+            debug!("Generating new master key at {}", key_path.display());
+            let key = ChaCha20Poly1305::generate_key(&mut OsRng);
+            write_private(&key_path, key.as_slice())?;
+            key
+        };
+
+        let path = dir.join("credentials.json");
+        let secrets = if path.exists() {
+            let contents = fs::read_to_string(&path).map_err(|e| store_error("read", &path, e))?;
+            serde_json::from_str::<SecretsFile>(&contents)
+                .map_err(|e| {
+                    ProxyError::KeyStore(format!("Failed to parse {}: {}", path.display(), e))
+                })?
+                .secrets
+        } else {
+            HashMap::new()
+        };
+
+        Ok(Self {
+            path,
+            cipher: ChaCha20Poly1305::new(&key),
+            secrets,
+        })
+    }
+
+    fn save(&self) -> ProxyResult<()> {
+        let file = SecretsFile {
+            secrets: self.secrets.clone(),
+        };
+        let contents = serde_json::to_string_pretty(&file)?;
+        write_private(&self.path, contents.as_bytes())
+    }
+}
+
+impl SecretBackend for FileBackend {
+    fn get(&self, name: &str) -> ProxyResult<Option<String>> {
+        let Some(secret) = self.secrets.get(name) else {
+            return Ok(None);
+        };
+
+        let decode = |value: &str| {
+            BASE64
+                .decode(value)
+                .map_err(|e| ProxyError::KeyStore(format!("Corrupt entry for {}: {}", name, e)))
+        };
+        let nonce = decode(&secret.nonce)?;
+        let ciphertext = decode(&secret.ciphertext)?;
+        if nonce.len() != 12 {
+            return Err(ProxyError::KeyStore(format!("Corrupt nonce for {}", name)));
+        }
+
+        let plaintext = self
+            .cipher
+            .decrypt(Nonce::from_slice(&nonce), ciphertext.as_slice())
+            .map_err(|_| ProxyError::KeyStore(format!("Failed to decrypt key for {}", name)))?;
+        String::from_utf8(plaintext)
+            .map(Some)
+            .map_err(|e| ProxyError::KeyStore(format!("Corrupt key for {}: {}", name, e)))
+    }
+
+    fn set(&mut self, name: &str, secret: &str) -> ProxyResult<()> {
+        let nonce = ChaCha20Poly1305::generate_nonce(&mut OsRng);
+        let ciphertext = self
+            .cipher
+            .encrypt(&nonce, secret.as_bytes())
+            .map_err(|_| ProxyError::KeyStore(format!("Failed to encrypt key for {}", name)))?;
+        self.secrets.insert(
+            name.to_string(),
+            EncryptedSecret {
+                nonce: BASE64.encode(nonce),
+                ciphertext: BASE64.encode(ciphertext),
+            },
+        );
+        self.save()
+    }
+
+    fn delete(&mut self, name: &str) -> ProxyResult<bool> {
+        if self.secrets.remove(name).is_none() {
+            return Ok(false);
+        }
+        self.save()?;
+        Ok(true)
+    }
+}
+
+/// Write a file that only the current user can read. The contents go to a temporary
+/// file in the same directory that is synced and renamed over `path`, so a crash
+/// mid-write leaves the previous file intact rather than a truncated one.
+fn write_private(path: &Path, contents: &[u8]) -> ProxyResult<()> {
+    let mut temp_name = path.file_name().unwrap_or_default().to_os_string();
+    temp_name.push(".tmp");
+    let temp_path = path.with_file_name(temp_name);
+
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    let mut file = options
+        .open(&temp_path)
+        .map_err(|e| store_error("open", &temp_path, e))?;
+    let written = file
+        .write_all(contents)
+        .and_then(|()| file.sync_all())
+        .map_err(|e| store_error("write", &temp_path, e))
+        .and_then(|()| fs::rename(&temp_path, path).map_err(|e| store_error("replace", path, e)));
+    if written.is_err() {
+        let _ = fs::remove_file(&temp_path);
+    }
+    written
+}
+
+fn store_error(action: &str, path: &Path, err: std::io::Error) -> ProxyError {
+    ProxyError::KeyStore(format!("Failed to {} {}: {}", action, path.display(), err))
+}
+
+/// Move keys left in the file store of `data_dir` into `backend`, then delete the
+/// file store. Keys already in `backend` win over the file's copies. The files are
+/// only removed once every key has been moved, so a failure loses nothing.
+#[cfg(any(feature = "keyring", test))]
+fn migrate_file_secrets(data_dir: &Path, backend: &mut dyn SecretBackend) -> ProxyResult<()> {
+    if !data_dir.join("credentials.json").exists() {
+        return Ok(());
+    }
+    let file = FileBackend::open(data_dir)?;
+    for name in file.secrets.keys() {
+        if backend.get(name)?.is_none() {
+            if let Some(secret) = file.get(name)? {
+                backend.set(name, &secret)?;
+            }
+        }
+    }
+    info!(
+        "Moved API keys from {} to the OS keyring",
+        file.path.display()
+    );
+    fs::remove_file(&file.path).map_err(|e| store_error("remove", &file.path, e))?;
+    let key_path = data_dir.join("credentials.key");
+    fs::remove_file(&key_path).map_err(|e| store_error("remove", &key_path, e))
+}
+
+/// Secrets stored in the platform keyring (Keychain, Credential Manager, Secret Service)
+#[cfg(feature = "keyring")]
+pub struct KeyringBackend;
+
+#[cfg(feature = "keyring")]
+impl KeyringBackend {
+    const SERVICE: &'static str = "pqp";
+
+    fn entry(name: &str) -> ProxyResult<keyring::Entry> {
+        keyring::Entry::new(Self::SERVICE, name)
+            .map_err(|e| ProxyError::KeyStore(format!("Keyring unavailable: {}", e)))
+    }
+
+    /// Check that a keyring answers, by looking up an entry that is never written
+    fn probe() -> ProxyResult<()> {
+        match Self::entry("pqp-probe")?.get_password() {
+            Ok(_) | Err(keyring::Error::NoEntry) => Ok(()),
+            Err(e) => Err(ProxyError::KeyStore(e.to_string())),
+        }
+    }
+}
+
+#[cfg(feature = "keyring")]
+impl SecretBackend for KeyringBackend {
+    fn get(&self, name: &str) -> ProxyResult<Option<String>> {
+        match Self::entry(name)?.get_password() {
+            Ok(secret) => Ok(Some(secret)),
+            Err(keyring::Error::NoEntry) => Ok(None),
+            Err(e) => Err(ProxyError::KeyStore(format!(
+                "Failed to read {} from keyring: {}",
+                name, e
+            ))),
+        }
+    }
+
+    fn set(&mut self, name: &str, secret: &str) -> ProxyResult<()> {
+        Self::entry(name)?.set_password(secret).map_err(|e| {
+            ProxyError::KeyStore(format!("Failed to write {} to keyring: {}", name, e))
+        })
+    }
+
+    fn delete(&mut self, name: &str) -> ProxyResult<bool> {
+        match Self::entry(name)?.delete_credential() {
+            Ok(()) => Ok(true),
+            Err(keyring::Error::NoEntry) => Ok(false),
+            Err(e) => Err(ProxyError::KeyStore(format!(
+                "Failed to delete {} from keyring: {}",
+                name, e
+            ))),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::process;
+
+    fn temp_dir(test: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("pqp-keystore-{}-{}", test, process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        dir
+    }
+
+    #[test]
+    fn file_backend_round_trips_across_reopening() {
+        let dir = temp_dir("round-trip");
+        let mut store = KeyStore::new(Box::new(FileBackend::open(&dir).unwrap()));
+        store.set("anthropic", "  sk-ant-ключ-🔑  ").unwrap();
+        store.set("groq", "gsk-123").unwrap();
+        assert!(store.delete("groq").unwrap());
+        assert!(!store.delete("groq").unwrap());
+
+        let reopened = KeyStore::new(Box::new(FileBackend::open(&dir).unwrap()));
+        assert_eq!(
+            reopened.get("anthropic").unwrap().as_deref(),
+            Some("sk-ant-ключ-🔑")
+        );
+        assert_eq!(reopened.get("groq").unwrap(), None);
+
+        // Only ciphertext reaches the file, and no temporary file is left behind
+        let contents = fs::read_to_string(dir.join("credentials.json")).unwrap();
+        assert!(!contents.contains("sk-ant"));
+        assert!(!dir.join("credentials.json.tmp").exists());
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = fs::metadata(dir.join("credentials.key"))
+                .unwrap()
+                .permissions()
+                .mode();
+            assert_eq!(mode & 0o777, 0o600);
+        }
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn file_backend_rejects_a_different_master_key() {
+        let dir = temp_dir("wrong-key");
+        let mut store = KeyStore::new(Box::new(FileBackend::open(&dir).unwrap()));
+        store.set("openai", "sk-test").unwrap();
+
+        fs::write(dir.join("credentials.key"), [7u8; 32]).unwrap();
+        let reopened = KeyStore::new(Box::new(FileBackend::open(&dir).unwrap()));
+        assert!(matches!(
+            reopened.get("openai"),
+            Err(ProxyError::KeyStore(_))
+        ));
+
+        fs::write(dir.join("credentials.key"), [7u8; 16]).unwrap();
+        assert!(FileBackend::open(&dir).is_err());
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    /// An in-memory stand-in for the OS keyring
+    #[derive(Default)]
+    struct MemoryBackend(HashMap<String, String>);
+
+    impl SecretBackend for MemoryBackend {
+        fn get(&self, name: &str) -> ProxyResult<Option<String>> {
+            Ok(self.0.get(name).cloned())
+        }
+
+        fn set(&mut self, name: &str, secret: &str) -> ProxyResult<()> {
+            self.0.insert(name.to_string(), secret.to_string());
+            Ok(())
+        }
+
+        fn delete(&mut self, name: &str) -> ProxyResult<bool> {
+            Ok(self.0.remove(name).is_some())
+        }
+    }
+
+    #[test]
+    fn file_secrets_move_to_the_keyring() {
+        let dir = temp_dir("migrate");
+        let mut file = FileBackend::open(&dir).unwrap();
+        file.set("openai", "sk-file").unwrap();
+        file.set("groq", "gsk-file").unwrap();
+        let mut keyring = MemoryBackend::default();
+        keyring.set("openai", "sk-keyring").unwrap();
+
+        migrate_file_secrets(&dir, &mut keyring).unwrap();
+        assert_eq!(
+            keyring.get("openai").unwrap().as_deref(),
+            Some("sk-keyring")
+        );
+        assert_eq!(keyring.get("groq").unwrap().as_deref(), Some("gsk-file"));
+        assert!(!dir.join("credentials.json").exists());
+        assert!(!dir.join("credentials.key").exists());
+
+        // With nothing left to move, the data directory is not touched
+        migrate_file_secrets(&dir, &mut keyring).unwrap();
+        assert!(!dir.join("credentials.key").exists());
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn empty_names_and_keys_are_rejected() {
+        let dir = temp_dir("empty");
+        let mut store = KeyStore::new(Box::new(FileBackend::open(&dir).unwrap()));
+        assert!(store.set(" ", "sk-test").is_err());
+        assert!(store.set("openai", "   ").is_err());
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -1,6 +1,6 @@
 use async_trait::async_trait;
-use dotenv::dotenv;
 use log::{debug, error, info};
+use serde::{ser::SerializeMap, Serialize, Serializer};
 use serde_json::Value;
 use std::env;
 use tauri::{Emitter, Window};
@@ -9,16 +9,44 @@ use thiserror::Error;
 
 // Expose provider modules
 mod anthropic;
+mod attachments;
+pub mod client;
+pub mod connection;
+pub mod errors;
+mod gemini;
+pub mod keystore;
+pub mod models;
+mod ollama;
 mod openai;
+mod openai_responses;
+pub mod profiles;
+pub mod request;
+pub mod retry;
+mod sse;
+pub mod titles;
 
 // Re-export provider structs
 pub use anthropic::AnthropicProvider;
+pub use client::{HttpClient, HttpSettings};
+pub use connection::{test_connection, ConnectionStatus, ConnectionTestResult};
+pub use errors::ProviderError;
+pub use gemini::GeminiProvider;
+pub use keystore::KeyStore;
+pub use models::{ModelCache, ModelInfo};
+pub use ollama::OllamaProvider;
 pub use openai::OpenAIProvider;
+pub use profiles::{OpenAIApi, OpenAISettings, ProfileStore, ProviderProfile};
+pub use request::{
+    ChatMessage, ChatRequest, ChatRole, ContentPart, ThinkingOptions, ToolDefinition,
+};
+pub use retry::{RetryNotice, RetryPolicy};
+pub use titles::{TitleGenerator, TitleSettings};
 
 // Event type constants
 pub(crate) const EVT_CHUNK: &str = "ai-stream-chunk";
 pub(crate) const EVT_ERROR: &str = "ai-stream-error";
 pub(crate) const EVT_END: &str = "ai-stream-end";
+pub(crate) const EVT_RETRYING: &str = "ai-stream-retrying";
 
 /// Errors that can occur when working with API proxies
 #[derive(Error, Debug)]
@@ -29,14 +57,82 @@ pub enum ProxyError {
     #[error("HTTP error: {0}")]
     Http(#[from] reqwest::Error),
 
-    #[error("API returned status code {0}")]
-    Status(u16),
-
     #[error("Failed to parse response: {0}")]
     Parse(#[from] serde_json::Error),
 
     #[error("Failed to emit event: {0}")]
     Emit(String),
+
+    #[error("Configuration error: {0}")]
+    Config(String),
+
+    #[error("{0}")]
+    Provider(ProviderError),
+
+    #[error("Invalid request: {0}")]
+    Validation(String),
+
+    #[error("Key store error: {0}")]
+    KeyStore(String),
+
+    #[error("Failed to acquire lock: {0}")]
+    Lock(String),
+}
+
+impl<T> From<std::sync::PoisonError<T>> for ProxyError {
+    fn from(err: std::sync::PoisonError<T>) -> Self {
+        ProxyError::Lock(err.to_string())
+    }
+}
+
+impl ProxyError {
+    /// Stable discriminator for the frontend, matching the variant name
+    pub fn kind(&self) -> &'static str {
+        match self {
+            ProxyError::ApiKey(_) => "ApiKey",
+            ProxyError::Http(_) => "Http",
+            ProxyError::Parse(_) => "Parse",
+            ProxyError::Emit(_) => "Emit",
+            ProxyError::Config(_) => "Config",
+            ProxyError::Provider(_) => "Provider",
+            ProxyError::Validation(_) => "Validation",
+            ProxyError::KeyStore(_) => "KeyStore",
+            ProxyError::Lock(_) => "Lock",
+        }
+    }
+}
+
+/// Serialized as `{kind, message, ...details}`; provider failures carry their
+/// parsed details, e.g. `{"kind": "Provider", "errorType": "rate_limit_error", ...}`
+impl Serialize for ProxyError {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut map = serializer.serialize_map(None)?;
+        map.serialize_entry("kind", self.kind())?;
+        match self {
+            ProxyError::Provider(details) => {
+                map.serialize_entry("provider", &details.provider)?;
+                map.serialize_entry("status", &details.status)?;
+                map.serialize_entry("errorType", &details.error_type)?;
+                map.serialize_entry("requestId", &details.request_id)?;
+                map.serialize_entry("retryAfterMs", &details.retry_after_ms)?;
+                map.serialize_entry("message", &details.message)?;
+                return map.end();
+            }
+            ProxyError::Http(e) => {
+                map.serialize_entry("status", &e.status().map(|s| s.as_u16()))?;
+                map.serialize_entry("url", &e.url().map(|u| u.to_string()))?;
+                map.serialize_entry("timeout", &e.is_timeout())?;
+                map.serialize_entry("connect", &e.is_connect())?;
+            }
+            ProxyError::Parse(e) => {
+                map.serialize_entry("line", &e.line())?;
+                map.serialize_entry("column", &e.column())?;
+            }
+            _ => {}
+        }
+        map.serialize_entry("message", &self.to_string())?;
+        map.end()
+    }
 }
 
 /// Result type for proxy operations
@@ -45,55 +141,127 @@ pub type ProxyResult<T> = Result<T, ProxyError>;
 /// Trait for API providers that can stream responses
 #[async_trait]
 pub trait ProxyProvider {
+    /// Translate a unified chat request into this provider's wire format
+    fn build_request(&self, request: &ChatRequest) -> ProxyResult<Value>;
+
     /// Stream a response from the API provider
     async fn stream(&self, window: Window, body: Value) -> ProxyResult<()>;
+
+    /// Run a built request to completion without streaming and return the text of
+    /// the response. Meant for background work, so nothing is emitted and failures
+    /// are not retried.
+    async fn complete(&self, body: Value) -> ProxyResult<String>;
+
+    /// List the models available from the API provider
+    async fn list_models(&self) -> ProxyResult<Vec<ModelInfo>>;
+
+    /// Make the cheapest authenticated request the provider supports, returning
+    /// the number of models it reports
+    async fn probe(&self) -> ProxyResult<usize> {
+        Ok(self.list_models().await?.len())
+    }
 }
 
-/// Load an API key from environment variables for the given provider
-pub fn load_api_key(provider: &str) -> ProxyResult<String> {
-    dotenv().ok();
-    let key_name = match provider {
-        "anthropic" => "ANTHROPIC_API_KEY",
-        "openai" => "OPENAI_API_KEY",
-        _ => {
-            return Err(ProxyError::ApiKey(format!(
-                "Unsupported provider: {}",
-                provider
-            )))
-        }
+/// Environment variable consulted when no key has been saved for a built-in provider
+fn api_key_env_var(provider: &str) -> Option<&'static str> {
+    match provider {
+        "anthropic" => Some("ANTHROPIC_API_KEY"),
+        "openai" => Some("OPENAI_API_KEY"),
+        "gemini" => Some("GEMINI_API_KEY"),
+        _ => None,
+    }
+}
+
+/// Load an API key for the given provider from the key store, falling back to
+/// environment variables
+pub fn load_api_key(provider: &str, keys: &KeyStore) -> ProxyResult<String> {
+    let Some(key_name) = api_key_env_var(provider) else {
+        return Err(ProxyError::ApiKey(format!(
+            "Unsupported provider: {}",
+            provider
+        )));
     };
 
-    debug!("Loading {} from environment/dotenv", key_name);
-
-    match env::var(key_name) {
-        Ok(key) => {
-            let redacted = if key.len() > 10 {
-                format!("{}...{}", &key[..5], &key[key.len() - 5..])
-            } else {
-                "Key too short to redact safely".to_string()
-            };
-            debug!("{} loaded (redacted: {})", key_name, redacted);
-            Ok(key)
+    let key = match keys.get(provider)? {
+        Some(key) => {
+            debug!("{} key loaded from key store", provider);
+            key
         }
-        Err(e) => {
-            let error_msg = format!("Failed to load {}: {}", key_name, e);
-            error!("{}", error_msg);
-            Err(ProxyError::ApiKey(error_msg))
+        None => {
+            debug!(
+                "No stored key for {}, loading {} from environment",
+                provider, key_name
+            );
+            env::var(key_name).map_err(|e| {
+                let error_msg =
+                    format!("No API key saved for {} and {}: {}", provider, key_name, e);
+                error!("{}", error_msg);
+                ProxyError::ApiKey(error_msg)
+            })?
         }
-    }
+    };
+
+    // Only the length is logged; no part of a key is safe to write to a log file
+    debug!(
+        "{} key loaded ({} characters)",
+        provider,
+        key.chars().count()
+    );
+    Ok(key)
 }
 
-/// Get a provider implementation based on the provider name
-pub fn get_provider(provider: &str) -> ProxyResult<Box<dyn ProxyProvider + Send + Sync>> {
-    let api_key = load_api_key(provider)?;
+/// Whether a key is available for the provider, either saved or from the environment
+pub fn has_api_key(provider: &str, keys: &KeyStore) -> ProxyResult<bool> {
+    if keys.get(provider)?.is_some() {
+        return Ok(true);
+    }
+    Ok(api_key_env_var(provider).is_some_and(|var| env::var(var).is_ok_and(|k| !k.is_empty())))
+}
 
+/// Get a provider implementation based on the provider name.
+///
+/// Built-in providers take precedence; any other name is resolved against the
+/// configured OpenAI-compatible profiles. Every provider shares the pooled `client`.
+pub fn get_provider(
+    provider: &str,
+    profiles: &ProfileStore,
+    keys: &KeyStore,
+    client: reqwest::Client,
+) -> ProxyResult<Box<dyn ProxyProvider + Send + Sync>> {
     match provider {
-        "anthropic" => Ok(Box::new(AnthropicProvider::new(api_key))),
-        "openai" => Ok(Box::new(OpenAIProvider::new(api_key))),
-        _ => Err(ProxyError::ApiKey(format!(
-            "Unsupported provider: {}",
-            provider
+        "anthropic" => Ok(Box::new(AnthropicProvider::new(
+            client,
+            load_api_key(provider, keys)?,
+        ))),
+        "openai" => Ok(Box::new(OpenAIProvider::new(
+            client,
+            load_api_key(provider, keys)?,
+            &profiles.openai_settings(),
+        ))),
+        "gemini" => Ok(Box::new(GeminiProvider::new(
+            client,
+            load_api_key(provider, keys)?,
         ))),
+        "ollama" => Ok(Box::new(OllamaProvider::from_env(client))),
+        _ => match profiles.get(provider) {
+            Some(profile) => {
+                debug!(
+                    "Resolved provider profile {} ({})",
+                    profile.name, profile.base_url
+                );
+                let api_key = match keys.get(&profile.name)? {
+                    Some(key) => Some(key),
+                    None => profile.resolve_api_key(),
+                };
+                Ok(Box::new(OpenAIProvider::from_profile(
+                    client, profile, api_key,
+                )))
+            }
+            None => Err(ProxyError::ApiKey(format!(
+                "Unsupported provider: {}",
+                provider
+            ))),
+        },
     }
 }
 
@@ -108,6 +276,14 @@ pub(crate) fn emit_error<S: Into<String>>(window: &Window, message: S) -> ProxyR
         .map_err(|e| ProxyError::Emit(format!("Failed to emit error event: {}", e)))
 }
 
+/// Emit a structured provider error so the frontend can react to its type
+pub(crate) fn emit_provider_error(window: &Window, err: &ProviderError) -> ProxyResult<()> {
+    error!("Emitting provider error: {}", err);
+    window
+        .emit(EVT_ERROR, err)
+        .map_err(|e| ProxyError::Emit(format!("Failed to emit error event: {}", e)))
+}
+
 /// Emit a chunk of data to the client
 pub(crate) fn emit_chunk<S: Into<String>>(window: &Window, data: S) -> ProxyResult<()> {
     let chunk_data = data.into();
@@ -117,6 +293,23 @@ pub(crate) fn emit_chunk<S: Into<String>>(window: &Window, data: S) -> ProxyResu
         .map_err(|e| ProxyError::Emit(format!("Failed to emit chunk event: {}", e)))
 }
 
+/// Emit a typed AI SDK data stream part (e.g. `9` tool call, `d` finish message)
+pub(crate) fn emit_part<T: Serialize>(window: &Window, code: char, value: &T) -> ProxyResult<()> {
+    let value_json = serde_json::to_string(value)?;
+    emit_chunk(window, format!("{}:{}\n", code, value_json))
+}
+
+/// Emit a retrying event before waiting out a transient provider failure
+pub(crate) fn emit_retrying(window: &Window, notice: &RetryNotice) -> ProxyResult<()> {
+    info!(
+        "Emitting retrying event ({} in {} ms)",
+        notice.reason, notice.delay_ms
+    );
+    window
+        .emit(EVT_RETRYING, notice)
+        .map_err(|e| ProxyError::Emit(format!("Failed to emit retrying event: {}", e)))
+}
+
 /// Emit an end event to the client
 pub(crate) fn emit_end(window: &Window) -> ProxyResult<()> {
     info!("Emitting stream end event");
@@ -124,3 +317,24 @@ pub(crate) fn emit_end(window: &Window) -> ProxyResult<()> {
         .emit(EVT_END, ())
         .map_err(|e| ProxyError::Emit(format!("Failed to emit end event: {}", e)))
 }
+
+/// Id for a tool call from a provider that does not assign its own. Random rather
+/// than counted per stream, so ids stay unique across a whole conversation and
+/// tool results match their call whatever the message order.
+pub(crate) fn generated_tool_call_id(provider: &str) -> String {
+    format!("{}-call-{:016x}", provider, rand::random::<u64>())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn generated_tool_call_ids_do_not_repeat() {
+        let first = generated_tool_call_id("gemini");
+        let second = generated_tool_call_id("gemini");
+        assert!(first.starts_with("gemini-call-"));
+        assert_eq!(first.len(), "gemini-call-".len() + 16);
+        assert_ne!(first, second);
+    }
+}
@@ -0,0 +1,182 @@
+use log::debug;
+use serde::Serialize;
+use serde_json::Value;
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+/// How long a provider's model list is served from cache
+pub const MODEL_CACHE_TTL: Duration = Duration::from_secs(10 * 60);
+
+/// A model offered by a provider, as returned to the frontend
+#[derive(Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct ModelInfo {
+    pub id: String,
+    pub display_name: String,
+    pub provider: String,
+    pub context_window: Option<u32>,
+    pub supports_tools: Option<bool>,
+    pub supports_vision: Option<bool>,
+    /// Provider-specific details (size, family, quantization, ...)
+    pub metadata: Option<Value>,
+}
+
+impl ModelInfo {
+    /// Build a model entry, filling in context window and capabilities for known model families
+    pub fn with_known_capabilities(
+        id: String,
+        display_name: Option<String>,
+        provider: &str,
+    ) -> Self {
+        let (context_window, supports_tools, supports_vision) = match known_capabilities(&id) {
+            Some((window, tools, vision)) => (Some(window), Some(tools), Some(vision)),
+            None => (None, None, None),
+        };
+        Self {
+            display_name: display_name.unwrap_or_else(|| id.clone()),
+            id,
+            provider: provider.to_string(),
+            context_window,
+            supports_tools,
+            supports_vision,
+            metadata: None,
+        }
+    }
+}
+
+/// Whether a well-known model accepts images, `None` when the model is not known
+pub(crate) fn supports_vision(model: &str) -> Option<bool> {
+    known_capabilities(model).map(|(_, _, vision)| vision)
+}
+
+/// Context window, tool support and vision support for well-known model id prefixes.
+///
+/// Listing endpoints rarely report these, so they are kept here. The first
+/// matching prefix wins, so a model must come before any family whose prefix it
+/// extends (`gpt-4.5` before `gpt-4`, `o1-preview` before `o1`).
+const KNOWN: &[(&str, u32, bool, bool)] = &[
+    ("claude-3-5-haiku", 200_000, true, false),
+    ("claude-instant", 100_000, false, false),
+    ("claude-2", 200_000, false, false),
+    ("claude-", 200_000, true, true),
+    ("gpt-5", 400_000, true, true),
+    ("gpt-4.5", 128_000, true, true),
+    ("gpt-4.1", 1_047_576, true, true),
+    ("gpt-4o", 128_000, true, true),
+    ("gpt-4-turbo", 128_000, true, true),
+    ("gpt-4-vision", 128_000, false, true),
+    ("gpt-4-1106", 128_000, true, false),
+    ("gpt-4-0125", 128_000, true, false),
+    ("gpt-4-32k", 32_768, true, false),
+    ("gpt-4", 8_192, true, false),
+    ("gpt-3.5-turbo-instruct", 4_096, false, false),
+    ("gpt-3.5-turbo", 16_385, true, false),
+    ("o1-preview", 128_000, false, false),
+    ("o1-mini", 128_000, false, false),
+    ("o1", 200_000, true, true),
+    ("o3-mini", 200_000, true, false),
+    ("o3", 200_000, true, true),
+    ("o4-mini", 200_000, true, true),
+    ("gemini-1.0-pro", 32_760, true, false),
+    ("gemini-1.5-pro", 2_097_152, true, true),
+    ("gemini-", 1_048_576, true, true),
+];
+
+fn known_capabilities(id: &str) -> Option<(u32, bool, bool)> {
+    KNOWN
+        .iter()
+        .find(|(prefix, ..)| id.starts_with(prefix))
+        .map(|(_, window, tools, vision)| (*window, *tools, *vision))
+}
+
+/// In-memory per-provider cache of model listings
+pub struct ModelCache {
+    ttl: Duration,
+    entries: HashMap<String, (Instant, Vec<ModelInfo>)>,
+}
+
+impl Default for ModelCache {
+    fn default() -> Self {
+        Self::new(MODEL_CACHE_TTL)
+    }
+}
+
+impl ModelCache {
+    pub fn new(ttl: Duration) -> Self {
+        Self {
+            ttl,
+            entries: HashMap::new(),
+        }
+    }
+
+    /// Return the cached listing for `provider` if it has not expired
+    pub fn get(&self, provider: &str) -> Option<Vec<ModelInfo>> {
+        self.entries
+            .get(provider)
+            .filter(|(fetched_at, _)| fetched_at.elapsed() < self.ttl)
+            .map(|(_, models)| {
+                debug!(
+                    "Serving {} models for {} from cache",
+                    models.len(),
+                    provider
+                );
+                models.clone()
+            })
+    }
+
+    pub fn insert(&mut self, provider: &str, models: Vec<ModelInfo>) {
+        self.entries
+            .insert(provider.to_string(), (Instant::now(), models));
+    }
+
+    pub fn invalidate(&mut self, provider: &str) {
+        self.entries.remove(provider);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn no_prefix_is_shadowed_by_an_earlier_one() {
+        for (index, (prefix, ..)) in KNOWN.iter().enumerate() {
+            if let Some((earlier, ..)) = KNOWN[..index]
+                .iter()
+                .find(|(earlier, ..)| prefix.starts_with(earlier))
+            {
+                panic!("{} is unreachable behind {}", prefix, earlier);
+            }
+        }
+    }
+
+    #[test]
+    fn looks_up_the_most_specific_family() {
+        let cases = [
+            ("gpt-4.5-preview", Some((128_000, true, true))),
+            ("gpt-4-0613", Some((8_192, true, false))),
+            ("gpt-4-turbo-2024-04-09", Some((128_000, true, true))),
+            ("gpt-4o-mini", Some((128_000, true, true))),
+            ("o1-preview-2024-09-12", Some((128_000, false, false))),
+            ("o1-2024-12-17", Some((200_000, true, true))),
+            ("o3-mini", Some((200_000, true, false))),
+            ("claude-3-5-haiku-latest", Some((200_000, true, false))),
+            ("claude-sonnet-4-0", Some((200_000, true, true))),
+            ("gemini-1.5-pro-002", Some((2_097_152, true, true))),
+            ("llama3.2", None),
+        ];
+        for (id, expected) in cases {
+            assert_eq!(known_capabilities(id), expected, "{}", id);
+        }
+        assert_eq!(supports_vision("gpt-3.5-turbo"), Some(false));
+    }
+
+    #[test]
+    fn serializes_camel_case() {
+        let info = ModelInfo::with_known_capabilities("gpt-4o".to_string(), None, "openai");
+        let json = serde_json::to_value(&info).unwrap();
+        assert_eq!(json["displayName"], "gpt-4o");
+        assert_eq!(json["contextWindow"], 128_000);
+        assert_eq!(json["supportsVision"], true);
+    }
+}
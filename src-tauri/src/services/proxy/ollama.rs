@@ -0,0 +1,567 @@
+use crate::services::proxy::attachments::{self, AttachmentSupport};
+use crate::services::proxy::errors::read_error;
+use crate::services::proxy::openai::function_tools;
+use crate::services::proxy::request::{invalid, tool_result_text};
+use crate::services::proxy::retry::{send_with_retry, RetryPolicy};
+use crate::services::proxy::{
+    emit_chunk, emit_end, emit_error, emit_part, emit_provider_error, generated_tool_call_id,
+};
+use crate::services::proxy::{
+    ChatRequest, ChatRole, ContentPart, ModelInfo, ProviderError, ProxyError, ProxyProvider,
+    ProxyResult,
+};
+use async_trait::async_trait;
+use futures_util::StreamExt;
+use log::{debug, error, info, warn};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Map, Value};
+use std::collections::HashMap;
+use std::env;
+use tauri::{Emitter, Window};
+use tauri_plugin_http::reqwest;
+
+const OLLAMA_DEFAULT_HOST: &str = "http://127.0.0.1:11434";
+
+/// Emitted with a `PullProgress` for each status line of a model download
+pub(crate) const EVT_PULL_PROGRESS: &str = "ollama-pull-progress";
+
+/// Client for Ollama's native API (`/api/chat`, `/api/tags`, `/api/ps`, `/api/pull`)
+pub struct OllamaProvider {
+    client: reqwest::Client,
+    base_url: String,
+}
+
+impl OllamaProvider {
+    pub fn new(client: reqwest::Client, base_url: String) -> Self {
+        Self {
+            client,
+            base_url: base_url.trim_end_matches('/').to_string(),
+        }
+    }
+
+    /// Build a provider from `OLLAMA_HOST`, following the same conventions as the
+    /// Ollama CLI (scheme optional, `0.0.0.0` meaning the local machine)
+    pub fn from_env(client: reqwest::Client) -> Self {
+        let host = env::var("OLLAMA_HOST")
+            .ok()
+            .filter(|h| !h.trim().is_empty())
+            .map(|h| {
+                let h = h.trim().replace("0.0.0.0", "127.0.0.1");
+                if h.starts_with("http://") || h.starts_with("https://") {
+                    h
+                } else {
+                    format!("http://{}", h)
+                }
+            })
+            .unwrap_or_else(|| OLLAMA_DEFAULT_HOST.to_string());
+        debug!("Using Ollama host {}", host);
+        Self::new(client, host)
+    }
+
+    /// Models currently loaded into memory, via `/api/ps`
+    async fn running_models(&self) -> ProxyResult<Vec<OllamaRunningModel>> {
+        let response = self
+            .client
+            .get(format!("{}/api/ps", self.base_url))
+            .send()
+            .await?;
+        if !response.status().is_success() {
+            return Err(ProxyError::Provider(read_error("ollama", response).await));
+        }
+        let running: OllamaRunning = response.json().await?;
+        Ok(running.models)
+    }
+
+    /// Download `model` via `/api/pull`, emitting a `PullProgress` for each status
+    /// line until Ollama reports success
+    pub async fn pull(&self, window: &Window, model: &str) -> ProxyResult<()> {
+        info!("Pulling Ollama model {}", model);
+        let response = self
+            .client
+            .post(format!("{}/api/pull", self.base_url))
+            .json(&json!({ "model": model, "stream": true }))
+            .send()
+            .await?;
+        if !response.status().is_success() {
+            return Err(ProxyError::Provider(read_error("ollama", response).await));
+        }
+
+        let mut stream = response.bytes_stream();
+        let mut buffer: Vec<u8> = Vec::new();
+        let mut finished = false;
+        while !finished {
+            match stream.next().await {
+                Some(chunk) => buffer.extend_from_slice(&chunk?),
+                None => finished = true,
+            }
+            while let Some(line) = next_line(&mut buffer, finished) {
+                let status = match serde_json::from_str::<OllamaPullStatus>(&line) {
+                    Ok(status) => status,
+                    Err(e) => {
+                        warn!("Failed to parse Ollama pull status: {}", e);
+                        continue;
+                    }
+                };
+                if let Some(message) = status.error {
+                    return Err(ProxyError::Provider(ProviderError::in_stream(
+                        "ollama",
+                        "api_error",
+                        &message,
+                    )));
+                }
+                let progress = PullProgress {
+                    model: model.to_string(),
+                    status: status.status,
+                    digest: status.digest,
+                    total: status.total,
+                    completed: status.completed,
+                };
+                window.emit(EVT_PULL_PROGRESS, &progress).map_err(|e| {
+                    ProxyError::Emit(format!("Failed to emit pull progress event: {}", e))
+                })?;
+            }
+        }
+        info!("Pulled Ollama model {}", model);
+        Ok(())
+    }
+}
+
+/// Take the next complete line out of an NDJSON buffer. Once the stream has
+/// `ended`, whatever is left counts as the last line even without a newline.
+fn next_line(buffer: &mut Vec<u8>, ended: bool) -> Option<String> {
+    loop {
+        let line: Vec<u8> = match buffer.iter().position(|b| *b == b'\n') {
+            Some(pos) => buffer.drain(..=pos).collect(),
+            None if ended && !buffer.is_empty() => std::mem::take(buffer),
+            None => return None,
+        };
+        let line = String::from_utf8_lossy(&line).trim().to_string();
+        if !line.is_empty() {
+            return Some(line);
+        }
+    }
+}
+
+#[derive(Deserialize, Debug)]
+struct OllamaTags {
+    #[serde(default)]
+    models: Vec<OllamaModel>,
+}
+
+#[derive(Deserialize, Debug)]
+struct OllamaModel {
+    name: String,
+    #[serde(default)]
+    modified_at: Option<String>,
+    #[serde(default)]
+    size: Option<u64>,
+    #[serde(default)]
+    digest: Option<String>,
+    #[serde(default)]
+    details: Option<Value>,
+}
+
+#[derive(Deserialize, Debug)]
+struct OllamaRunning {
+    #[serde(default)]
+    models: Vec<OllamaRunningModel>,
+}
+
+#[derive(Deserialize, Debug)]
+struct OllamaRunningModel {
+    name: String,
+    #[serde(default)]
+    size_vram: Option<u64>,
+    /// When Ollama unloads the model if it stays idle
+    #[serde(default)]
+    expires_at: Option<String>,
+}
+
+/// One line of `/api/pull`
+#[derive(Deserialize, Debug)]
+struct OllamaPullStatus {
+    #[serde(default)]
+    status: String,
+    digest: Option<String>,
+    total: Option<u64>,
+    completed: Option<u64>,
+    error: Option<String>,
+}
+
+/// Progress of a model download: "pulling manifest", one status per layer with
+/// its byte counts, then "verifying sha256 digest", "writing manifest" and "success"
+#[derive(Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct PullProgress {
+    pub model: String,
+    pub status: String,
+    /// Layer being downloaded, with its size and the bytes received so far
+    pub digest: Option<String>,
+    pub total: Option<u64>,
+    pub completed: Option<u64>,
+}
+
+impl OllamaModel {
+    fn into_model_info(self, running: Option<&OllamaRunningModel>) -> ModelInfo {
+        ModelInfo {
+            id: self.name.clone(),
+            display_name: self.name,
+            provider: "ollama".to_string(),
+            context_window: None,
+            supports_tools: None,
+            supports_vision: None,
+            metadata: Some(json!({
+                "modified_at": self.modified_at,
+                "size": self.size,
+                "digest": self.digest,
+                "details": self.details,
+                "loaded": running.is_some(),
+                "size_vram": running.and_then(|r| r.size_vram),
+                "expires_at": running.and_then(|r| r.expires_at.clone()),
+            })),
+        }
+    }
+}
+
+#[derive(Deserialize, Debug)]
+struct OllamaChatChunk {
+    message: Option<OllamaMessage>,
+    #[serde(default)]
+    done: bool,
+    done_reason: Option<String>,
+    prompt_eval_count: Option<u64>,
+    eval_count: Option<u64>,
+    error: Option<String>,
+}
+
+#[derive(Deserialize, Debug)]
+struct OllamaMessage {
+    #[serde(default)]
+    content: String,
+    #[serde(default)]
+    tool_calls: Vec<OllamaToolCall>,
+}
+
+#[derive(Deserialize, Debug)]
+struct OllamaToolCall {
+    function: OllamaFunctionCall,
+}
+
+#[derive(Deserialize, Debug)]
+struct OllamaFunctionCall {
+    name: String,
+    #[serde(default)]
+    arguments: Value,
+}
+
+#[async_trait]
+impl ProxyProvider for OllamaProvider {
+    /// List locally installed models via `/api/tags`, marking those `/api/ps`
+    /// reports as loaded
+    async fn list_models(&self) -> ProxyResult<Vec<ModelInfo>> {
+        let client = &self.client;
+        let response = client
+            .get(format!("{}/api/tags", self.base_url))
+            .send()
+            .await?;
+
+        if !response.status().is_success() {
+            return Err(ProxyError::Provider(read_error("ollama", response).await));
+        }
+
+        let tags: OllamaTags = response.json().await?;
+        // Load status is extra detail, so a server without `/api/ps` still lists models
+        let running: HashMap<String, OllamaRunningModel> = match self.running_models().await {
+            Ok(models) => models.into_iter().map(|m| (m.name.clone(), m)).collect(),
+            Err(e) => {
+                warn!("Failed to read loaded Ollama models: {}", e);
+                HashMap::new()
+            }
+        };
+        Ok(tags
+            .models
+            .into_iter()
+            .map(|model| {
+                let loaded = running.get(&model.name);
+                model.into_model_info(loaded)
+            })
+            .collect())
+    }
+
+    fn build_request(&self, request: &ChatRequest) -> ProxyResult<Value> {
+        attachments::check_support(
+            request,
+            &AttachmentSupport {
+                provider: "Ollama",
+                images: true,
+                documents: false,
+                vision_models: &[],
+                // Local models are rarely in the table and there is no profile to declare them in
+                allow_unknown_models: true,
+            },
+        )?;
+
+        let mut messages = Vec::new();
+        if let Some(system) = request.system_prompt() {
+            messages.push(json!({ "role": "system", "content": system }));
+        }
+
+        for message in request.conversation() {
+            match message.role {
+                ChatRole::Tool => {
+                    for part in &message.content {
+                        if let ContentPart::ToolResult { name, content, .. } = part {
+                            let mut result = json!({
+                                "role": "tool",
+                                "content": tool_result_text(content),
+                            });
+                            if let Some(name) = name {
+                                result["tool_name"] = json!(name);
+                            }
+                            messages.push(result);
+                        }
+                    }
+                }
+                ChatRole::Assistant => {
+                    // Unlike Chat Completions, Ollama takes tool arguments as an object
+                    let tool_calls: Vec<Value> = message
+                        .content
+                        .iter()
+                        .filter_map(|part| match part {
+                            ContentPart::ToolCall {
+                                name, arguments, ..
+                            } => Some(json!({
+                                "function": { "name": name, "arguments": arguments },
+                            })),
+                            _ => None,
+                        })
+                        .collect();
+                    let mut assistant = json!({ "role": "assistant", "content": message.text() });
+                    if !tool_calls.is_empty() {
+                        assistant["tool_calls"] = Value::Array(tool_calls);
+                    }
+                    messages.push(assistant);
+                }
+                _ => {
+                    let mut user = json!({ "role": "user", "content": message.text() });
+                    // Ollama takes images as bare base64 next to the text
+                    let images = message
+                        .content
+                        .iter()
+                        .filter_map(|part| match part {
+                            ContentPart::Attachment { path, media_type } => {
+                                Some(attachments::load(path, media_type.as_deref()))
+                            }
+                            _ => None,
+                        })
+                        .map(|attachment| attachment.map(|a| a.data))
+                        .collect::<ProxyResult<Vec<String>>>()?;
+                    if !images.is_empty() {
+                        user["images"] = json!(images);
+                    }
+                    messages.push(user);
+                }
+            }
+        }
+
+        let mut options = Map::new();
+        if let Some(max_tokens) = request.max_tokens {
+            options.insert("num_predict".to_string(), json!(max_tokens));
+        }
+        if let Some(temperature) = request.temperature {
+            options.insert("temperature".to_string(), json!(temperature));
+        }
+        if !request.stop.is_empty() {
+            options.insert("stop".to_string(), json!(request.stop));
+        }
+
+        let mut body = Map::new();
+        body.insert("model".to_string(), json!(request.model));
+        body.insert("messages".to_string(), Value::Array(messages));
+        body.insert("stream".to_string(), Value::Bool(true));
+        if !options.is_empty() {
+            body.insert("options".to_string(), Value::Object(options));
+        }
+        if !request.tools.is_empty() {
+            body.insert(
+                "tools".to_string(),
+                Value::Array(function_tools(&request.tools)),
+            );
+        }
+        if !request.builtin_tools.is_empty() {
+            return Err(invalid("Ollama has no built-in tools"));
+        }
+
+        Ok(Value::Object(body))
+    }
+
+    async fn complete(&self, mut body: Value) -> ProxyResult<String> {
+        body["stream"] = Value::Bool(false);
+        let response = self
+            .client
+            .post(format!("{}/api/chat", self.base_url))
+            .json(&body)
+            .send()
+            .await?;
+        if !response.status().is_success() {
+            return Err(ProxyError::Provider(read_error("ollama", response).await));
+        }
+
+        let completion: Value = response.json().await?;
+        Ok(completion["message"]["content"]
+            .as_str()
+            .unwrap_or_default()
+            .to_string())
+    }
+
+    async fn stream(&self, window: Window, body: Value) -> ProxyResult<()> {
+        info!("Starting Ollama stream request");
+        let client = &self.client;
+
+        let url = format!("{}/api/chat", self.base_url);
+        let response = send_with_retry(&window, "ollama", &RetryPolicy::default(), &mut 0, || {
+            client.post(&url).json(&body)
+        })
+        .await?;
+
+        let status = response.status();
+        if !status.is_success() {
+            let err = read_error("ollama", response).await;
+            emit_provider_error(&window, &err)?;
+            return Err(ProxyError::Provider(err));
+        }
+        info!("Ollama API request successful (status: {})", status);
+
+        let mut stream = response.bytes_stream();
+        // NDJSON lines may be split across chunks, so buffer raw bytes until a newline
+        let mut buffer: Vec<u8> = Vec::new();
+        let mut tool_call_count = 0usize;
+        let mut finished = false;
+
+        debug!("Starting to process Ollama stream");
+        while !finished {
+            match stream.next().await {
+                Some(Ok(chunk)) => {
+                    debug!("Received raw bytes chunk: {} bytes", chunk.len());
+                    buffer.extend_from_slice(&chunk);
+                }
+                Some(Err(e)) => {
+                    let error_msg = format!("Error reading stream chunk: {}", e);
+                    error!("{}", error_msg);
+                    emit_error(&window, &error_msg)?;
+                    return Err(ProxyError::Http(e));
+                }
+                None => finished = true,
+            }
+
+            while let Some(line) = next_line(&mut buffer, finished) {
+                let chunk_event = match serde_json::from_str::<OllamaChatChunk>(&line) {
+                    Ok(event) => event,
+                    Err(e) => {
+                        warn!("Failed to parse Ollama NDJSON line: {}", e);
+                        continue;
+                    }
+                };
+
+                if let Some(message) = chunk_event.error {
+                    let err = ProviderError::in_stream("ollama", "api_error", &message);
+                    emit_provider_error(&window, &err)?;
+                    return Err(ProxyError::Provider(err));
+                }
+
+                if let Some(message) = chunk_event.message {
+                    if !message.content.is_empty() {
+                        let text_json =
+                            serde_json::to_string(&message.content).map_err(ProxyError::Parse)?;
+                        emit_chunk(&window, format!("0:{}\n", text_json))?;
+                    }
+
+                    for call in message.tool_calls {
+                        // Ollama does not assign tool call ids
+                        tool_call_count += 1;
+                        emit_part(
+                            &window,
+                            '9',
+                            &json!({
+                                "toolCallId": generated_tool_call_id("ollama"),
+                                "toolName": call.function.name,
+                                "args": call.function.arguments,
+                            }),
+                        )?;
+                    }
+                }
+
+                if chunk_event.done {
+                    let finish_reason = if tool_call_count > 0 {
+                        "tool-calls".to_string()
+                    } else {
+                        chunk_event
+                            .done_reason
+                            .unwrap_or_else(|| "stop".to_string())
+                    };
+                    debug!("Ollama stream done with reason: {}", finish_reason);
+                    emit_part(
+                        &window,
+                        'd',
+                        &json!({
+                            "finishReason": finish_reason,
+                            "usage": {
+                                "promptTokens": chunk_event.prompt_eval_count.unwrap_or(0),
+                                "completionTokens": chunk_event.eval_count.unwrap_or(0),
+                            },
+                        }),
+                    )?;
+                }
+            }
+        }
+
+        info!("Ollama stream completed");
+        emit_end(&window)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn next_line_waits_for_a_newline_until_the_stream_ends() {
+        let mut buffer = b"{\"done\":false}\n\n{\"done\":".to_vec();
+        assert_eq!(
+            next_line(&mut buffer, false).as_deref(),
+            Some("{\"done\":false}")
+        );
+        // A partial line stays buffered while more may arrive
+        assert_eq!(next_line(&mut buffer, false), None);
+
+        buffer.extend_from_slice(b"true}");
+        assert_eq!(next_line(&mut buffer, false), None);
+        assert_eq!(
+            next_line(&mut buffer, true).as_deref(),
+            Some("{\"done\":true}")
+        );
+        assert!(buffer.is_empty());
+        assert_eq!(next_line(&mut buffer, true), None);
+    }
+
+    #[test]
+    fn marks_loaded_models() {
+        let model = OllamaModel {
+            name: "llama3:8b".to_string(),
+            modified_at: None,
+            size: Some(4_661_224_676),
+            digest: None,
+            details: None,
+        };
+        let running = OllamaRunningModel {
+            name: "llama3:8b".to_string(),
+            size_vram: Some(5_137_025_024),
+            expires_at: Some("2024-06-04T14:38:31Z".to_string()),
+        };
+        let metadata = model.into_model_info(Some(&running)).metadata.unwrap();
+        assert_eq!(metadata["loaded"], json!(true));
+        assert_eq!(metadata["size_vram"], json!(5_137_025_024u64));
+        assert_eq!(metadata["expires_at"], json!("2024-06-04T14:38:31Z"));
+    }
+}
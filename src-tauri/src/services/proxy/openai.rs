@@ -1,174 +1,1072 @@
-use crate::services::proxy::{emit_chunk, emit_end, emit_error};
-use crate::services::proxy::{ProxyError, ProxyProvider, ProxyResult};
+use crate::services::proxy::attachments::{self, AttachmentSupport};
+use crate::services::proxy::errors::read_error;
+use crate::services::proxy::openai_responses;
+use crate::services::proxy::request::{invalid, tool_result_text};
+use crate::services::proxy::retry::{send_with_retry, RetryPolicy};
+use crate::services::proxy::sse::SseBuffer;
+use crate::services::proxy::{
+    emit_end, emit_error, emit_part, emit_provider_error, generated_tool_call_id,
+};
+use crate::services::proxy::{
+    ChatRequest, ChatRole, ContentPart, ModelInfo, OpenAIApi, OpenAISettings, ProviderError,
+    ProviderProfile, ProxyError, ProxyProvider, ProxyResult, ToolDefinition,
+};
 use async_trait::async_trait;
 use futures_util::StreamExt;
 use log::{debug, error, info, warn};
 use serde::{Deserialize, Serialize};
-use serde_json::Value;
+use serde_json::{json, Map, Value};
+use std::collections::btree_map::Entry;
+use std::collections::{BTreeMap, HashMap};
 use tauri::Window;
 use tauri_plugin_http::reqwest::{
     self,
-    header::{HeaderMap, HeaderValue, AUTHORIZATION, CONTENT_TYPE},
+    header::{HeaderMap, HeaderName, HeaderValue, AUTHORIZATION, CONTENT_TYPE},
 };
 
+const OPENAI_BASE_URL: &str = "https://api.openai.com/v1";
+
+/// Chat Completions client for OpenAI and any OpenAI-compatible endpoint
 pub struct OpenAIProvider {
-    api_key: String,
+    client: reqwest::Client,
+    name: String,
+    api_key: Option<String>,
+    base_url: String,
+    extra_headers: HashMap<String, String>,
+    /// Models declared by a profile, served instead of querying `/models`
+    declared_models: Vec<String>,
+    /// Models a profile routes through the Responses API
+    responses_models: Vec<String>,
+    /// Models a profile declares as taking images and PDFs
+    vision_models: Vec<String>,
+    /// API chosen per model for the official endpoint
+    model_apis: HashMap<String, OpenAIApi>,
 }
 
 impl OpenAIProvider {
-    pub fn new(api_key: String) -> Self {
-        Self { api_key }
+    pub fn new(client: reqwest::Client, api_key: String, settings: &OpenAISettings) -> Self {
+        Self {
+            client,
+            name: "openai".to_string(),
+            api_key: Some(api_key),
+            base_url: OPENAI_BASE_URL.to_string(),
+            extra_headers: HashMap::new(),
+            declared_models: Vec::new(),
+            responses_models: Vec::new(),
+            vision_models: Vec::new(),
+            model_apis: settings.model_apis.clone(),
+        }
+    }
+
+    /// Build a provider for a configured OpenAI-compatible profile
+    pub fn from_profile(
+        client: reqwest::Client,
+        profile: &ProviderProfile,
+        api_key: Option<String>,
+    ) -> Self {
+        Self {
+            client,
+            name: profile.name.clone(),
+            api_key,
+            base_url: profile.base_url.trim_end_matches('/').to_string(),
+            extra_headers: profile.headers.clone(),
+            declared_models: profile.models.clone(),
+            responses_models: profile.responses_models.clone(),
+            vision_models: profile.vision_models.clone(),
+            model_apis: HashMap::new(),
+        }
+    }
+
+    /// Whether a model is called through `/responses` rather than `/chat/completions`.
+    /// Compatible servers rarely implement the Responses API, so they opt in per model.
+    fn uses_responses_api(&self, model: &str) -> bool {
+        if let Some(api) = self.model_apis.get(model) {
+            return *api == OpenAIApi::Responses;
+        }
+        self.responses_models.iter().any(|m| m == model)
+            || (self.base_url == OPENAI_BASE_URL && openai_responses::prefers_responses_api(model))
+    }
+
+    /// Attachments `api` can take. Models of the official endpoint are mostly in the
+    /// capability table; a compatible server's have to be declared by its profile.
+    fn attachment_support(&self, api: &'static str, documents: bool) -> AttachmentSupport<'_> {
+        AttachmentSupport {
+            provider: api,
+            images: true,
+            documents,
+            vision_models: &self.vision_models,
+            allow_unknown_models: self.base_url == OPENAI_BASE_URL,
+        }
+    }
+
+    /// List available models via `/models`
+    async fn fetch_models(&self) -> ProxyResult<Vec<ModelInfo>> {
+        let client = &self.client;
+        let response = client
+            .get(format!("{}/models", self.base_url))
+            .headers(self.headers()?)
+            .send()
+            .await?;
+
+        if !response.status().is_success() {
+            return Err(ProxyError::Provider(read_error(&self.name, response).await));
+        }
+
+        let list: OpenAIModelList = response.json().await?;
+        let is_openai = self.base_url == OPENAI_BASE_URL;
+        Ok(list
+            .data
+            .into_iter()
+            // The official listing includes embedding, audio and image models
+            .filter(|model| !is_openai || is_chat_model(&model.id))
+            .map(|model| ModelInfo::with_known_capabilities(model.id, None, &self.name))
+            .collect())
+    }
+
+    fn headers(&self) -> ProxyResult<HeaderMap> {
+        let mut headers = HeaderMap::new();
+        headers.insert(CONTENT_TYPE, HeaderValue::from_static("application/json"));
+        if let Some(api_key) = &self.api_key {
+            headers.insert(
+                AUTHORIZATION,
+                HeaderValue::from_str(&format!("Bearer {}", api_key)).map_err(|e| {
+                    ProxyError::ApiKey(format!("Invalid {} API key format: {}", self.name, e))
+                })?,
+            );
+        }
+        for (name, value) in &self.extra_headers {
+            let header_name = HeaderName::from_bytes(name.as_bytes()).map_err(|e| {
+                ProxyError::Config(format!("Invalid header name '{}': {}", name, e))
+            })?;
+            let header_value = HeaderValue::from_str(value).map_err(|e| {
+                ProxyError::Config(format!("Invalid value for header '{}': {}", name, e))
+            })?;
+            headers.insert(header_name, header_value);
+        }
+        Ok(headers)
     }
 }
 
+/// Convert tool definitions to the `{"type": "function", ...}` shape shared by
+/// OpenAI-compatible servers and Ollama
+pub(crate) fn function_tools(tools: &[ToolDefinition]) -> Vec<Value> {
+    tools
+        .iter()
+        .map(|tool| {
+            let mut function = json!({
+                "name": tool.name,
+                "parameters": tool.parameters,
+            });
+            if let Some(description) = &tool.description {
+                function["description"] = json!(description);
+            }
+            json!({ "type": "function", "function": function })
+        })
+        .collect()
+}
+
+fn is_chat_model(id: &str) -> bool {
+    const NON_CHAT: &[&str] = &[
+        "embedding",
+        "whisper",
+        "tts",
+        "dall-e",
+        "moderation",
+        "davinci",
+        "babbage",
+        "transcribe",
+        "image",
+    ];
+    !NON_CHAT.iter().any(|marker| id.contains(marker))
+}
+
+#[derive(Deserialize, Debug)]
+struct OpenAIModelList {
+    data: Vec<OpenAIModel>,
+}
+
+#[derive(Deserialize, Debug)]
+struct OpenAIModel {
+    id: String,
+}
+
 #[derive(Deserialize, Debug)]
 struct OpenAIChatCompletionChunk {
+    // Compatible servers are not consistent about these, so tolerate their absence
+    #[serde(default)]
     id: String,
     #[allow(dead_code)]
+    #[serde(default)]
     object: String,
     #[allow(dead_code)]
+    #[serde(default)]
     created: u64,
     #[allow(dead_code)]
+    #[serde(default)]
     model: String,
     #[allow(dead_code)]
     system_fingerprint: Option<String>,
+    #[serde(default)]
     choices: Vec<OpenAIChoice>,
+    /// Only sent, on a final chunk without choices, when the request asks for it
+    usage: Option<OpenAIUsage>,
 }
 
 #[derive(Deserialize, Debug)]
 struct OpenAIChoice {
     #[allow(dead_code)]
     index: u32,
+    #[serde(default)]
     delta: OpenAIDelta,
     #[allow(dead_code)]
     logprobs: Option<Value>,
     finish_reason: Option<String>,
 }
 
-#[derive(Deserialize, Serialize, Debug, Clone)]
+#[derive(Deserialize, Serialize, Debug, Clone, Default)]
 struct OpenAIDelta {
     role: Option<String>,
     content: Option<String>,
+    tool_calls: Option<Vec<OpenAIToolCallDelta>>,
+}
+
+/// A fragment of a streamed tool call. The first fragment for an index carries the
+/// id and name; later ones carry pieces of the JSON arguments.
+#[derive(Deserialize, Serialize, Debug, Clone, Default)]
+struct OpenAIToolCallDelta {
+    #[serde(default)]
+    index: u32,
+    id: Option<String>,
+    #[serde(default)]
+    function: OpenAIFunctionDelta,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, Default)]
+struct OpenAIFunctionDelta {
+    name: Option<String>,
+    arguments: Option<String>,
+}
+
+#[derive(Deserialize, Debug, Clone, Copy, Default)]
+struct OpenAIUsage {
+    #[serde(default)]
+    prompt_tokens: u64,
+    #[serde(default)]
+    completion_tokens: u64,
+}
+
+/// A tool call being assembled from its streamed fragments
+#[derive(Debug)]
+struct PendingToolCall {
+    id: String,
+    name: String,
+    arguments: String,
+}
+
+/// Turns Chat Completions chunks into data stream parts. Tool calls are announced
+/// as they start, their arguments streamed as they arrive, and the whole call sent
+/// once the choice finishes.
+#[derive(Debug, Default)]
+struct ChatCompletionStream {
+    tool_calls: BTreeMap<u32, PendingToolCall>,
+    finish_reason: Option<String>,
+    usage: OpenAIUsage,
+}
+
+impl ChatCompletionStream {
+    /// The parts to emit for one chunk
+    fn parts(
+        &mut self,
+        provider: &str,
+        chunk: OpenAIChatCompletionChunk,
+    ) -> ProxyResult<Vec<(char, Value)>> {
+        let mut parts = Vec::new();
+        if let Some(usage) = chunk.usage {
+            self.usage = usage;
+        }
+        for choice in chunk.choices {
+            if let Some(content) = choice.delta.content.filter(|c| !c.is_empty()) {
+                parts.push(('0', json!(content)));
+            }
+            for call in choice.delta.tool_calls.unwrap_or_default() {
+                let pending = match self.tool_calls.entry(call.index) {
+                    Entry::Occupied(entry) => entry.into_mut(),
+                    Entry::Vacant(entry) => {
+                        // Some compatible servers leave out the id
+                        let pending = entry.insert(PendingToolCall {
+                            id: call.id.unwrap_or_else(|| generated_tool_call_id(provider)),
+                            name: call.function.name.unwrap_or_default(),
+                            arguments: String::new(),
+                        });
+                        parts.push((
+                            'b',
+                            json!({ "toolCallId": pending.id, "toolName": pending.name }),
+                        ));
+                        pending
+                    }
+                };
+                if let Some(delta) = call.function.arguments.filter(|a| !a.is_empty()) {
+                    parts.push((
+                        'c',
+                        json!({ "toolCallId": pending.id, "argsTextDelta": delta }),
+                    ));
+                    pending.arguments.push_str(&delta);
+                }
+            }
+            if let Some(reason) = choice.finish_reason {
+                debug!("Choice finished with reason: {}", reason);
+                parts.extend(self.complete_tool_calls()?);
+                self.finish_reason = Some(reason);
+            }
+        }
+        Ok(parts)
+    }
+
+    /// The parts closing the stream: any tool calls left open and the finish part
+    fn finish(&mut self) -> ProxyResult<Vec<(char, Value)>> {
+        let mut parts = self.complete_tool_calls()?;
+        if let Some(reason) = self.finish_reason.take() {
+            parts.push((
+                'd',
+                json!({
+                    "finishReason": map_finish_reason(&reason),
+                    "usage": {
+                        "promptTokens": self.usage.prompt_tokens,
+                        "completionTokens": self.usage.completion_tokens,
+                    },
+                }),
+            ));
+        }
+        Ok(parts)
+    }
+
+    fn complete_tool_calls(&mut self) -> ProxyResult<Vec<(char, Value)>> {
+        let mut parts = Vec::new();
+        for call in std::mem::take(&mut self.tool_calls).into_values() {
+            let args: Value = if call.arguments.trim().is_empty() {
+                json!({})
+            } else {
+                serde_json::from_str(&call.arguments)?
+            };
+            parts.push((
+                '9',
+                json!({ "toolCallId": call.id, "toolName": call.name, "args": args }),
+            ));
+        }
+        Ok(parts)
+    }
+}
+
+/// Map Chat Completions finish reasons onto AI SDK finish reasons
+fn map_finish_reason(reason: &str) -> &'static str {
+    match reason {
+        "stop" => "stop",
+        "length" => "length",
+        "tool_calls" | "function_call" => "tool-calls",
+        "content_filter" => "content-filter",
+        _ => "other",
+    }
 }
 
 #[async_trait]
 impl ProxyProvider for OpenAIProvider {
-    async fn stream(&self, window: Window, body: Value) -> ProxyResult<()> {
-        info!("Starting OpenAI stream request");
-        let client = reqwest::Client::new();
-        let mut headers = HeaderMap::new();
-        headers.insert(CONTENT_TYPE, HeaderValue::from_static("application/json"));
-        headers.insert(
-            AUTHORIZATION,
-            HeaderValue::from_str(&format!("Bearer {}", self.api_key))
-                .map_err(|e| ProxyError::ApiKey(format!("Invalid OpenAI API key format: {}", e)))?,
-        );
+    /// List available models, preferring a profile's declared list
+    async fn list_models(&self) -> ProxyResult<Vec<ModelInfo>> {
+        // A profile's own model list takes precedence over querying the endpoint
+        if !self.declared_models.is_empty() {
+            return Ok(self
+                .declared_models
+                .iter()
+                .map(|id| ModelInfo::with_known_capabilities(id.clone(), None, &self.name))
+                .collect());
+        }
+        self.fetch_models().await
+    }
 
-        let response = client
-            .post("https://api.openai.com/v1/chat/completions")
-            .headers(headers)
+    /// Always query the endpoint, so declared models cannot mask a broken profile
+    async fn probe(&self) -> ProxyResult<usize> {
+        Ok(self.fetch_models().await?.len())
+    }
+
+    fn build_request(&self, request: &ChatRequest) -> ProxyResult<Value> {
+        if self.uses_responses_api(&request.model) {
+            return openai_responses::build_request(
+                request,
+                &self.attachment_support("the Responses API", true),
+            );
+        }
+        attachments::check_support(request, &self.attachment_support("Chat Completions", false))?;
+        if !request.builtin_tools.is_empty() {
+            return Err(invalid(format!(
+                "Built-in tools require the Responses API, which {} does not use for {}",
+                self.name, request.model
+            )));
+        }
+
+        let mut messages = Vec::new();
+        if let Some(system) = request.system_prompt() {
+            messages.push(json!({ "role": "system", "content": system }));
+        }
+
+        for message in request.conversation() {
+            match message.role {
+                ChatRole::Tool => {
+                    // Each tool result is its own message in Chat Completions
+                    for part in &message.content {
+                        if let ContentPart::ToolResult {
+                            tool_call_id,
+                            content,
+                            ..
+                        } = part
+                        {
+                            messages.push(json!({
+                                "role": "tool",
+                                "tool_call_id": tool_call_id,
+                                "content": tool_result_text(content),
+                            }));
+                        }
+                    }
+                }
+                ChatRole::Assistant => {
+                    let text = message.text();
+                    let tool_calls: Vec<Value> = message
+                        .content
+                        .iter()
+                        .filter_map(|part| match part {
+                            ContentPart::ToolCall {
+                                id,
+                                name,
+                                arguments,
+                            } => Some(json!({
+                                "id": id,
+                                "type": "function",
+                                "function": {
+                                    "name": name,
+                                    "arguments": if arguments.is_null() {
+                                        "{}".to_string()
+                                    } else {
+                                        arguments.to_string()
+                                    },
+                                },
+                            })),
+                            _ => None,
+                        })
+                        .collect();
+                    let mut assistant = json!({
+                        "role": "assistant",
+                        "content": if text.is_empty() { Value::Null } else { json!(text) },
+                    });
+                    if !tool_calls.is_empty() {
+                        assistant["tool_calls"] = Value::Array(tool_calls);
+                    }
+                    messages.push(assistant);
+                }
+                _ => {
+                    if !message
+                        .content
+                        .iter()
+                        .any(|p| matches!(p, ContentPart::Attachment { .. }))
+                    {
+                        messages.push(json!({ "role": "user", "content": message.text() }));
+                        continue;
+                    }
+                    // With attachments the content becomes an ordered list of parts
+                    let mut parts = Vec::new();
+                    for part in &message.content {
+                        match part {
+                            ContentPart::Text { text } => {
+                                parts.push(json!({ "type": "text", "text": text }))
+                            }
+                            ContentPart::Attachment { path, media_type } => {
+                                let attachment = attachments::load(path, media_type.as_deref())?;
+                                parts.push(json!({
+                                    "type": "image_url",
+                                    "image_url": { "url": attachment.data_url() },
+                                }));
+                            }
+                            _ => {}
+                        }
+                    }
+                    messages.push(json!({ "role": "user", "content": parts }));
+                }
+            }
+        }
+
+        let mut body = Map::new();
+        body.insert("model".to_string(), json!(request.model));
+        body.insert("messages".to_string(), Value::Array(messages));
+        body.insert("stream".to_string(), Value::Bool(true));
+        // Compatible servers may reject stream options they do not know
+        if self.base_url == OPENAI_BASE_URL {
+            body.insert(
+                "stream_options".to_string(),
+                json!({ "include_usage": true }),
+            );
+        }
+        if let Some(max_tokens) = request.max_tokens {
+            body.insert("max_tokens".to_string(), json!(max_tokens));
+        }
+        if let Some(temperature) = request.temperature {
+            openai_responses::check_temperature(&request.model)?;
+            body.insert("temperature".to_string(), json!(temperature));
+        }
+        if !request.stop.is_empty() {
+            body.insert("stop".to_string(), json!(request.stop));
+        }
+        if !request.tools.is_empty() {
+            body.insert(
+                "tools".to_string(),
+                Value::Array(function_tools(&request.tools)),
+            );
+        }
+
+        Ok(Value::Object(body))
+    }
+
+    async fn complete(&self, mut body: Value) -> ProxyResult<String> {
+        body["stream"] = Value::Bool(false);
+        if let Some(body) = body.as_object_mut() {
+            body.remove("stream_options");
+        }
+        let responses_api = body.get("input").is_some();
+        let url = if responses_api {
+            format!("{}/responses", self.base_url)
+        } else {
+            format!("{}/chat/completions", self.base_url)
+        };
+        let response = self
+            .client
+            .post(&url)
+            .headers(self.headers()?)
             .json(&body)
             .send()
             .await?;
+        if !response.status().is_success() {
+            return Err(ProxyError::Provider(read_error(&self.name, response).await));
+        }
+
+        let completion: Value = response.json().await?;
+        if responses_api {
+            return Ok(openai_responses::output_text(&completion));
+        }
+        Ok(completion["choices"][0]["message"]["content"]
+            .as_str()
+            .unwrap_or_default()
+            .to_string())
+    }
+
+    async fn stream(&self, window: Window, body: Value) -> ProxyResult<()> {
+        info!("Starting {} stream request", self.name);
+        let client = &self.client;
+        let headers = self.headers()?;
+
+        // Responses API bodies carry `input` where Chat Completions has `messages`
+        let responses_api = body.get("input").is_some();
+        let url = if responses_api {
+            format!("{}/responses", self.base_url)
+        } else {
+            format!("{}/chat/completions", self.base_url)
+        };
+        let response =
+            send_with_retry(&window, &self.name, &RetryPolicy::default(), &mut 0, || {
+                client.post(&url).headers(headers.clone()).json(&body)
+            })
+            .await?;
 
         let status = response.status();
         if !status.is_success() {
-            let error_body = response
-                .text()
-                .await
-                .unwrap_or_else(|_| "Failed to read error body".to_string());
-            let error_msg = format!(
-                "OpenAI API request failed with status {}: {}",
-                status, error_body
-            );
-            emit_error(&window, &error_msg)?;
-            return Err(ProxyError::Status(status.as_u16()));
+            let err = read_error(&self.name, response).await;
+            emit_provider_error(&window, &err)?;
+            return Err(ProxyError::Provider(err));
+        }
+        info!("{} API request successful (status: {})", self.name, status);
+
+        if responses_api {
+            return openai_responses::stream_events(&window, &self.name, response).await;
         }
-        info!("OpenAI API request successful (status: {})", status);
 
         let mut stream = response.bytes_stream();
-        let mut buffer = String::new();
+        let mut sse = SseBuffer::default();
+        let mut state = ChatCompletionStream::default();
 
         debug!("Starting to process OpenAI stream");
         while let Some(item) = stream.next().await {
-            match item {
-                Ok(chunk) => {
-                    debug!("Received raw bytes chunk: {} bytes", chunk.len());
-                    match String::from_utf8(chunk.to_vec()) {
-                        Ok(chunk_string) => {
-                            buffer.push_str(&chunk_string);
-
-                            while let Some(pos) = buffer.find("\n\n") {
-                                let event_data = buffer[..pos].trim().to_string();
-                                buffer = buffer[pos + 2..].to_string(); // Skip "\n\n"
-
-                                for line in event_data.lines() {
-                                    if let Some(json_str) = line.strip_prefix("data: ") {
-                                        if json_str.trim() == "[DONE]" {
-                                            debug!("OpenAI [DONE] signal received");
-                                            continue;
-                                        }
-
-                                        match serde_json::from_str::<OpenAIChatCompletionChunk>(
-                                            json_str,
-                                        ) {
-                                            Ok(chunk_event) => {
-                                                debug!(
-                                                    "Processing chunk event ID: {}",
-                                                    chunk_event.id
-                                                );
-
-                                                for choice in chunk_event.choices {
-                                                    if let Some(content) = choice.delta.content {
-                                                        if !content.is_empty() {
-                                                            let text_json =
-                                                                serde_json::to_string(&content)
-                                                                    .map_err(ProxyError::Parse)?;
-                                                            emit_chunk(
-                                                                &window,
-                                                                format!("0:{}\n", text_json),
-                                                            )?;
-                                                        }
-                                                    }
-
-                                                    if let Some(reason) = choice.finish_reason {
-                                                        debug!(
-                                                            "Choice finished with reason: {}",
-                                                            reason
-                                                        );
-                                                    }
-                                                }
-                                            }
-                                            Err(e) => {
-                                                warn!("Failed to parse chunk event: {}", e);
-                                                emit_error(
-                                                    &window,
-                                                    format!("Failed to parse OpenAI JSON: {}", e),
-                                                )?;
-                                            }
-                                        }
-                                    }
-                                }
-                            }
-                        }
-                        Err(e) => {
-                            let error_msg = format!("Failed to decode chunk as UTF-8: {}", e);
-                            error!("{}", error_msg);
-                            emit_error(&window, &error_msg)?;
-                        }
-                    }
-                }
+            let chunk = match item {
+                Ok(chunk) => chunk,
                 Err(e) => {
                     let error_msg = format!("Error reading stream chunk: {}", e);
                     error!("{}", error_msg);
                     emit_error(&window, &error_msg)?;
                     return Err(ProxyError::Http(e));
                 }
+            };
+            debug!("Received raw bytes chunk: {} bytes", chunk.len());
+            sse.push(&chunk);
+
+            while let Some(data) = sse.next_data() {
+                if data.trim() == "[DONE]" {
+                    debug!("OpenAI [DONE] signal received");
+                    continue;
+                }
+                match serde_json::from_str::<OpenAIChatCompletionChunk>(&data) {
+                    Ok(chunk_event) => {
+                        debug!("Processing chunk event ID: {}", chunk_event.id);
+                        for (code, value) in state.parts(&self.name, chunk_event)? {
+                            emit_part(&window, code, &value)?;
+                        }
+                    }
+                    // Errors after the stream opened arrive as `{"error": {...}}`
+                    Err(_) if data.contains("\"error\"") => {
+                        let err = ProviderError::parse(&self.name, None, &HeaderMap::new(), &data);
+                        emit_provider_error(&window, &err)?;
+                        return Err(ProxyError::Provider(err));
+                    }
+                    Err(e) => {
+                        warn!("Failed to parse chunk event: {}", e);
+                        emit_error(&window, format!("Failed to parse OpenAI JSON: {}", e))?;
+                    }
+                }
             }
         }
 
-        info!("OpenAI stream completed");
+        for (code, value) in state.finish()? {
+            emit_part(&window, code, &value)?;
+        }
+        info!("{} stream completed", self.name);
         emit_end(&window)?;
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{BufRead, BufReader, Read, Write};
+    use std::net::TcpListener;
+    use std::sync::mpsc;
+    use std::thread;
+
+    /// A request as the mock server received it
+    struct Recorded {
+        request_line: String,
+        headers: HashMap<String, String>,
+        body: String,
+    }
+
+    /// Serve `responses` in order, one per connection, on a local port. Returns the
+    /// base URL and the requests received.
+    fn serve(responses: Vec<(u16, &'static str)>) -> (String, mpsc::Receiver<Recorded>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let base_url = format!("http://{}/v1", listener.local_addr().unwrap());
+        let (tx, rx) = mpsc::channel();
+        thread::spawn(move || {
+            for (status, body) in responses {
+                let (stream, _) = listener.accept().unwrap();
+                let mut reader = BufReader::new(stream);
+                let mut request_line = String::new();
+                reader.read_line(&mut request_line).unwrap();
+                let mut headers = HashMap::new();
+                loop {
+                    let mut line = String::new();
+                    reader.read_line(&mut line).unwrap();
+                    let line = line.trim_end();
+                    if line.is_empty() {
+                        break;
+                    }
+                    if let Some((name, value)) = line.split_once(':') {
+                        headers.insert(name.to_ascii_lowercase(), value.trim().to_string());
+                    }
+                }
+                let length = headers
+                    .get("content-length")
+                    .and_then(|len| len.parse().ok())
+                    .unwrap_or(0);
+                let mut request_body = vec![0; length];
+                reader.read_exact(&mut request_body).unwrap();
+
+                let mut stream = reader.into_inner();
+                write!(
+                    stream,
+                    "HTTP/1.1 {} Mock\r\nContent-Type: application/json\r\n\
+                     Content-Length: {}\r\nConnection: close\r\n\r\n{}",
+                    status,
+                    body.len(),
+                    body
+                )
+                .unwrap();
+                tx.send(Recorded {
+                    request_line: request_line.trim_end().to_string(),
+                    headers,
+                    body: String::from_utf8(request_body).unwrap(),
+                })
+                .unwrap();
+            }
+        });
+        (base_url, rx)
+    }
+
+    fn profile(base_url: &str, models: &[&str]) -> ProviderProfile {
+        ProviderProfile {
+            name: "local".to_string(),
+            base_url: format!("{}/", base_url),
+            api_key_env: None,
+            headers: HashMap::from([("X-Title".to_string(), "pqp".to_string())]),
+            models: models.iter().map(|m| m.to_string()).collect(),
+            responses_models: Vec::new(),
+            vision_models: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn lists_models_from_the_profile_endpoint() {
+        let (base_url, requests) = serve(vec![(
+            200,
+            r#"{"object":"list","data":[{"id":"llama3"},{"id":"text-embedding-3-small"}]}"#,
+        )]);
+        let provider = OpenAIProvider::from_profile(
+            reqwest::Client::new(),
+            &profile(&base_url, &[]),
+            Some("sk-local".to_string()),
+        );
+
+        let models = tauri::async_runtime::block_on(provider.list_models()).unwrap();
+        // Only the official endpoint's listing is filtered down to chat models
+        let ids: Vec<&str> = models.iter().map(|m| m.id.as_str()).collect();
+        assert_eq!(ids, ["llama3", "text-embedding-3-small"]);
+        assert!(models.iter().all(|m| m.provider == "local"));
+
+        let request = requests.recv().unwrap();
+        assert_eq!(request.request_line, "GET /v1/models HTTP/1.1");
+        assert_eq!(request.headers["authorization"], "Bearer sk-local");
+        assert_eq!(request.headers["x-title"], "pqp");
+    }
+
+    #[test]
+    fn declared_models_skip_the_endpoint_but_probe_does_not() {
+        let (base_url, requests) = serve(vec![(200, r#"{"data":[{"id":"served"}]}"#)]);
+        let provider = OpenAIProvider::from_profile(
+            reqwest::Client::new(),
+            &profile(&base_url, &["declared-a", "declared-b"]),
+            None,
+        );
+
+        let models = tauri::async_runtime::block_on(provider.list_models()).unwrap();
+        let ids: Vec<&str> = models.iter().map(|m| m.id.as_str()).collect();
+        assert_eq!(ids, ["declared-a", "declared-b"]);
+        assert!(requests.try_recv().is_err());
+
+        assert_eq!(tauri::async_runtime::block_on(provider.probe()).unwrap(), 1);
+        let request = requests.recv().unwrap();
+        assert!(!request.headers.contains_key("authorization"));
+    }
+
+    #[test]
+    fn completes_through_chat_completions() {
+        let (base_url, requests) = serve(vec![(
+            200,
+            r#"{"choices":[{"index":0,"message":{"role":"assistant","content":"Hello there"}}]}"#,
+        )]);
+        let provider =
+            OpenAIProvider::from_profile(reqwest::Client::new(), &profile(&base_url, &[]), None);
+
+        let body = json!({ "model": "llama3", "messages": [], "stream": true });
+        let text = tauri::async_runtime::block_on(provider.complete(body)).unwrap();
+        assert_eq!(text, "Hello there");
+
+        let request = requests.recv().unwrap();
+        assert_eq!(request.request_line, "POST /v1/chat/completions HTTP/1.1");
+        let sent: Value = serde_json::from_str(&request.body).unwrap();
+        assert_eq!(sent["stream"], json!(false));
+        assert_eq!(sent["model"], json!("llama3"));
+    }
+
+    #[test]
+    fn endpoint_errors_become_provider_errors() {
+        let (base_url, _requests) = serve(vec![(
+            401,
+            r#"{"error":{"message":"Invalid API key","type":"invalid_request_error","code":"invalid_api_key"}}"#,
+        )]);
+        let provider = OpenAIProvider::from_profile(
+            reqwest::Client::new(),
+            &profile(&base_url, &[]),
+            Some("wrong".to_string()),
+        );
+
+        match tauri::async_runtime::block_on(provider.probe()) {
+            Err(ProxyError::Provider(err)) => {
+                assert_eq!(err.provider, "local");
+                assert_eq!(err.status, Some(401));
+                assert_eq!(err.error_type, "invalid_api_key");
+                assert_eq!(err.message, "Invalid API key");
+            }
+            other => panic!("expected a provider error, got {:?}", other.map(|_| ())),
+        }
+    }
+
+    fn chat_request(model: &str, temperature: Option<f32>) -> ChatRequest {
+        serde_json::from_value(json!({
+            "model": model,
+            "messages": [{ "role": "user", "content": "Hi" }],
+            "temperature": temperature,
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn builds_chat_messages_with_roles_mapped() {
+        let request: ChatRequest = serde_json::from_value(json!({
+            "model": "llama3",
+            "system": "Be brief.",
+            "messages": [
+                { "role": "user", "content": "Weather in Oslo?" },
+                { "role": "assistant", "content": [
+                    { "type": "tool_call", "id": "call_1", "name": "weather" },
+                    { "type": "tool_call", "id": "call_2", "name": "time",
+                      "arguments": { "zone": "CET" } },
+                ] },
+                { "role": "tool", "content": [
+                    { "type": "tool_result", "tool_call_id": "call_1", "content": "4C" },
+                    { "type": "tool_result", "tool_call_id": "call_2", "content": { "h": 9 } },
+                ] },
+            ],
+            "tools": [{
+                "name": "weather",
+                "description": "Current weather",
+                "parameters": { "type": "object" },
+            }],
+            "max_tokens": 200,
+            "stop": ["END"],
+        }))
+        .unwrap();
+        let provider = OpenAIProvider::from_profile(
+            reqwest::Client::new(),
+            &profile("http://localhost:8080/v1", &[]),
+            None,
+        );
+
+        let body = provider.build_request(&request).unwrap();
+        assert_eq!(
+            body,
+            json!({
+                "model": "llama3",
+                "stream": true,
+                "messages": [
+                    { "role": "system", "content": "Be brief." },
+                    { "role": "user", "content": "Weather in Oslo?" },
+                    {
+                        "role": "assistant",
+                        "content": null,
+                        "tool_calls": [
+                            {
+                                "id": "call_1",
+                                "type": "function",
+                                "function": { "name": "weather", "arguments": "{}" },
+                            },
+                            {
+                                "id": "call_2",
+                                "type": "function",
+                                "function": { "name": "time", "arguments": "{\"zone\":\"CET\"}" },
+                            },
+                        ],
+                    },
+                    // Each tool result is a message of its own
+                    { "role": "tool", "tool_call_id": "call_1", "content": "4C" },
+                    { "role": "tool", "tool_call_id": "call_2", "content": "{\"h\":9}" },
+                ],
+                "max_tokens": 200,
+                "stop": ["END"],
+                "tools": [{
+                    "type": "function",
+                    "function": {
+                        "name": "weather",
+                        "description": "Current weather",
+                        "parameters": { "type": "object" },
+                    },
+                }],
+            })
+        );
+    }
+
+    #[test]
+    fn official_models_follow_the_configured_api() {
+        let settings = OpenAISettings {
+            model_apis: HashMap::from([
+                ("o3".to_string(), OpenAIApi::ChatCompletions),
+                ("gpt-4.1".to_string(), OpenAIApi::Responses),
+            ]),
+        };
+        let provider = OpenAIProvider::new(reqwest::Client::new(), "sk".to_string(), &settings);
+
+        // Without a setting, reasoning models default to the Responses API
+        assert!(provider.uses_responses_api("o4-mini"));
+        assert!(!provider.uses_responses_api("gpt-4o"));
+        assert!(!provider.uses_responses_api("o3"));
+        assert!(provider.uses_responses_api("gpt-4.1"));
+
+        let body = provider.build_request(&chat_request("o3", None)).unwrap();
+        assert!(body.get("messages").is_some());
+        let body = provider
+            .build_request(&chat_request("gpt-4.1", None))
+            .unwrap();
+        assert!(body.get("input").is_some());
+    }
+
+    #[test]
+    fn reasoning_models_reject_temperature_on_both_apis() {
+        let settings = OpenAISettings {
+            model_apis: HashMap::from([("o3".to_string(), OpenAIApi::ChatCompletions)]),
+        };
+        let provider = OpenAIProvider::new(reqwest::Client::new(), "sk".to_string(), &settings);
+
+        for model in ["o3", "o4-mini", "gpt-5"] {
+            assert!(matches!(
+                provider.build_request(&chat_request(model, Some(0.2))),
+                Err(ProxyError::Validation(_))
+            ));
+            assert!(provider.build_request(&chat_request(model, None)).is_ok());
+        }
+        for model in ["gpt-4o", "gpt-5-chat-latest"] {
+            let body = provider
+                .build_request(&chat_request(model, Some(0.2)))
+                .unwrap();
+            assert!(body.get("temperature").is_some());
+        }
+    }
+
+    fn stream_parts(chunks: &[&str]) -> Vec<(char, Value)> {
+        let mut state = ChatCompletionStream::default();
+        let mut parts = Vec::new();
+        for chunk in chunks {
+            parts.extend(
+                state
+                    .parts("local", serde_json::from_str(chunk).unwrap())
+                    .unwrap(),
+            );
+        }
+        parts.extend(state.finish().unwrap());
+        parts
+    }
+
+    #[test]
+    fn streams_text_and_the_finish_part() {
+        let parts = stream_parts(&[
+            r#"{"choices":[{"index":0,"delta":{"role":"assistant","content":""}}]}"#,
+            r#"{"choices":[{"index":0,"delta":{"content":"Hel"}}]}"#,
+            r#"{"choices":[{"index":0,"delta":{"content":"lo"}}]}"#,
+            r#"{"choices":[{"index":0,"delta":{},"finish_reason":"length"}]}"#,
+            r#"{"choices":[],"usage":{"prompt_tokens":9,"completion_tokens":2}}"#,
+        ]);
+
+        assert_eq!(
+            parts,
+            [
+                ('0', json!("Hel")),
+                ('0', json!("lo")),
+                (
+                    'd',
+                    json!({
+                        "finishReason": "length",
+                        "usage": { "promptTokens": 9, "completionTokens": 2 },
+                    })
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn assembles_tool_calls_from_their_fragments() {
+        let parts = stream_parts(&[
+            r#"{"choices":[{"index":0,"delta":{"role":"assistant","tool_calls":[
+                {"index":0,"id":"call_a","type":"function",
+                 "function":{"name":"weather","arguments":""}}
+            ]}}]}"#,
+            r#"{"choices":[{"index":0,"delta":{"tool_calls":[
+                {"index":0,"function":{"arguments":"{\"city\":"}}
+            ]}}]}"#,
+            r#"{"choices":[{"index":0,"delta":{"tool_calls":[
+                {"index":1,"id":"call_b","type":"function",
+                 "function":{"name":"time","arguments":"{}"}}
+            ]}}]}"#,
+            r#"{"choices":[{"index":0,"delta":{"tool_calls":[
+                {"index":0,"function":{"arguments":"\"Oslo\"}"}}
+            ]}}]}"#,
+            r#"{"choices":[{"index":0,"delta":{},"finish_reason":"tool_calls"}]}"#,
+        ]);
+
+        assert_eq!(
+            parts,
+            [
+                (
+                    'b',
+                    json!({ "toolCallId": "call_a", "toolName": "weather" })
+                ),
+                (
+                    'c',
+                    json!({ "toolCallId": "call_a", "argsTextDelta": "{\"city\":" })
+                ),
+                ('b', json!({ "toolCallId": "call_b", "toolName": "time" })),
+                (
+                    'c',
+                    json!({ "toolCallId": "call_b", "argsTextDelta": "{}" })
+                ),
+                (
+                    'c',
+                    json!({ "toolCallId": "call_a", "argsTextDelta": "\"Oslo\"}" })
+                ),
+                (
+                    '9',
+                    json!({
+                        "toolCallId": "call_a",
+                        "toolName": "weather",
+                        "args": { "city": "Oslo" },
+                    })
+                ),
+                (
+                    '9',
+                    json!({ "toolCallId": "call_b", "toolName": "time", "args": {} })
+                ),
+                (
+                    'd',
+                    json!({
+                        "finishReason": "tool-calls",
+                        "usage": { "promptTokens": 0, "completionTokens": 0 },
+                    })
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn tool_calls_without_an_id_get_one() {
+        let parts = stream_parts(&[r#"{"choices":[{"index":0,"delta":{"tool_calls":[
+                {"index":0,"function":{"name":"time","arguments":""}}
+            ]},"finish_reason":"tool_calls"}]}"#]);
+
+        let id = parts[0].1["toolCallId"].as_str().unwrap();
+        assert!(id.starts_with("local-call-"));
+        assert_eq!(parts[1].0, '9');
+        assert_eq!(parts[1].1["toolCallId"], json!(id));
+        assert_eq!(parts[1].1["args"], json!({}));
+    }
+
+    #[test]
+    fn only_the_official_endpoint_asks_for_stream_usage() {
+        let official = OpenAIProvider::new(
+            reqwest::Client::new(),
+            "sk".to_string(),
+            &Default::default(),
+        );
+        let body = official
+            .build_request(&chat_request("gpt-4o", None))
+            .unwrap();
+        assert_eq!(body["stream_options"], json!({ "include_usage": true }));
+
+        let local = OpenAIProvider::from_profile(
+            reqwest::Client::new(),
+            &profile("http://localhost:8080/v1", &[]),
+            None,
+        );
+        let body = local.build_request(&chat_request("llama3", None)).unwrap();
+        assert!(body.get("stream_options").is_none());
+    }
+}
@@ -0,0 +1,539 @@
+use crate::services::proxy::attachments::{self, AttachmentKind, AttachmentSupport};
+use crate::services::proxy::request::{invalid, tool_result_text};
+use crate::services::proxy::sse::SseBuffer;
+use crate::services::proxy::{emit_end, emit_error, emit_part, emit_provider_error};
+use crate::services::proxy::{
+    ChatRequest, ChatRole, ContentPart, ProviderError, ProxyError, ProxyResult,
+};
+use futures_util::StreamExt;
+use log::{debug, error, info};
+use serde::Deserialize;
+use serde_json::{json, Map, Value};
+use std::collections::HashMap;
+use tauri::Window;
+use tauri_plugin_http::reqwest::Response;
+
+/// Models with internal reasoning, which accept `reasoning` options. The
+/// `gpt-5-chat` models are the non-reasoning ones of their family.
+pub(crate) fn is_reasoning_model(model: &str) -> bool {
+    ["o1", "o3", "o4", "gpt-5", "codex"]
+        .iter()
+        .any(|prefix| model.starts_with(prefix))
+        && !model.contains("-chat")
+}
+
+/// Reasoning models reject sampling parameters on both APIs, so a request that
+/// sets `temperature` for one fails before it is sent
+pub(crate) fn check_temperature(model: &str) -> ProxyResult<()> {
+    if is_reasoning_model(model) {
+        return Err(invalid(format!(
+            "temperature is not supported by reasoning model {}",
+            model
+        )));
+    }
+    Ok(())
+}
+
+/// Official OpenAI models sent through the Responses API unless configured
+/// otherwise: reasoning models, whose summaries are only available there, and
+/// models that only exist on it
+pub(crate) fn prefers_responses_api(model: &str) -> bool {
+    is_reasoning_model(model) || model.starts_with("computer-use")
+}
+
+/// Map a thinking budget onto the coarser reasoning effort levels
+fn reasoning_effort(budget_tokens: u32) -> &'static str {
+    match budget_tokens {
+        0..=4095 => "low",
+        4096..=16383 => "medium",
+        _ => "high",
+    }
+}
+
+/// Build a `/responses` request. Its `input` field is what tells `stream` which
+/// endpoint the body belongs to.
+pub(crate) fn build_request(
+    request: &ChatRequest,
+    support: &AttachmentSupport,
+) -> ProxyResult<Value> {
+    attachments::check_support(request, support)?;
+
+    let mut input = Vec::new();
+    for message in request.conversation() {
+        match message.role {
+            ChatRole::Tool => {
+                for part in &message.content {
+                    if let ContentPart::ToolResult {
+                        tool_call_id,
+                        content,
+                        ..
+                    } = part
+                    {
+                        input.push(json!({
+                            "type": "function_call_output",
+                            "call_id": tool_call_id,
+                            "output": tool_result_text(content),
+                        }));
+                    }
+                }
+            }
+            ChatRole::Assistant => {
+                let text = message.text();
+                if !text.is_empty() {
+                    input.push(json!({ "role": "assistant", "content": text }));
+                }
+                // Function calls are separate input items, after the text that introduced them
+                for part in &message.content {
+                    if let ContentPart::ToolCall {
+                        id,
+                        name,
+                        arguments,
+                    } = part
+                    {
+                        input.push(json!({
+                            "type": "function_call",
+                            "call_id": id,
+                            "name": name,
+                            "arguments": if arguments.is_null() {
+                                "{}".to_string()
+                            } else {
+                                arguments.to_string()
+                            },
+                        }));
+                    }
+                }
+            }
+            _ => {
+                let mut content = Vec::new();
+                for part in &message.content {
+                    match part {
+                        ContentPart::Text { text } => {
+                            content.push(json!({ "type": "input_text", "text": text }))
+                        }
+                        ContentPart::Attachment { path, media_type } => {
+                            let attachment = attachments::load(path, media_type.as_deref())?;
+                            content.push(match attachment.kind {
+                                AttachmentKind::Image => json!({
+                                    "type": "input_image",
+                                    "image_url": attachment.data_url(),
+                                }),
+                                AttachmentKind::Document => json!({
+                                    "type": "input_file",
+                                    "filename": file_name(path),
+                                    "file_data": attachment.data_url(),
+                                }),
+                            });
+                        }
+                        _ => {}
+                    }
+                }
+                input.push(json!({ "role": "user", "content": content }));
+            }
+        }
+    }
+
+    let mut body = Map::new();
+    body.insert("model".to_string(), json!(request.model));
+    body.insert("input".to_string(), Value::Array(input));
+    body.insert("stream".to_string(), Value::Bool(true));
+    // Conversations are replayed in full on every turn, so nothing needs storing server-side
+    body.insert("store".to_string(), Value::Bool(false));
+    if let Some(system) = request.system_prompt() {
+        body.insert("instructions".to_string(), json!(system));
+    }
+    if let Some(max_tokens) = request.max_tokens {
+        body.insert("max_output_tokens".to_string(), json!(max_tokens));
+    }
+    if let Some(temperature) = request.temperature {
+        check_temperature(&request.model)?;
+        body.insert("temperature".to_string(), json!(temperature));
+    }
+    if !request.stop.is_empty() {
+        return Err(invalid(
+            "stop sequences are not supported by the OpenAI Responses API",
+        ));
+    }
+    if is_reasoning_model(&request.model) {
+        let mut reasoning = json!({ "summary": "auto" });
+        if let Some(thinking) = &request.thinking {
+            reasoning["effort"] = json!(reasoning_effort(thinking.budget_tokens));
+        }
+        body.insert("reasoning".to_string(), reasoning);
+    }
+
+    let mut tools: Vec<Value> = request
+        .tools
+        .iter()
+        .map(|tool| {
+            let mut function = json!({
+                "type": "function",
+                "name": tool.name,
+                "parameters": tool.parameters,
+            });
+            if let Some(description) = &tool.description {
+                function["description"] = json!(description);
+            }
+            function
+        })
+        .collect();
+    tools.extend(request.builtin_tools.iter().cloned());
+    if !tools.is_empty() {
+        body.insert("tools".to_string(), Value::Array(tools));
+    }
+
+    Ok(Value::Object(body))
+}
+
+fn file_name(path: &str) -> String {
+    std::path::Path::new(path)
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_else(|| "document.pdf".to_string())
+}
+
+/// Text of a non-streamed response: the `output_text` parts of its message items
+pub(crate) fn output_text(response: &Value) -> String {
+    response["output"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter(|item| item["type"] == "message")
+        .filter_map(|item| item["content"].as_array())
+        .flatten()
+        .filter(|part| part["type"] == "output_text")
+        .filter_map(|part| part["text"].as_str())
+        .collect()
+}
+
+#[derive(Deserialize, Debug)]
+struct ResponsesEvent {
+    #[serde(rename = "type")]
+    event_type: String,
+    #[serde(default)]
+    delta: Option<String>,
+    #[serde(default)]
+    item_id: Option<String>,
+    #[serde(default)]
+    item: Option<ResponsesItem>,
+    #[serde(default)]
+    response: Option<ResponsesResponse>,
+    #[serde(default)]
+    code: Option<String>,
+    #[serde(default)]
+    message: Option<String>,
+}
+
+#[derive(Deserialize, Debug)]
+struct ResponsesItem {
+    #[serde(rename = "type")]
+    item_type: String,
+    #[serde(default)]
+    id: Option<String>,
+    #[serde(default)]
+    call_id: Option<String>,
+    #[serde(default)]
+    name: Option<String>,
+    #[serde(default)]
+    arguments: Option<String>,
+}
+
+#[derive(Deserialize, Debug)]
+struct ResponsesResponse {
+    #[serde(default)]
+    status: Option<String>,
+    #[serde(default)]
+    usage: Option<ResponsesUsage>,
+    #[serde(default)]
+    error: Option<ResponsesError>,
+}
+
+#[derive(Deserialize, Debug, Default)]
+struct ResponsesUsage {
+    #[serde(default)]
+    input_tokens: u64,
+    #[serde(default)]
+    output_tokens: u64,
+}
+
+#[derive(Deserialize, Debug)]
+struct ResponsesError {
+    #[serde(default)]
+    code: Option<String>,
+    message: String,
+}
+
+/// Translate the typed Responses SSE events into AI SDK data stream parts
+pub(crate) async fn stream_events(
+    window: &Window,
+    provider: &str,
+    response: Response,
+) -> ProxyResult<()> {
+    let mut stream = response.bytes_stream();
+    let mut sse = SseBuffer::default();
+    // Argument deltas reference the output item id, the AI SDK expects the call id
+    let mut call_ids: HashMap<String, String> = HashMap::new();
+    let mut called_tools = false;
+
+    debug!("Starting to process {} Responses stream", provider);
+    while let Some(item) = stream.next().await {
+        let chunk = match item {
+            Ok(chunk) => chunk,
+            Err(e) => {
+                let error_msg = format!("Error reading stream chunk: {}", e);
+                error!("{}", error_msg);
+                emit_error(window, &error_msg)?;
+                return Err(ProxyError::Http(e));
+            }
+        };
+        sse.push(&chunk);
+
+        while let Some(data) = sse.next_data() {
+            let event = match serde_json::from_str::<ResponsesEvent>(&data) {
+                Ok(event) => event,
+                Err(e) => {
+                    debug!("Skipping unparseable Responses event: {}", e);
+                    continue;
+                }
+            };
+
+            match event.event_type.as_str() {
+                "response.output_text.delta" => {
+                    if let Some(delta) = event.delta.filter(|d| !d.is_empty()) {
+                        emit_part(window, '0', &delta)?;
+                    }
+                }
+                "response.reasoning_summary_text.delta" => {
+                    if let Some(delta) = event.delta.filter(|d| !d.is_empty()) {
+                        emit_part(window, 'g', &delta)?;
+                    }
+                }
+                "response.reasoning_summary_part.done" => {
+                    // Keep separate summary paragraphs apart
+                    emit_part(window, 'g', &"\n\n")?;
+                }
+                "response.output_item.added" => {
+                    if let Some(ResponsesItem {
+                        item_type,
+                        id: Some(id),
+                        call_id: Some(call_id),
+                        name,
+                        ..
+                    }) = event.item
+                    {
+                        if item_type == "function_call" {
+                            emit_part(
+                                window,
+                                'b',
+                                &json!({ "toolCallId": call_id, "toolName": name }),
+                            )?;
+                            call_ids.insert(id, call_id);
+                        }
+                    }
+                }
+                "response.function_call_arguments.delta" => {
+                    let call_id = event.item_id.and_then(|id| call_ids.get(&id).cloned());
+                    if let (Some(call_id), Some(delta)) = (call_id, event.delta) {
+                        emit_part(
+                            window,
+                            'c',
+                            &json!({ "toolCallId": call_id, "argsTextDelta": delta }),
+                        )?;
+                    }
+                }
+                "response.output_item.done" => {
+                    let Some(item) = event.item else { continue };
+                    if item.item_type == "function_call" {
+                        let args: Value = item
+                            .arguments
+                            .as_deref()
+                            .filter(|a| !a.trim().is_empty())
+                            .map(serde_json::from_str)
+                            .transpose()?
+                            .unwrap_or_else(|| json!({}));
+                        emit_part(
+                            window,
+                            '9',
+                            &json!({
+                                "toolCallId": item.call_id,
+                                "toolName": item.name,
+                                "args": args,
+                            }),
+                        )?;
+                        called_tools = true;
+                    } else {
+                        // Built-in tools (web search, file search, ...) run server-side;
+                        // their findings arrive as output text
+                        debug!("Output item done: {}", item.item_type);
+                    }
+                }
+                "response.completed" | "response.incomplete" => {
+                    let response = event.response;
+                    let usage = response
+                        .as_ref()
+                        .and_then(|r| r.usage.as_ref())
+                        .map(|u| (u.input_tokens, u.output_tokens))
+                        .unwrap_or_default();
+                    let finish_reason = match response.and_then(|r| r.status).as_deref() {
+                        Some("incomplete") => "length",
+                        _ if called_tools => "tool-calls",
+                        _ => "stop",
+                    };
+                    emit_part(
+                        window,
+                        'd',
+                        &json!({
+                            "finishReason": finish_reason,
+                            "usage": {
+                                "promptTokens": usage.0,
+                                "completionTokens": usage.1,
+                            },
+                        }),
+                    )?;
+                }
+                "response.failed" | "error" => {
+                    let (code, message) = match event.response.and_then(|r| r.error) {
+                        Some(err) => (err.code, err.message),
+                        None => (
+                            event.code,
+                            event
+                                .message
+                                .unwrap_or_else(|| "Response failed".to_string()),
+                        ),
+                    };
+                    let err = ProviderError::in_stream(
+                        provider,
+                        code.unwrap_or_else(|| "api_error".to_string()),
+                        message,
+                    );
+                    emit_provider_error(window, &err)?;
+                    return Err(ProxyError::Provider(err));
+                }
+                other => debug!("Ignoring Responses event {}", other),
+            }
+        }
+    }
+
+    info!("{} Responses stream completed", provider);
+    emit_end(window)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn chat_request(request: Value) -> ChatRequest {
+        serde_json::from_value(request).unwrap()
+    }
+
+    /// Build a request as the official endpoint does
+    fn build(request: &ChatRequest) -> ProxyResult<Value> {
+        build_request(
+            request,
+            &AttachmentSupport {
+                provider: "the Responses API",
+                images: true,
+                documents: true,
+                vision_models: &[],
+                allow_unknown_models: true,
+            },
+        )
+    }
+
+    #[test]
+    fn builds_input_items_and_instructions() {
+        let request = chat_request(json!({
+            "model": "o4-mini",
+            "system": "Be brief.",
+            "messages": [
+                { "role": "system", "content": "Use metric units." },
+                { "role": "user", "content": "Weather in Oslo?" },
+                { "role": "assistant", "content": [
+                    { "type": "text", "text": "Checking." },
+                    { "type": "tool_call", "id": "call_1", "name": "weather",
+                      "arguments": { "city": "Oslo" } },
+                ] },
+                { "role": "tool", "content": [
+                    { "type": "tool_result", "tool_call_id": "call_1", "content": "4C" },
+                ] },
+            ],
+            "tools": [{ "name": "weather", "parameters": { "type": "object" } }],
+            "builtin_tools": [{ "type": "web_search_preview" }],
+            "max_tokens": 500,
+            "thinking": { "budget_tokens": 8192 },
+        }));
+
+        let body = build(&request).unwrap();
+        assert_eq!(
+            body,
+            json!({
+                "model": "o4-mini",
+                "stream": true,
+                "store": false,
+                "instructions": "Be brief.\n\nUse metric units.",
+                "input": [
+                    { "role": "user", "content": [
+                        { "type": "input_text", "text": "Weather in Oslo?" },
+                    ] },
+                    { "role": "assistant", "content": "Checking." },
+                    {
+                        "type": "function_call",
+                        "call_id": "call_1",
+                        "name": "weather",
+                        "arguments": "{\"city\":\"Oslo\"}",
+                    },
+                    { "type": "function_call_output", "call_id": "call_1", "output": "4C" },
+                ],
+                "max_output_tokens": 500,
+                "reasoning": { "summary": "auto", "effort": "medium" },
+                "tools": [
+                    { "type": "function", "name": "weather", "parameters": { "type": "object" } },
+                    { "type": "web_search_preview" },
+                ],
+            })
+        );
+    }
+
+    #[test]
+    fn only_reasoning_models_get_reasoning_options() {
+        let request = chat_request(json!({
+            "model": "gpt-4.1",
+            "messages": [{ "role": "user", "content": "Hi" }],
+            "temperature": 0.5,
+        }));
+
+        let body = build(&request).unwrap();
+        assert!(body.get("reasoning").is_none());
+        assert_eq!(body["temperature"], json!(0.5));
+        assert!(is_reasoning_model("gpt-5-mini"));
+        assert!(!is_reasoning_model("gpt-5-chat-latest"));
+    }
+
+    #[test]
+    fn rejects_stop_sequences_and_reasoning_temperature() {
+        for request in [
+            json!({
+                "model": "gpt-4.1",
+                "messages": [{ "role": "user", "content": "Hi" }],
+                "stop": ["END"],
+            }),
+            json!({
+                "model": "o3",
+                "messages": [{ "role": "user", "content": "Hi" }],
+                "temperature": 0.3,
+            }),
+        ] {
+            assert!(matches!(
+                build(&chat_request(request)),
+                Err(ProxyError::Validation(_))
+            ));
+        }
+    }
+
+    #[test]
+    fn reasoning_effort_follows_the_budget() {
+        assert_eq!(reasoning_effort(1024), "low");
+        assert_eq!(reasoning_effort(4096), "medium");
+        assert_eq!(reasoning_effort(32000), "high");
+    }
+}
@@ -0,0 +1,393 @@
+use crate::services::proxy::{KeyStore, ProxyError, ProxyResult};
+use crate::services::settings;
+use log::{debug, info, warn};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+
+/// Provider names handled natively that cannot be shadowed by a profile
+pub(crate) const BUILTIN_PROVIDERS: &[&str] = &["anthropic", "openai", "gemini", "ollama"];
+
+/// A named OpenAI-compatible endpoint (Ollama, vLLM, OpenRouter, Groq, ...)
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ProviderProfile {
+    pub name: String,
+    /// Base URL up to and including the version segment, e.g. `http://localhost:11434/v1`
+    pub base_url: String,
+    /// Environment variable to read the key from when none is saved in the key store
+    #[serde(default)]
+    pub api_key_env: Option<String>,
+    #[serde(default)]
+    pub headers: HashMap<String, String>,
+    #[serde(default)]
+    pub models: Vec<String>,
+    /// Models on this endpoint to call through the Responses API instead of Chat Completions
+    #[serde(default)]
+    pub responses_models: Vec<String>,
+    /// Models on this endpoint that accept image attachments, and PDFs through the
+    /// Responses API. Well-known models need no entry.
+    #[serde(default)]
+    pub vision_models: Vec<String>,
+}
+
+impl ProviderProfile {
+    /// Resolve the API key from the profile's environment variable, if any. Keys
+    /// saved through the key store take precedence; local servers usually need none.
+    pub fn resolve_api_key(&self) -> Option<String> {
+        self.api_key_env
+            .as_ref()
+            .and_then(|var| env::var(var).ok())
+            .filter(|k| !k.is_empty())
+    }
+
+    fn is_reserved(&self) -> bool {
+        BUILTIN_PROVIDERS.contains(&self.name.as_str())
+    }
+
+    fn validate(&self) -> ProxyResult<()> {
+        if self.name.trim().is_empty() {
+            return Err(ProxyError::Config(
+                "Profile name cannot be empty".to_string(),
+            ));
+        }
+        if !(self.base_url.starts_with("http://") || self.base_url.starts_with("https://")) {
+            return Err(ProxyError::Config(format!(
+                "Profile '{}' has an invalid base URL: {}",
+                self.name, self.base_url
+            )));
+        }
+        Ok(())
+    }
+}
+
+/// API the built-in OpenAI provider calls a model through
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum OpenAIApi {
+    ChatCompletions,
+    Responses,
+}
+
+/// Settings for the built-in `openai` provider, which cannot have a profile
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+#[serde(default)]
+pub struct OpenAISettings {
+    /// API to call each listed model through. Unlisted models use the Responses
+    /// API if they are reasoning models and Chat Completions otherwise.
+    pub model_apis: HashMap<String, OpenAIApi>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Default)]
+struct ProfilesFile {
+    #[serde(default)]
+    profiles: Vec<ProviderProfile>,
+    #[serde(default)]
+    openai: OpenAISettings,
+}
+
+/// Profiles used to keep their API key in plain text in `providers.json`
+#[derive(Deserialize)]
+struct LegacyProfilesFile {
+    #[serde(default)]
+    profiles: Vec<LegacyProfile>,
+}
+
+#[derive(Deserialize)]
+struct LegacyProfile {
+    #[serde(default)]
+    api_key: Option<String>,
+}
+
+/// Provider profiles persisted as JSON in the app config directory. API keys are
+/// not part of a profile; they are saved in the key store under the profile name.
+pub struct ProfileStore {
+    path: PathBuf,
+    profiles: Vec<ProviderProfile>,
+    openai: OpenAISettings,
+    /// Plain-text keys found in the file, by profile name, until `migrate_keys`
+    legacy_keys: Vec<(String, String)>,
+    /// Profiles renamed on load as (old name, new name), whose keys `migrate_keys`
+    /// moves along
+    renamed: Vec<(String, String)>,
+}
+
+impl ProfileStore {
+    /// Load profiles from `path`, starting empty if the file does not exist yet
+    pub fn load(path: PathBuf) -> ProxyResult<Self> {
+        let mut renamed = Vec::new();
+        let (profiles, openai, legacy_keys) = if path.exists() {
+            let contents = fs::read_to_string(&path).map_err(|e| {
+                ProxyError::Config(format!("Failed to read {}: {}", path.display(), e))
+            })?;
+            let file: ProfilesFile = serde_json::from_str(&contents).map_err(|e| {
+                ProxyError::Config(format!("Failed to parse {}: {}", path.display(), e))
+            })?;
+            for profile in &file.profiles {
+                profile.validate()?;
+            }
+            let mut profiles = file.profiles;
+            renamed = rename_reserved(&mut profiles);
+
+            // Same entries as `profiles`, so they pair up by position
+            let legacy: LegacyProfilesFile = serde_json::from_str(&contents).map_err(|e| {
+                ProxyError::Config(format!("Failed to parse {}: {}", path.display(), e))
+            })?;
+            let keys = profiles
+                .iter()
+                .zip(legacy.profiles)
+                .filter_map(|(profile, legacy)| {
+                    let key = legacy.api_key.filter(|key| !key.trim().is_empty())?;
+                    Some((profile.name.clone(), key))
+                })
+                .collect();
+            (profiles, file.openai, keys)
+        } else {
+            debug!("No provider profiles at {}, starting empty", path.display());
+            (Vec::new(), OpenAISettings::default(), Vec::new())
+        };
+
+        info!("Loaded {} provider profile(s)", profiles.len());
+        Ok(Self {
+            path,
+            profiles,
+            openai,
+            legacy_keys,
+            renamed,
+        })
+    }
+
+    /// Move API keys stored in plain text in the profiles file into the key store,
+    /// then rewrite the file without them. A key already saved for the profile wins.
+    ///
+    /// Keys of profiles renamed on load move to the new name too, since the old
+    /// one is now the built-in provider's slot.
+    pub fn migrate_keys(&mut self, keys: &mut KeyStore) -> ProxyResult<()> {
+        if self.legacy_keys.is_empty() && self.renamed.is_empty() {
+            return Ok(());
+        }
+        for (old, new) in &self.renamed {
+            if let Some(key) = keys.get(old)? {
+                if keys.get(new)?.is_none() {
+                    keys.set(new, &key)?;
+                }
+                keys.delete(old)?;
+                info!("Moved the API key of profile '{}' to '{}'", old, new);
+            }
+        }
+        for (name, key) in &self.legacy_keys {
+            if keys.get(name)?.is_none() {
+                keys.set(name, key)?;
+                info!("Moved the API key of profile '{}' into the key store", name);
+            }
+        }
+        self.save()?;
+        self.legacy_keys.clear();
+        self.renamed.clear();
+        Ok(())
+    }
+
+    pub fn get(&self, name: &str) -> Option<&ProviderProfile> {
+        self.profiles.iter().find(|p| p.name == name)
+    }
+
+    pub fn list(&self) -> Vec<ProviderProfile> {
+        self.profiles.clone()
+    }
+
+    pub fn openai_settings(&self) -> OpenAISettings {
+        self.openai.clone()
+    }
+
+    /// Replace the built-in OpenAI provider's settings and persist the store
+    pub fn set_openai_settings(&mut self, settings: OpenAISettings) -> ProxyResult<()> {
+        let previous = std::mem::replace(&mut self.openai, settings);
+        if let Err(e) = self.save() {
+            self.openai = previous;
+            return Err(e);
+        }
+        Ok(())
+    }
+
+    /// Insert or replace a profile by name and persist the store
+    pub fn upsert(&mut self, profile: ProviderProfile) -> ProxyResult<()> {
+        profile.validate()?;
+        if profile.is_reserved() {
+            return Err(ProxyError::Config(format!(
+                "Profile name '{}' is reserved for a built-in provider",
+                profile.name
+            )));
+        }
+        match self.profiles.iter_mut().find(|p| p.name == profile.name) {
+            Some(existing) => *existing = profile,
+            None => self.profiles.push(profile),
+        }
+        self.save()
+    }
+
+    /// Remove a profile by name, returning whether it existed
+    pub fn remove(&mut self, name: &str) -> ProxyResult<bool> {
+        let before = self.profiles.len();
+        self.profiles.retain(|p| p.name != name);
+        if self.profiles.len() == before {
+            return Ok(false);
+        }
+        self.save()?;
+        Ok(true)
+    }
+
+    fn save(&self) -> ProxyResult<()> {
+        let file = ProfilesFile {
+            profiles: self.profiles.clone(),
+            openai: self.openai.clone(),
+        };
+        settings::save(&self.path, &file).map_err(|e| {
+            ProxyError::Config(format!("Failed to write {}: {}", self.path.display(), e))
+        })
+    }
+}
+
+/// Give profiles saved before their name became a built-in provider (a profile
+/// called "ollama", say) a free name, so they stay usable instead of failing the
+/// load. Returns the renames as (old name, new name); the new names are written
+/// back the next time the store is saved.
+fn rename_reserved(profiles: &mut [ProviderProfile]) -> Vec<(String, String)> {
+    let mut renames = Vec::new();
+    for index in 0..profiles.len() {
+        if !profiles[index].is_reserved() {
+            continue;
+        }
+        let original = profiles[index].name.clone();
+        let renamed = (1..)
+            .map(|n| match n {
+                1 => format!("{}-profile", original),
+                n => format!("{}-profile-{}", original, n),
+            })
+            .find(|name| !profiles.iter().any(|p| &p.name == name))
+            .unwrap_or_default();
+        warn!(
+            "Provider profile '{}' shadows a built-in provider; renamed it to '{}'",
+            original, renamed
+        );
+        profiles[index].name = renamed.clone();
+        renames.push((original, renamed));
+    }
+    renames
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::services::proxy::keystore::FileBackend;
+    use std::process;
+
+    fn profile(name: &str) -> ProviderProfile {
+        ProviderProfile {
+            name: name.to_string(),
+            base_url: "http://localhost:8000/v1".to_string(),
+            api_key_env: None,
+            headers: HashMap::new(),
+            models: Vec::new(),
+            responses_models: Vec::new(),
+            vision_models: Vec::new(),
+        }
+    }
+
+    fn profiles_file(test: &str, profiles: &[ProviderProfile]) -> PathBuf {
+        let path = env::temp_dir().join(format!("pqp-profiles-{}-{}.json", test, process::id()));
+        let file = ProfilesFile {
+            profiles: profiles.to_vec(),
+            openai: OpenAISettings::default(),
+        };
+        fs::write(&path, serde_json::to_string(&file).unwrap()).unwrap();
+        path
+    }
+
+    #[test]
+    fn load_renames_profiles_named_after_builtin_providers() {
+        let path = profiles_file(
+            "reserved",
+            &[
+                profile("ollama"),
+                profile("ollama-profile"),
+                profile("vllm"),
+            ],
+        );
+        let store = ProfileStore::load(path.clone()).unwrap();
+        fs::remove_file(path).unwrap();
+
+        let names: Vec<String> = store.list().into_iter().map(|p| p.name).collect();
+        assert_eq!(names, ["ollama-profile-2", "ollama-profile", "vllm"]);
+    }
+
+    #[test]
+    fn upsert_rejects_builtin_provider_names() {
+        let path = env::temp_dir().join(format!("pqp-profiles-upsert-{}.json", process::id()));
+        let mut store = ProfileStore::load(path.clone()).unwrap();
+        assert!(store.upsert(profile("gemini")).is_err());
+
+        store.upsert(profile("groq")).unwrap();
+        fs::remove_file(path).unwrap();
+        assert!(store.get("groq").is_some());
+    }
+
+    #[test]
+    fn migrate_keys_moves_plain_text_keys_into_the_key_store() {
+        let dir = env::temp_dir().join(format!("pqp-profiles-keys-{}", process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("providers.json");
+        fs::write(
+            &path,
+            r#"{"profiles": [
+                {"name": "groq", "base_url": "https://api.groq.com/openai/v1", "api_key": "gsk-old"},
+                {"name": "local", "base_url": "http://localhost:8000/v1", "api_key": ""}
+            ]}"#,
+        )
+        .unwrap();
+        let mut keys = KeyStore::new(Box::new(FileBackend::open(&dir).unwrap()));
+
+        let mut store = ProfileStore::load(path.clone()).unwrap();
+        store.migrate_keys(&mut keys).unwrap();
+
+        assert_eq!(keys.get("groq").unwrap().as_deref(), Some("gsk-old"));
+        assert_eq!(keys.get("local").unwrap(), None);
+        let contents = fs::read_to_string(&path).unwrap();
+        assert!(!contents.contains("gsk-old"));
+        assert_eq!(ProfileStore::load(path).unwrap().list().len(), 2);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn migrate_keys_moves_the_key_of_a_renamed_profile() {
+        let dir = env::temp_dir().join(format!("pqp-profiles-renamed-{}", process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("providers.json");
+        let file = ProfilesFile {
+            profiles: vec![profile("ollama")],
+            openai: OpenAISettings::default(),
+        };
+        fs::write(&path, serde_json::to_string(&file).unwrap()).unwrap();
+        let mut keys = KeyStore::new(Box::new(FileBackend::open(&dir).unwrap()));
+        keys.set("ollama", "sk-profile").unwrap();
+
+        let mut store = ProfileStore::load(path.clone()).unwrap();
+        store.migrate_keys(&mut keys).unwrap();
+
+        assert_eq!(
+            keys.get("ollama-profile").unwrap().as_deref(),
+            Some("sk-profile")
+        );
+        assert_eq!(keys.get("ollama").unwrap(), None);
+        let names: Vec<String> = ProfileStore::load(path)
+            .unwrap()
+            .list()
+            .into_iter()
+            .map(|p| p.name)
+            .collect();
+        assert_eq!(names, ["ollama-profile"]);
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,508 @@
+use crate::services::proxy::{ProxyError, ProxyResult};
+use serde::{Deserialize, Deserializer, Serialize};
+use serde_json::Value;
+use std::collections::HashSet;
+
+/// Provider-neutral chat request sent by the frontend.
+///
+/// Each provider translates this into its own wire format, so the frontend never
+/// needs to know about system prompt placement, tool schema shapes or role names.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ChatRequest {
+    pub model: String,
+    pub messages: Vec<ChatMessage>,
+    #[serde(default)]
+    pub system: Option<String>,
+    #[serde(default)]
+    pub tools: Vec<ToolDefinition>,
+    /// Provider built-in tools passed through unchanged, e.g. OpenAI
+    /// `{"type": "web_search_preview"}` or Anthropic `{"type": "web_search_20250305", ...}`
+    #[serde(default)]
+    pub builtin_tools: Vec<Value>,
+    #[serde(default)]
+    pub temperature: Option<f32>,
+    #[serde(default)]
+    pub max_tokens: Option<u32>,
+    #[serde(default)]
+    pub stop: Vec<String>,
+    /// Extended thinking; providers without a thinking budget ignore it
+    #[serde(default)]
+    pub thinking: Option<ThinkingOptions>,
+    /// Prompt caching for providers that need explicit breakpoints; on unless `false`
+    #[serde(default)]
+    pub prompt_caching: Option<bool>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ThinkingOptions {
+    /// Tokens the model may spend reasoning before it answers
+    pub budget_tokens: u32,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum ChatRole {
+    System,
+    User,
+    Assistant,
+    Tool,
+}
+
+impl ChatRole {
+    /// Lowercase name, as serialized
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ChatRole::System => "system",
+            ChatRole::User => "user",
+            ChatRole::Assistant => "assistant",
+            ChatRole::Tool => "tool",
+        }
+    }
+
+    pub fn parse(name: &str) -> Option<Self> {
+        match name {
+            "system" => Some(ChatRole::System),
+            "user" => Some(ChatRole::User),
+            "assistant" => Some(ChatRole::Assistant),
+            "tool" => Some(ChatRole::Tool),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ChatMessage {
+    pub role: ChatRole,
+    /// Either a plain string or an array of typed parts
+    #[serde(deserialize_with = "deserialize_content")]
+    pub content: Vec<ContentPart>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ContentPart {
+    Text {
+        text: String,
+    },
+    ToolCall {
+        id: String,
+        name: String,
+        #[serde(default)]
+        arguments: Value,
+    },
+    ToolResult {
+        tool_call_id: String,
+        #[serde(default)]
+        name: Option<String>,
+        content: Value,
+        #[serde(default)]
+        is_error: bool,
+    },
+    /// Reasoning from an earlier assistant turn. The signature must be sent back
+    /// unchanged for multi-turn thinking conversations to stay valid.
+    Reasoning {
+        text: String,
+        #[serde(default)]
+        signature: Option<String>,
+    },
+    /// Reasoning the provider returned encrypted, passed back as-is
+    RedactedReasoning {
+        data: String,
+    },
+    /// A local file to send alongside the message
+    Attachment {
+        path: String,
+        #[serde(default)]
+        media_type: Option<String>,
+    },
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ToolDefinition {
+    pub name: String,
+    #[serde(default)]
+    pub description: Option<String>,
+    /// JSON Schema describing the tool's arguments
+    pub parameters: Value,
+}
+
+pub(crate) fn deserialize_content<'de, D>(deserializer: D) -> Result<Vec<ContentPart>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum ContentInput {
+        Text(String),
+        Parts(Vec<ContentPart>),
+    }
+
+    Ok(match ContentInput::deserialize(deserializer)? {
+        ContentInput::Text(text) => vec![ContentPart::Text { text }],
+        ContentInput::Parts(parts) => parts,
+    })
+}
+
+impl ChatMessage {
+    /// Concatenated text of all text parts
+    pub fn text(&self) -> String {
+        self.content
+            .iter()
+            .filter_map(|part| match part {
+                ContentPart::Text { text } => Some(text.as_str()),
+                _ => None,
+            })
+            .collect::<Vec<_>>()
+            .join("")
+    }
+}
+
+impl ChatRequest {
+    /// The system prompt, merging the top-level `system` field with any system-role messages
+    pub fn system_prompt(&self) -> Option<String> {
+        let prompts: Vec<String> = self
+            .system
+            .iter()
+            .cloned()
+            .chain(
+                self.messages
+                    .iter()
+                    .filter(|m| m.role == ChatRole::System)
+                    .map(ChatMessage::text),
+            )
+            .filter(|p| !p.trim().is_empty())
+            .collect();
+        if prompts.is_empty() {
+            None
+        } else {
+            Some(prompts.join("\n\n"))
+        }
+    }
+
+    /// Messages other than system-role ones, which providers place separately
+    pub fn conversation(&self) -> impl Iterator<Item = &ChatMessage> {
+        self.messages.iter().filter(|m| m.role != ChatRole::System)
+    }
+
+    /// Check the request for problems that every provider would reject
+    pub fn validate(&self) -> ProxyResult<()> {
+        if self.model.trim().is_empty() {
+            return Err(invalid("model is required"));
+        }
+        if self.conversation().next().is_none() {
+            return Err(invalid("at least one non-system message is required"));
+        }
+        if let Some(temperature) = self.temperature {
+            if !(0.0..=2.0).contains(&temperature) {
+                return Err(invalid(format!(
+                    "temperature must be between 0 and 2, got {}",
+                    temperature
+                )));
+            }
+        }
+        if self.max_tokens == Some(0) {
+            return Err(invalid("max_tokens must be greater than 0"));
+        }
+        if let Some(thinking) = &self.thinking {
+            if thinking.budget_tokens < 1024 {
+                return Err(invalid("thinking budget_tokens must be at least 1024"));
+            }
+            if self
+                .max_tokens
+                .is_some_and(|max_tokens| max_tokens <= thinking.budget_tokens)
+            {
+                return Err(invalid(
+                    "max_tokens must be greater than the thinking budget_tokens",
+                ));
+            }
+        }
+
+        let mut tool_names = HashSet::new();
+        for tool in &self.tools {
+            let valid_name = !tool.name.is_empty()
+                && tool.name.len() <= 64
+                && tool
+                    .name
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-');
+            if !valid_name {
+                return Err(invalid(format!(
+                    "tool name '{}' must be 1-64 characters of letters, digits, '_' or '-'",
+                    tool.name
+                )));
+            }
+            if !tool_names.insert(tool.name.as_str()) {
+                return Err(invalid(format!("duplicate tool name '{}'", tool.name)));
+            }
+            if !tool.parameters.is_object() {
+                return Err(invalid(format!(
+                    "parameters for tool '{}' must be a JSON Schema object",
+                    tool.name
+                )));
+            }
+        }
+
+        if self.builtin_tools.iter().any(|tool| !tool.is_object()) {
+            return Err(invalid("builtin_tools entries must be JSON objects"));
+        }
+
+        let mut call_ids = HashSet::new();
+        for (index, message) in self.messages.iter().enumerate() {
+            for part in &message.content {
+                match (message.role, part) {
+                    (ChatRole::Assistant, ContentPart::ToolCall { id, .. }) => {
+                        call_ids.insert(id.as_str());
+                    }
+                    (ChatRole::Tool, ContentPart::ToolResult { tool_call_id, .. })
+                        if !call_ids.contains(tool_call_id.as_str()) =>
+                    {
+                        return Err(invalid(format!(
+                            "message {} has a tool result for unknown call '{}'",
+                            index, tool_call_id
+                        )));
+                    }
+                    (role, part) if !part.allowed_in(role) => {
+                        return Err(invalid(format!(
+                            "message {} with role {:?} cannot contain a {} part",
+                            index,
+                            role,
+                            part.kind()
+                        )));
+                    }
+                    _ => {}
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl ContentPart {
+    pub(crate) fn kind(&self) -> &'static str {
+        match self {
+            ContentPart::Text { .. } => "text",
+            ContentPart::ToolCall { .. } => "tool_call",
+            ContentPart::ToolResult { .. } => "tool_result",
+            ContentPart::Reasoning { .. } => "reasoning",
+            ContentPart::RedactedReasoning { .. } => "redacted_reasoning",
+            ContentPart::Attachment { .. } => "attachment",
+        }
+    }
+
+    /// Whether a message with this role may carry this kind of part
+    pub(crate) fn allowed_in(&self, role: ChatRole) -> bool {
+        match (role, self) {
+            (ChatRole::Assistant, ContentPart::ToolCall { .. })
+            | (ChatRole::Tool, ContentPart::ToolResult { .. })
+            | (ChatRole::User, ContentPart::Attachment { .. })
+            | (
+                ChatRole::Assistant,
+                ContentPart::Reasoning { .. } | ContentPart::RedactedReasoning { .. },
+            ) => true,
+            (role, ContentPart::Text { .. }) => role != ChatRole::Tool,
+            _ => false,
+        }
+    }
+}
+
+/// Render a tool result as the plain string most providers expect
+pub(crate) fn tool_result_text(content: &Value) -> String {
+    match content {
+        Value::String(text) => text.clone(),
+        other => other.to_string(),
+    }
+}
+
+pub(crate) fn invalid<S: Into<String>>(message: S) -> ProxyError {
+    ProxyError::Validation(message.into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn text(role: ChatRole, text: &str) -> ChatMessage {
+        ChatMessage {
+            role,
+            content: vec![ContentPart::Text {
+                text: text.to_string(),
+            }],
+        }
+    }
+
+    fn request(messages: Vec<ChatMessage>) -> ChatRequest {
+        ChatRequest {
+            model: "model".to_string(),
+            messages,
+            system: None,
+            tools: Vec::new(),
+            builtin_tools: Vec::new(),
+            temperature: None,
+            max_tokens: None,
+            stop: Vec::new(),
+            thinking: None,
+            prompt_caching: None,
+        }
+    }
+
+    fn tool(name: &str, parameters: Value) -> ToolDefinition {
+        ToolDefinition {
+            name: name.to_string(),
+            description: None,
+            parameters,
+        }
+    }
+
+    fn tool_call(id: &str) -> ContentPart {
+        ContentPart::ToolCall {
+            id: id.to_string(),
+            name: "weather".to_string(),
+            arguments: json!({}),
+        }
+    }
+
+    fn tool_result(id: &str) -> ContentPart {
+        ContentPart::ToolResult {
+            tool_call_id: id.to_string(),
+            name: None,
+            content: json!("sunny"),
+            is_error: false,
+        }
+    }
+
+    #[test]
+    fn rejects_invalid_requests() {
+        type Change = fn(&mut ChatRequest);
+        let valid = || request(vec![text(ChatRole::User, "Hi")]);
+        let cases: Vec<(&str, Change)> = vec![
+            ("model is required", |r| r.model = " ".to_string()),
+            ("at least one non-system message", |r| {
+                r.messages = vec![text(ChatRole::System, "Be brief")]
+            }),
+            ("temperature must be between 0 and 2", |r| {
+                r.temperature = Some(2.5)
+            }),
+            ("max_tokens must be greater than 0", |r| {
+                r.max_tokens = Some(0)
+            }),
+            ("budget_tokens must be at least 1024", |r| {
+                r.thinking = Some(ThinkingOptions { budget_tokens: 512 })
+            }),
+            ("max_tokens must be greater than the thinking", |r| {
+                r.thinking = Some(ThinkingOptions {
+                    budget_tokens: 2048,
+                });
+                r.max_tokens = Some(2048);
+            }),
+            ("tool name 'get weather'", |r| {
+                r.tools = vec![tool("get weather", json!({}))]
+            }),
+            ("duplicate tool name 'weather'", |r| {
+                r.tools = vec![tool("weather", json!({})), tool("weather", json!({}))]
+            }),
+            ("must be a JSON Schema object", |r| {
+                r.tools = vec![tool("weather", json!("string"))]
+            }),
+            ("builtin_tools entries must be JSON objects", |r| {
+                r.builtin_tools = vec![json!("web_search")]
+            }),
+            (
+                "message 1 has a tool result for unknown call 'call_1'",
+                |r| {
+                    r.messages.push(ChatMessage {
+                        role: ChatRole::Tool,
+                        content: vec![tool_result("call_1")],
+                    })
+                },
+            ),
+            (
+                "message 0 with role User cannot contain a tool_call part",
+                |r| r.messages[0].content.push(tool_call("call_1")),
+            ),
+        ];
+
+        assert!(valid().validate().is_ok());
+        for (expected, change) in cases {
+            let mut request = valid();
+            change(&mut request);
+            match request.validate() {
+                Err(ProxyError::Validation(message)) => {
+                    assert!(
+                        message.contains(expected),
+                        "{:?} for {:?}",
+                        message,
+                        expected
+                    )
+                }
+                other => panic!("expected {:?}, got {:?}", expected, other.map(|_| ())),
+            }
+        }
+    }
+
+    #[test]
+    fn accepts_tool_results_for_earlier_calls() {
+        let mut request = request(vec![
+            text(ChatRole::User, "Weather?"),
+            ChatMessage {
+                role: ChatRole::Assistant,
+                content: vec![tool_call("call_1")],
+            },
+            ChatMessage {
+                role: ChatRole::Tool,
+                content: vec![tool_result("call_1")],
+            },
+        ]);
+        request.tools = vec![tool("weather", json!({ "type": "object" }))];
+
+        assert!(request.validate().is_ok());
+    }
+
+    #[test]
+    fn system_prompt_merges_the_field_and_system_messages() {
+        let mut request = request(vec![
+            text(ChatRole::System, "Be brief."),
+            text(ChatRole::User, "Hi"),
+            text(ChatRole::System, "  "),
+            text(ChatRole::Assistant, "Hello"),
+        ]);
+        request.system = Some("You are helpful.".to_string());
+
+        assert_eq!(
+            request.system_prompt().as_deref(),
+            Some("You are helpful.\n\nBe brief.")
+        );
+        let roles: Vec<ChatRole> = request.conversation().map(|m| m.role).collect();
+        assert_eq!(roles, [ChatRole::User, ChatRole::Assistant]);
+
+        request.system = None;
+        request.messages.remove(0);
+        assert_eq!(request.system_prompt(), None);
+    }
+
+    #[test]
+    fn content_is_a_string_or_a_list_of_parts() {
+        let message: ChatMessage =
+            serde_json::from_value(json!({ "role": "user", "content": "Hi" })).unwrap();
+        assert_eq!(message.text(), "Hi");
+
+        let message: ChatMessage = serde_json::from_value(json!({
+            "role": "assistant",
+            "content": [
+                { "type": "text", "text": "Let me " },
+                { "type": "tool_call", "id": "call_1", "name": "weather" },
+                { "type": "text", "text": "check." },
+            ],
+        }))
+        .unwrap();
+        assert_eq!(message.text(), "Let me check.");
+        assert!(matches!(
+            &message.content[1],
+            ContentPart::ToolCall {
+                arguments: Value::Null,
+                ..
+            }
+        ));
+    }
+}
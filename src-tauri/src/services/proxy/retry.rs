@@ -0,0 +1,304 @@
+use crate::services::proxy::{emit_retrying, ProxyError, ProxyResult};
+use chrono::{DateTime, Utc};
+use log::{debug, warn};
+use rand::Rng;
+use serde::Serialize;
+use std::time::Duration;
+use tauri::Window;
+use tauri_plugin_http::reqwest::{header::HeaderMap, RequestBuilder, Response, StatusCode};
+
+/// How transient provider failures are retried before any output is emitted
+#[derive(Debug, Clone)]
+pub struct RetryPolicy {
+    /// Total attempts, including the first request
+    pub max_attempts: u32,
+    pub base_delay: Duration,
+    /// Upper bound on any single wait; a server asking for longer is not retried
+    pub max_delay: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_attempts: 4,
+            base_delay: Duration::from_millis(1000),
+            max_delay: Duration::from_secs(60),
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// Exponential backoff with jitter for the given (1-based) attempt
+    pub fn backoff(&self, attempt: u32) -> Duration {
+        let exp = self
+            .base_delay
+            .saturating_mul(2u32.saturating_pow(attempt.saturating_sub(1)));
+        let capped = exp.min(self.max_delay);
+        let jitter = rand::thread_rng().gen_range(0.5..=1.0);
+        capped.mul_f64(jitter)
+    }
+
+    /// The delay before the next attempt, or `None` when the attempts are used up
+    /// or the server asked for a longer wait than the policy allows
+    pub(crate) fn next_delay(&self, attempt: u32, requested: Option<Duration>) -> Option<Duration> {
+        if attempt >= self.max_attempts {
+            return None;
+        }
+        match requested {
+            Some(delay) if delay > self.max_delay => None,
+            Some(delay) => Some(delay),
+            None => Some(self.backoff(attempt)),
+        }
+    }
+}
+
+/// Payload of the retrying event, so the UI can show e.g. "rate limited, retrying in 4s"
+#[derive(Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct RetryNotice {
+    pub provider: String,
+    pub attempt: u32,
+    pub max_attempts: u32,
+    pub delay_ms: u64,
+    pub reason: String,
+    pub status: Option<u16>,
+}
+
+/// Whether an HTTP status is worth retrying
+pub(crate) fn is_retryable_status(status: StatusCode) -> bool {
+    // 529 is Anthropic's `overloaded_error`
+    matches!(status.as_u16(), 408 | 429 | 500 | 502 | 503 | 504 | 529)
+}
+
+/// Wait requested by the server via `retry-after`, `retry-after-ms` or, when a
+/// limit is exhausted, the matching `anthropic-ratelimit-*-reset` timestamp
+pub(crate) fn requested_delay(headers: &HeaderMap) -> Option<Duration> {
+    let header = |name: &str| headers.get(name).and_then(|v| v.to_str().ok());
+
+    if let Some(ms) = header("retry-after-ms").and_then(|v| v.trim().parse::<f64>().ok()) {
+        return Some(Duration::from_secs_f64(ms.max(0.0) / 1000.0));
+    }
+    if let Some(retry_after) = header("retry-after") {
+        if let Ok(secs) = retry_after.trim().parse::<f64>() {
+            return Some(Duration::from_secs_f64(secs.max(0.0)));
+        }
+        if let Ok(date) = DateTime::parse_from_rfc2822(retry_after.trim()) {
+            return Some(until(date.with_timezone(&Utc)));
+        }
+    }
+
+    ["requests", "tokens", "input-tokens", "output-tokens"]
+        .iter()
+        .filter(|limit| {
+            header(&format!("anthropic-ratelimit-{}-remaining", limit))
+                .is_some_and(|remaining| remaining.trim() == "0")
+        })
+        .filter_map(|limit| header(&format!("anthropic-ratelimit-{}-reset", limit)))
+        .filter_map(|reset| DateTime::parse_from_rfc3339(reset.trim()).ok())
+        .map(|reset| until(reset.with_timezone(&Utc)))
+        .max()
+}
+
+fn until(time: DateTime<Utc>) -> Duration {
+    (time - Utc::now()).to_std().unwrap_or(Duration::ZERO)
+}
+
+/// Send a request, retrying transient failures with backoff.
+///
+/// Returns the first successful response, or the last failed one once retries are
+/// exhausted or the failure is not retryable, so callers keep their own error
+/// reporting. Nothing has been streamed to the window at this point, so retrying
+/// is always safe.
+///
+/// `attempt` counts the requests made under `policy` and is advanced for each one
+/// sent, so a caller that also retries after the response (such as an overload
+/// reported inside the stream) shares one budget and one attempt numbering.
+pub(crate) async fn send_with_retry<F>(
+    window: &Window,
+    provider: &str,
+    policy: &RetryPolicy,
+    attempt: &mut u32,
+    build_request: F,
+) -> ProxyResult<Response>
+where
+    F: Fn() -> RequestBuilder,
+{
+    loop {
+        *attempt += 1;
+        let attempt = *attempt;
+        let (delay, reason, status) = match build_request().send().await {
+            Ok(response) if response.status().is_success() => return Ok(response),
+            Ok(response) if is_retryable_status(response.status()) => {
+                let status = response.status();
+                match policy.next_delay(attempt, requested_delay(response.headers())) {
+                    Some(delay) => (delay, status_reason(status), Some(status.as_u16())),
+                    None => return Ok(response),
+                }
+            }
+            Ok(response) => return Ok(response),
+            Err(e) if e.is_connect() || e.is_timeout() => match policy.next_delay(attempt, None) {
+                Some(delay) => (delay, format!("connection failed: {}", e), None),
+                None => return Err(ProxyError::Http(e)),
+            },
+            Err(e) => return Err(ProxyError::Http(e)),
+        };
+
+        warn!(
+            "{} request failed ({}), retrying in {:?} (attempt {}/{})",
+            provider, reason, delay, attempt, policy.max_attempts
+        );
+        retry_wait(window, provider, policy, attempt, delay, reason, status).await?;
+    }
+}
+
+/// Short human-readable reason for a retryable status
+fn status_reason(status: StatusCode) -> String {
+    match status.as_u16() {
+        429 => "rate limited".to_string(),
+        529 => "overloaded".to_string(),
+        _ => status
+            .canonical_reason()
+            .unwrap_or("request failed")
+            .to_lowercase(),
+    }
+}
+
+/// Notify the UI and sleep before the next attempt
+pub(crate) async fn retry_wait(
+    window: &Window,
+    provider: &str,
+    policy: &RetryPolicy,
+    attempt: u32,
+    delay: Duration,
+    reason: String,
+    status: Option<u16>,
+) -> ProxyResult<()> {
+    emit_retrying(
+        window,
+        &RetryNotice {
+            provider: provider.to_string(),
+            attempt,
+            max_attempts: policy.max_attempts,
+            delay_ms: delay.as_millis() as u64,
+            reason,
+            status,
+        },
+    )?;
+    debug!("Sleeping {:?} before retrying {}", delay, provider);
+    tokio::time::sleep(delay).await;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tauri_plugin_http::reqwest::header::HeaderValue;
+
+    fn headers(pairs: &[(&'static str, String)]) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        for (name, value) in pairs {
+            headers.insert(*name, HeaderValue::from_str(value).unwrap());
+        }
+        headers
+    }
+
+    #[test]
+    fn next_delay_stops_when_attempts_run_out() {
+        let policy = RetryPolicy::default();
+        assert!(policy.next_delay(1, None).is_some());
+        assert!(policy.next_delay(3, None).is_some());
+        assert_eq!(policy.next_delay(4, None), None);
+        assert_eq!(policy.next_delay(4, Some(Duration::from_secs(1))), None);
+    }
+
+    #[test]
+    fn next_delay_honours_requested_waits_within_the_cap() {
+        let policy = RetryPolicy::default();
+        assert_eq!(
+            policy.next_delay(1, Some(Duration::from_secs(5))),
+            Some(Duration::from_secs(5))
+        );
+        assert_eq!(
+            policy.next_delay(1, Some(Duration::ZERO)),
+            Some(Duration::ZERO)
+        );
+        // Waiting longer than the policy allows is not worth holding the request
+        assert_eq!(policy.next_delay(1, Some(Duration::from_secs(61))), None);
+    }
+
+    #[test]
+    fn backoff_doubles_with_jitter_up_to_the_cap() {
+        let policy = RetryPolicy::default();
+        for attempt in 1..=3 {
+            let full = Duration::from_secs(1 << (attempt - 1));
+            let delay = policy.next_delay(attempt, None).unwrap();
+            assert!(delay >= full / 2 && delay <= full, "{:?}", delay);
+        }
+        let delay = policy.backoff(20);
+        assert!(delay >= policy.max_delay / 2 && delay <= policy.max_delay);
+    }
+
+    #[test]
+    fn parses_retry_after_in_seconds_milliseconds_and_dates() {
+        assert_eq!(
+            requested_delay(&headers(&[("retry-after", "3".to_string())])),
+            Some(Duration::from_secs(3))
+        );
+        assert_eq!(
+            requested_delay(&headers(&[("retry-after", "1.5".to_string())])),
+            Some(Duration::from_millis(1500))
+        );
+        // The millisecond header is more precise, so it wins
+        assert_eq!(
+            requested_delay(&headers(&[
+                ("retry-after", "3".to_string()),
+                ("retry-after-ms", "250".to_string()),
+            ])),
+            Some(Duration::from_millis(250))
+        );
+
+        let date = (Utc::now() + chrono::Duration::seconds(30)).to_rfc2822();
+        let delay = requested_delay(&headers(&[("retry-after", date)])).unwrap();
+        assert!(delay > Duration::from_secs(25) && delay <= Duration::from_secs(30));
+        // A date in the past means retry right away
+        let past = (Utc::now() - chrono::Duration::seconds(30)).to_rfc2822();
+        assert_eq!(
+            requested_delay(&headers(&[("retry-after", past)])),
+            Some(Duration::ZERO)
+        );
+
+        assert_eq!(
+            requested_delay(&headers(&[("retry-after", "soon".to_string())])),
+            None
+        );
+        assert_eq!(requested_delay(&HeaderMap::new()), None);
+    }
+
+    #[test]
+    fn anthropic_reset_headers_count_only_for_exhausted_limits() {
+        let reset = |secs: i64| (Utc::now() + chrono::Duration::seconds(secs)).to_rfc3339();
+        let delay = requested_delay(&headers(&[
+            ("anthropic-ratelimit-requests-remaining", "0".to_string()),
+            ("anthropic-ratelimit-requests-reset", reset(10)),
+            ("anthropic-ratelimit-tokens-remaining", "0".to_string()),
+            ("anthropic-ratelimit-tokens-reset", reset(20)),
+            (
+                "anthropic-ratelimit-output-tokens-remaining",
+                "900".to_string(),
+            ),
+            ("anthropic-ratelimit-output-tokens-reset", reset(50)),
+        ]))
+        .unwrap();
+        assert!(delay > Duration::from_secs(15) && delay <= Duration::from_secs(20));
+    }
+
+    #[test]
+    fn retries_transient_statuses_only() {
+        for status in [408, 429, 500, 502, 503, 504, 529] {
+            assert!(is_retryable_status(StatusCode::from_u16(status).unwrap()));
+        }
+        for status in [400, 401, 403, 404, 413, 422] {
+            assert!(!is_retryable_status(StatusCode::from_u16(status).unwrap()));
+        }
+    }
+}
@@ -0,0 +1,67 @@
+/// Splits a server-sent event stream into the data of each event.
+///
+/// Network chunks are buffered as raw bytes and only decoded once an event is
+/// complete, so a multibyte character split across two chunks comes out whole.
+/// Carriage returns are dropped on the way in, so CRLF-framed streams split the
+/// same way as LF ones.
+#[derive(Default)]
+pub(crate) struct SseBuffer {
+    buffer: Vec<u8>,
+}
+
+impl SseBuffer {
+    pub fn push(&mut self, chunk: &[u8]) {
+        self.buffer.extend(chunk.iter().filter(|b| **b != b'\r'));
+    }
+
+    /// The data of the next complete event, its `data:` lines joined by newlines.
+    /// Events without data (comments, keep-alives) are skipped.
+    pub fn next_data(&mut self) -> Option<String> {
+        while let Some(pos) = self.buffer.windows(2).position(|w| w == b"\n\n") {
+            let event: Vec<u8> = self.buffer.drain(..pos + 2).collect();
+            let event = String::from_utf8_lossy(&event);
+            let data: Vec<&str> = event
+                .lines()
+                .filter_map(|line| line.strip_prefix("data:"))
+                .map(|data| data.strip_prefix(' ').unwrap_or(data))
+                .collect();
+            if !data.is_empty() {
+                return Some(data.join("\n"));
+            }
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keeps_characters_split_across_chunks() {
+        let event = "data: {\"text\": \"héllo ✓\"}\n\n".as_bytes();
+        let split = event.iter().position(|b| *b == 0xc3).unwrap() + 1;
+        let mut sse = SseBuffer::default();
+
+        sse.push(&event[..split]);
+        assert_eq!(sse.next_data(), None);
+        sse.push(&event[split..]);
+        assert_eq!(sse.next_data().as_deref(), Some("{\"text\": \"héllo ✓\"}"));
+        assert_eq!(sse.next_data(), None);
+    }
+
+    #[test]
+    fn splits_crlf_framed_events() {
+        let mut sse = SseBuffer::default();
+        sse.push(
+            b"event: ping\r\ndata: 1\r\n\r\n: keep-alive\r\n\r\ndata:2\r\ndata: 3\r\n\r\ndata: 4",
+        );
+
+        assert_eq!(sse.next_data().as_deref(), Some("1"));
+        assert_eq!(sse.next_data().as_deref(), Some("2\n3"));
+        // The last event is not complete until its blank line arrives
+        assert_eq!(sse.next_data(), None);
+        sse.push(b"\r\n\r\n");
+        assert_eq!(sse.next_data().as_deref(), Some("4"));
+    }
+}
@@ -0,0 +1,195 @@
+use crate::services::proxy::{
+    ChatMessage, ChatRequest, ChatRole, ContentPart, ProxyError, ProxyProvider, ProxyResult,
+};
+use crate::services::settings;
+use log::info;
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+
+/// Characters of each message included in the titling prompt
+const MAX_MESSAGE_CHARS: usize = 2000;
+/// Characters kept of a generated title
+const MAX_TITLE_CHARS: usize = 80;
+
+const TITLE_PROMPT: &str = "You name chat conversations. Reply with a title of at most six \
+words that captures what the conversation is about, in the language of the conversation. \
+Reply with the title only: no quotes, no markdown and no closing punctuation.";
+
+/// Which model names conversations after their first exchange
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(default)]
+pub struct TitleSettings {
+    pub enabled: bool,
+    /// Provider name as passed to `stream_api_request`, e.g. `anthropic` or a profile name
+    pub provider: String,
+    /// A small, cheap model is plenty
+    pub model: String,
+}
+
+impl Default for TitleSettings {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            provider: "anthropic".to_string(),
+            model: "claude-3-5-haiku-latest".to_string(),
+        }
+    }
+}
+
+impl TitleSettings {
+    fn validate(&self) -> ProxyResult<()> {
+        if self.enabled && (self.provider.trim().is_empty() || self.model.trim().is_empty()) {
+            return Err(ProxyError::Config(
+                "Automatic titles need a provider and a model".to_string(),
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// Persisted title settings
+pub struct TitleGenerator {
+    path: PathBuf,
+    settings: TitleSettings,
+}
+
+impl TitleGenerator {
+    pub fn load(path: PathBuf) -> Self {
+        let settings = settings::load(&path);
+        Self { path, settings }
+    }
+
+    pub fn settings(&self) -> TitleSettings {
+        self.settings.clone()
+    }
+
+    /// Replace the settings and persist them; they are kept only once written
+    pub fn update(&mut self, settings: TitleSettings) -> ProxyResult<()> {
+        settings.validate()?;
+        settings::save(&self.path, &settings).map_err(|e| {
+            ProxyError::Config(format!("Failed to write {}: {}", self.path.display(), e))
+        })?;
+        self.settings = settings;
+        Ok(())
+    }
+}
+
+/// Ask `provider` for a short title summarizing `messages`
+pub async fn generate_title(
+    provider: &(dyn ProxyProvider + Send + Sync),
+    model: &str,
+    messages: &[ChatMessage],
+) -> ProxyResult<String> {
+    let transcript = messages
+        .iter()
+        .filter_map(|message| {
+            let speaker = match message.role {
+                ChatRole::User => "User",
+                ChatRole::Assistant => "Assistant",
+                ChatRole::System | ChatRole::Tool => return None,
+            };
+            let text = message.text();
+            let text = text.trim();
+            if text.is_empty() {
+                return None;
+            }
+            let text: String = text.chars().take(MAX_MESSAGE_CHARS).collect();
+            Some(format!("{}: {}", speaker, text))
+        })
+        .collect::<Vec<_>>()
+        .join("\n\n");
+    if transcript.is_empty() {
+        return Err(ProxyError::Validation(
+            "conversation has no text to title".to_string(),
+        ));
+    }
+
+    let request = ChatRequest {
+        model: model.to_string(),
+        messages: vec![ChatMessage {
+            role: ChatRole::User,
+            content: vec![ContentPart::Text {
+                text: format!("Conversation:\n\n{}", transcript),
+            }],
+        }],
+        system: Some(TITLE_PROMPT.to_string()),
+        tools: Vec::new(),
+        builtin_tools: Vec::new(),
+        temperature: None,
+        max_tokens: Some(32),
+        stop: Vec::new(),
+        thinking: None,
+        prompt_caching: Some(false),
+    };
+    request.validate()?;
+    let body = provider.build_request(&request)?;
+    let response = provider.complete(body).await?;
+
+    let title = clean_title(&response);
+    if title.is_empty() {
+        return Err(ProxyError::Validation(
+            "model returned an empty title".to_string(),
+        ));
+    }
+    info!("Generated conversation title: {}", title);
+    Ok(title)
+}
+
+/// Models don't always follow instructions: keep the first line and drop a
+/// `Title:` label, wrapping quotes or markdown and closing punctuation
+fn clean_title(response: &str) -> String {
+    let line = response
+        .lines()
+        .map(str::trim)
+        .find(|line| !line.is_empty())
+        .unwrap_or_default();
+    let line = line
+        .strip_prefix("Title:")
+        .or_else(|| line.strip_prefix("title:"))
+        .unwrap_or(line);
+    let title = line
+        .trim_matches(|c: char| c.is_whitespace() || "\"'`*#_“”‘’".contains(c))
+        .trim_end_matches(['.', '!', ':', ';'])
+        .trim();
+    title.chars().take(MAX_TITLE_CHARS).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+    use std::fs;
+    use std::process;
+
+    #[test]
+    fn failed_update_keeps_the_previous_settings() {
+        // A file where the settings directory should be makes the write fail
+        let blocker = env::temp_dir().join(format!("pqp-titles-{}", process::id()));
+        fs::write(&blocker, "").unwrap();
+        let mut generator = TitleGenerator::load(blocker.join("titles.json"));
+
+        let changed = TitleSettings {
+            model: "gpt-4o-mini".to_string(),
+            ..TitleSettings::default()
+        };
+        assert!(generator.update(changed).is_err());
+        fs::remove_file(blocker).unwrap();
+        assert_eq!(generator.settings(), TitleSettings::default());
+    }
+
+    #[test]
+    fn update_rejects_an_enabled_setting_without_a_model() {
+        let path = env::temp_dir().join(format!("pqp-titles-{}.json", process::id()));
+        let mut generator = TitleGenerator::load(path.clone());
+
+        let invalid = TitleSettings {
+            model: " ".to_string(),
+            ..TitleSettings::default()
+        };
+        assert!(matches!(
+            generator.update(invalid),
+            Err(ProxyError::Config(_))
+        ));
+        assert!(!path.exists());
+    }
+}
@@ -0,0 +1,135 @@
+use log::{debug, warn};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::fs;
+use std::io::{self, Write};
+use std::path::Path;
+
+/// Read a JSON settings file. A missing file gives the defaults, and so does one
+/// that cannot be read or parsed, with a warning: a bad hand edit should not keep
+/// the app from starting. The file is left as it is until the settings are saved.
+pub(crate) fn load<T: DeserializeOwned + Default>(path: &Path) -> T {
+    let contents = match fs::read_to_string(path) {
+        Ok(contents) => contents,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => {
+            debug!("No settings at {}, using defaults", path.display());
+            return T::default();
+        }
+        Err(e) => {
+            warn!("Failed to read {}, using defaults: {}", path.display(), e);
+            return T::default();
+        }
+    };
+    serde_json::from_str(&contents).unwrap_or_else(|e| {
+        warn!("Failed to parse {}, using defaults: {}", path.display(), e);
+        T::default()
+    })
+}
+
+/// Write settings to `path` as JSON, creating its directory if needed. They go to
+/// a temporary file that is renamed over `path`, so a crash or a full disk
+/// mid-write leaves the previous settings intact.
+pub(crate) fn save<T: Serialize>(path: &Path, settings: &T) -> io::Result<()> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let contents = serde_json::to_vec_pretty(settings)?;
+
+    let mut temp_name = path.file_name().unwrap_or_default().to_os_string();
+    temp_name.push(".tmp");
+    let temp_path = path.with_file_name(temp_name);
+    let written = fs::File::create(&temp_path)
+        .and_then(|mut file| {
+            file.write_all(&contents)?;
+            file.sync_all()
+        })
+        .and_then(|()| fs::rename(&temp_path, path));
+    if written.is_err() {
+        let _ = fs::remove_file(&temp_path);
+    }
+    written
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde::Deserialize;
+    use std::env;
+    use std::path::PathBuf;
+    use std::process;
+
+    #[derive(Serialize, Deserialize, Debug, PartialEq)]
+    #[serde(default)]
+    struct Settings {
+        name: String,
+        count: u32,
+    }
+
+    impl Default for Settings {
+        fn default() -> Self {
+            Self {
+                name: "default".to_string(),
+                count: 1,
+            }
+        }
+    }
+
+    fn temp_dir(test: &str) -> PathBuf {
+        let dir = env::temp_dir().join(format!("pqp-settings-{}-{}", test, process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        dir
+    }
+
+    #[test]
+    fn missing_file_gives_defaults() {
+        let dir = temp_dir("missing");
+        assert_eq!(
+            load::<Settings>(&dir.join("settings.json")),
+            Settings::default()
+        );
+    }
+
+    #[test]
+    fn malformed_file_gives_defaults_and_is_kept() {
+        let dir = temp_dir("malformed");
+        let path = dir.join("settings.json");
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(&path, "{ \"name\": ").unwrap();
+
+        assert_eq!(load::<Settings>(&path), Settings::default());
+        assert_eq!(fs::read_to_string(&path).unwrap(), "{ \"name\": ");
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn save_creates_the_directory_and_round_trips() {
+        let dir = temp_dir("save");
+        let path = dir.join("nested").join("settings.json");
+        let settings = Settings {
+            name: "saved".to_string(),
+            count: 3,
+        };
+        save(&path, &settings).unwrap();
+
+        assert_eq!(load::<Settings>(&path), settings);
+        assert!(!dir.join("nested").join("settings.json.tmp").exists());
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn failed_save_leaves_the_previous_file() {
+        let dir = temp_dir("failed");
+        let path = dir.join("settings.json");
+        save(&path, &Settings::default()).unwrap();
+        // A directory where the temporary file should go makes the write fail
+        fs::create_dir_all(dir.join("settings.json.tmp")).unwrap();
+
+        let changed = Settings {
+            name: "changed".to_string(),
+            count: 2,
+        };
+        assert!(save(&path, &changed).is_err());
+        assert_eq!(load::<Settings>(&path), Settings::default());
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
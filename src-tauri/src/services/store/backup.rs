@@ -0,0 +1,570 @@
+use crate::services::settings;
+use crate::services::store::{migrations, paths, ConversationStore, StoreError, StoreResult};
+use chrono::{DateTime, Local, Utc};
+use log::{debug, info, warn};
+use rusqlite::{Connection, OpenFlags};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+/// Default backup directory, inside the data directory
+const BACKUP_DIR: &str = "backups";
+const BACKUP_EXTENSION: &str = "db";
+
+const AUTOMATIC_PREFIX: &str = "auto-";
+const MANUAL_PREFIX: &str = "manual-";
+const PRE_RESTORE_PREFIX: &str = "pre-restore-";
+
+/// How a backup came to be, recorded in its file name
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum BackupKind {
+    /// Taken on the schedule; only the newest `keep` are kept
+    Automatic,
+    /// Asked for by the user, or any other database file in the backup directory
+    Manual,
+    /// The database as it was right before a restore replaced it
+    PreRestore,
+}
+
+impl BackupKind {
+    fn prefix(&self) -> &'static str {
+        match self {
+            BackupKind::Automatic => AUTOMATIC_PREFIX,
+            BackupKind::Manual => MANUAL_PREFIX,
+            BackupKind::PreRestore => PRE_RESTORE_PREFIX,
+        }
+    }
+
+    fn from_file_name(name: &str) -> Self {
+        if name.starts_with(AUTOMATIC_PREFIX) {
+            BackupKind::Automatic
+        } else if name.starts_with(PRE_RESTORE_PREFIX) {
+            BackupKind::PreRestore
+        } else {
+            BackupKind::Manual
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "camelCase", default)]
+pub struct BackupSettings {
+    /// Take automatic backups
+    pub enabled: bool,
+    /// Hours between automatic backups
+    pub interval_hours: u32,
+    /// Automatic backups kept; older ones are deleted
+    pub keep: u32,
+    /// Where backups go; `backups` in the data directory when unset
+    pub directory: Option<PathBuf>,
+}
+
+impl Default for BackupSettings {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            interval_hours: 24,
+            keep: 7,
+            directory: None,
+        }
+    }
+}
+
+impl BackupSettings {
+    fn validate(&self) -> StoreResult<()> {
+        if self.interval_hours == 0 {
+            return Err(StoreError::Validation(
+                "backup interval must be at least one hour".to_string(),
+            ));
+        }
+        if self.keep == 0 {
+            return Err(StoreError::Validation(
+                "at least one automatic backup must be kept".to_string(),
+            ));
+        }
+        if self
+            .directory
+            .as_ref()
+            .is_some_and(|dir| !dir.is_absolute())
+        {
+            return Err(StoreError::Validation(
+                "backup directory must be an absolute path".to_string(),
+            ));
+        }
+        Ok(())
+    }
+
+    /// The backup directory for a database at `database_path`
+    pub fn directory_for(&self, database_path: &Path) -> PathBuf {
+        match &self.directory {
+            Some(dir) => dir.clone(),
+            None => database_path
+                .parent()
+                .unwrap_or(Path::new("."))
+                .join(BACKUP_DIR),
+        }
+    }
+}
+
+/// Persisted backup settings
+pub struct BackupConfig {
+    path: PathBuf,
+    settings: BackupSettings,
+}
+
+impl BackupConfig {
+    pub fn load(path: PathBuf) -> Self {
+        let settings = settings::load(&path);
+        Self { path, settings }
+    }
+
+    pub fn settings(&self) -> BackupSettings {
+        self.settings.clone()
+    }
+
+    /// Replace the settings and persist them
+    pub fn update(&mut self, settings: BackupSettings) -> StoreResult<()> {
+        settings.validate()?;
+        settings::save(&self.path, &settings).map_err(|e| {
+            StoreError::Io(format!("Failed to write {}: {}", self.path.display(), e))
+        })?;
+        self.settings = settings;
+        Ok(())
+    }
+}
+
+#[derive(Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct BackupInfo {
+    pub path: PathBuf,
+    pub kind: BackupKind,
+    pub size_bytes: u64,
+    /// When the file was written, RFC 3339
+    pub created_at: String,
+}
+
+impl BackupInfo {
+    pub(crate) fn read(path: &Path) -> StoreResult<Self> {
+        let metadata = fs::metadata(path)
+            .map_err(|e| StoreError::Io(format!("Failed to read {}: {}", path.display(), e)))?;
+        let created_at = metadata
+            .modified()
+            .map(|time| DateTime::<Utc>::from(time).to_rfc3339())
+            .unwrap_or_default();
+        let name = path.file_name().unwrap_or_default().to_string_lossy();
+        Ok(Self {
+            path: path.to_path_buf(),
+            kind: BackupKind::from_file_name(&name),
+            size_bytes: metadata.len(),
+            created_at,
+        })
+    }
+}
+
+/// Result of `PRAGMA integrity_check` and `PRAGMA foreign_key_check`
+#[derive(Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct IntegrityReport {
+    pub ok: bool,
+    /// One line per problem found; empty when `ok`
+    pub problems: Vec<String>,
+}
+
+#[derive(Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct RestoreSummary {
+    pub restored_from: PathBuf,
+    /// Schema version of the backup, before it was brought up to date
+    pub schema_version: u32,
+    /// Copy of the database that was replaced
+    pub previous: BackupInfo,
+}
+
+/// Take a backup of kind `kind` of the database at `database` into `dir`, named
+/// after the current time
+pub fn create_backup(database: &Path, dir: &Path, kind: BackupKind) -> StoreResult<BackupInfo> {
+    let name = format!(
+        "{}{}.{}",
+        kind.prefix(),
+        Local::now().format("%Y%m%d-%H%M%S"),
+        BACKUP_EXTENSION
+    );
+    let path = dir.join(name);
+    copy_database(database, &path)?;
+    info!("Backed up the database to {}", path.display());
+    BackupInfo::read(&path)
+}
+
+/// Database files in `dir`, newest first
+pub fn list_backups(dir: &Path) -> StoreResult<Vec<BackupInfo>> {
+    if !dir.exists() {
+        return Ok(Vec::new());
+    }
+    let entries = fs::read_dir(dir)
+        .map_err(|e| StoreError::Io(format!("Failed to read {}: {}", dir.display(), e)))?;
+    let mut backups = Vec::new();
+    for entry in entries.flatten() {
+        let path = entry.path();
+        let is_backup = path.is_file()
+            && path
+                .extension()
+                .is_some_and(|ext| ext.eq_ignore_ascii_case(BACKUP_EXTENSION));
+        if is_backup {
+            backups.push(BackupInfo::read(&path)?);
+        }
+    }
+    backups.sort_by(|a, b| b.created_at.cmp(&a.created_at));
+    Ok(backups)
+}
+
+/// Move the backups in `from` into `to`, as when the default backup directory
+/// follows the database to a new data directory. A backup whose name is already
+/// taken in `to`, or that cannot be moved, is left where it is. Returns the
+/// number moved.
+pub fn move_backups(from: &Path, to: &Path) -> StoreResult<usize> {
+    let backups = list_backups(from)?;
+    if backups.is_empty() {
+        return Ok(0);
+    }
+    paths::create_dir(to)?;
+    let mut moved = 0;
+    for backup in backups {
+        let target = to.join(backup.path.file_name().unwrap_or_default());
+        if target.exists() {
+            warn!(
+                "Not moving backup {}: {} already exists",
+                backup.path.display(),
+                target.display()
+            );
+            continue;
+        }
+        // A rename fails across file systems, where the backup is copied instead
+        let result = fs::rename(&backup.path, &target).or_else(|_| {
+            fs::copy(&backup.path, &target)
+                .inspect_err(|_| {
+                    let _ = fs::remove_file(&target);
+                })
+                .and_then(|_| fs::remove_file(&backup.path))
+        });
+        match result {
+            Ok(()) => moved += 1,
+            Err(e) => warn!("Failed to move backup {}: {}", backup.path.display(), e),
+        }
+    }
+    // Only succeeds once nothing is left behind
+    let _ = fs::remove_dir(from);
+    info!(
+        "Moved {} backups from {} to {}",
+        moved,
+        from.display(),
+        to.display()
+    );
+    Ok(moved)
+}
+
+/// Take an automatic backup if the newest one is older than the interval, then
+/// delete automatic backups beyond the number kept. Returns the new backup, if any.
+pub fn run_scheduled(
+    database: &Path,
+    settings: &BackupSettings,
+) -> StoreResult<Option<BackupInfo>> {
+    if !settings.enabled {
+        return Ok(None);
+    }
+    let dir = settings.directory_for(database);
+    let automatic: Vec<BackupInfo> = list_backups(&dir)?
+        .into_iter()
+        .filter(|backup| backup.kind == BackupKind::Automatic)
+        .collect();
+    let interval = Duration::from_secs(u64::from(settings.interval_hours) * 3600);
+    let due = match automatic.first() {
+        Some(newest) => DateTime::parse_from_rfc3339(&newest.created_at)
+            .ok()
+            .and_then(|created| (Utc::now() - created.with_timezone(&Utc)).to_std().ok())
+            .is_none_or(|age| age >= interval),
+        None => true,
+    };
+    if !due {
+        return Ok(None);
+    }
+
+    let backup = create_backup(database, &dir, BackupKind::Automatic)?;
+    // `automatic` predates the new backup, which is kept in addition to it
+    for old in automatic
+        .iter()
+        .skip(settings.keep.saturating_sub(1) as usize)
+    {
+        match fs::remove_file(&old.path) {
+            Ok(()) => debug!("Removed old backup {}", old.path.display()),
+            Err(e) => warn!("Failed to remove old backup {}: {}", old.path.display(), e),
+        }
+    }
+    Ok(Some(backup))
+}
+
+/// Check that `path` is an intact pqp database this version can open, returning
+/// its schema version
+pub fn inspect_backup(path: &Path) -> StoreResult<u32> {
+    if !path.is_file() {
+        return Err(StoreError::Validation(format!(
+            "{} is not a file",
+            path.display()
+        )));
+    }
+    let not_a_database = |e: rusqlite::Error| {
+        StoreError::Validation(format!("{} is not a database: {}", path.display(), e))
+    };
+    let conn = Connection::open_with_flags(path, OpenFlags::SQLITE_OPEN_READ_ONLY)
+        .map_err(not_a_database)?;
+    let version: u32 = conn
+        .query_row("PRAGMA user_version", [], |row| row.get(0))
+        .map_err(not_a_database)?;
+
+    let tables: u32 = conn.query_row(
+        "SELECT count(*) FROM sqlite_master
+         WHERE type = 'table' AND name IN ('conversations', 'messages')",
+        [],
+        |row| row.get(0),
+    )?;
+    // Databases from before the schema was versioned have version 0 but still
+    // migrate, so the tables are what tells a pqp database apart
+    if tables < 2 {
+        return Err(StoreError::Validation(format!(
+            "{} is not a pqp database",
+            path.display()
+        )));
+    }
+    let latest = migrations::latest_version();
+    if version > latest {
+        return Err(StoreError::Validation(format!(
+            "{} was made by a newer version of pqp (schema {}, this version supports up to {})",
+            path.display(),
+            version,
+            latest
+        )));
+    }
+
+    let report = integrity(&conn)?;
+    if !report.ok {
+        return Err(StoreError::Validation(format!(
+            "{} failed the integrity check: {}",
+            path.display(),
+            report.problems.join("; ")
+        )));
+    }
+    Ok(version)
+}
+
+/// Write a consistent copy of the database at `source` to `target`. `VACUUM INTO`
+/// reads through a connection of its own, so the store need not be locked while
+/// a large database is copied.
+pub(crate) fn copy_database(source: &Path, target: &Path) -> StoreResult<()> {
+    if target.exists() {
+        return Err(StoreError::Validation(format!(
+            "{} already exists; move or remove it first",
+            target.display()
+        )));
+    }
+    if let Some(parent) = target.parent() {
+        paths::create_dir(parent)?;
+    }
+    let conn = Connection::open_with_flags(source, OpenFlags::SQLITE_OPEN_READ_ONLY)?;
+    conn.busy_timeout(Duration::from_secs(5))?;
+    conn.execute("VACUUM INTO ?1", [target.to_string_lossy()])?;
+    Ok(())
+}
+
+/// Run `PRAGMA integrity_check` and `PRAGMA foreign_key_check` on the database at
+/// `database`, through a read-only connection of its own
+pub fn check_integrity(database: &Path) -> StoreResult<IntegrityReport> {
+    let conn = Connection::open_with_flags(database, OpenFlags::SQLITE_OPEN_READ_ONLY)?;
+    conn.busy_timeout(Duration::from_secs(5))?;
+    integrity(&conn)
+}
+
+/// Replace the store's database with the backup at `path`. The backup is checked
+/// first, and the current database is backed up into `dir` before it is replaced.
+pub fn restore(
+    store: &mut ConversationStore,
+    path: &Path,
+    dir: &Path,
+) -> StoreResult<RestoreSummary> {
+    if path == store.path() {
+        return Err(StoreError::Validation(
+            "cannot restore the database from itself".to_string(),
+        ));
+    }
+    let schema_version = inspect_backup(path)?;
+    let previous = create_backup(store.path(), dir, BackupKind::PreRestore)?;
+    store.replace_database(path, &previous.path)?;
+    info!(
+        "Restored the database from {} (schema {}); the previous one is at {}",
+        path.display(),
+        schema_version,
+        previous.path.display()
+    );
+    Ok(RestoreSummary {
+        restored_from: path.to_path_buf(),
+        schema_version,
+        previous,
+    })
+}
+
+fn integrity(conn: &Connection) -> StoreResult<IntegrityReport> {
+    let mut problems: Vec<String> = conn
+        .prepare("PRAGMA integrity_check")?
+        .query_map([], |row| row.get(0))?
+        .collect::<rusqlite::Result<_>>()?;
+    if problems.len() == 1 && problems[0] == "ok" {
+        problems.clear();
+    }
+
+    let mut stmt = conn.prepare("PRAGMA foreign_key_check")?;
+    let dangling = stmt.query_map([], |row| {
+        let table: String = row.get(0)?;
+        let rowid: Option<i64> = row.get(1)?;
+        let parent: String = row.get(2)?;
+        Ok(format!(
+            "{} row {} refers to a missing {} row",
+            table,
+            rowid.map(|id| id.to_string()).unwrap_or_default(),
+            parent
+        ))
+    })?;
+    for problem in dangling {
+        problems.push(problem?);
+    }
+
+    Ok(IntegrityReport {
+        ok: problems.is_empty(),
+        problems,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+    use std::process;
+
+    fn temp_dir(test: &str) -> PathBuf {
+        let dir = env::temp_dir().join(format!("pqp-backup-{}-{}", test, process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        dir
+    }
+
+    fn names(store: &ConversationStore) -> Vec<String> {
+        let conversations = store.list_conversations().unwrap();
+        conversations.into_iter().map(|c| c.name).collect()
+    }
+
+    #[test]
+    fn restore_brings_back_the_backup_and_keeps_the_replaced_database() {
+        let dir = temp_dir("restore");
+        let backups = dir.join(BACKUP_DIR);
+        let mut store = ConversationStore::open(dir.join("pqp_chats.db")).unwrap();
+        store.create_conversation("Kept").unwrap();
+        let backup = create_backup(store.path(), &backups, BackupKind::Manual).unwrap();
+        assert_eq!(backup.kind, BackupKind::Manual);
+        assert_eq!(
+            inspect_backup(&backup.path).unwrap(),
+            migrations::latest_version()
+        );
+
+        store.create_conversation("After the backup").unwrap();
+        let summary = restore(&mut store, &backup.path, &backups).unwrap();
+        assert_eq!(names(&store), ["Kept"]);
+        assert_eq!(summary.previous.kind, BackupKind::PreRestore);
+        assert!(check_integrity(store.path()).unwrap().ok);
+
+        // The store writes to the restored file, not a stand-in
+        store.create_conversation("After the restore").unwrap();
+        drop(store);
+        let mut store = ConversationStore::open(dir.join("pqp_chats.db")).unwrap();
+        assert_eq!(names(&store), ["After the restore", "Kept"]);
+
+        let previous = ConversationStore::open(summary.previous.path).unwrap();
+        assert_eq!(names(&previous), ["After the backup", "Kept"]);
+        let own_path = store.path().to_path_buf();
+        assert!(matches!(
+            restore(&mut store, &own_path, &backups),
+            Err(StoreError::Validation(_))
+        ));
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn failed_restore_puts_the_previous_database_back() {
+        let dir = temp_dir("failed-restore");
+        let mut store = ConversationStore::open(dir.join("pqp_chats.db")).unwrap();
+        store.create_conversation("Kept").unwrap();
+        // Passes for a pqp database, but migration 3 cannot index `messages`
+        let broken = dir.join("broken.db");
+        Connection::open(&broken)
+            .unwrap()
+            .execute_batch("CREATE TABLE conversations (x); CREATE TABLE messages (y);")
+            .unwrap();
+        let previous = create_backup(store.path(), &dir, BackupKind::PreRestore).unwrap();
+
+        assert!(store.replace_database(&broken, &previous.path).is_err());
+        assert_eq!(names(&store), ["Kept"]);
+        store.create_conversation("After the restore").unwrap();
+        drop(store);
+        let store = ConversationStore::open(dir.join("pqp_chats.db")).unwrap();
+        assert_eq!(names(&store), ["After the restore", "Kept"]);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn copy_database_refuses_to_overwrite() {
+        let dir = temp_dir("copy");
+        let store = ConversationStore::open(dir.join("pqp_chats.db")).unwrap();
+        let target = dir.join("copy.db");
+        copy_database(store.path(), &target).unwrap();
+        assert!(matches!(
+            copy_database(store.path(), &target),
+            Err(StoreError::Validation(_))
+        ));
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn move_backups_keeps_names_already_taken() {
+        let dir = temp_dir("move");
+        let (from, to) = (
+            dir.join("old").join("backups"),
+            dir.join("new").join("backups"),
+        );
+        fs::create_dir_all(&from).unwrap();
+        fs::create_dir_all(&to).unwrap();
+        for name in ["auto-20260101-000000.db", "manual-20260102-000000.db"] {
+            fs::write(from.join(name), name).unwrap();
+        }
+        fs::write(to.join("manual-20260102-000000.db"), "newer").unwrap();
+
+        assert_eq!(move_backups(&from, &to).unwrap(), 1);
+        assert!(to.join("auto-20260101-000000.db").is_file());
+        assert_eq!(
+            fs::read_to_string(to.join("manual-20260102-000000.db")).unwrap(),
+            "newer"
+        );
+        // The backup that could not move keeps the old directory in place
+        assert!(from.join("manual-20260102-000000.db").is_file());
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn move_backups_removes_the_emptied_directory() {
+        let dir = temp_dir("empty");
+        let (from, to) = (dir.join("old"), dir.join("new"));
+        fs::create_dir_all(&from).unwrap();
+        fs::write(from.join("auto-20260101-000000.db"), "").unwrap();
+
+        assert_eq!(move_backups(&from, &to).unwrap(), 1);
+        assert!(!from.exists());
+        assert_eq!(move_backups(&from, &to).unwrap(), 0);
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,1231 @@
+use crate::services::proxy::request::deserialize_content;
+use crate::services::proxy::{ChatRole, ContentPart};
+use crate::services::store::backup;
+use crate::services::store::import::ImportedConversation;
+use crate::services::store::search::{self, SearchFilters, SearchHit};
+use crate::services::store::{migrations, paths, StoreError, StoreResult};
+use chrono::Utc;
+use log::{debug, info, warn};
+use rusqlite::{params, types::Type, Connection, OptionalExtension, Row};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+/// Longest conversation name accepted, in characters
+const MAX_NAME_CHARS: usize = 200;
+
+/// Name for imported conversations that arrive without one
+const IMPORTED_NAME: &str = "Imported conversation";
+
+/// Where a conversation's name came from; only default names are replaced by a
+/// generated title
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum NameSource {
+    Default,
+    Generated,
+    User,
+}
+
+impl NameSource {
+    fn parse(name: &str) -> Option<Self> {
+        match name {
+            "default" => Some(NameSource::Default),
+            "generated" => Some(NameSource::Generated),
+            "user" => Some(NameSource::User),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct Conversation {
+    pub id: i64,
+    pub name: String,
+    pub name_source: NameSource,
+    /// Last message of the branch currently shown
+    pub active_leaf_id: Option<i64>,
+    pub created_at: String,
+    pub updated_at: String,
+}
+
+impl Conversation {
+    fn from_row(row: &Row<'_>) -> rusqlite::Result<Self> {
+        let name_source: String = row.get("name_source")?;
+        let name_source = NameSource::parse(&name_source).ok_or_else(|| {
+            rusqlite::Error::FromSqlConversionFailure(
+                0,
+                Type::Text,
+                format!("unknown name source '{}'", name_source).into(),
+            )
+        })?;
+        Ok(Self {
+            id: row.get("id")?,
+            name: row.get("name")?,
+            name_source,
+            active_leaf_id: row.get("active_leaf_id")?,
+            created_at: row.get("created_at")?,
+            updated_at: row.get("updated_at")?,
+        })
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct TokenUsage {
+    pub prompt_tokens: u64,
+    pub completion_tokens: u64,
+}
+
+/// A message as stored: its parts in the same shape as a chat request's content,
+/// plus how it was produced when it came from a model
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct StoredMessage {
+    pub id: i64,
+    pub conversation_id: i64,
+    /// The message this one answers or follows; `None` for the first message.
+    /// Messages sharing a parent are alternative branches.
+    pub parent_id: Option<i64>,
+    pub role: ChatRole,
+    pub parts: Vec<ContentPart>,
+    pub model: Option<String>,
+    pub provider: Option<String>,
+    pub usage: Option<TokenUsage>,
+    pub finish_reason: Option<String>,
+    /// Time from sending the request to the end of the response
+    pub latency_ms: Option<u64>,
+    pub created_at: String,
+}
+
+impl StoredMessage {
+    fn from_row(row: &Row<'_>) -> rusqlite::Result<Self> {
+        let role: String = row.get("role")?;
+        let role = ChatRole::parse(&role).ok_or_else(|| {
+            rusqlite::Error::FromSqlConversionFailure(
+                0,
+                Type::Text,
+                format!("unknown role '{}'", role).into(),
+            )
+        })?;
+        let parts: String = row.get("parts")?;
+        let parts = serde_json::from_str(&parts)
+            .map_err(|e| rusqlite::Error::FromSqlConversionFailure(0, Type::Text, Box::new(e)))?;
+        let prompt_tokens: Option<u64> = row.get("prompt_tokens")?;
+        let completion_tokens: Option<u64> = row.get("completion_tokens")?;
+        let usage = match (prompt_tokens, completion_tokens) {
+            (None, None) => None,
+            (prompt, completion) => Some(TokenUsage {
+                prompt_tokens: prompt.unwrap_or_default(),
+                completion_tokens: completion.unwrap_or_default(),
+            }),
+        };
+
+        Ok(Self {
+            id: row.get("id")?,
+            conversation_id: row.get("conversation_id")?,
+            parent_id: row.get("parent_id")?,
+            role,
+            parts,
+            model: row.get("model")?,
+            provider: row.get("provider")?,
+            usage,
+            finish_reason: row.get("finish_reason")?,
+            latency_ms: row.get("latency_ms")?,
+            created_at: row.get("created_at")?,
+        })
+    }
+}
+
+/// A message to append, as sent by the frontend
+#[derive(Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct NewMessage {
+    pub role: ChatRole,
+    /// Either a plain string or an array of typed parts
+    #[serde(deserialize_with = "deserialize_content")]
+    pub parts: Vec<ContentPart>,
+    /// Defaults to the last message of the active branch
+    #[serde(default)]
+    pub parent_id: Option<i64>,
+    #[serde(default)]
+    pub model: Option<String>,
+    #[serde(default)]
+    pub provider: Option<String>,
+    #[serde(default)]
+    pub usage: Option<TokenUsage>,
+    #[serde(default)]
+    pub finish_reason: Option<String>,
+    #[serde(default)]
+    pub latency_ms: Option<u64>,
+}
+
+impl NewMessage {
+    fn validate(&self) -> StoreResult<()> {
+        validate_parts(self.role, &self.parts)
+    }
+}
+
+/// What to do when the database is moved into a directory that already holds one
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum ExistingDatabase {
+    /// Switch to the database already there
+    Use,
+    /// Overwrite it with the current database
+    Replace,
+}
+
+/// The chat database. All reads and writes go through here, so the webview
+/// never needs SQL access of its own.
+pub struct ConversationStore {
+    path: PathBuf,
+    /// `None` only while a restore swaps files, or after one could open neither
+    /// the restored database nor the previous one again
+    conn: Option<Connection>,
+}
+
+impl ConversationStore {
+    /// Open (or create) the database at `path` and bring its schema up to date
+    pub fn open(path: PathBuf) -> StoreResult<Self> {
+        let conn = connect(&path)?;
+        info!("Conversation store opened at {}", path.display());
+        Ok(Self {
+            path,
+            conn: Some(conn),
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    fn conn(&self) -> StoreResult<&Connection> {
+        self.conn.as_ref().ok_or_else(|| not_open(&self.path))
+    }
+
+    fn conn_mut(&mut self) -> StoreResult<&mut Connection> {
+        self.conn.as_mut().ok_or_else(|| not_open(&self.path))
+    }
+
+    /// Move the database to `path`. The copy is made with `VACUUM INTO`, so it is
+    /// consistent even with a write-ahead log, and opened before switching over;
+    /// the old files are only removed once the new database is in use.
+    ///
+    /// If `path` already holds a database, `existing` says whether to switch to
+    /// it, leaving the current database where it is, or to overwrite it; without
+    /// a choice nothing changes and the error is `DatabaseExists`.
+    pub fn relocate(
+        &mut self,
+        path: PathBuf,
+        existing: Option<ExistingDatabase>,
+    ) -> StoreResult<()> {
+        // A store left closed by a failed restore has nothing trustworthy to move
+        self.conn()?;
+        if path.exists() {
+            match existing {
+                None => return Err(StoreError::DatabaseExists(path)),
+                Some(ExistingDatabase::Use) => {
+                    info!("Switching to the database at {}", path.display());
+                    self.conn = Some(connect(&path)?);
+                    self.path = path;
+                    return Ok(());
+                }
+                Some(ExistingDatabase::Replace) => {
+                    info!(
+                        "Replacing the database at {} with {}",
+                        path.display(),
+                        self.path.display()
+                    );
+                    // Copy next to it first, so the database there is only
+                    // replaced once the copy is complete
+                    let staged = PathBuf::from(format!("{}.relocate", path.display()));
+                    remove_if_exists(&staged)?;
+                    backup::copy_database(&self.path, &staged)?;
+                    let replaced = remove_log_files(&path).and_then(|()| {
+                        fs::rename(&staged, &path).map_err(|e| {
+                            StoreError::Io(format!("Failed to replace {}: {}", path.display(), e))
+                        })
+                    });
+                    if replaced.is_err() {
+                        let _ = fs::remove_file(&staged);
+                    }
+                    replaced?;
+                }
+            }
+        } else {
+            info!(
+                "Moving database from {} to {}",
+                self.path.display(),
+                path.display()
+            );
+            backup::copy_database(&self.path, &path)?;
+        }
+        let conn = match connect(&path) {
+            Ok(conn) => conn,
+            Err(e) => {
+                let _ = fs::remove_file(&path);
+                return Err(e);
+            }
+        };
+
+        let old_path = std::mem::replace(&mut self.path, path);
+        self.conn = Some(conn);
+        for suffix in ["", "-wal", "-shm"] {
+            let file = PathBuf::from(format!("{}{}", old_path.display(), suffix));
+            match fs::remove_file(&file) {
+                Ok(()) => {}
+                Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+                Err(e) => warn!(
+                    "Failed to remove old database file {}: {}",
+                    file.display(),
+                    e
+                ),
+            }
+        }
+        Ok(())
+    }
+
+    /// Swap in the database at `source`, which must already have been checked
+    /// with `backup::inspect_backup`, and bring its schema up to date. If the
+    /// restored database cannot be opened, `previous`, a copy of the database
+    /// being replaced, is put back instead.
+    pub(crate) fn replace_database(&mut self, source: &Path, previous: &Path) -> StoreResult<()> {
+        // Copy the backup next to the database first, so the swap is a rename
+        let staged = PathBuf::from(format!("{}.restore", self.path.display()));
+        remove_if_exists(&staged)?;
+        backup::copy_database(source, &staged)?;
+
+        // Close the current database so its write-ahead log is checkpointed and
+        // nothing from it can be replayed onto the restored file
+        self.conn = None;
+        let restored = remove_log_files(&self.path)
+            .and_then(|()| {
+                fs::rename(&staged, &self.path).map_err(|e| {
+                    StoreError::Io(format!("Failed to replace {}: {}", self.path.display(), e))
+                })
+            })
+            .and_then(|()| connect(&self.path));
+        match restored {
+            Ok(conn) => {
+                self.conn = Some(conn);
+                Ok(())
+            }
+            Err(e) => {
+                warn!(
+                    "Failed to restore the database, putting back {}: {}",
+                    previous.display(),
+                    e
+                );
+                let _ = fs::remove_file(&staged);
+                if let Err(put_back) = self.put_back(previous) {
+                    warn!("Failed to put back the previous database: {}", put_back);
+                }
+                Err(e)
+            }
+        }
+    }
+
+    /// Reopen the database from `previous` after a failed restore. Until this
+    /// succeeds the store stays closed, so writes fail rather than go nowhere.
+    fn put_back(&mut self, previous: &Path) -> StoreResult<()> {
+        remove_log_files(&self.path)?;
+        fs::copy(previous, &self.path)
+            .map_err(|e| StoreError::Io(format!("Failed to copy {}: {}", previous.display(), e)))?;
+        self.conn = Some(connect(&self.path)?);
+        Ok(())
+    }
+
+    pub fn create_conversation(&mut self, name: &str) -> StoreResult<Conversation> {
+        let name = validate_name(name)?;
+        let now = now();
+        self.conn()?.execute(
+            "INSERT INTO conversations (name, created_at, updated_at) VALUES (?1, ?2, ?2)",
+            params![name, now],
+        )?;
+        let id = self.conn()?.last_insert_rowid();
+        debug!("Created conversation {} ({})", id, name);
+        self.get_conversation(id)
+    }
+
+    /// All conversations, most recently active first
+    pub fn list_conversations(&self) -> StoreResult<Vec<Conversation>> {
+        let mut stmt = self
+            .conn()?
+            .prepare("SELECT * FROM conversations ORDER BY updated_at DESC, id DESC")?;
+        let conversations = stmt
+            .query_map([], Conversation::from_row)?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+        Ok(conversations)
+    }
+
+    pub fn get_conversation(&self, id: i64) -> StoreResult<Conversation> {
+        self.conn()?
+            .query_row(
+                "SELECT * FROM conversations WHERE id = ?1",
+                [id],
+                Conversation::from_row,
+            )
+            .optional()?
+            .ok_or(StoreError::ConversationNotFound(id))
+    }
+
+    pub fn rename_conversation(&mut self, id: i64, name: &str) -> StoreResult<Conversation> {
+        let name = validate_name(name)?;
+        let updated = self.conn()?.execute(
+            "UPDATE conversations SET name = ?1, name_source = 'user', updated_at = ?2
+             WHERE id = ?3",
+            params![name, now(), id],
+        )?;
+        if updated == 0 {
+            return Err(StoreError::ConversationNotFound(id));
+        }
+        self.get_conversation(id)
+    }
+
+    /// The branch to title, if `message` completes the first exchange of a
+    /// conversation that still has its default name
+    pub fn needs_title(&self, message: &StoredMessage) -> StoreResult<Option<Vec<StoredMessage>>> {
+        if message.role != ChatRole::Assistant {
+            return Ok(None);
+        }
+        let conversation = self.get_conversation(message.conversation_id)?;
+        if conversation.name_source != NameSource::Default {
+            return Ok(None);
+        }
+        let branch = self.get_messages(message.conversation_id)?;
+        let replies = branch
+            .iter()
+            .filter(|m| m.role == ChatRole::Assistant)
+            .count();
+        Ok((replies == 1).then_some(branch))
+    }
+
+    /// Apply a generated title, unless the conversation was renamed (or deleted)
+    /// while it was being generated. Returns the updated conversation if applied.
+    pub fn set_generated_name(&mut self, id: i64, name: &str) -> StoreResult<Option<Conversation>> {
+        let name = validate_name(name)?;
+        let updated = self.conn()?.execute(
+            "UPDATE conversations SET name = ?1, name_source = 'generated', updated_at = ?2
+             WHERE id = ?3 AND name_source = 'default'",
+            params![name, now(), id],
+        )?;
+        if updated == 0 {
+            return Ok(None);
+        }
+        self.get_conversation(id).map(Some)
+    }
+
+    /// Delete a conversation; its messages go with it
+    pub fn delete_conversation(&mut self, id: i64) -> StoreResult<()> {
+        let deleted = self
+            .conn()?
+            .execute("DELETE FROM conversations WHERE id = ?1", [id])?;
+        if deleted == 0 {
+            return Err(StoreError::ConversationNotFound(id));
+        }
+        info!("Deleted conversation {}", id);
+        Ok(())
+    }
+
+    /// Add a message after the active branch's last message, or after `parent_id`
+    /// when given, and make it the conversation's active leaf
+    pub fn append_message(
+        &mut self,
+        conversation_id: i64,
+        message: NewMessage,
+    ) -> StoreResult<StoredMessage> {
+        message.validate()?;
+        let tx = self.conn_mut()?.transaction()?;
+        let parent_id = match message.parent_id {
+            Some(parent_id) => {
+                let parent = message_by_id(&tx, parent_id)?;
+                if parent.conversation_id != conversation_id {
+                    return Err(StoreError::Validation(format!(
+                        "message {} belongs to another conversation",
+                        parent_id
+                    )));
+                }
+                Some(parent_id)
+            }
+            None => tx
+                .query_row(
+                    "SELECT active_leaf_id FROM conversations WHERE id = ?1",
+                    [conversation_id],
+                    |row| row.get(0),
+                )
+                .optional()?
+                .ok_or(StoreError::ConversationNotFound(conversation_id))?,
+        };
+        let stored = insert_message(&tx, conversation_id, parent_id, &message)?;
+        tx.commit()?;
+        Ok(stored)
+    }
+
+    /// Add an alternative to an existing message, sharing its parent: an edited user
+    /// message or a regenerated answer. The new message becomes the active leaf; the
+    /// original and everything after it stay reachable through `switch_branch`.
+    pub fn fork_message(
+        &mut self,
+        message_id: i64,
+        message: NewMessage,
+    ) -> StoreResult<StoredMessage> {
+        message.validate()?;
+        let tx = self.conn_mut()?.transaction()?;
+        let original = message_by_id(&tx, message_id)?;
+        if original.role != message.role {
+            return Err(StoreError::Validation(format!(
+                "message {} is a {} message and can only be forked by another",
+                message_id,
+                original.role.as_str()
+            )));
+        }
+        let stored = insert_message(&tx, original.conversation_id, original.parent_id, &message)?;
+        tx.commit()?;
+        debug!("Forked message {} as {}", message_id, stored.id);
+        Ok(stored)
+    }
+
+    /// Make the branch through `message_id` active, continuing below it along the
+    /// most recent reply at each step, and return that branch
+    pub fn switch_branch(&mut self, message_id: i64) -> StoreResult<Vec<StoredMessage>> {
+        let message = message_by_id(self.conn()?, message_id)?;
+        // Replies always have higher ids than their parent, so the last id on the
+        // way down is the leaf
+        let leaf_id: i64 = self.conn()?.query_row(
+            "WITH RECURSIVE descent(id) AS (
+                SELECT ?1
+                UNION ALL
+                SELECT (SELECT MAX(child.id) FROM messages child WHERE child.parent_id = descent.id)
+                FROM descent WHERE descent.id IS NOT NULL
+            )
+            SELECT MAX(id) FROM descent",
+            [message_id],
+            |row| row.get(0),
+        )?;
+        self.conn()?.execute(
+            "UPDATE conversations SET active_leaf_id = ?1 WHERE id = ?2",
+            params![leaf_id, message.conversation_id],
+        )?;
+        debug!(
+            "Switched conversation {} to the branch ending at {}",
+            message.conversation_id, leaf_id
+        );
+        self.get_messages(message.conversation_id)
+    }
+
+    /// The alternatives to a message, itself included, oldest first
+    pub fn list_siblings(&self, message_id: i64) -> StoreResult<Vec<StoredMessage>> {
+        let message = message_by_id(self.conn()?, message_id)?;
+        let mut stmt = self.conn()?.prepare(
+            "SELECT * FROM messages WHERE conversation_id = ?1 AND parent_id IS ?2 ORDER BY id",
+        )?;
+        let siblings = stmt
+            .query_map(
+                params![message.conversation_id, message.parent_id],
+                StoredMessage::from_row,
+            )?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+        Ok(siblings)
+    }
+
+    /// Full-text search over message text and conversation names, across all
+    /// branches, best matches first
+    pub fn search_messages(
+        &self,
+        query: &str,
+        filters: &SearchFilters,
+    ) -> StoreResult<Vec<SearchHit>> {
+        search::search(self.conn()?, query, filters)
+    }
+
+    /// Add a conversation from another app's export with its original timestamps,
+    /// all in one transaction. Returns `None` without changing anything if the
+    /// same conversation was imported from `source` before.
+    pub fn import_conversation(
+        &mut self,
+        source: &str,
+        imported: &ImportedConversation,
+    ) -> StoreResult<Option<Conversation>> {
+        let tx = self.conn_mut()?.transaction()?;
+        let seen = tx
+            .query_row(
+                "SELECT 1 FROM conversations WHERE import_source = ?1 AND import_id = ?2",
+                params![source, imported.source_id],
+                |_| Ok(()),
+            )
+            .optional()?
+            .is_some();
+        if seen {
+            return Ok(None);
+        }
+
+        // Titles from other apps may be empty or longer than ours
+        let name: String = imported.name.trim().chars().take(MAX_NAME_CHARS).collect();
+        let name = match name.trim_end() {
+            "" => IMPORTED_NAME,
+            name => name,
+        };
+        tx.execute(
+            "INSERT INTO conversations (
+                name, name_source, created_at, updated_at, import_source, import_id
+             ) VALUES (?1, 'user', ?2, ?3, ?4, ?5)",
+            params![
+                name,
+                imported.created_at,
+                imported.updated_at,
+                source,
+                imported.source_id
+            ],
+        )?;
+        let conversation_id = tx.last_insert_rowid();
+
+        let mut ids: Vec<i64> = Vec::with_capacity(imported.messages.len());
+        for message in &imported.messages {
+            validate_parts(message.role, &message.parts)?;
+            let parent_id = match message.parent {
+                Some(index) => Some(*ids.get(index).ok_or_else(|| {
+                    StoreError::Validation(
+                        "an imported message must come after its parent".to_string(),
+                    )
+                })?),
+                None => None,
+            };
+            tx.execute(
+                "INSERT INTO messages (
+                    conversation_id, parent_id, role, parts, model, created_at
+                 ) VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+                params![
+                    conversation_id,
+                    parent_id,
+                    message.role.as_str(),
+                    serde_json::to_string(&message.parts)?,
+                    message.model,
+                    message.created_at,
+                ],
+            )?;
+            ids.push(tx.last_insert_rowid());
+        }
+
+        let leaf_id = imported
+            .active_leaf
+            .and_then(|index| ids.get(index))
+            .or(ids.last());
+        tx.execute(
+            "UPDATE conversations SET active_leaf_id = ?1 WHERE id = ?2",
+            params![leaf_id, conversation_id],
+        )?;
+        tx.commit()?;
+        debug!(
+            "Imported conversation {} from {} as {} with {} messages",
+            imported.source_id,
+            source,
+            conversation_id,
+            ids.len()
+        );
+        self.get_conversation(conversation_id).map(Some)
+    }
+
+    /// Every message of a conversation, all branches, in the order they were added
+    pub fn all_messages(&self, conversation_id: i64) -> StoreResult<Vec<StoredMessage>> {
+        self.get_conversation(conversation_id)?;
+        let mut stmt = self
+            .conn()?
+            .prepare("SELECT * FROM messages WHERE conversation_id = ?1 ORDER BY id")?;
+        let messages = stmt
+            .query_map([conversation_id], StoredMessage::from_row)?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+        Ok(messages)
+    }
+
+    /// The active branch of a conversation, from its first message to the active leaf
+    pub fn get_messages(&self, conversation_id: i64) -> StoreResult<Vec<StoredMessage>> {
+        // Distinguish an empty conversation from a missing one
+        self.get_conversation(conversation_id)?;
+        let mut stmt = self.conn()?.prepare(
+            "WITH RECURSIVE branch(id) AS (
+                SELECT active_leaf_id FROM conversations WHERE id = ?1
+                UNION ALL
+                SELECT messages.parent_id FROM messages JOIN branch ON messages.id = branch.id
+            )
+            SELECT messages.* FROM messages JOIN branch ON messages.id = branch.id
+            ORDER BY messages.id",
+        )?;
+        let messages = stmt
+            .query_map([conversation_id], StoredMessage::from_row)?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+        Ok(messages)
+    }
+}
+
+fn message_by_id(conn: &Connection, id: i64) -> StoreResult<StoredMessage> {
+    conn.query_row(
+        "SELECT * FROM messages WHERE id = ?1",
+        [id],
+        StoredMessage::from_row,
+    )
+    .optional()?
+    .ok_or(StoreError::MessageNotFound(id))
+}
+
+/// Insert a message under `parent_id` and make it the conversation's active leaf
+fn insert_message(
+    conn: &Connection,
+    conversation_id: i64,
+    parent_id: Option<i64>,
+    message: &NewMessage,
+) -> StoreResult<StoredMessage> {
+    let usage = message.usage;
+    conn.execute(
+        "INSERT INTO messages (
+            conversation_id, parent_id, role, parts, model, provider,
+            prompt_tokens, completion_tokens, finish_reason, latency_ms, created_at
+         ) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11)",
+        params![
+            conversation_id,
+            parent_id,
+            message.role.as_str(),
+            serde_json::to_string(&message.parts)?,
+            message.model,
+            message.provider,
+            usage.map(|u| u.prompt_tokens),
+            usage.map(|u| u.completion_tokens),
+            message.finish_reason,
+            message.latency_ms,
+            now(),
+        ],
+    )?;
+    let id = conn.last_insert_rowid();
+    let updated = conn.execute(
+        "UPDATE conversations SET active_leaf_id = ?1, updated_at = ?2 WHERE id = ?3",
+        params![id, now(), conversation_id],
+    )?;
+    if updated == 0 {
+        return Err(StoreError::ConversationNotFound(conversation_id));
+    }
+    message_by_id(conn, id)
+}
+
+fn validate_parts(role: ChatRole, parts: &[ContentPart]) -> StoreResult<()> {
+    if parts.is_empty() {
+        return Err(StoreError::Validation(
+            "a message needs at least one part".to_string(),
+        ));
+    }
+    if let Some(part) = parts.iter().find(|p| !p.allowed_in(role)) {
+        return Err(StoreError::Validation(format!(
+            "a {} message cannot contain a {} part",
+            role.as_str(),
+            part.kind()
+        )));
+    }
+    Ok(())
+}
+
+fn validate_name(name: &str) -> StoreResult<&str> {
+    let name = name.trim();
+    if name.is_empty() {
+        return Err(StoreError::Validation(
+            "conversation name cannot be empty".to_string(),
+        ));
+    }
+    if name.chars().count() > MAX_NAME_CHARS {
+        return Err(StoreError::Validation(format!(
+            "conversation name is longer than {} characters",
+            MAX_NAME_CHARS
+        )));
+    }
+    Ok(name)
+}
+
+/// Open the database at `path`, creating it if needed, and migrate it
+fn connect(path: &Path) -> StoreResult<Connection> {
+    if let Some(parent) = path.parent() {
+        paths::create_dir(parent)?;
+    }
+    let mut conn = Connection::open(path)?;
+    conn.busy_timeout(Duration::from_secs(5))?;
+    conn.pragma_update(None, "journal_mode", "WAL")?;
+    migrations::migrate(&mut conn)?;
+    Ok(conn)
+}
+
+fn not_open(path: &Path) -> StoreError {
+    StoreError::Io(format!(
+        "{} is not open after a failed restore; restore a backup or restart pqp",
+        path.display()
+    ))
+}
+
+/// Remove the write-ahead log and shared memory files of the database at `path`
+fn remove_log_files(path: &Path) -> StoreResult<()> {
+    ["-wal", "-shm"].iter().try_for_each(|suffix| {
+        remove_if_exists(&PathBuf::from(format!("{}{}", path.display(), suffix)))
+    })
+}
+
+fn remove_if_exists(path: &Path) -> StoreResult<()> {
+    match fs::remove_file(path) {
+        Ok(()) => Ok(()),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(()),
+        Err(e) => Err(StoreError::Io(format!(
+            "Failed to remove {}: {}",
+            path.display(),
+            e
+        ))),
+    }
+}
+
+fn now() -> String {
+    Utc::now().to_rfc3339()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+    use std::process;
+
+    fn store() -> ConversationStore {
+        let mut conn = Connection::open_in_memory().unwrap();
+        migrations::migrate(&mut conn).unwrap();
+        ConversationStore {
+            path: PathBuf::from(":memory:"),
+            conn: Some(conn),
+        }
+    }
+
+    fn text(role: ChatRole, text: &str) -> NewMessage {
+        NewMessage {
+            role,
+            parts: vec![ContentPart::Text {
+                text: text.to_string(),
+            }],
+            parent_id: None,
+            model: None,
+            provider: None,
+            usage: None,
+            finish_reason: None,
+            latency_ms: None,
+        }
+    }
+
+    fn texts(messages: &[StoredMessage]) -> Vec<String> {
+        messages
+            .iter()
+            .flat_map(|m| &m.parts)
+            .filter_map(|part| match part {
+                ContentPart::Text { text } => Some(text.clone()),
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn creates_lists_and_renames_conversations() {
+        let mut store = store();
+        let first = store.create_conversation("  First  ").unwrap();
+        let second = store.create_conversation("Second").unwrap();
+        assert_eq!(first.name, "First");
+        assert_eq!(first.name_source, NameSource::Default);
+        assert_eq!(first.active_leaf_id, None);
+
+        let ids: Vec<i64> = store
+            .list_conversations()
+            .unwrap()
+            .iter()
+            .map(|c| c.id)
+            .collect();
+        assert_eq!(ids, [second.id, first.id]);
+
+        // Renaming counts as activity, so the conversation moves to the top
+        let renamed = store.rename_conversation(first.id, "Renamed").unwrap();
+        assert_eq!(renamed.name, "Renamed");
+        assert_eq!(renamed.name_source, NameSource::User);
+        assert_eq!(store.list_conversations().unwrap()[0].id, first.id);
+        // A user's name is not replaced by a generated title
+        assert!(store
+            .set_generated_name(first.id, "Generated")
+            .unwrap()
+            .is_none());
+    }
+
+    #[test]
+    fn rejects_bad_names_and_unknown_conversations() {
+        let mut store = store();
+        let conversation = store.create_conversation("Chat").unwrap();
+
+        assert!(matches!(
+            store.create_conversation("   "),
+            Err(StoreError::Validation(_))
+        ));
+        let too_long = "x".repeat(MAX_NAME_CHARS + 1);
+        assert!(matches!(
+            store.rename_conversation(conversation.id, &too_long),
+            Err(StoreError::Validation(_))
+        ));
+        let longest = "é".repeat(MAX_NAME_CHARS);
+        assert!(store.rename_conversation(conversation.id, &longest).is_ok());
+        assert!(matches!(
+            store.rename_conversation(999, "Name"),
+            Err(StoreError::ConversationNotFound(999))
+        ));
+        assert!(matches!(
+            store.get_messages(999),
+            Err(StoreError::ConversationNotFound(999))
+        ));
+    }
+
+    #[test]
+    fn appends_messages_to_the_active_branch() {
+        let mut store = store();
+        let conversation = store.create_conversation("Chat").unwrap();
+        assert!(store.get_messages(conversation.id).unwrap().is_empty());
+
+        let question = store
+            .append_message(conversation.id, text(ChatRole::User, "Hi"))
+            .unwrap();
+        let mut reply = text(ChatRole::Assistant, "Hello");
+        reply.model = Some("claude-sonnet-4-5".to_string());
+        reply.usage = Some(TokenUsage {
+            prompt_tokens: 10,
+            completion_tokens: 2,
+        });
+        let answer = store.append_message(conversation.id, reply).unwrap();
+
+        assert_eq!(question.parent_id, None);
+        assert_eq!(answer.parent_id, Some(question.id));
+        assert_eq!(answer.model.as_deref(), Some("claude-sonnet-4-5"));
+        assert_eq!(answer.usage.unwrap().completion_tokens, 2);
+        assert_eq!(
+            store
+                .get_conversation(conversation.id)
+                .unwrap()
+                .active_leaf_id,
+            Some(answer.id)
+        );
+        assert_eq!(
+            texts(&store.get_messages(conversation.id).unwrap()),
+            ["Hi", "Hello"]
+        );
+    }
+
+    #[test]
+    fn append_rejects_invalid_messages() {
+        let mut store = store();
+        let first = store.create_conversation("First").unwrap();
+        let other = store.create_conversation("Other").unwrap();
+        let message = store
+            .append_message(first.id, text(ChatRole::User, "Hi"))
+            .unwrap();
+
+        let mut misplaced = text(ChatRole::Assistant, "Hello");
+        misplaced.parent_id = Some(message.id);
+        assert!(matches!(
+            store.append_message(other.id, misplaced),
+            Err(StoreError::Validation(_))
+        ));
+        let mut empty = text(ChatRole::User, "");
+        empty.parts.clear();
+        assert!(matches!(
+            store.append_message(first.id, empty),
+            Err(StoreError::Validation(_))
+        ));
+        let mut tool_call_from_user = text(ChatRole::User, "Hi");
+        tool_call_from_user.parts = vec![ContentPart::ToolCall {
+            id: "call_1".to_string(),
+            name: "weather".to_string(),
+            arguments: serde_json::Value::Null,
+        }];
+        assert!(matches!(
+            store.append_message(first.id, tool_call_from_user),
+            Err(StoreError::Validation(_))
+        ));
+        assert!(matches!(
+            store.append_message(999, text(ChatRole::User, "Hi")),
+            Err(StoreError::ConversationNotFound(999))
+        ));
+        // Nothing from the failed appends was kept
+        assert_eq!(store.all_messages(first.id).unwrap().len(), 1);
+        assert!(store.all_messages(other.id).unwrap().is_empty());
+    }
+
+    #[test]
+    fn delete_removes_the_conversation_and_its_messages() {
+        let mut store = store();
+        let conversation = store.create_conversation("Chat").unwrap();
+        let kept = store.create_conversation("Kept").unwrap();
+        let question = store
+            .append_message(conversation.id, text(ChatRole::User, "Hi"))
+            .unwrap();
+        store
+            .append_message(conversation.id, text(ChatRole::Assistant, "Hello"))
+            .unwrap();
+        store
+            .append_message(kept.id, text(ChatRole::User, "Still here"))
+            .unwrap();
+
+        store.delete_conversation(conversation.id).unwrap();
+        assert!(matches!(
+            store.get_conversation(conversation.id),
+            Err(StoreError::ConversationNotFound(_))
+        ));
+        assert!(matches!(
+            store.list_siblings(question.id),
+            Err(StoreError::MessageNotFound(_))
+        ));
+        assert!(matches!(
+            store.delete_conversation(conversation.id),
+            Err(StoreError::ConversationNotFound(_))
+        ));
+        assert_eq!(texts(&store.get_messages(kept.id).unwrap()), ["Still here"]);
+    }
+
+    /// A conversation of user and assistant messages, each a reply to the one before
+    fn thread(store: &mut ConversationStore, messages: &[&str]) -> (i64, Vec<StoredMessage>) {
+        let conversation = store.create_conversation("Chat").unwrap();
+        let stored = messages
+            .iter()
+            .enumerate()
+            .map(|(i, message)| {
+                let role = if i % 2 == 0 {
+                    ChatRole::User
+                } else {
+                    ChatRole::Assistant
+                };
+                store
+                    .append_message(conversation.id, text(role, message))
+                    .unwrap()
+            })
+            .collect();
+        (conversation.id, stored)
+    }
+
+    #[test]
+    fn fork_then_switch_back() {
+        let mut store = store();
+        let (id, thread) = thread(&mut store, &["Hi", "Hello", "Joke?", "No."]);
+
+        // Regenerating the last answer starts a branch next to it
+        let retry = store
+            .fork_message(thread[3].id, text(ChatRole::Assistant, "Why not."))
+            .unwrap();
+        assert_eq!(retry.parent_id, Some(thread[2].id));
+        assert_eq!(
+            texts(&store.get_messages(id).unwrap()),
+            ["Hi", "Hello", "Joke?", "Why not."]
+        );
+
+        let branch = store.switch_branch(thread[3].id).unwrap();
+        assert_eq!(texts(&branch), ["Hi", "Hello", "Joke?", "No."]);
+        assert_eq!(
+            store.get_conversation(id).unwrap().active_leaf_id,
+            Some(thread[3].id)
+        );
+        assert_eq!(texts(&store.get_messages(id).unwrap()), texts(&branch));
+
+        store.switch_branch(retry.id).unwrap();
+        assert_eq!(
+            texts(&store.get_messages(id).unwrap()),
+            ["Hi", "Hello", "Joke?", "Why not."]
+        );
+    }
+
+    #[test]
+    fn forking_mid_thread_moves_the_active_leaf() {
+        let mut store = store();
+        let (id, thread) = thread(&mut store, &["Hi", "Hello", "Joke?", "No."]);
+
+        // Editing the second question drops what followed it from the active branch
+        let edit = store
+            .fork_message(thread[2].id, text(ChatRole::User, "Riddle?"))
+            .unwrap();
+        assert_eq!(
+            store.get_conversation(id).unwrap().active_leaf_id,
+            Some(edit.id)
+        );
+        assert_eq!(
+            texts(&store.get_messages(id).unwrap()),
+            ["Hi", "Hello", "Riddle?"]
+        );
+
+        // Appending continues the new branch
+        let answer = store
+            .append_message(id, text(ChatRole::Assistant, "Sure."))
+            .unwrap();
+        assert_eq!(answer.parent_id, Some(edit.id));
+        assert_eq!(
+            texts(&store.get_messages(id).unwrap()),
+            ["Hi", "Hello", "Riddle?", "Sure."]
+        );
+
+        // Switching to the original question follows it down to its answer
+        let branch = store.switch_branch(thread[2].id).unwrap();
+        assert_eq!(texts(&branch), ["Hi", "Hello", "Joke?", "No."]);
+        // Every message is kept, whichever branch is active
+        assert_eq!(store.all_messages(id).unwrap().len(), 6);
+    }
+
+    #[test]
+    fn siblings_come_back_oldest_first() {
+        let mut store = store();
+        let (id, thread) = thread(&mut store, &["Hi", "Hello"]);
+        let second = store
+            .fork_message(thread[1].id, text(ChatRole::Assistant, "Hey"))
+            .unwrap();
+        let third = store
+            .fork_message(thread[1].id, text(ChatRole::Assistant, "Howdy"))
+            .unwrap();
+
+        for message in [&thread[1], &second, &third] {
+            let siblings = store.list_siblings(message.id).unwrap();
+            assert_eq!(texts(&siblings), ["Hello", "Hey", "Howdy"]);
+        }
+        // First messages are siblings of each other too
+        let other_start = store
+            .fork_message(thread[0].id, text(ChatRole::User, "Hey there"))
+            .unwrap();
+        assert_eq!(
+            texts(&store.list_siblings(other_start.id).unwrap()),
+            ["Hi", "Hey there"]
+        );
+        assert_eq!(texts(&store.get_messages(id).unwrap()), ["Hey there"]);
+    }
+
+    #[test]
+    fn switch_follows_the_latest_reply_at_each_step() {
+        let mut store = store();
+        let (id, thread) = thread(&mut store, &["Hi", "Hello", "Joke?", "No."]);
+        let retry = store
+            .fork_message(thread[1].id, text(ChatRole::Assistant, "Hey"))
+            .unwrap();
+        store.switch_branch(thread[3].id).unwrap();
+
+        // From the first message, the newest reply is the regenerated one
+        let branch = store.switch_branch(thread[0].id).unwrap();
+        assert_eq!(texts(&branch), ["Hi", "Hey"]);
+        assert_eq!(
+            store.get_conversation(id).unwrap().active_leaf_id,
+            Some(retry.id)
+        );
+    }
+
+    #[test]
+    fn fork_keeps_roles() {
+        let mut store = store();
+        let (_, thread) = thread(&mut store, &["Hi", "Hello"]);
+        assert!(matches!(
+            store.fork_message(thread[1].id, text(ChatRole::User, "Hi again")),
+            Err(StoreError::Validation(_))
+        ));
+        assert!(matches!(
+            store.fork_message(999, text(ChatRole::User, "Hi")),
+            Err(StoreError::MessageNotFound(999))
+        ));
+        assert!(matches!(
+            store.switch_branch(999),
+            Err(StoreError::MessageNotFound(999))
+        ));
+    }
+
+    fn temp_dir(test: &str) -> PathBuf {
+        let dir = env::temp_dir().join(format!("pqp-conversations-{}-{}", test, process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        dir
+    }
+
+    fn names(store: &ConversationStore) -> Vec<String> {
+        let conversations = store.list_conversations().unwrap();
+        conversations.into_iter().map(|c| c.name).collect()
+    }
+
+    #[test]
+    fn relocate_moves_the_database_and_removes_the_old_one() {
+        let dir = temp_dir("relocate");
+        let old_path = dir.join("old").join("pqp_chats.db");
+        let mut store = ConversationStore::open(old_path.clone()).unwrap();
+        store.create_conversation("Moved").unwrap();
+
+        let new_path = dir.join("new").join("pqp_chats.db");
+        store.relocate(new_path.clone(), None).unwrap();
+        assert_eq!(store.path(), new_path);
+        assert_eq!(names(&store), ["Moved"]);
+        assert!(!old_path.exists());
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn relocate_onto_a_database_needs_a_choice() {
+        let dir = temp_dir("relocate-exists");
+        let old_path = dir.join("old").join("pqp_chats.db");
+        let new_path = dir.join("new").join("pqp_chats.db");
+        ConversationStore::open(new_path.clone())
+            .unwrap()
+            .create_conversation("Already there")
+            .unwrap();
+        let mut store = ConversationStore::open(old_path.clone()).unwrap();
+        store.create_conversation("Current").unwrap();
+
+        assert!(matches!(
+            store.relocate(new_path.clone(), None),
+            Err(StoreError::DatabaseExists(path)) if path == new_path
+        ));
+        assert_eq!(store.path(), old_path);
+        assert_eq!(names(&store), ["Current"]);
+        assert_eq!(
+            names(&ConversationStore::open(new_path).unwrap()),
+            ["Already there"]
+        );
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn relocate_can_use_the_database_already_there() {
+        let dir = temp_dir("relocate-use");
+        let old_path = dir.join("old").join("pqp_chats.db");
+        let new_path = dir.join("new").join("pqp_chats.db");
+        ConversationStore::open(new_path.clone())
+            .unwrap()
+            .create_conversation("Already there")
+            .unwrap();
+        let mut store = ConversationStore::open(old_path.clone()).unwrap();
+        store.create_conversation("Current").unwrap();
+
+        store
+            .relocate(new_path.clone(), Some(ExistingDatabase::Use))
+            .unwrap();
+        assert_eq!(store.path(), new_path);
+        assert_eq!(names(&store), ["Already there"]);
+        // The database it switched away from is left alone
+        assert_eq!(
+            names(&ConversationStore::open(old_path).unwrap()),
+            ["Current"]
+        );
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn relocate_can_replace_the_database_already_there() {
+        let dir = temp_dir("relocate-replace");
+        let old_path = dir.join("old").join("pqp_chats.db");
+        let new_path = dir.join("new").join("pqp_chats.db");
+        ConversationStore::open(new_path.clone())
+            .unwrap()
+            .create_conversation("Already there")
+            .unwrap();
+        let mut store = ConversationStore::open(old_path.clone()).unwrap();
+        store.create_conversation("Current").unwrap();
+
+        store
+            .relocate(new_path.clone(), Some(ExistingDatabase::Replace))
+            .unwrap();
+        assert_eq!(store.path(), new_path);
+        assert_eq!(names(&store), ["Current"]);
+        assert!(!old_path.exists());
+        assert!(!dir.join("new").join("pqp_chats.db.relocate").exists());
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
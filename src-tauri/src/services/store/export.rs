@@ -0,0 +1,562 @@
+use crate::services::proxy::request::tool_result_text;
+use crate::services::proxy::{ChatRole, ContentPart};
+use crate::services::store::{
+    paths, Conversation, ConversationStore, StoreError, StoreResult, StoredMessage,
+};
+use log::info;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::fs::{self, File};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use zip::write::SimpleFileOptions;
+use zip::{CompressionMethod, ZipWriter};
+
+/// Marks exported JSON documents so an import can recognize them
+pub const EXPORT_FORMAT_NAME: &str = "pqp-conversation";
+pub const EXPORT_FORMAT_VERSION: u32 = 1;
+
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum ExportFormat {
+    /// Readable transcript of the active branch; tool calls, results and reasoning
+    /// fold into `<details>` sections
+    Markdown,
+    /// Lossless document with every branch and all message metadata
+    Json,
+    /// The active branch in OpenAI's fine-tuning chat format, one conversation per
+    /// line. Only text and tool calls are kept.
+    Jsonl,
+}
+
+impl ExportFormat {
+    pub fn extension(&self) -> &'static str {
+        match self {
+            ExportFormat::Markdown => "md",
+            ExportFormat::Json => "json",
+            ExportFormat::Jsonl => "jsonl",
+        }
+    }
+}
+
+/// A lossless export: the conversation, its active branch pointer and all messages
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct ConversationExport {
+    pub format: String,
+    pub version: u32,
+    pub conversation: Conversation,
+    pub messages: Vec<StoredMessage>,
+}
+
+#[derive(Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct BulkExportSummary {
+    pub path: PathBuf,
+    pub conversations: usize,
+}
+
+/// Render one conversation in `format`
+pub fn export_conversation(
+    store: &ConversationStore,
+    conversation_id: i64,
+    format: ExportFormat,
+) -> StoreResult<String> {
+    let conversation = store.get_conversation(conversation_id)?;
+    match format {
+        ExportFormat::Markdown => Ok(markdown(
+            &conversation,
+            &store.get_messages(conversation_id)?,
+        )),
+        ExportFormat::Json => {
+            let export = ConversationExport {
+                format: EXPORT_FORMAT_NAME.to_string(),
+                version: EXPORT_FORMAT_VERSION,
+                messages: store.all_messages(conversation_id)?,
+                conversation,
+            };
+            Ok(serde_json::to_string_pretty(&export)?)
+        }
+        ExportFormat::Jsonl => {
+            let line = fine_tuning_line(&store.get_messages(conversation_id)?);
+            Ok(format!("{}\n", serde_json::to_string(&line)?))
+        }
+    }
+}
+
+/// Render every conversation, for writing out with `write_bulk_export`
+pub fn export_all(
+    store: &ConversationStore,
+    format: ExportFormat,
+) -> StoreResult<Vec<(String, String)>> {
+    store
+        .list_conversations()?
+        .iter()
+        .map(|conversation| {
+            let contents = export_conversation(store, conversation.id, format)?;
+            Ok((file_name(conversation, format), contents))
+        })
+        .collect()
+}
+
+/// Write rendered conversations into a directory, or a zip archive when `path`
+/// ends in `.zip`. Files already in the directory are never overwritten: if any
+/// export file exists there, nothing is written.
+pub fn write_bulk_export(
+    path: &Path,
+    files: &[(String, String)],
+) -> StoreResult<BulkExportSummary> {
+    let io_error = |e: &dyn std::fmt::Display| {
+        StoreError::Io(format!("Failed to write {}: {}", path.display(), e))
+    };
+    let is_zip = path
+        .extension()
+        .is_some_and(|ext| ext.eq_ignore_ascii_case("zip"));
+
+    if is_zip {
+        if let Some(parent) = path.parent() {
+            paths::create_dir(parent)?;
+        }
+        let mut zip = ZipWriter::new(File::create(path).map_err(|e| io_error(&e))?);
+        let options = SimpleFileOptions::default().compression_method(CompressionMethod::Deflated);
+        for (name, contents) in files {
+            zip.start_file(name.as_str(), options)
+                .map_err(|e| io_error(&e))?;
+            zip.write_all(contents.as_bytes())
+                .map_err(|e| io_error(&e))?;
+        }
+        zip.finish().map_err(|e| io_error(&e))?;
+    } else {
+        // Refuse before writing anything, rather than overwrite an earlier export
+        // or leave it half replaced
+        let taken: Vec<&str> = files
+            .iter()
+            .map(|(name, _)| name.as_str())
+            .filter(|name| path.join(name).exists())
+            .collect();
+        if let Some(first) = taken.first() {
+            return Err(StoreError::Validation(format!(
+                "{} of the export files already exist in {}, e.g. {}; choose another directory",
+                taken.len(),
+                path.display(),
+                first
+            )));
+        }
+        paths::create_dir(path)?;
+        for (name, contents) in files {
+            let file = path.join(name);
+            fs::write(&file, contents).map_err(|e| {
+                StoreError::Io(format!("Failed to write {}: {}", file.display(), e))
+            })?;
+        }
+    }
+
+    info!(
+        "Exported {} conversations to {}",
+        files.len(),
+        path.display()
+    );
+    Ok(BulkExportSummary {
+        path: path.to_path_buf(),
+        conversations: files.len(),
+    })
+}
+
+/// `0042-fixing-the-build.md`: the id keeps names unique and sorted by age
+fn file_name(conversation: &Conversation, format: ExportFormat) -> String {
+    let mut slug = String::new();
+    for c in conversation.name.chars().flat_map(char::to_lowercase) {
+        if c.is_alphanumeric() {
+            slug.push(c);
+        } else if !slug.is_empty() && !slug.ends_with('-') {
+            slug.push('-');
+        }
+        if slug.chars().count() >= 60 {
+            break;
+        }
+    }
+    let slug = slug.trim_end_matches('-');
+    if slug.is_empty() {
+        format!("{:04}.{}", conversation.id, format.extension())
+    } else {
+        format!("{:04}-{}.{}", conversation.id, slug, format.extension())
+    }
+}
+
+fn markdown(conversation: &Conversation, messages: &[StoredMessage]) -> String {
+    let mut out = format!("# {}\n\n", conversation.name);
+    out.push_str(&format!("*Created {}*\n", conversation.created_at));
+
+    for message in messages {
+        let heading = match message.role {
+            ChatRole::System => "System",
+            ChatRole::User => "User",
+            ChatRole::Assistant => "Assistant",
+            ChatRole::Tool => "Tool",
+        };
+        match (&message.model, message.role) {
+            (Some(model), ChatRole::Assistant) => {
+                out.push_str(&format!("\n## {} ({})\n\n", heading, model))
+            }
+            _ => out.push_str(&format!("\n## {}\n\n", heading)),
+        }
+
+        for part in &message.parts {
+            match part {
+                ContentPart::Text { text } => {
+                    out.push_str(text.trim_end());
+                    out.push_str("\n\n");
+                }
+                ContentPart::ToolCall {
+                    name, arguments, ..
+                } => details(
+                    &mut out,
+                    &format!("Tool call: <code>{}</code>", name),
+                    "json",
+                    &serde_json::to_string_pretty(arguments).unwrap_or_default(),
+                ),
+                ContentPart::ToolResult {
+                    name,
+                    content,
+                    is_error,
+                    ..
+                } => {
+                    let label = if *is_error {
+                        "Tool error"
+                    } else {
+                        "Tool result"
+                    };
+                    let summary = match name {
+                        Some(name) => format!("{}: <code>{}</code>", label, name),
+                        None => label.to_string(),
+                    };
+                    let (language, body) = match content {
+                        Value::String(text) => ("", text.clone()),
+                        other => (
+                            "json",
+                            serde_json::to_string_pretty(other).unwrap_or_default(),
+                        ),
+                    };
+                    details(&mut out, &summary, language, &body);
+                }
+                ContentPart::Reasoning { text, .. } => details(&mut out, "Reasoning", "", text),
+                ContentPart::RedactedReasoning { .. } => {
+                    out.push_str("*Redacted reasoning*\n\n");
+                }
+                ContentPart::Attachment { path, .. } => {
+                    out.push_str(&format!("*Attachment: `{}`*\n\n", path));
+                }
+            }
+        }
+    }
+    out
+}
+
+/// A collapsible section with a fenced body; the fence is made longer than any
+/// backtick run in the body so it cannot be closed early
+fn details(out: &mut String, summary: &str, language: &str, body: &str) {
+    let longest_run = body
+        .split(|c| c != '`')
+        .map(str::len)
+        .max()
+        .unwrap_or_default();
+    let fence = "`".repeat(longest_run.max(2) + 1);
+    out.push_str(&format!(
+        "<details>\n<summary>{}</summary>\n\n{}{}\n{}\n{}\n\n</details>\n\n",
+        summary,
+        fence,
+        language,
+        body.trim_end(),
+        fence
+    ));
+}
+
+/// `{"messages": [...]}` with Chat Completions roles, tool calls and tool results
+fn fine_tuning_line(messages: &[StoredMessage]) -> Value {
+    let mut lines = Vec::new();
+    for message in messages {
+        let text = message
+            .parts
+            .iter()
+            .filter_map(|part| match part {
+                ContentPart::Text { text } => Some(text.as_str()),
+                _ => None,
+            })
+            .collect::<Vec<_>>()
+            .join("");
+
+        match message.role {
+            ChatRole::Tool => {
+                for part in &message.parts {
+                    if let ContentPart::ToolResult {
+                        tool_call_id,
+                        content,
+                        ..
+                    } = part
+                    {
+                        lines.push(json!({
+                            "role": "tool",
+                            "tool_call_id": tool_call_id,
+                            "content": tool_result_text(content),
+                        }));
+                    }
+                }
+            }
+            ChatRole::Assistant => {
+                let tool_calls: Vec<Value> = message
+                    .parts
+                    .iter()
+                    .filter_map(|part| match part {
+                        ContentPart::ToolCall {
+                            id,
+                            name,
+                            arguments,
+                        } => Some(json!({
+                            "id": id,
+                            "type": "function",
+                            "function": {
+                                "name": name,
+                                "arguments": if arguments.is_null() {
+                                    "{}".to_string()
+                                } else {
+                                    arguments.to_string()
+                                },
+                            },
+                        })),
+                        _ => None,
+                    })
+                    .collect();
+                let mut line = json!({ "role": "assistant", "content": text });
+                if !tool_calls.is_empty() {
+                    line["tool_calls"] = Value::Array(tool_calls);
+                }
+                lines.push(line);
+            }
+            role => lines.push(json!({ "role": role.as_str(), "content": text })),
+        }
+    }
+    json!({ "messages": lines })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::services::store::NameSource;
+    use std::env;
+    use std::io::Read;
+    use std::process;
+    use zip::ZipArchive;
+
+    fn temp_dir(test: &str) -> PathBuf {
+        let dir = env::temp_dir().join(format!("pqp-export-{}-{}", test, process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        dir
+    }
+
+    fn conversation(id: i64, name: &str) -> Conversation {
+        Conversation {
+            id,
+            name: name.to_string(),
+            name_source: NameSource::User,
+            active_leaf_id: None,
+            created_at: "2026-01-01T00:00:00+00:00".to_string(),
+            updated_at: "2026-01-01T00:00:00+00:00".to_string(),
+        }
+    }
+
+    fn message(id: i64, role: ChatRole, parts: Vec<ContentPart>) -> StoredMessage {
+        StoredMessage {
+            id,
+            conversation_id: 1,
+            parent_id: (id > 1).then_some(id - 1),
+            role,
+            parts,
+            model: None,
+            provider: None,
+            usage: None,
+            finish_reason: None,
+            latency_ms: None,
+            created_at: "2026-01-01T00:00:00+00:00".to_string(),
+        }
+    }
+
+    fn text(text: &str) -> ContentPart {
+        ContentPart::Text {
+            text: text.to_string(),
+        }
+    }
+
+    #[test]
+    fn file_names_slug_the_conversation_name() {
+        let cases = [
+            ("Fixing the build!", "0007-fixing-the-build.md"),
+            ("Café: naïve résumé", "0007-café-naïve-résumé.md"),
+            ("日本語のメモ", "0007-日本語のメモ.md"),
+            ("  ?!  ", "0007.md"),
+            ("", "0007.md"),
+        ];
+        for (name, expected) in cases {
+            assert_eq!(
+                file_name(&conversation(7, name), ExportFormat::Markdown),
+                expected,
+                "{:?}",
+                name
+            );
+        }
+    }
+
+    #[test]
+    fn file_names_cut_the_slug_at_60_characters() {
+        let long = file_name(&conversation(12345, &"é".repeat(80)), ExportFormat::Json);
+        assert_eq!(long, format!("12345-{}.json", "é".repeat(60)));
+
+        // A cut that lands on a separator does not leave it dangling
+        let name = format!("{} {}", "a".repeat(59), "b".repeat(10));
+        let cut = file_name(&conversation(1, &name), ExportFormat::Jsonl);
+        assert_eq!(cut, format!("0001-{}.jsonl", "a".repeat(59)));
+    }
+
+    #[test]
+    fn details_fence_outlasts_backticks_in_the_body() {
+        let mut out = String::new();
+        details(&mut out, "Tool result", "", "plain");
+        assert!(out.contains("\n```\nplain\n```\n"), "{}", out);
+
+        let mut out = String::new();
+        let body = "a ````` run and a ``` fence";
+        details(&mut out, "Tool result", "json", body);
+        assert!(out.contains("\n``````json\n"), "{}", out);
+        assert!(out.contains(&format!("\n{}\n``````\n", body)), "{}", out);
+    }
+
+    #[test]
+    fn fine_tuning_line_keeps_tool_calls_and_results() {
+        let messages = [
+            message(1, ChatRole::User, vec![text("Weather in Oslo?")]),
+            message(
+                2,
+                ChatRole::Assistant,
+                vec![
+                    text("Checking."),
+                    ContentPart::ToolCall {
+                        id: "call_1".to_string(),
+                        name: "weather".to_string(),
+                        arguments: json!({"city": "Oslo"}),
+                    },
+                    ContentPart::ToolCall {
+                        id: "call_2".to_string(),
+                        name: "time".to_string(),
+                        arguments: Value::Null,
+                    },
+                ],
+            ),
+            message(
+                3,
+                ChatRole::Tool,
+                vec![
+                    ContentPart::ToolResult {
+                        tool_call_id: "call_1".to_string(),
+                        name: Some("weather".to_string()),
+                        content: json!({"celsius": 4}),
+                        is_error: false,
+                    },
+                    ContentPart::ToolResult {
+                        tool_call_id: "call_2".to_string(),
+                        name: None,
+                        content: json!("12:00"),
+                        is_error: false,
+                    },
+                ],
+            ),
+            message(4, ChatRole::Assistant, vec![text("4°C at noon.")]),
+        ];
+
+        assert_eq!(
+            fine_tuning_line(&messages),
+            json!({"messages": [
+                {"role": "user", "content": "Weather in Oslo?"},
+                {
+                    "role": "assistant",
+                    "content": "Checking.",
+                    "tool_calls": [
+                        {
+                            "id": "call_1",
+                            "type": "function",
+                            "function": {"name": "weather", "arguments": "{\"city\":\"Oslo\"}"},
+                        },
+                        {
+                            "id": "call_2",
+                            "type": "function",
+                            "function": {"name": "time", "arguments": "{}"},
+                        },
+                    ],
+                },
+                {"role": "tool", "tool_call_id": "call_1", "content": "{\"celsius\":4}"},
+                {"role": "tool", "tool_call_id": "call_2", "content": "12:00"},
+                {"role": "assistant", "content": "4°C at noon."},
+            ]})
+        );
+    }
+
+    fn files() -> Vec<(String, String)> {
+        vec![
+            ("0001-first.md".to_string(), "# First\n".to_string()),
+            ("0002-second.md".to_string(), "# Second\n".to_string()),
+        ]
+    }
+
+    #[test]
+    fn bulk_export_writes_a_directory() {
+        let dir = temp_dir("directory");
+        let out = dir.join("export");
+
+        let summary = write_bulk_export(&out, &files()).unwrap();
+        assert_eq!(summary.path, out);
+        assert_eq!(summary.conversations, 2);
+        for (name, contents) in files() {
+            assert_eq!(fs::read_to_string(out.join(name)).unwrap(), contents);
+        }
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn bulk_export_refuses_to_overwrite_files_in_the_directory() {
+        let dir = temp_dir("overwrite");
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("0002-second.md"), "kept").unwrap();
+
+        assert!(matches!(
+            write_bulk_export(&dir, &files()),
+            Err(StoreError::Validation(_))
+        ));
+        assert_eq!(
+            fs::read_to_string(dir.join("0002-second.md")).unwrap(),
+            "kept"
+        );
+        // Nothing is written, not even the files that were free
+        assert!(!dir.join("0001-first.md").exists());
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn bulk_export_writes_a_zip() {
+        let dir = temp_dir("zip");
+        let out = dir.join("nested").join("export.ZIP");
+
+        let summary = write_bulk_export(&out, &files()).unwrap();
+        assert_eq!(summary.conversations, 2);
+        assert!(out.is_file());
+        let mut archive = ZipArchive::new(File::open(&out).unwrap()).unwrap();
+        assert_eq!(archive.len(), 2);
+        for (name, contents) in files() {
+            let mut read = String::new();
+            archive
+                .by_name(&name)
+                .unwrap()
+                .read_to_string(&mut read)
+                .unwrap();
+            assert_eq!(read, contents);
+        }
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,613 @@
+use crate::services::proxy::{ChatRole, ContentPart};
+use crate::services::store::import::{
+    conversation_list, label, timestamp_from_epoch, ImportedConversation, ImportedMessage, Parsed,
+    SkippedItem,
+};
+use crate::services::store::StoreResult;
+use chrono::Utc;
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::collections::{HashMap, HashSet};
+
+#[derive(Deserialize)]
+struct Conversation {
+    #[serde(default)]
+    title: Option<String>,
+    #[serde(default)]
+    create_time: Option<f64>,
+    #[serde(default)]
+    update_time: Option<f64>,
+    mapping: HashMap<String, Node>,
+    #[serde(default)]
+    current_node: Option<String>,
+    #[serde(default)]
+    conversation_id: Option<String>,
+    #[serde(default)]
+    id: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct Node {
+    /// `None` for the synthetic root
+    #[serde(default)]
+    message: Option<Message>,
+    #[serde(default)]
+    parent: Option<String>,
+    #[serde(default)]
+    children: Vec<String>,
+}
+
+#[derive(Deserialize)]
+struct Message {
+    id: String,
+    author: Author,
+    #[serde(default)]
+    create_time: Option<f64>,
+    #[serde(default)]
+    content: Value,
+    /// `all` for messages to the user, otherwise the tool an assistant message calls
+    #[serde(default)]
+    recipient: Option<String>,
+    #[serde(default)]
+    metadata: Value,
+}
+
+#[derive(Deserialize)]
+struct Author {
+    role: String,
+    /// The tool, for tool messages
+    #[serde(default)]
+    name: Option<String>,
+}
+
+/// What becomes of one node
+enum Converted {
+    Message {
+        role: ChatRole,
+        parts: Vec<ContentPart>,
+    },
+    /// Not shown in ChatGPT either: the root, hidden system prompts, custom
+    /// instructions, memory updates and "Thought for 5s" recaps
+    Hidden,
+    Skipped(String),
+}
+
+/// A tool call waiting for its output, along the branch being walked
+#[derive(Clone)]
+struct PendingCall {
+    id: String,
+    name: String,
+}
+
+/// ChatGPT's `conversations.json`: each conversation is a `mapping` of nodes
+/// linked by `parent` and `children`, with `current_node` the leaf last shown.
+/// Edits and regenerations are sibling nodes, so branches carry over directly.
+pub(crate) fn parse(export: Value) -> StoreResult<Parsed> {
+    let mut parsed = Parsed {
+        conversations: Vec::new(),
+        skipped: Vec::new(),
+    };
+    for (index, value) in conversation_list(export)?.into_iter().enumerate() {
+        let fallback_name = value["title"].as_str().unwrap_or_default().to_string();
+        let conversation: Conversation = match serde_json::from_value(value) {
+            Ok(conversation) => conversation,
+            Err(e) => {
+                parsed.skipped.push(SkippedItem {
+                    conversation: label(&fallback_name, &format!("#{}", index + 1)),
+                    message: None,
+                    reason: format!("unreadable conversation: {}", e),
+                });
+                continue;
+            }
+        };
+        if let Some(conversation) = convert(conversation, &mut parsed.skipped) {
+            parsed.conversations.push(conversation);
+        }
+    }
+    Ok(parsed)
+}
+
+fn convert(
+    conversation: Conversation,
+    skipped: &mut Vec<SkippedItem>,
+) -> Option<ImportedConversation> {
+    let source_id = conversation
+        .conversation_id
+        .clone()
+        .or(conversation.id.clone())
+        .unwrap_or_default();
+    let name = conversation.title.clone().unwrap_or_default();
+    let conversation_label = label(&name, &source_id);
+    if source_id.is_empty() {
+        skipped.push(SkippedItem {
+            conversation: conversation_label,
+            message: None,
+            reason: "conversation has no id".to_string(),
+        });
+        return None;
+    }
+    let created_at = conversation
+        .create_time
+        .and_then(timestamp_from_epoch)
+        .unwrap_or_else(|| Utc::now().to_rfc3339());
+    let updated_at = conversation
+        .update_time
+        .and_then(timestamp_from_epoch)
+        .unwrap_or_else(|| created_at.clone());
+
+    // Roots are nodes without a parent in the mapping; sorted for a stable order
+    let mut roots: Vec<&String> = conversation
+        .mapping
+        .iter()
+        .filter(|(_, node)| {
+            node.parent
+                .as_ref()
+                .is_none_or(|parent| !conversation.mapping.contains_key(parent))
+        })
+        .map(|(id, _)| id)
+        .collect();
+    roots.sort();
+
+    let mut messages: Vec<ImportedMessage> = Vec::new();
+    // Each node's nearest imported message, itself if it was imported
+    let mut imported_as: HashMap<&str, Option<usize>> = HashMap::new();
+    let mut visited: HashSet<&str> = HashSet::new();
+    // Depth first, so parents are always added before their replies
+    let mut stack: Vec<(&String, Option<usize>, Option<PendingCall>, String)> = roots
+        .into_iter()
+        .rev()
+        .map(|id| (id, None, None, created_at.clone()))
+        .collect();
+
+    while let Some((id, parent, pending, parent_time)) = stack.pop() {
+        if !visited.insert(id.as_str()) {
+            continue;
+        }
+        let Some(node) = conversation.mapping.get(id) else {
+            continue;
+        };
+
+        let (mut here, mut pending, mut time) = (parent, pending, parent_time);
+        if let Some(message) = &node.message {
+            if let Some(created) = message.create_time.and_then(timestamp_from_epoch) {
+                time = created;
+            }
+            match convert_message(message, pending.as_ref(), &conversation_label, skipped) {
+                Converted::Message { role, parts } => {
+                    if role != ChatRole::Tool {
+                        let call = parts.iter().find_map(|part| match part {
+                            ContentPart::ToolCall { id, name, .. } => Some(PendingCall {
+                                id: id.clone(),
+                                name: name.clone(),
+                            }),
+                            _ => None,
+                        });
+                        let reasoning_only = parts
+                            .iter()
+                            .all(|part| matches!(part, ContentPart::Reasoning { .. }));
+                        if !reasoning_only {
+                            pending = call;
+                        }
+                    }
+                    let model = match role {
+                        ChatRole::Assistant => message.metadata["model_slug"].as_str(),
+                        _ => None,
+                    };
+                    messages.push(ImportedMessage {
+                        parent,
+                        role,
+                        parts,
+                        model: model.map(str::to_string),
+                        created_at: time.clone(),
+                    });
+                    here = Some(messages.len() - 1);
+                }
+                Converted::Hidden => {}
+                Converted::Skipped(reason) => skipped.push(SkippedItem {
+                    conversation: conversation_label.clone(),
+                    message: Some(message.id.clone()),
+                    reason,
+                }),
+            }
+        }
+        imported_as.insert(id.as_str(), here);
+
+        for child in node.children.iter().rev() {
+            stack.push((child, here, pending.clone(), time.clone()));
+        }
+    }
+
+    if messages.is_empty() {
+        skipped.push(SkippedItem {
+            conversation: conversation_label,
+            message: None,
+            reason: "conversation has no messages that can be imported".to_string(),
+        });
+        return None;
+    }
+
+    let active_leaf = conversation
+        .current_node
+        .as_deref()
+        .and_then(|id| imported_as.get(id).copied().flatten());
+    Some(ImportedConversation {
+        source_id,
+        name,
+        created_at,
+        updated_at,
+        messages,
+        active_leaf,
+    })
+}
+
+fn convert_message(
+    message: &Message,
+    pending: Option<&PendingCall>,
+    conversation: &str,
+    skipped: &mut Vec<SkippedItem>,
+) -> Converted {
+    let content_type = message.content["content_type"].as_str().unwrap_or_default();
+    if message.metadata["is_visually_hidden_from_conversation"].as_bool() == Some(true)
+        || matches!(
+            content_type,
+            "user_editable_context" | "model_editable_context" | "reasoning_recap"
+        )
+    {
+        return Converted::Hidden;
+    }
+
+    if content_type == "thoughts" {
+        let parts: Vec<ContentPart> = message.content["thoughts"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .filter_map(|thought| thought["content"].as_str())
+            .filter(|text| !text.trim().is_empty())
+            .map(|text| ContentPart::Reasoning {
+                text: text.to_string(),
+                signature: None,
+            })
+            .collect();
+        return if parts.is_empty() {
+            Converted::Hidden
+        } else {
+            Converted::Message {
+                role: ChatRole::Assistant,
+                parts,
+            }
+        };
+    }
+
+    let (text, dropped) = content_text(&message.content);
+    if dropped > 0 {
+        let reason = format!(
+            "{} image or file part{} not included",
+            dropped,
+            if dropped == 1 { "" } else { "s" }
+        );
+        if text.is_empty() {
+            return Converted::Skipped(reason);
+        }
+        skipped.push(SkippedItem {
+            conversation: conversation.to_string(),
+            message: Some(message.id.clone()),
+            reason,
+        });
+    }
+    if text.is_empty() {
+        return if content_type.is_empty() || is_known(content_type) {
+            Converted::Hidden
+        } else {
+            Converted::Skipped(format!("unsupported content type '{}'", content_type))
+        };
+    }
+
+    match message.author.role.as_str() {
+        "user" => Converted::Message {
+            role: ChatRole::User,
+            parts: vec![ContentPart::Text { text }],
+        },
+        "system" => Converted::Message {
+            role: ChatRole::System,
+            parts: vec![ContentPart::Text { text }],
+        },
+        "assistant" => match message.recipient.as_deref() {
+            None | Some("all") => Converted::Message {
+                role: ChatRole::Assistant,
+                parts: vec![ContentPart::Text { text }],
+            },
+            // Code for the interpreter, a search query and the like
+            Some(tool) => Converted::Message {
+                role: ChatRole::Assistant,
+                parts: vec![ContentPart::ToolCall {
+                    id: message.id.clone(),
+                    name: tool.to_string(),
+                    arguments: json!({ "input": text }),
+                }],
+            },
+        },
+        "tool" => match pending {
+            Some(call) => Converted::Message {
+                role: ChatRole::Tool,
+                parts: vec![ContentPart::ToolResult {
+                    tool_call_id: call.id.clone(),
+                    name: message
+                        .author
+                        .name
+                        .clone()
+                        .or_else(|| Some(call.name.clone())),
+                    content: Value::String(text),
+                    is_error: content_type == "system_error",
+                }],
+            },
+            None => Converted::Skipped("tool output without the call it answers".to_string()),
+        },
+        role => Converted::Skipped(format!("unknown author role '{}'", role)),
+    }
+}
+
+/// The readable text of a message and the number of parts without any, such
+/// as images, whose files are not part of `conversations.json`
+fn content_text(content: &Value) -> (String, usize) {
+    let mut texts: Vec<&str> = Vec::new();
+    let mut dropped = 0;
+    for part in content["parts"].as_array().into_iter().flatten() {
+        match part {
+            Value::String(text) => texts.push(text),
+            // Audio transcriptions carry their text; images and files don't
+            Value::Object(_) => match part["text"].as_str() {
+                Some(text) => texts.push(text),
+                None => dropped += 1,
+            },
+            _ => {}
+        }
+    }
+    for key in ["text", "result"] {
+        if let Some(text) = content[key].as_str() {
+            texts.push(text);
+        }
+    }
+    let text = texts
+        .into_iter()
+        .map(str::trim)
+        .filter(|text| !text.is_empty())
+        .collect::<Vec<_>>()
+        .join("\n\n");
+    (text, dropped)
+}
+
+/// Content types whose empty instances are routine, like the blank assistant
+/// message that precedes a tool call
+fn is_known(content_type: &str) -> bool {
+    matches!(
+        content_type,
+        "text"
+            | "multimodal_text"
+            | "code"
+            | "execution_output"
+            | "tether_browsing_display"
+            | "tether_quote"
+            | "system_error"
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn node(id: &str, parent: Option<&str>, children: &[&str], message: Value) -> (String, Value) {
+        (
+            id.to_string(),
+            json!({
+                "id": id,
+                "message": message,
+                "parent": parent,
+                "children": children,
+            }),
+        )
+    }
+
+    fn message(id: &str, role: &str, time: f64, content: Value) -> Value {
+        json!({
+            "id": id,
+            "author": { "role": role },
+            "create_time": time,
+            "content": content,
+            "recipient": "all",
+            "metadata": {},
+        })
+    }
+
+    fn text(text: &str) -> Value {
+        json!({ "content_type": "text", "parts": [text] })
+    }
+
+    fn conversation(nodes: Vec<(String, Value)>, current_node: &str) -> Value {
+        json!({
+            "title": "Fixture",
+            "create_time": 1_700_000_000.0,
+            "update_time": 1_700_000_100.5,
+            "mapping": nodes.into_iter().collect::<serde_json::Map<_, _>>(),
+            "current_node": current_node,
+            "conversation_id": "chatgpt-1",
+        })
+    }
+
+    fn parse_one(conversation: Value) -> (ImportedConversation, Vec<SkippedItem>) {
+        let mut parsed = parse(json!([conversation])).unwrap();
+        assert_eq!(parsed.conversations.len(), 1, "{:?}", parsed.skipped);
+        (parsed.conversations.remove(0), parsed.skipped)
+    }
+
+    fn text_of(message: &ImportedMessage) -> &str {
+        match message.parts.as_slice() {
+            [ContentPart::Text { text }] => text,
+            parts => panic!("expected one text part, got {:?}", parts),
+        }
+    }
+
+    #[test]
+    fn regenerated_replies_become_branches() {
+        let mut system = message("system", "system", 1_700_000_000.0, text(""));
+        system["metadata"] = json!({ "is_visually_hidden_from_conversation": true });
+        let mut second = message("a2", "assistant", 1_700_000_030.0, text("Second try"));
+        second["metadata"] = json!({ "model_slug": "gpt-4o" });
+        let (conversation, skipped) = parse_one(conversation(
+            vec![
+                node("root", None, &["system"], Value::Null),
+                node("system", Some("root"), &["u1"], system),
+                node(
+                    "u1",
+                    Some("system"),
+                    &["a1", "a2"],
+                    message("u1", "user", 1_700_000_010.0, text("Hello")),
+                ),
+                node(
+                    "a1",
+                    Some("u1"),
+                    &[],
+                    message("a1", "assistant", 1_700_000_020.0, text("First try")),
+                ),
+                node("a2", Some("u1"), &[], second),
+            ],
+            "a2",
+        ));
+
+        assert!(skipped.is_empty(), "{:?}", skipped);
+        assert_eq!(conversation.source_id, "chatgpt-1");
+        assert_eq!(conversation.name, "Fixture");
+        assert_eq!(
+            conversation.updated_at,
+            timestamp_from_epoch(1_700_000_100.5).unwrap()
+        );
+        let messages = &conversation.messages;
+        assert_eq!(messages.len(), 3);
+        assert_eq!(messages[0].role, ChatRole::User);
+        assert_eq!(messages[0].parent, None);
+        assert_eq!(text_of(&messages[1]), "First try");
+        assert_eq!(messages[1].parent, Some(0));
+        assert_eq!(text_of(&messages[2]), "Second try");
+        assert_eq!(messages[2].parent, Some(0));
+        assert_eq!(messages[2].model.as_deref(), Some("gpt-4o"));
+        assert_eq!(conversation.active_leaf, Some(2));
+    }
+
+    #[test]
+    fn tool_output_answers_the_call_before_it() {
+        let mut call = message(
+            "call",
+            "assistant",
+            1_700_000_020.0,
+            json!({ "content_type": "code", "text": "print(1 + 1)" }),
+        );
+        call["recipient"] = json!("python");
+        let mut output = message(
+            "output",
+            "tool",
+            1_700_000_030.0,
+            json!({ "content_type": "execution_output", "text": "2" }),
+        );
+        output["author"]["name"] = json!("python");
+        let (conversation, _) = parse_one(conversation(
+            vec![
+                node(
+                    "u1",
+                    None,
+                    &["call"],
+                    message("u1", "user", 1_700_000_010.0, text("Add one and one")),
+                ),
+                node("call", Some("u1"), &["output"], call),
+                node("output", Some("call"), &["a1"], output),
+                node(
+                    "a1",
+                    Some("output"),
+                    &[],
+                    message("a1", "assistant", 1_700_000_040.0, text("It is 2")),
+                ),
+            ],
+            "a1",
+        ));
+
+        let messages = &conversation.messages;
+        assert_eq!(messages.len(), 4);
+        assert!(matches!(
+            messages[1].parts.as_slice(),
+            [ContentPart::ToolCall { id, name, arguments }]
+                if id == "call" && name == "python" && arguments["input"] == "print(1 + 1)"
+        ));
+        assert_eq!(messages[2].role, ChatRole::Tool);
+        assert!(matches!(
+            messages[2].parts.as_slice(),
+            [ContentPart::ToolResult { tool_call_id, content, is_error: false, .. }]
+                if tool_call_id == "call" && content == "2"
+        ));
+        assert_eq!(messages[3].parent, Some(2));
+    }
+
+    #[test]
+    fn reports_dropped_images_and_orphaned_tool_output() {
+        let (conversation, skipped) = parse_one(conversation(
+            vec![
+                node(
+                    "u1",
+                    None,
+                    &["t1"],
+                    message(
+                        "u1",
+                        "user",
+                        1_700_000_010.0,
+                        json!({
+                            "content_type": "multimodal_text",
+                            "parts": [{ "asset_pointer": "file-service://1" }, "What is this?"],
+                        }),
+                    ),
+                ),
+                node(
+                    "t1",
+                    Some("u1"),
+                    &[],
+                    message("t1", "tool", 1_700_000_020.0, text("stray output")),
+                ),
+            ],
+            "t1",
+        ));
+
+        assert_eq!(conversation.messages.len(), 1);
+        assert_eq!(text_of(&conversation.messages[0]), "What is this?");
+        // The tool output was not imported, so the branch ends at the user message
+        assert_eq!(conversation.active_leaf, Some(0));
+        let reasons: Vec<(&str, &str)> = skipped
+            .iter()
+            .map(|item| (item.message.as_deref().unwrap(), item.reason.as_str()))
+            .collect();
+        assert_eq!(
+            reasons,
+            [
+                ("u1", "1 image or file part not included"),
+                ("t1", "tool output without the call it answers"),
+            ]
+        );
+    }
+
+    #[test]
+    fn skips_unreadable_and_empty_conversations() {
+        let parsed = parse(json!([
+            { "title": "No mapping" },
+            { "title": "Empty", "mapping": {}, "id": "chatgpt-2" },
+        ]))
+        .unwrap();
+
+        assert!(parsed.conversations.is_empty());
+        assert_eq!(parsed.skipped.len(), 2);
+        assert_eq!(parsed.skipped[0].conversation, "No mapping");
+        assert!(parsed.skipped[0]
+            .reason
+            .starts_with("unreadable conversation"));
+        assert_eq!(parsed.skipped[1].conversation, "Empty");
+        assert_eq!(
+            parsed.skipped[1].reason,
+            "conversation has no messages that can be imported"
+        );
+        assert!(parse(json!({ "conversations": [] })).is_err());
+    }
+}
@@ -0,0 +1,518 @@
+use crate::services::proxy::{ChatRole, ContentPart};
+use crate::services::store::import::{
+    conversation_list, label, timestamp_from_rfc3339, ImportedConversation, ImportedMessage,
+    Parsed, SkippedItem,
+};
+use crate::services::store::StoreResult;
+use chrono::Utc;
+use serde::Deserialize;
+use serde_json::Value;
+use std::collections::HashMap;
+
+/// Parent of the first message in exports that record parents
+const ROOT_MESSAGE_UUID: &str = "00000000-0000-4000-8000-000000000000";
+
+#[derive(Deserialize)]
+struct Conversation {
+    uuid: String,
+    #[serde(default)]
+    name: Option<String>,
+    #[serde(default)]
+    created_at: Option<String>,
+    #[serde(default)]
+    updated_at: Option<String>,
+    #[serde(default)]
+    chat_messages: Vec<Message>,
+    #[serde(default)]
+    current_leaf_message_uuid: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct Message {
+    uuid: String,
+    /// `human` or `assistant`
+    sender: String,
+    /// Plain text of the message; older exports have nothing else
+    #[serde(default)]
+    text: String,
+    /// Typed blocks, including tool use and thinking
+    #[serde(default)]
+    content: Vec<Value>,
+    #[serde(default)]
+    created_at: Option<String>,
+    /// Files whose text Claude extracted; the text is part of the export
+    #[serde(default)]
+    attachments: Vec<Attachment>,
+    /// Uploaded images and other files; the files themselves are not
+    #[serde(default)]
+    files: Vec<Value>,
+    #[serde(default)]
+    parent_message_uuid: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct Attachment {
+    #[serde(default)]
+    file_name: String,
+    #[serde(default)]
+    extracted_content: String,
+}
+
+/// Claude's `conversations.json`: each conversation lists its messages in order.
+/// Newer exports also record each message's parent, which keeps edited and
+/// regenerated branches; older ones are imported as a single branch.
+pub(crate) fn parse(export: Value) -> StoreResult<Parsed> {
+    let mut parsed = Parsed {
+        conversations: Vec::new(),
+        skipped: Vec::new(),
+    };
+    for (index, value) in conversation_list(export)?.into_iter().enumerate() {
+        let fallback_name = value["name"].as_str().unwrap_or_default().to_string();
+        let conversation: Conversation = match serde_json::from_value(value) {
+            Ok(conversation) => conversation,
+            Err(e) => {
+                parsed.skipped.push(SkippedItem {
+                    conversation: label(&fallback_name, &format!("#{}", index + 1)),
+                    message: None,
+                    reason: format!("unreadable conversation: {}", e),
+                });
+                continue;
+            }
+        };
+        if let Some(conversation) = convert(conversation, &mut parsed.skipped) {
+            parsed.conversations.push(conversation);
+        }
+    }
+    Ok(parsed)
+}
+
+fn convert(
+    conversation: Conversation,
+    skipped: &mut Vec<SkippedItem>,
+) -> Option<ImportedConversation> {
+    let name = conversation.name.unwrap_or_default();
+    let conversation_label = label(&name, &conversation.uuid);
+    let created_at = conversation
+        .created_at
+        .as_deref()
+        .and_then(timestamp_from_rfc3339)
+        .unwrap_or_else(|| Utc::now().to_rfc3339());
+    let updated_at = conversation
+        .updated_at
+        .as_deref()
+        .and_then(timestamp_from_rfc3339)
+        .unwrap_or_else(|| created_at.clone());
+
+    let mut messages: Vec<ImportedMessage> = Vec::new();
+    // Each source message's last imported message; tool results split one
+    // source message into several
+    let mut imported_as: HashMap<&str, usize> = HashMap::new();
+    let mut previous: Option<usize> = None;
+
+    for message in &conversation.chat_messages {
+        let parent = match message.parent_message_uuid.as_deref() {
+            Some(ROOT_MESSAGE_UUID) => None,
+            Some(uuid) => imported_as.get(uuid).copied().or(previous),
+            None => previous,
+        };
+        let role = match message.sender.as_str() {
+            "human" => ChatRole::User,
+            "assistant" => ChatRole::Assistant,
+            sender => {
+                skipped.push(SkippedItem {
+                    conversation: conversation_label.clone(),
+                    message: Some(message.uuid.clone()),
+                    reason: format!("unknown sender '{}'", sender),
+                });
+                continue;
+            }
+        };
+        let time = message
+            .created_at
+            .as_deref()
+            .and_then(timestamp_from_rfc3339)
+            .or_else(|| parent.map(|index| messages[index].created_at.clone()))
+            .unwrap_or_else(|| created_at.clone());
+
+        let (segments, dropped) = segments(message, role);
+        for reason in dropped {
+            skipped.push(SkippedItem {
+                conversation: conversation_label.clone(),
+                message: Some(message.uuid.clone()),
+                reason,
+            });
+        }
+        if segments.is_empty() {
+            continue;
+        }
+
+        let mut parent = parent;
+        for (role, parts) in segments {
+            messages.push(ImportedMessage {
+                parent,
+                role,
+                parts,
+                model: None,
+                created_at: time.clone(),
+            });
+            parent = Some(messages.len() - 1);
+        }
+        imported_as.insert(message.uuid.as_str(), messages.len() - 1);
+        previous = parent;
+    }
+
+    if messages.is_empty() {
+        skipped.push(SkippedItem {
+            conversation: conversation_label,
+            message: None,
+            reason: "conversation has no messages that can be imported".to_string(),
+        });
+        return None;
+    }
+
+    let active_leaf = conversation
+        .current_leaf_message_uuid
+        .as_deref()
+        .and_then(|uuid| imported_as.get(uuid).copied());
+    Some(ImportedConversation {
+        source_id: conversation.uuid,
+        name,
+        created_at,
+        updated_at,
+        messages,
+        active_leaf,
+    })
+}
+
+/// Split a message into the messages pqp stores it as: Claude keeps tool results
+/// inside the assistant message, pqp in tool messages between assistant turns.
+/// Also returns what could not be imported.
+fn segments(message: &Message, role: ChatRole) -> (Vec<(ChatRole, Vec<ContentPart>)>, Vec<String>) {
+    let mut segments: Vec<(ChatRole, Vec<ContentPart>)> = Vec::new();
+    let mut dropped = Vec::new();
+    let mut push = |segment_role: ChatRole, part: ContentPart| match segments.last_mut() {
+        Some((last_role, parts)) if *last_role == segment_role => parts.push(part),
+        _ => segments.push((segment_role, vec![part])),
+    };
+
+    let mut last_call: Option<(String, String)> = None;
+    for (index, block) in message.content.iter().enumerate() {
+        match block["type"].as_str().unwrap_or_default() {
+            "text" => {
+                if let Some(text) = non_empty(&block["text"]) {
+                    push(role, ContentPart::Text { text });
+                }
+            }
+            "thinking" if role == ChatRole::Assistant => {
+                if let Some(text) = non_empty(&block["thinking"]) {
+                    push(
+                        role,
+                        ContentPart::Reasoning {
+                            text,
+                            signature: None,
+                        },
+                    );
+                }
+            }
+            "tool_use" if role == ChatRole::Assistant => {
+                let id = block["id"]
+                    .as_str()
+                    .map(str::to_string)
+                    .unwrap_or_else(|| format!("{}-{}", message.uuid, index));
+                let name = block["name"].as_str().unwrap_or("tool").to_string();
+                last_call = Some((id.clone(), name.clone()));
+                push(
+                    role,
+                    ContentPart::ToolCall {
+                        id,
+                        name,
+                        arguments: block["input"].clone(),
+                    },
+                );
+            }
+            "tool_result" if role == ChatRole::Assistant => {
+                let call = block["tool_use_id"]
+                    .as_str()
+                    .map(|id| (id.to_string(), block["name"].as_str().map(str::to_string)))
+                    .or_else(|| {
+                        last_call
+                            .as_ref()
+                            .map(|(id, name)| (id.clone(), Some(name.clone())))
+                    });
+                let Some((tool_call_id, name)) = call else {
+                    dropped.push("tool result without the call it answers".to_string());
+                    continue;
+                };
+                push(
+                    ChatRole::Tool,
+                    ContentPart::ToolResult {
+                        tool_call_id,
+                        name: block["name"].as_str().map(str::to_string).or(name),
+                        content: result_content(&block["content"]),
+                        is_error: block["is_error"].as_bool().unwrap_or_default(),
+                    },
+                );
+            }
+            kind => dropped.push(format!("unsupported content block '{}'", kind)),
+        }
+    }
+    if message.content.is_empty() && !message.text.trim().is_empty() {
+        push(
+            role,
+            ContentPart::Text {
+                text: message.text.clone(),
+            },
+        );
+    }
+
+    for attachment in &message.attachments {
+        if attachment.extracted_content.trim().is_empty() {
+            dropped.push(format!(
+                "attachment '{}' has no text in the export",
+                attachment.file_name
+            ));
+            continue;
+        }
+        push(
+            role,
+            ContentPart::Text {
+                text: format!(
+                    "Attached file {}:\n\n{}",
+                    attachment.file_name, attachment.extracted_content
+                ),
+            },
+        );
+    }
+    if !message.files.is_empty() {
+        let count = message.files.len();
+        dropped.push(format!(
+            "{} uploaded file{} not included",
+            count,
+            if count == 1 { "" } else { "s" }
+        ));
+    }
+
+    (segments, dropped)
+}
+
+fn non_empty(value: &Value) -> Option<String> {
+    value
+        .as_str()
+        .filter(|text| !text.trim().is_empty())
+        .map(str::to_string)
+}
+
+/// Tool results come as a list of text blocks; keep plain text where possible
+fn result_content(content: &Value) -> Value {
+    let Some(blocks) = content.as_array() else {
+        return content.clone();
+    };
+    let texts: Option<Vec<&str>> = blocks
+        .iter()
+        .map(|block| match block["type"].as_str() {
+            Some("text") => block["text"].as_str(),
+            _ => None,
+        })
+        .collect();
+    match texts {
+        Some(texts) => Value::String(texts.join("\n")),
+        None => content.clone(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn message(fixture: Value) -> Message {
+        serde_json::from_value(fixture).unwrap()
+    }
+
+    #[test]
+    fn tool_results_split_the_assistant_message() {
+        let message = message(json!({
+            "uuid": "m2",
+            "sender": "assistant",
+            "content": [
+                { "type": "thinking", "thinking": "Check the weather" },
+                { "type": "text", "text": "Let me look." },
+                {
+                    "type": "tool_use",
+                    "id": "toolu_1",
+                    "name": "weather",
+                    "input": { "city": "Oslo" },
+                },
+                {
+                    "type": "tool_result",
+                    "tool_use_id": "toolu_1",
+                    "name": "weather",
+                    "content": [{ "type": "text", "text": "Rain" }],
+                },
+                { "type": "text", "text": "It is raining." },
+                { "type": "image", "source": {} },
+            ],
+        }));
+        let (segments, dropped) = segments(&message, ChatRole::Assistant);
+
+        let roles: Vec<ChatRole> = segments.iter().map(|(role, _)| *role).collect();
+        assert_eq!(
+            roles,
+            [ChatRole::Assistant, ChatRole::Tool, ChatRole::Assistant]
+        );
+        assert!(matches!(
+            segments[0].1.as_slice(),
+            [
+                ContentPart::Reasoning { signature: None, .. },
+                ContentPart::Text { .. },
+                ContentPart::ToolCall { id, arguments, .. },
+            ] if id == "toolu_1" && arguments["city"] == "Oslo"
+        ));
+        assert!(matches!(
+            segments[1].1.as_slice(),
+            [ContentPart::ToolResult { tool_call_id, name: Some(name), content, is_error: false }]
+                if tool_call_id == "toolu_1" && name == "weather" && content == "Rain"
+        ));
+        assert!(matches!(
+            segments[2].1.as_slice(),
+            [ContentPart::Text { text }] if text == "It is raining."
+        ));
+        assert_eq!(dropped, ["unsupported content block 'image'"]);
+    }
+
+    #[test]
+    fn tool_result_without_an_id_answers_the_last_call() {
+        let message = message(json!({
+            "uuid": "m2",
+            "sender": "assistant",
+            "content": [
+                { "type": "tool_result", "content": "too early" },
+                { "type": "tool_use", "name": "search", "input": {} },
+                { "type": "tool_result", "content": "found", "is_error": true },
+            ],
+        }));
+        let (segments, dropped) = segments(&message, ChatRole::Assistant);
+
+        assert_eq!(dropped, ["tool result without the call it answers"]);
+        assert_eq!(segments.len(), 2);
+        assert!(matches!(
+            segments[1].1.as_slice(),
+            [ContentPart::ToolResult { tool_call_id, name: Some(name), is_error: true, .. }]
+                if tool_call_id == "m2-1" && name == "search"
+        ));
+    }
+
+    #[test]
+    fn plain_text_messages_keep_attachment_text() {
+        let message = message(json!({
+            "uuid": "m1",
+            "sender": "human",
+            "text": "Summarize these",
+            "attachments": [
+                { "file_name": "notes.txt", "extracted_content": "Buy milk" },
+                { "file_name": "scan.pdf", "extracted_content": "" },
+            ],
+            "files": [{ "file_name": "photo.png" }],
+        }));
+        let (segments, dropped) = segments(&message, ChatRole::User);
+
+        assert_eq!(segments.len(), 1);
+        let texts: Vec<&str> = segments[0]
+            .1
+            .iter()
+            .map(|part| match part {
+                ContentPart::Text { text } => text.as_str(),
+                part => panic!("unexpected part {:?}", part),
+            })
+            .collect();
+        assert_eq!(
+            texts,
+            ["Summarize these", "Attached file notes.txt:\n\nBuy milk"]
+        );
+        assert_eq!(
+            dropped,
+            [
+                "attachment 'scan.pdf' has no text in the export",
+                "1 uploaded file not included",
+            ]
+        );
+    }
+
+    #[test]
+    fn parent_uuids_keep_edited_branches() {
+        let parsed = parse(json!([{
+            "uuid": "claude-1",
+            "name": "",
+            "created_at": "2026-01-01T10:00:00+01:00",
+            "chat_messages": [
+                {
+                    "uuid": "m1",
+                    "sender": "human",
+                    "text": "Hi",
+                    "parent_message_uuid": ROOT_MESSAGE_UUID,
+                },
+                {
+                    "uuid": "m2",
+                    "sender": "assistant",
+                    "text": "Hello",
+                    "parent_message_uuid": "m1",
+                },
+                {
+                    "uuid": "m3",
+                    "sender": "human",
+                    "text": "Hi again",
+                    "parent_message_uuid": ROOT_MESSAGE_UUID,
+                },
+                {
+                    "uuid": "m4",
+                    "sender": "system",
+                    "text": "?",
+                    "parent_message_uuid": "m3",
+                },
+                {
+                    "uuid": "m5",
+                    "sender": "assistant",
+                    "text": "Welcome back",
+                    "parent_message_uuid": "m3",
+                },
+            ],
+            "current_leaf_message_uuid": "m5",
+        }]))
+        .unwrap();
+
+        assert_eq!(parsed.conversations.len(), 1);
+        let conversation = &parsed.conversations[0];
+        assert_eq!(conversation.created_at, "2026-01-01T09:00:00+00:00");
+        assert_eq!(conversation.updated_at, conversation.created_at);
+        let parents: Vec<Option<usize>> = conversation.messages.iter().map(|m| m.parent).collect();
+        assert_eq!(parents, [None, Some(0), None, Some(2)]);
+        assert_eq!(conversation.active_leaf, Some(3));
+        assert_eq!(parsed.skipped.len(), 1);
+        assert_eq!(parsed.skipped[0].conversation, "claude-1");
+        assert_eq!(parsed.skipped[0].reason, "unknown sender 'system'");
+    }
+
+    #[test]
+    fn messages_without_parents_form_one_branch() {
+        let parsed = parse(json!([
+            {
+                "uuid": "claude-2",
+                "name": "Old export",
+                "chat_messages": [
+                    { "uuid": "m1", "sender": "human", "text": "One" },
+                    { "uuid": "m2", "sender": "assistant", "text": " " },
+                    { "uuid": "m3", "sender": "assistant", "text": "Two" },
+                ],
+            },
+            { "name": "No uuid" },
+        ]))
+        .unwrap();
+
+        let conversation = &parsed.conversations[0];
+        let parents: Vec<Option<usize>> = conversation.messages.iter().map(|m| m.parent).collect();
+        assert_eq!(parents, [None, Some(0)]);
+        assert_eq!(conversation.active_leaf, None);
+        assert_eq!(parsed.skipped.len(), 1);
+        assert_eq!(parsed.skipped[0].conversation, "No uuid");
+    }
+}
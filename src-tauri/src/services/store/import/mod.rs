@@ -0,0 +1,248 @@
+use crate::services::proxy::{ChatRole, ContentPart};
+use crate::services::store::{ConversationStore, StoreError, StoreResult};
+use chrono::{DateTime, Utc};
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::fs::File;
+use std::io::{BufReader, Read};
+use std::path::Path;
+use std::sync::Mutex;
+use zip::ZipArchive;
+
+mod chatgpt;
+mod claude;
+
+/// Emitted with an `ImportProgress` after each conversation of an import
+pub(crate) const EVT_IMPORT_PROGRESS: &str = "import-progress";
+
+/// The file both ChatGPT and Claude put their conversations in
+const CONVERSATIONS_FILE: &str = "conversations.json";
+
+/// Which app an export comes from
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum ImportSource {
+    /// `conversations.json` from ChatGPT's "Export data"
+    Chatgpt,
+    /// `conversations.json` from Claude's "Export data"
+    Claude,
+}
+
+impl ImportSource {
+    /// Stored with each imported conversation to recognize it on a second import
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ImportSource::Chatgpt => "chatgpt",
+            ImportSource::Claude => "claude",
+        }
+    }
+}
+
+/// A conversation converted from an export, ready for
+/// `ConversationStore::import_conversation`
+#[derive(Debug, Clone)]
+pub struct ImportedConversation {
+    /// The conversation's id in the source app
+    pub source_id: String,
+    pub name: String,
+    pub created_at: String,
+    pub updated_at: String,
+    /// Parents always come before their replies
+    pub messages: Vec<ImportedMessage>,
+    /// Index of the message the source app was showing; the last message if unset
+    pub active_leaf: Option<usize>,
+}
+
+#[derive(Debug, Clone)]
+pub struct ImportedMessage {
+    /// Index of the parent in `ImportedConversation::messages`
+    pub parent: Option<usize>,
+    pub role: ChatRole,
+    pub parts: Vec<ContentPart>,
+    pub model: Option<String>,
+    pub created_at: String,
+}
+
+/// Something in the export that was left out, and why
+#[derive(Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct SkippedItem {
+    /// Title (or id, if untitled) of the conversation it belongs to
+    pub conversation: String,
+    /// Id of the skipped message in the source app; `None` when the whole
+    /// conversation was skipped
+    pub message: Option<String>,
+    pub reason: String,
+}
+
+#[derive(Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct ImportProgress {
+    pub source: ImportSource,
+    /// Conversations handled so far, out of `total`
+    pub processed: usize,
+    pub total: usize,
+    pub imported: usize,
+}
+
+#[derive(Serialize, Debug, Clone, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct ImportSummary {
+    pub imported: usize,
+    pub messages: usize,
+    /// Conversations left alone because an earlier import already added them
+    pub duplicates: usize,
+    pub skipped: Vec<SkippedItem>,
+}
+
+/// What a parser made of an export
+pub(crate) struct Parsed {
+    pub conversations: Vec<ImportedConversation>,
+    pub skipped: Vec<SkippedItem>,
+}
+
+/// Import every conversation in the export at `path`, either `conversations.json`
+/// itself or the zip archive it came in.
+///
+/// The store is locked per conversation rather than for the whole import, so the
+/// app stays usable while a large export is read in; `progress` is called after
+/// each conversation.
+pub fn import_export(
+    store: &Mutex<ConversationStore>,
+    source: ImportSource,
+    path: &Path,
+    mut progress: impl FnMut(&ImportProgress),
+) -> StoreResult<ImportSummary> {
+    let export = read_export(path)?;
+    let Parsed {
+        conversations,
+        skipped,
+    } = match source {
+        ImportSource::Chatgpt => chatgpt::parse(export)?,
+        ImportSource::Claude => claude::parse(export)?,
+    };
+    info!(
+        "Importing {} conversations from {} export {}",
+        conversations.len(),
+        source.as_str(),
+        path.display()
+    );
+
+    let mut summary = ImportSummary {
+        skipped,
+        ..ImportSummary::default()
+    };
+    let total = conversations.len();
+    for (index, conversation) in conversations.iter().enumerate() {
+        let result = store
+            .lock()?
+            .import_conversation(source.as_str(), conversation);
+        match result {
+            Ok(Some(_)) => {
+                summary.imported += 1;
+                summary.messages += conversation.messages.len();
+            }
+            Ok(None) => summary.duplicates += 1,
+            // A conversation the store rejects is reported, not fatal
+            Err(e @ (StoreError::Validation(_) | StoreError::Serialization(_))) => {
+                warn!(
+                    "Skipping imported conversation {}: {}",
+                    conversation.source_id, e
+                );
+                summary.skipped.push(SkippedItem {
+                    conversation: label(&conversation.name, &conversation.source_id),
+                    message: None,
+                    reason: e.to_string(),
+                });
+            }
+            Err(e) => return Err(e),
+        }
+        progress(&ImportProgress {
+            source,
+            processed: index + 1,
+            total,
+            imported: summary.imported,
+        });
+    }
+
+    info!(
+        "Imported {} conversations ({} messages); {} already present, {} items skipped",
+        summary.imported,
+        summary.messages,
+        summary.duplicates,
+        summary.skipped.len()
+    );
+    Ok(summary)
+}
+
+/// Parse `conversations.json`, reading it out of the archive if given a zip
+fn read_export(path: &Path) -> StoreResult<Value> {
+    let io_error = |e: &dyn std::fmt::Display| {
+        StoreError::Io(format!("Failed to read {}: {}", path.display(), e))
+    };
+    let file = File::open(path).map_err(|e| io_error(&e))?;
+    let is_zip = path
+        .extension()
+        .is_some_and(|ext| ext.eq_ignore_ascii_case("zip"));
+    if !is_zip {
+        return parse_json(BufReader::new(file), path);
+    }
+
+    let mut archive = ZipArchive::new(BufReader::new(file)).map_err(|e| io_error(&e))?;
+    let name = archive
+        .file_names()
+        .filter(|name| {
+            name.rsplit('/').next() == Some(CONVERSATIONS_FILE) && !name.starts_with("__MACOSX")
+        })
+        .min_by_key(|name| name.len())
+        .map(str::to_string)
+        .ok_or_else(|| {
+            StoreError::Validation(format!(
+                "{} does not contain {}",
+                path.display(),
+                CONVERSATIONS_FILE
+            ))
+        })?;
+    let entry = archive.by_name(&name).map_err(|e| io_error(&e))?;
+    parse_json(BufReader::new(entry), path)
+}
+
+fn parse_json(reader: impl Read, path: &Path) -> StoreResult<Value> {
+    serde_json::from_reader(reader)
+        .map_err(|e| StoreError::Validation(format!("{} is not valid JSON: {}", path.display(), e)))
+}
+
+/// The conversations array of an export, each entry still to be parsed on its own
+/// so one malformed conversation does not sink the rest
+pub(crate) fn conversation_list(export: Value) -> StoreResult<Vec<Value>> {
+    match export {
+        Value::Array(conversations) => Ok(conversations),
+        _ => Err(StoreError::Validation(format!(
+            "expected {} to hold a list of conversations",
+            CONVERSATIONS_FILE
+        ))),
+    }
+}
+
+/// How a conversation is named in skipped items
+pub(crate) fn label(name: &str, source_id: &str) -> String {
+    match name.trim() {
+        "" => source_id.to_string(),
+        name => name.to_string(),
+    }
+}
+
+/// Seconds since the epoch, as ChatGPT stores them, in the store's RFC 3339 form
+pub(crate) fn timestamp_from_epoch(seconds: f64) -> Option<String> {
+    let whole = seconds.floor();
+    let nanos = ((seconds - whole) * 1e9) as u32;
+    DateTime::from_timestamp(whole as i64, nanos).map(|time| time.to_rfc3339())
+}
+
+/// An RFC 3339 timestamp normalized to UTC, so imported and native rows sort together
+pub(crate) fn timestamp_from_rfc3339(timestamp: &str) -> Option<String> {
+    DateTime::parse_from_rfc3339(timestamp)
+        .ok()
+        .map(|time| time.with_timezone(&Utc).to_rfc3339())
+}
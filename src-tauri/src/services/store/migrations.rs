@@ -0,0 +1,347 @@
+use crate::services::store::{StoreError, StoreResult};
+use log::info;
+use rusqlite::Connection;
+use std::collections::HashSet;
+
+struct Migration {
+    version: u32,
+    description: &'static str,
+    sql: &'static str,
+}
+
+/// Schema history, applied in order and tracked in `PRAGMA user_version`.
+///
+/// The first two match the migrations previously run by the SQL plugin and use
+/// `IF NOT EXISTS`, so databases created by it are picked up unchanged.
+const MIGRATIONS: &[Migration] = &[
+    Migration {
+        version: 1,
+        description: "create_conversations_table",
+        sql: "CREATE TABLE IF NOT EXISTS conversations (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            name TEXT NOT NULL,
+            created_at TEXT NOT NULL,
+            updated_at TEXT NOT NULL
+        )",
+    },
+    Migration {
+        version: 2,
+        description: "create_messages_table",
+        sql: "CREATE TABLE IF NOT EXISTS messages (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            conversation_id INTEGER NOT NULL,
+            content TEXT NOT NULL,
+            sender TEXT NOT NULL,
+            timestamp TEXT NOT NULL,
+            FOREIGN KEY (conversation_id) REFERENCES conversations(id)
+        )",
+    },
+    Migration {
+        version: 3,
+        description: "index_messages_by_conversation",
+        sql: "CREATE INDEX IF NOT EXISTS idx_messages_conversation
+            ON messages(conversation_id, id)",
+    },
+    Migration {
+        version: 4,
+        description: "structured_messages",
+        // Existing rows become a single text part, each parented to the message
+        // before it in the same conversation. Messages of conversations that no
+        // longer exist are dropped.
+        sql: "CREATE TABLE messages_new (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            conversation_id INTEGER NOT NULL
+                REFERENCES conversations(id) ON DELETE CASCADE,
+            parent_id INTEGER REFERENCES messages(id),
+            role TEXT NOT NULL CHECK (role IN ('system', 'user', 'assistant', 'tool')),
+            parts TEXT NOT NULL CHECK (json_valid(parts) AND json_type(parts) = 'array'),
+            model TEXT,
+            provider TEXT,
+            prompt_tokens INTEGER,
+            completion_tokens INTEGER,
+            finish_reason TEXT,
+            latency_ms INTEGER,
+            created_at TEXT NOT NULL
+        );
+        INSERT INTO messages_new (id, conversation_id, parent_id, role, parts, created_at)
+        SELECT
+            id,
+            conversation_id,
+            LAG(id) OVER (PARTITION BY conversation_id ORDER BY id),
+            CASE WHEN sender IN ('system', 'user', 'assistant', 'tool') THEN sender
+                 ELSE 'user' END,
+            json_array(json_object('type', 'text', 'text', content)),
+            timestamp
+        FROM messages
+        WHERE conversation_id IN (SELECT id FROM conversations);
+        DROP TABLE messages;
+        ALTER TABLE messages_new RENAME TO messages;
+        CREATE INDEX idx_messages_conversation ON messages(conversation_id, id);
+        CREATE INDEX idx_messages_parent ON messages(parent_id);",
+    },
+    Migration {
+        version: 5,
+        description: "active_branch",
+        // Until now each conversation was a single chain ending at its newest message
+        sql: "ALTER TABLE conversations ADD COLUMN active_leaf_id INTEGER
+            REFERENCES messages(id) ON DELETE SET NULL;
+        UPDATE conversations SET active_leaf_id =
+            (SELECT MAX(id) FROM messages WHERE conversation_id = conversations.id);",
+    },
+    Migration {
+        version: 6,
+        description: "full_text_search",
+        // Both indexes use the indexed row's id as their rowid. Message text is the
+        // message's text parts joined by newlines; triggers keep both in sync,
+        // including deletes cascaded from a conversation.
+        sql: "CREATE VIRTUAL TABLE message_search USING fts5(
+            text, tokenize = 'unicode61 remove_diacritics 2'
+        );
+        CREATE VIRTUAL TABLE conversation_search USING fts5(
+            name, tokenize = 'unicode61 remove_diacritics 2'
+        );
+
+        CREATE TRIGGER messages_search_insert AFTER INSERT ON messages BEGIN
+            INSERT INTO message_search (rowid, text)
+            SELECT NEW.id, group_concat(json_extract(value, '$.text'), char(10))
+            FROM json_each(NEW.parts) WHERE json_extract(value, '$.type') = 'text'
+            HAVING count(*) > 0;
+        END;
+        CREATE TRIGGER messages_search_update AFTER UPDATE OF parts ON messages BEGIN
+            DELETE FROM message_search WHERE rowid = OLD.id;
+            INSERT INTO message_search (rowid, text)
+            SELECT NEW.id, group_concat(json_extract(value, '$.text'), char(10))
+            FROM json_each(NEW.parts) WHERE json_extract(value, '$.type') = 'text'
+            HAVING count(*) > 0;
+        END;
+        CREATE TRIGGER messages_search_delete AFTER DELETE ON messages BEGIN
+            DELETE FROM message_search WHERE rowid = OLD.id;
+        END;
+
+        CREATE TRIGGER conversations_search_insert AFTER INSERT ON conversations BEGIN
+            INSERT INTO conversation_search (rowid, name) VALUES (NEW.id, NEW.name);
+        END;
+        CREATE TRIGGER conversations_search_update AFTER UPDATE OF name ON conversations BEGIN
+            UPDATE conversation_search SET name = NEW.name WHERE rowid = OLD.id;
+        END;
+        CREATE TRIGGER conversations_search_delete AFTER DELETE ON conversations BEGIN
+            DELETE FROM conversation_search WHERE rowid = OLD.id;
+        END;
+
+        INSERT INTO message_search (rowid, text)
+        SELECT messages.id, group_concat(json_extract(part.value, '$.text'), char(10))
+        FROM messages, json_each(messages.parts) AS part
+        WHERE json_extract(part.value, '$.type') = 'text'
+        GROUP BY messages.id;
+        INSERT INTO conversation_search (rowid, name) SELECT id, name FROM conversations;",
+    },
+    Migration {
+        version: 7,
+        description: "conversation_name_source",
+        // Names that predate automatic titles are kept as if the user chose them
+        sql: "ALTER TABLE conversations ADD COLUMN name_source TEXT NOT NULL DEFAULT 'default'
+            CHECK (name_source IN ('default', 'generated', 'user'));
+        UPDATE conversations SET name_source = 'user';",
+    },
+    Migration {
+        version: 8,
+        description: "conversation_import_origin",
+        // Where an imported conversation came from, so importing the same export
+        // twice does not duplicate it
+        sql: "ALTER TABLE conversations ADD COLUMN import_source TEXT;
+        ALTER TABLE conversations ADD COLUMN import_id TEXT;
+        CREATE UNIQUE INDEX idx_conversations_import
+            ON conversations(import_source, import_id) WHERE import_id IS NOT NULL;",
+    },
+];
+
+/// The schema version this build migrates databases to
+pub(crate) fn latest_version() -> u32 {
+    MIGRATIONS.last().map_or(0, |migration| migration.version)
+}
+
+/// Bring the schema up to date, each migration in its own transaction.
+///
+/// Foreign keys are off while migrating, since rebuilding a table drops the one
+/// its rows point at; each migration is checked for new dangling references before
+/// it commits, and enforcement is turned back on afterwards. References that were
+/// already dangling (the SQL plugin never enforced them) are left for the
+/// migration that cleans them up.
+pub(crate) fn migrate(conn: &mut Connection) -> StoreResult<()> {
+    conn.pragma_update(None, "foreign_keys", false)?;
+    let result = apply(conn);
+    conn.pragma_update(None, "foreign_keys", true)?;
+    result
+}
+
+fn apply(conn: &mut Connection) -> StoreResult<()> {
+    let current: u32 = conn.query_row("PRAGMA user_version", [], |row| row.get(0))?;
+    for migration in MIGRATIONS.iter().filter(|m| m.version > current) {
+        info!(
+            "Applying database migration {}: {}",
+            migration.version, migration.description
+        );
+        let tx = conn.transaction()?;
+        let before = dangling_references(&tx)?;
+        tx.execute_batch(migration.sql)?;
+        let introduced = dangling_references(&tx)?
+            .difference(&before)
+            .next()
+            .is_some();
+        if introduced {
+            return Err(StoreError::Migration(format!(
+                "migration {} left rows with dangling references",
+                migration.version
+            )));
+        }
+        tx.pragma_update(None, "user_version", migration.version)?;
+        tx.commit()?;
+    }
+    Ok(())
+}
+
+/// Rows whose foreign keys point nowhere, as (table, rowid, referenced table)
+fn dangling_references(conn: &Connection) -> StoreResult<HashSet<(String, Option<i64>, String)>> {
+    let mut stmt = conn.prepare("PRAGMA foreign_key_check")?;
+    let rows = stmt.query_map([], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)))?;
+    Ok(rows.collect::<rusqlite::Result<_>>()?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A database as the SQL plugin left it: its two tables, no schema version,
+    /// and foreign keys that were never enforced
+    fn legacy_database() -> Connection {
+        let conn = Connection::open_in_memory().unwrap();
+        conn.execute_batch(
+            "PRAGMA foreign_keys = OFF;
+            CREATE TABLE conversations (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                name TEXT NOT NULL,
+                created_at TEXT NOT NULL,
+                updated_at TEXT NOT NULL
+            );
+            CREATE TABLE messages (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                conversation_id INTEGER NOT NULL,
+                content TEXT NOT NULL,
+                sender TEXT NOT NULL,
+                timestamp TEXT NOT NULL,
+                FOREIGN KEY (conversation_id) REFERENCES conversations(id)
+            );
+            INSERT INTO conversations VALUES
+                (1, 'Rust questions', '2024-05-01T10:00:00Z', '2024-05-01T10:05:00Z'),
+                (2, 'Empty', '2024-05-02T09:00:00Z', '2024-05-02T09:00:00Z');
+            INSERT INTO messages VALUES
+                (1, 1, 'What is a lifetime?', 'user', '2024-05-01T10:00:00Z'),
+                (2, 1, 'A lifetime names how long a borrow is valid.', 'assistant', '2024-05-01T10:00:05Z'),
+                (3, 99, 'Left behind by a deleted conversation', 'user', '2024-05-01T11:00:00Z'),
+                (4, 1, 'And \"quotes\"?', 'human', '2024-05-01T10:05:00Z');",
+        )
+        .unwrap();
+        conn
+    }
+
+    #[test]
+    fn upgrades_plain_text_messages_to_parts() {
+        let mut conn = legacy_database();
+        migrate(&mut conn).unwrap();
+
+        let version: u32 = conn
+            .query_row("PRAGMA user_version", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(version, latest_version());
+
+        let rows: Vec<(i64, Option<i64>, String, String, String)> = conn
+            .prepare("SELECT id, parent_id, role, parts, created_at FROM messages ORDER BY id")
+            .unwrap()
+            .query_map([], |row| {
+                Ok((
+                    row.get(0)?,
+                    row.get(1)?,
+                    row.get(2)?,
+                    row.get(3)?,
+                    row.get(4)?,
+                ))
+            })
+            .unwrap()
+            .collect::<rusqlite::Result<_>>()
+            .unwrap();
+        assert_eq!(
+            rows,
+            [
+                (
+                    1,
+                    None,
+                    "user".to_string(),
+                    r#"[{"type":"text","text":"What is a lifetime?"}]"#.to_string(),
+                    "2024-05-01T10:00:00Z".to_string(),
+                ),
+                (
+                    2,
+                    Some(1),
+                    "assistant".to_string(),
+                    r#"[{"type":"text","text":"A lifetime names how long a borrow is valid."}]"#
+                        .to_string(),
+                    "2024-05-01T10:00:05Z".to_string(),
+                ),
+                // The orphaned message is dropped, and an unknown sender becomes the user
+                (
+                    4,
+                    Some(2),
+                    "user".to_string(),
+                    r#"[{"type":"text","text":"And \"quotes\"?"}]"#.to_string(),
+                    "2024-05-01T10:05:00Z".to_string(),
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn later_migrations_pick_up_upgraded_rows() {
+        let mut conn = legacy_database();
+        migrate(&mut conn).unwrap();
+
+        let leaves: Vec<(i64, Option<i64>, String)> = conn
+            .prepare("SELECT id, active_leaf_id, name_source FROM conversations ORDER BY id")
+            .unwrap()
+            .query_map([], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)))
+            .unwrap()
+            .collect::<rusqlite::Result<_>>()
+            .unwrap();
+        assert_eq!(
+            leaves,
+            [
+                (1, Some(4), "user".to_string()),
+                (2, None, "user".to_string())
+            ]
+        );
+
+        let found: i64 = conn
+            .query_row(
+                "SELECT rowid FROM message_search WHERE message_search MATCH 'borrow'",
+                [],
+                |row| row.get(0),
+            )
+            .unwrap();
+        assert_eq!(found, 2);
+
+        let foreign_keys: bool = conn
+            .query_row("PRAGMA foreign_keys", [], |row| row.get(0))
+            .unwrap();
+        assert!(foreign_keys);
+    }
+
+    #[test]
+    fn migrating_twice_changes_nothing() {
+        let mut conn = legacy_database();
+        migrate(&mut conn).unwrap();
+        migrate(&mut conn).unwrap();
+        let count: i64 = conn
+            .query_row("SELECT count(*) FROM messages", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(count, 3);
+    }
+}
@@ -0,0 +1,103 @@
+use serde::{ser::SerializeMap, Serialize, Serializer};
+use std::path::PathBuf;
+use thiserror::Error;
+
+pub mod backup;
+pub mod conversations;
+pub mod export;
+pub mod import;
+mod migrations;
+pub mod paths;
+pub mod search;
+
+pub use backup::{
+    BackupConfig, BackupInfo, BackupKind, BackupSettings, IntegrityReport, RestoreSummary,
+};
+pub use conversations::{
+    Conversation, ConversationStore, ExistingDatabase, NameSource, NewMessage, StoredMessage,
+    TokenUsage,
+};
+pub use export::{BulkExportSummary, ConversationExport, ExportFormat};
+pub use import::{ImportProgress, ImportSource, ImportSummary, SkippedItem};
+pub use paths::{DataDir, DataDirSource, StorageConfig, StorageSettings};
+pub use search::{SearchField, SearchFilters, SearchHit};
+
+/// Emitted with the updated `Conversation` when the backend changes one on its own,
+/// e.g. after generating a title
+pub(crate) const EVT_CONVERSATION_UPDATED: &str = "conversation-updated";
+
+/// Errors from the conversation database
+#[derive(Error, Debug)]
+pub enum StoreError {
+    #[error("Database error: {0}")]
+    Database(#[from] rusqlite::Error),
+
+    #[error("Conversation not found: {0}")]
+    ConversationNotFound(i64),
+
+    #[error("Message not found: {0}")]
+    MessageNotFound(i64),
+
+    #[error("Failed to serialize message: {0}")]
+    Serialization(#[from] serde_json::Error),
+
+    #[error("Migration failed: {0}")]
+    Migration(String),
+
+    #[error("Invalid input: {0}")]
+    Validation(String),
+
+    /// Relocating onto a directory that already holds a database, without
+    /// saying whether to use it or replace it
+    #[error("{} already holds a database", .0.display())]
+    DatabaseExists(PathBuf),
+
+    #[error("IO error: {0}")]
+    Io(String),
+
+    #[error("Failed to acquire lock: {0}")]
+    Lock(String),
+}
+
+impl<T> From<std::sync::PoisonError<T>> for StoreError {
+    fn from(err: std::sync::PoisonError<T>) -> Self {
+        StoreError::Lock(err.to_string())
+    }
+}
+
+impl StoreError {
+    /// Stable discriminator for the frontend, matching the variant name
+    pub fn kind(&self) -> &'static str {
+        match self {
+            StoreError::Database(_) => "Database",
+            StoreError::ConversationNotFound(_) => "ConversationNotFound",
+            StoreError::MessageNotFound(_) => "MessageNotFound",
+            StoreError::Serialization(_) => "Serialization",
+            StoreError::Migration(_) => "Migration",
+            StoreError::Validation(_) => "Validation",
+            StoreError::DatabaseExists(_) => "DatabaseExists",
+            StoreError::Io(_) => "Io",
+            StoreError::Lock(_) => "Lock",
+        }
+    }
+}
+
+/// Serialized as `{kind, message, ...details}`, e.g.
+/// `{"kind": "ConversationNotFound", "conversationId": 4, "message": "..."}`
+impl Serialize for StoreError {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut map = serializer.serialize_map(None)?;
+        map.serialize_entry("kind", self.kind())?;
+        match self {
+            StoreError::ConversationNotFound(id) => map.serialize_entry("conversationId", id)?,
+            StoreError::MessageNotFound(id) => map.serialize_entry("messageId", id)?,
+            StoreError::DatabaseExists(path) => map.serialize_entry("path", path)?,
+            _ => {}
+        }
+        map.serialize_entry("message", &self.to_string())?;
+        map.end()
+    }
+}
+
+/// Result type for store operations
+pub type StoreResult<T> = Result<T, StoreError>;
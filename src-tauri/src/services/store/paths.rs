@@ -0,0 +1,263 @@
+use crate::services::settings;
+use crate::services::store::{StoreError, StoreResult};
+use log::info;
+use serde::{Deserialize, Serialize};
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Overrides every other data directory setting when set
+pub const DATA_DIR_ENV: &str = "PQP_DATA_DIR";
+
+pub const DATABASE_FILE: &str = "pqp_chats.db";
+
+/// Where the data directory setting came from
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum DataDirSource {
+    /// The `PQP_DATA_DIR` environment variable
+    Env,
+    /// Chosen by the user and saved in the storage settings
+    Settings,
+    /// `~/Documents/pqp_data`, used by earlier versions and kept while it holds a
+    /// database, until another directory is chosen
+    Legacy,
+    /// The platform app data directory, e.g. `$XDG_DATA_HOME/<identifier>` on Linux
+    Default,
+}
+
+/// The resolved directory holding the database
+#[derive(Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct DataDir {
+    pub path: PathBuf,
+    pub source: DataDirSource,
+}
+
+impl DataDir {
+    pub fn database_path(&self) -> PathBuf {
+        self.path.join(DATABASE_FILE)
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+#[serde(default)]
+pub struct StorageSettings {
+    /// Directory for the database; the platform default when unset
+    pub data_dir: Option<PathBuf>,
+    /// Set once a directory has been chosen, so that going back to the default
+    /// means the platform default even if the legacy directory holds a database
+    pub skip_legacy: bool,
+}
+
+/// The persisted storage settings. They live in the config directory, since
+/// they say where the data directory is.
+pub struct StorageConfig {
+    path: PathBuf,
+    settings: StorageSettings,
+    /// Platform app data directory, used when nothing else is configured
+    default_dir: PathBuf,
+    /// Where the directory of the open database came from, recorded when the
+    /// store is opened or moved
+    source: Option<DataDirSource>,
+}
+
+impl StorageConfig {
+    pub fn load(path: PathBuf, default_dir: PathBuf) -> Self {
+        Self {
+            settings: settings::load(&path),
+            path,
+            default_dir,
+            source: None,
+        }
+    }
+
+    pub fn settings(&self) -> StorageSettings {
+        self.settings.clone()
+    }
+
+    /// Pick the data directory: `PQP_DATA_DIR`, then the saved setting, then the
+    /// legacy location if it already holds a database and no directory was ever
+    /// chosen, then the platform default.
+    /// The directory is created if needed.
+    pub fn resolve(&self) -> StoreResult<DataDir> {
+        let data_dir = if let Some(path) = env_data_dir() {
+            DataDir {
+                path,
+                source: DataDirSource::Env,
+            }
+        } else if let Some(path) = self.settings.data_dir.clone() {
+            DataDir {
+                path,
+                source: DataDirSource::Settings,
+            }
+        } else if let Some(path) = legacy_data_dir()
+            .filter(|dir| !self.settings.skip_legacy && dir.join(DATABASE_FILE).exists())
+        {
+            DataDir {
+                path,
+                source: DataDirSource::Legacy,
+            }
+        } else {
+            DataDir {
+                path: self.default_dir.clone(),
+                source: DataDirSource::Default,
+            }
+        };
+
+        if !data_dir.path.is_absolute() {
+            return Err(StoreError::Validation(format!(
+                "data directory {} must be an absolute path",
+                data_dir.path.display()
+            )));
+        }
+        create_dir(&data_dir.path)?;
+        info!(
+            "Using data directory {} ({:?})",
+            data_dir.path.display(),
+            data_dir.source
+        );
+        Ok(data_dir)
+    }
+
+    /// Record where the directory of the database now in use came from
+    pub fn set_source(&mut self, source: DataDirSource) {
+        self.source = Some(source);
+    }
+
+    /// The data directory holding `database`, the open database, with the source
+    /// recorded for it. Unlike `resolve` this neither reads the environment nor
+    /// touches the filesystem.
+    pub fn data_dir(&self, database: &Path) -> StoreResult<DataDir> {
+        let source = self
+            .source
+            .ok_or_else(|| StoreError::Io("the database has not been opened yet".to_string()))?;
+        Ok(DataDir {
+            path: database.parent().unwrap_or(database).to_path_buf(),
+            source,
+        })
+    }
+
+    /// Replace the settings and persist them
+    pub fn update(&mut self, settings: StorageSettings) -> StoreResult<()> {
+        settings::save(&self.path, &settings).map_err(|e| {
+            StoreError::Io(format!("Failed to write {}: {}", self.path.display(), e))
+        })?;
+        self.settings = settings;
+        Ok(())
+    }
+}
+
+/// The `PQP_DATA_DIR` override, if set to a non-empty value
+pub fn env_data_dir() -> Option<PathBuf> {
+    env::var_os(DATA_DIR_ENV)
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+}
+
+fn legacy_data_dir() -> Option<PathBuf> {
+    dirs::home_dir().map(|home| home.join("Documents").join("pqp_data"))
+}
+
+pub(crate) fn create_dir(path: &Path) -> StoreResult<()> {
+    fs::create_dir_all(path)
+        .map_err(|e| StoreError::Io(format!("Failed to create {}: {}", path.display(), e)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::process;
+
+    fn temp_dir(test: &str) -> PathBuf {
+        let dir = env::temp_dir().join(format!("pqp-paths-{}-{}", test, process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        dir
+    }
+
+    fn config(dir: &Path, settings: StorageSettings) -> StorageConfig {
+        let mut config = StorageConfig::load(dir.join("storage.json"), dir.join("default"));
+        config.update(settings).unwrap();
+        config
+    }
+
+    #[test]
+    fn resolve_prefers_the_saved_directory_and_creates_it() {
+        let dir = temp_dir("saved");
+        let chosen = dir.join("chosen");
+        let config = config(
+            &dir,
+            StorageSettings {
+                data_dir: Some(chosen.clone()),
+                skip_legacy: true,
+            },
+        );
+
+        let data_dir = config.resolve().unwrap();
+        assert_eq!(data_dir.path, chosen);
+        assert_eq!(data_dir.source, DataDirSource::Settings);
+        assert_eq!(data_dir.database_path(), chosen.join(DATABASE_FILE));
+        assert!(chosen.is_dir());
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn resolve_falls_back_to_the_platform_default() {
+        let dir = temp_dir("default");
+        let config = config(
+            &dir,
+            StorageSettings {
+                data_dir: None,
+                skip_legacy: true,
+            },
+        );
+
+        let data_dir = config.resolve().unwrap();
+        assert_eq!(data_dir.path, dir.join("default"));
+        assert_eq!(data_dir.source, DataDirSource::Default);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn resolve_rejects_a_relative_directory() {
+        let dir = temp_dir("relative");
+        let config = config(
+            &dir,
+            StorageSettings {
+                data_dir: Some(PathBuf::from("relative/data")),
+                skip_legacy: true,
+            },
+        );
+
+        assert!(matches!(config.resolve(), Err(StoreError::Validation(_))));
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn saved_settings_are_loaded_again() {
+        let dir = temp_dir("reload");
+        let settings = StorageSettings {
+            data_dir: Some(dir.join("chosen")),
+            skip_legacy: true,
+        };
+        config(&dir, settings.clone());
+
+        let loaded = StorageConfig::load(dir.join("storage.json"), dir.join("default"));
+        assert_eq!(loaded.settings(), settings);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn data_dir_reports_the_recorded_source_without_creating_anything() {
+        let dir = temp_dir("recorded");
+        let mut config = StorageConfig::load(dir.join("storage.json"), dir.join("default"));
+        let database = dir.join("elsewhere").join(DATABASE_FILE);
+        assert!(matches!(config.data_dir(&database), Err(StoreError::Io(_))));
+
+        config.set_source(DataDirSource::Legacy);
+        let data_dir = config.data_dir(&database).unwrap();
+        assert_eq!(data_dir.path, dir.join("elsewhere"));
+        assert_eq!(data_dir.source, DataDirSource::Legacy);
+        assert!(!dir.exists());
+    }
+}
@@ -0,0 +1,369 @@
+use crate::services::proxy::ChatRole;
+use crate::services::store::import::timestamp_from_rfc3339;
+use crate::services::store::{StoreError, StoreResult};
+use rusqlite::{params, Connection};
+use serde::{Deserialize, Serialize};
+
+/// Markers `snippet()` puts around matched terms; control characters that never
+/// appear in stored text, stripped again before the snippet is returned
+const MATCH_START: char = '\u{2}';
+const MATCH_END: char = '\u{3}';
+
+/// Tokens of context `snippet()` keeps around a match
+const SNIPPET_TOKENS: u32 = 16;
+
+const DEFAULT_LIMIT: u32 = 50;
+const MAX_LIMIT: u32 = 500;
+
+/// Narrow a search; all fields are optional
+#[derive(Deserialize, Debug, Clone, Default)]
+#[serde(rename_all = "camelCase", default)]
+pub struct SearchFilters {
+    pub conversation_id: Option<i64>,
+    /// Only messages with this role; conversation names are not matched when set
+    pub role: Option<ChatRole>,
+    /// RFC 3339 timestamps bounding the message creation time, in any offset
+    pub since: Option<String>,
+    pub until: Option<String>,
+    pub limit: Option<u32>,
+}
+
+/// Where a match was found
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum SearchField {
+    Message,
+    ConversationName,
+}
+
+#[derive(Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct SearchHit {
+    pub field: SearchField,
+    pub conversation_id: i64,
+    pub conversation_name: String,
+    /// `None` for conversation name matches
+    pub message_id: Option<i64>,
+    pub role: Option<ChatRole>,
+    pub created_at: String,
+    /// Excerpt around the match, or the whole name
+    pub snippet: String,
+    /// `[start, end)` ranges of matched terms in `snippet`, in UTF-16 code units
+    /// so they can be passed to `String.prototype.slice` directly
+    pub highlights: Vec<[usize; 2]>,
+    /// BM25 score; lower is a better match
+    pub rank: f64,
+}
+
+/// Turn free text into an FTS5 query: every word must appear, the last one may be
+/// a prefix (for search-as-you-type), and FTS5 operators are taken literally
+fn match_expression(query: &str) -> Option<String> {
+    let terms: Vec<String> = query
+        .split_whitespace()
+        .map(|term| format!("\"{}\"", term.replace('"', "\"\"")))
+        .collect();
+    let last = terms.len().checked_sub(1)?;
+    Some(
+        terms
+            .iter()
+            .enumerate()
+            .map(|(i, term)| {
+                if i == last {
+                    format!("{}*", term)
+                } else {
+                    term.clone()
+                }
+            })
+            .collect::<Vec<_>>()
+            .join(" "),
+    )
+}
+
+/// Strip the match markers from a snippet, recording where they were
+fn highlight(marked: &str) -> (String, Vec<[usize; 2]>) {
+    let mut snippet = String::with_capacity(marked.len());
+    let mut highlights = Vec::new();
+    let mut offset = 0;
+    let mut start = None;
+    for c in marked.chars() {
+        match c {
+            MATCH_START => start = Some(offset),
+            MATCH_END => {
+                if let Some(start) = start.take() {
+                    highlights.push([start, offset]);
+                }
+            }
+            c => {
+                snippet.push(c);
+                offset += c.len_utf16();
+            }
+        }
+    }
+    (snippet, highlights)
+}
+
+/// Parse a `since`/`until` bound into the UTC form timestamps are stored in, so
+/// that comparing them as text compares the times
+fn time_bound(name: &str, bound: Option<&str>) -> StoreResult<Option<String>> {
+    bound
+        .map(|bound| {
+            timestamp_from_rfc3339(bound.trim()).ok_or_else(|| {
+                StoreError::Validation(format!(
+                    "{} must be an RFC 3339 timestamp such as 2024-05-01T10:00:00Z, got '{}'",
+                    name, bound
+                ))
+            })
+        })
+        .transpose()
+}
+
+/// Search message text and conversation names, best matches first
+pub(crate) fn search(
+    conn: &Connection,
+    query: &str,
+    filters: &SearchFilters,
+) -> StoreResult<Vec<SearchHit>> {
+    let Some(expression) = match_expression(query) else {
+        return Ok(Vec::new());
+    };
+    let limit = filters.limit.unwrap_or(DEFAULT_LIMIT);
+    if limit == 0 || limit > MAX_LIMIT {
+        return Err(StoreError::Validation(format!(
+            "search limit must be between 1 and {}",
+            MAX_LIMIT
+        )));
+    }
+    let since = time_bound("since", filters.since.as_deref())?;
+    let until = time_bound("until", filters.until.as_deref())?;
+
+    let mut hits = Vec::new();
+
+    let mut stmt = conn.prepare(
+        "SELECT messages.id, messages.conversation_id, conversations.name, messages.role,
+                messages.created_at,
+                snippet(message_search, 0, ?1, ?2, '…', ?3) AS snippet,
+                bm25(message_search) AS rank
+         FROM message_search
+         JOIN messages ON messages.id = message_search.rowid
+         JOIN conversations ON conversations.id = messages.conversation_id
+         WHERE message_search MATCH ?4
+           AND (?5 IS NULL OR messages.conversation_id = ?5)
+           AND (?6 IS NULL OR messages.role = ?6)
+           AND (?7 IS NULL OR messages.created_at >= ?7)
+           AND (?8 IS NULL OR messages.created_at <= ?8)
+         ORDER BY rank
+         LIMIT ?9",
+    )?;
+    let rows = stmt.query_map(
+        params![
+            MATCH_START.to_string(),
+            MATCH_END.to_string(),
+            SNIPPET_TOKENS,
+            expression,
+            filters.conversation_id,
+            filters.role.map(|role| role.as_str()),
+            since,
+            until,
+            limit,
+        ],
+        |row| {
+            let role: String = row.get(3)?;
+            let (snippet, highlights) = highlight(&row.get::<_, String>(5)?);
+            Ok(SearchHit {
+                field: SearchField::Message,
+                message_id: Some(row.get(0)?),
+                conversation_id: row.get(1)?,
+                conversation_name: row.get(2)?,
+                role: ChatRole::parse(&role),
+                created_at: row.get(4)?,
+                snippet,
+                highlights,
+                rank: row.get(6)?,
+            })
+        },
+    )?;
+    for hit in rows {
+        hits.push(hit?);
+    }
+
+    if filters.role.is_none() {
+        let mut stmt = conn.prepare(
+            "SELECT conversations.id, conversations.name, conversations.created_at,
+                    highlight(conversation_search, 0, ?1, ?2) AS snippet,
+                    bm25(conversation_search) AS rank
+             FROM conversation_search
+             JOIN conversations ON conversations.id = conversation_search.rowid
+             WHERE conversation_search MATCH ?3
+               AND (?4 IS NULL OR conversations.id = ?4)
+               AND (?5 IS NULL OR conversations.created_at >= ?5)
+               AND (?6 IS NULL OR conversations.created_at <= ?6)
+             ORDER BY rank
+             LIMIT ?7",
+        )?;
+        let rows = stmt.query_map(
+            params![
+                MATCH_START.to_string(),
+                MATCH_END.to_string(),
+                expression,
+                filters.conversation_id,
+                since,
+                until,
+                limit,
+            ],
+            |row| {
+                let (snippet, highlights) = highlight(&row.get::<_, String>(3)?);
+                Ok(SearchHit {
+                    field: SearchField::ConversationName,
+                    conversation_id: row.get(0)?,
+                    conversation_name: row.get(1)?,
+                    message_id: None,
+                    role: None,
+                    created_at: row.get(2)?,
+                    snippet,
+                    highlights,
+                    rank: row.get(4)?,
+                })
+            },
+        )?;
+        for hit in rows {
+            hits.push(hit?);
+        }
+    }
+
+    // Scores from the two indexes are on the same BM25 scale, close enough to merge
+    hits.sort_by(|a, b| a.rank.total_cmp(&b.rank));
+    hits.truncate(limit as usize);
+    Ok(hits)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::services::store::migrations;
+
+    #[test]
+    fn match_expression_quotes_terms_and_prefixes_the_last() {
+        assert_eq!(
+            match_expression("rust lifetimes").as_deref(),
+            Some("\"rust\" \"lifetimes\"*")
+        );
+        // FTS5 operators and quotes are searched for literally
+        assert_eq!(
+            match_expression("  a OR \"b\"  NEAR(c ").as_deref(),
+            Some("\"a\" \"OR\" \"\"\"b\"\"\" \"NEAR(c\"*")
+        );
+        assert_eq!(match_expression("   "), None);
+        assert_eq!(match_expression(""), None);
+    }
+
+    #[test]
+    fn highlight_offsets_are_utf16_code_units() {
+        let marked = format!(
+            "café {}crab{} 🦀 {}rust{}",
+            MATCH_START, MATCH_END, MATCH_START, MATCH_END
+        );
+        let (snippet, highlights) = highlight(&marked);
+        assert_eq!(snippet, "café crab 🦀 rust");
+        // "é" is one code unit and "🦀" a surrogate pair
+        assert_eq!(highlights, [[5, 9], [13, 17]]);
+
+        let encoded: Vec<u16> = snippet.encode_utf16().collect();
+        assert_eq!(String::from_utf16(&encoded[13..17]).unwrap(), "rust");
+    }
+
+    #[test]
+    fn highlight_ignores_unbalanced_markers() {
+        let (snippet, highlights) = highlight(&format!("a{}b{}{}", MATCH_END, MATCH_START, "c"));
+        assert_eq!(snippet, "abc");
+        assert!(highlights.is_empty());
+    }
+
+    #[test]
+    fn finds_messages_and_conversation_names() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        migrations::migrate(&mut conn).unwrap();
+        conn.execute_batch(
+            "INSERT INTO conversations (id, name, created_at, updated_at)
+                 VALUES (1, 'Borrow checker', '2024-05-01T10:00:00Z', '2024-05-01T10:00:00Z');
+             INSERT INTO messages (conversation_id, role, parts, created_at) VALUES
+                 (1, 'user', '[{\"type\":\"text\",\"text\":\"Why does the borrow checker complain?\"}]',
+                  '2024-05-01T10:00:00Z'),
+                 (1, 'assistant', '[{\"type\":\"text\",\"text\":\"Two mutable borrows overlap.\"}]',
+                  '2024-05-01T10:00:05Z');",
+        )
+        .unwrap();
+
+        let hits = search(&conn, "borrow", &SearchFilters::default()).unwrap();
+        assert_eq!(hits.len(), 3);
+        assert!(hits
+            .iter()
+            .any(|hit| matches!(hit.field, SearchField::ConversationName)));
+
+        let filters = SearchFilters {
+            role: Some(ChatRole::Assistant),
+            ..SearchFilters::default()
+        };
+        let hits = search(&conn, "borr", &filters).unwrap();
+        assert_eq!(hits.len(), 1);
+        assert_eq!(hits[0].snippet, "Two mutable borrows overlap.");
+        assert_eq!(hits[0].highlights, [[12, 19]]);
+
+        let too_many = SearchFilters {
+            limit: Some(MAX_LIMIT + 1),
+            ..SearchFilters::default()
+        };
+        assert!(search(&conn, "borrow", &too_many).is_err());
+    }
+
+    #[test]
+    fn time_bounds_are_compared_in_utc() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        migrations::migrate(&mut conn).unwrap();
+        // Stored timestamps are UTC, with fractional seconds when written by pqp
+        conn.execute_batch(
+            "INSERT INTO conversations (id, name, created_at, updated_at) VALUES
+                 (1, 'Chat', '2024-05-01T10:00:00+00:00', '2024-05-01T10:00:00+00:00');
+             INSERT INTO messages (conversation_id, role, parts, created_at) VALUES
+                 (1, 'user', '[{\"type\":\"text\",\"text\":\"tea at ten\"}]',
+                  '2024-05-01T10:00:00.250+00:00'),
+                 (1, 'assistant', '[{\"type\":\"text\",\"text\":\"tea is ready\"}]',
+                  '2024-05-01T10:00:05+00:00');",
+        )
+        .unwrap();
+        let found = |since: Option<&str>, until: Option<&str>| {
+            let filters = SearchFilters {
+                since: since.map(str::to_string),
+                until: until.map(str::to_string),
+                ..SearchFilters::default()
+            };
+            search(&conn, "tea", &filters)
+                .unwrap()
+                .iter()
+                .map(|hit| hit.snippet.clone())
+                .collect::<Vec<_>>()
+        };
+
+        // Compared as text, an unconverted `Z` bound would miss the message of its own second
+        assert_eq!(found(Some("2024-05-01T10:00:00Z"), None).len(), 2);
+        assert_eq!(
+            found(Some("2024-05-01T12:00:01+02:00"), None),
+            ["tea is ready"]
+        );
+        assert_eq!(
+            found(None, Some("2024-05-01T10:00:04.999Z")),
+            ["tea at ten"]
+        );
+        assert!(found(None, Some("2024-05-01T05:59:59-04:00")).is_empty());
+
+        for bad in ["yesterday", "2024-05-01", "2024-05-01 10:00:00"] {
+            let filters = SearchFilters {
+                since: Some(bad.to_string()),
+                ..SearchFilters::default()
+            };
+            assert!(matches!(
+                search(&conn, "tea", &filters),
+                Err(StoreError::Validation(_))
+            ));
+        }
+    }
+}
@@ -2,6 +2,7 @@ import { useState, useEffect, useRef } from "react";
 import { useChat } from "@ai-sdk/react";
 import { useMCPServices } from "./hooks/useMCPServices";
 import { useDebugGrid } from "./hooks/useDebugGrid";
+import { useModels } from "./hooks/useModels";
 import { ChatMessageArea } from "./components/chat/chat-message-area";
 import { ResizableChatInput } from "./components/chat/resizable-chat-input";
 import { ChatFooter } from "./components/chat/chat-footer";
@@ -10,14 +11,14 @@ import { AppSidebar } from "@/components/app-sidebar";
 import LayoutIcon from "./assets/layout.svg";
 import ChatIcon from "./assets/chat.svg";
 import { setupApiRoutes } from "./lib/api-routes";
-import { defaultModel } from "./lib/ai-provider";
+import { defaultSelection, type ModelSelection } from "./lib/models";
 
 setupApiRoutes();
 
 function App() {
   const [modelsOpen, setModelsOpen] = useState(false);
   const [mcpOpen, setMcpOpen] = useState(false);
-  const [selectedModel, setSelectedModel] = useState<string>(defaultModel);
+  const [selectedModel, setSelectedModel] = useState<ModelSelection>(defaultSelection);
   const [chatKey, setChatKey] = useState(0);
   
   const {
@@ -29,9 +30,7 @@ function App() {
     error,
   } = useChat({
     api: '/api/chat',
-    body: {
-      model: selectedModel,
-    },
+    body: selectedModel,
     id: `chat-${chatKey}`,
   });
 
@@ -48,12 +47,20 @@ function App() {
   } = useMCPServices();
   
   const { showGrid, toggleGrid } = useDebugGrid();
+
+  const { models, loading: modelsLoading, fetchModels } = useModels();
   
   useEffect(() => {
     if (mcpOpen) {
       fetchServices();
     }
   }, [mcpOpen, fetchServices]);
+
+  useEffect(() => {
+    if (modelsOpen) {
+      fetchModels();
+    }
+  }, [modelsOpen, fetchModels]);
   
   useEffect(() => {
     const handleKeyDown = (event: KeyboardEvent) => {
@@ -72,9 +79,9 @@ function App() {
     return () => window.removeEventListener('keydown', handleKeyDown);
   }, []);
   
-  const handleSelectModel = (modelId: string) => {
-    console.log(`Selected model ID: ${modelId}`);
-    setSelectedModel(modelId);
+  const handleSelectModel = (selection: ModelSelection) => {
+    console.log(`Selected model: ${selection.provider}/${selection.model}`);
+    setSelectedModel(selection);
   };
   
   const handleSelectService = (service: string) => {
@@ -84,9 +91,7 @@ function App() {
   const handleFormSubmit = (e: React.FormEvent<HTMLFormElement>) => {
     e.preventDefault();
     handleSubmit(e, {
-      body: {
-        model: selectedModel,
-      },
+      body: selectedModel,
     });
   };
 
@@ -136,8 +141,10 @@ function App() {
                 mcpOpen={mcpOpen}
                 setMcpOpen={setMcpOpen}
                 onToggleGrid={toggleGrid}
+                models={models}
+                modelsLoading={modelsLoading}
                 onSelectModel={handleSelectModel}
-                selectedModelId={selectedModel}
+                selectedModel={selectedModel}
                 onSelectService={handleSelectService}
               />
             </form>
@@ -1,6 +1,7 @@
 import { Button } from "../ui/button";
 import { ModelsDropdown } from "../mcp/models-dropdown";
 import { MCPServicesDropdown } from "../mcp/mcp-services-dropdown";
+import type { ModelInfo, ModelSelection } from "@/lib/models";
 
 interface ChatFooterProps {
   isLoading: boolean;
@@ -11,8 +12,10 @@ interface ChatFooterProps {
   mcpOpen: boolean;
   setMcpOpen: (open: boolean) => void;
   onToggleGrid: () => void;
-  onSelectModel?: (model: string) => void;
-  selectedModelId: string;
+  models: ModelInfo[];
+  modelsLoading: boolean;
+  onSelectModel?: (selection: ModelSelection) => void;
+  selectedModel: ModelSelection;
   onSelectService?: (service: string) => void;
 }
 
@@ -25,8 +28,10 @@ export function ChatFooter({
   mcpOpen,
   setMcpOpen,
   onToggleGrid,
+  models,
+  modelsLoading,
   onSelectModel,
-  selectedModelId,
+  selectedModel,
   onSelectService
 }: ChatFooterProps) {
   return (
@@ -36,8 +41,10 @@ export function ChatFooter({
         <ModelsDropdown 
           open={modelsOpen} 
           onOpenChange={setModelsOpen}
+          models={models}
+          loading={modelsLoading}
           onSelectModel={onSelectModel}
-          selectedModelId={selectedModelId}
+          selected={selectedModel}
         />
         
         <MCPServicesDropdown
@@ -2,59 +2,70 @@ import {
   DropdownMenu,
   DropdownMenuContent,
   DropdownMenuItem,
+  DropdownMenuLabel,
   DropdownMenuTrigger,
 } from "../ui/dropdown-menu";
-
-interface Model {
-  id: string;
-  name: string;
-}
+import type { ModelInfo, ModelSelection } from "@/lib/models";
 
 interface ModelsDropdownProps {
   open: boolean;
   onOpenChange: (open: boolean) => void;
-  onSelectModel?: (modelId: string) => void; 
-  selectedModelId: string;
+  models: ModelInfo[];
+  loading: boolean;
+  onSelectModel?: (selection: ModelSelection) => void;
+  selected: ModelSelection;
 }
 
-export function ModelsDropdown({ open, onOpenChange, onSelectModel, selectedModelId }: ModelsDropdownProps) {
-  const models: Model[] = [
-    { id: "claude-3-5-sonnet-latest", name: "Claude 3.5 Sonnet" },
-    { id: "claude-3-7-sonnet-latest", name: "Claude 3.7 Sonnet" },
-    { id: "claude-3-5-haiku-latest", name: "Claude 3.5 Haiku" },
-    { id: "gpt-4.1-2025-04-14", name: "GPT-4.1 Preview" }
-  ];
-  
-  const selectedModel = models.find(model => model.id === selectedModelId);
-  
-  const handleSelect = (modelId: string) => { 
+export function ModelsDropdown({ open, onOpenChange, models, loading, onSelectModel, selected }: ModelsDropdownProps) {
+  const selectedModel = models.find(
+    model => model.provider === selected.provider && model.id === selected.model
+  );
+
+  // Grouped by provider, in the order the providers were listed
+  const providers = [...new Set(models.map(model => model.provider))];
+
+  const handleSelect = (model: ModelInfo) => {
     if (onSelectModel) {
-      onSelectModel(modelId); 
+      onSelectModel({ provider: model.provider, model: model.id });
     }
   };
-  
+
   return (
     <DropdownMenu open={open} onOpenChange={onOpenChange}>
       <DropdownMenuTrigger 
         className="px-1ch py-0 h-auto hover:text-[var(--accent-color)] transition-colors duration-200 font-mono text-xs text-[#D6A97A]"
       >
-        {selectedModel ? `${selectedModel.name}` : 'Select Model [⌘/]'}
+        {selectedModel ? selectedModel.displayName : selected.model || 'Select Model [⌘/]'}
       </DropdownMenuTrigger>
       <DropdownMenuContent 
         align="end" 
-        className="font-mono text-xs bg-black border-2 border-[var(--text-color)] rounded-none shadow-lg p-0 z-50"
+        className="font-mono text-xs bg-black border-2 border-[var(--text-color)] rounded-none shadow-lg p-0 z-50 max-h-[60vh] overflow-y-auto"
         style={{ lineHeight: 'var(--line-height)' }}
       >
-        {models.map((model, index) => (
-          <DropdownMenuItem 
-            key={index} 
-            onClick={() => handleSelect(model.id)} 
-            className="px-2ch py-0 h-[var(--line-height)] hover:bg-[rgba(214,169,122,0.1)] hover:text-[#D6A97A] text-white cursor-pointer"
-          >
-            {model.name} 
-          </DropdownMenuItem>
+        {models.length === 0 && (
+          <DropdownMenuLabel className="px-2ch py-0 h-[var(--line-height)] text-muted-foreground font-normal">
+            {loading ? 'Loading models...' : 'No models available'}
+          </DropdownMenuLabel>
+        )}
+        {providers.map(provider => (
+          <div key={provider}>
+            <DropdownMenuLabel className="px-2ch py-0 h-[var(--line-height)] text-muted-foreground font-normal">
+              {provider}
+            </DropdownMenuLabel>
+            {models
+              .filter(model => model.provider === provider)
+              .map(model => (
+                <DropdownMenuItem 
+                  key={`${model.provider}/${model.id}`} 
+                  onClick={() => handleSelect(model)} 
+                  className="px-2ch py-0 h-[var(--line-height)] hover:bg-[rgba(214,169,122,0.1)] hover:text-[#D6A97A] text-white cursor-pointer"
+                >
+                  {model.displayName} 
+                </DropdownMenuItem>
+              ))}
+          </div>
         ))}
       </DropdownMenuContent>
     </DropdownMenu>
   );
-} 
\ No newline at end of file
+} 
@@ -18,12 +18,6 @@ export const APP_CONFIG = {
     }
   },
   
-  models: [
-    "Claude 3 Opus",
-    "Claude 3 Sonnet",
-    "Claude 3 Haiku"
-  ],
-  
   styling: {
     accentColor: "#D6A97A"
   }
@@ -0,0 +1,24 @@
+import { useState, useCallback } from 'react';
+import { listAllModels, type ModelInfo } from '../lib/models';
+
+export function useModels() {
+  const [models, setModels] = useState<ModelInfo[]>([]);
+  const [loading, setLoading] = useState(false);
+
+  const fetchModels = useCallback(async (refresh = false) => {
+    setLoading(true);
+    try {
+      const list = await listAllModels(refresh);
+      setModels(list);
+      return list;
+    } finally {
+      setLoading(false);
+    }
+  }, []);
+
+  return {
+    models,
+    loading,
+    fetchModels
+  };
+}
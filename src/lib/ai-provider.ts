@@ -1,6 +1,7 @@
 import { createAnthropic } from '@ai-sdk/anthropic';
 import { createOpenAI } from '@ai-sdk/openai';
 import { customTauriFetch } from './custom-fetch';
+import { defaultSelection } from './models';
 
 
 export const anthropicProvider = createAnthropic({
@@ -11,25 +12,38 @@ export const openaiProvider = createOpenAI({
   fetch: customTauriFetch as typeof fetch,
 });
 
+type ReasoningDetail =
+  | { type: 'text'; text: string; signature?: string }
+  | { type: 'redacted'; data: string };
 
-const modelProviderMap: Record<string, 'anthropic' | 'openai'> = {
-  'claude-3-5-sonnet': 'anthropic', 
-  'claude-': 'anthropic',          
-  'gpt-': 'openai',              
-};
-
-function getProviderForModel(model: string): 'anthropic' | 'openai' {
-  for (const key in modelProviderMap) {
-    if (model.startsWith(key)) {
-      return modelProviderMap[key];
-    }
-  }
-  console.warn(`Could not determine provider for model: ${model}. Defaulting to anthropic.`);
-  return 'anthropic'; 
+interface UIMessageLike {
+  role: string;
+  content: string;
+  parts?: Array<{ type: string; details?: ReasoningDetail[] }>;
 }
 
+/**
+ * Convert a UI message into the provider-neutral shape, keeping assistant
+ * reasoning (and its signatures) so multi-turn thinking conversations stay valid
+ */
+function toChatMessage(msg: UIMessageLike) {
+  const reasoning = (msg.parts ?? [])
+    .filter(part => part.type === 'reasoning')
+    .flatMap(part => part.details ?? [])
+    .map(detail =>
+      detail.type === 'redacted'
+        ? { type: 'redacted_reasoning', data: detail.data }
+        : { type: 'reasoning', text: detail.text, signature: detail.signature },
+    );
 
-export const defaultModel = 'claude-3-5-sonnet-latest';
+  if (msg.role !== 'assistant' || reasoning.length === 0) {
+    return { role: msg.role, content: msg.content };
+  }
+  return {
+    role: msg.role,
+    content: [...reasoning, { type: 'text', text: msg.content }],
+  };
+}
 
 export async function handleChatRequest(request: Request): Promise<Response> {
   try {
@@ -39,8 +53,10 @@ export async function handleChatRequest(request: Request): Promise<Response> {
     console.log('handleChatRequest: Request body parsed', body);
 
     const { messages } = body;
-    const model = body.model || defaultModel;
-    const provider = getProviderForModel(model);
+    // The picker sends the provider that listed the model, so any built-in
+    // provider or profile can serve it
+    const model = body.model || defaultSelection.model;
+    const provider = body.provider || defaultSelection.provider;
 
     if (!messages || !Array.isArray(messages)) {
       throw new Error('Invalid request: messages array is required');
@@ -49,21 +65,18 @@ export async function handleChatRequest(request: Request): Promise<Response> {
     console.log('handleChatRequest: Messages extracted', messages);
     console.log(`handleChatRequest: Using model: ${model}, provider: ${provider}`);
 
-    const transformedMessages = messages.map(msg => ({
-      role: msg.role,
-      content: msg.content
-    }));
+    const transformedMessages = messages.map(toChatMessage);
 
     console.log('handleChatRequest: Transformed messages', transformedMessages);
 
-    const apiPayload = JSON.stringify({
+    // Provider-neutral request; the Rust side translates it to each provider's wire format
+    const chatRequest = {
       model: model,
       messages: transformedMessages,
-      stream: true,
-      ...(provider === 'anthropic' && { max_tokens: 1000 }),
-    });
+      ...(body.thinking ? { thinking: body.thinking } : {}),
+    };
 
-    console.log(`handleChatRequest: Prepared API payload for ${provider}`);
+    console.log(`handleChatRequest: Prepared chat request for ${provider}`);
 
     const response = await customTauriFetch('api/stream', {
       method: 'POST',
@@ -72,7 +85,7 @@ export async function handleChatRequest(request: Request): Promise<Response> {
       },
       body: JSON.stringify({
          provider: provider,
-         payload: apiPayload
+         request: chatRequest
       }),
     });
 
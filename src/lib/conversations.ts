@@ -0,0 +1,282 @@
+import { invoke } from '@tauri-apps/api/core';
+import { listen, type UnlistenFn } from '@tauri-apps/api/event';
+
+// Typed wrappers around the conversation store commands; the webview has no
+// direct database access
+
+export interface Conversation {
+  id: number;
+  name: string;
+  // Only 'default' names are replaced by a generated title
+  nameSource: 'default' | 'generated' | 'user';
+  // Last message of the branch currently shown
+  activeLeafId: number | null;
+  createdAt: string;
+  updatedAt: string;
+}
+
+export type Role = 'user' | 'assistant' | 'system' | 'tool';
+
+// Same shape as the content parts of a chat request
+export type MessagePart =
+  | { type: 'text'; text: string }
+  | { type: 'tool_call'; id: string; name: string; arguments: unknown }
+  | { type: 'tool_result'; tool_call_id: string; name?: string; content: unknown; is_error?: boolean }
+  | { type: 'reasoning'; text: string; signature?: string }
+  | { type: 'redacted_reasoning'; data: string }
+  | { type: 'attachment'; path: string; media_type?: string };
+
+export interface TokenUsage {
+  promptTokens: number;
+  completionTokens: number;
+}
+
+export interface StoredMessage {
+  id: number;
+  conversationId: number;
+  parentId: number | null;
+  role: Role;
+  parts: MessagePart[];
+  model: string | null;
+  provider: string | null;
+  usage: TokenUsage | null;
+  finishReason: string | null;
+  latencyMs: number | null;
+  createdAt: string;
+}
+
+export interface NewMessage {
+  role: Role;
+  parts: string | MessagePart[];
+  // Defaults to the last message of the active branch
+  parentId?: number;
+  model?: string;
+  provider?: string;
+  usage?: TokenUsage;
+  finishReason?: string;
+  latencyMs?: number;
+}
+
+export function createConversation(name: string): Promise<Conversation> {
+  return invoke('create_conversation', { name });
+}
+
+export function listConversations(): Promise<Conversation[]> {
+  return invoke('list_conversations');
+}
+
+export function renameConversation(conversationId: number, name: string): Promise<Conversation> {
+  return invoke('rename_conversation', { conversationId, name });
+}
+
+export function deleteConversation(conversationId: number): Promise<void> {
+  return invoke('delete_conversation', { conversationId });
+}
+
+// The first reply in a conversation also starts titling it in the background;
+// see `onConversationUpdated`
+export function appendMessage(conversationId: number, message: NewMessage): Promise<StoredMessage> {
+  return invoke('append_message', { conversationId, message });
+}
+
+// The active branch, first message to last
+export function getMessages(conversationId: number): Promise<StoredMessage[]> {
+  return invoke('get_messages', { conversationId });
+}
+
+// Add an edited or regenerated alternative to a message and switch to it
+export function forkMessage(messageId: number, message: NewMessage): Promise<StoredMessage> {
+  return invoke('fork_message', { messageId, message });
+}
+
+// Switch to the branch through a message, returning the new active branch
+export function switchBranch(messageId: number): Promise<StoredMessage[]> {
+  return invoke('switch_branch', { messageId });
+}
+
+export function listSiblings(messageId: number): Promise<StoredMessage[]> {
+  return invoke('list_siblings', { messageId });
+}
+
+export interface SearchFilters {
+  conversationId?: number;
+  // Only messages with this role; conversation names are not matched when set
+  role?: Role;
+  // RFC 3339 bounds on the creation time
+  since?: string;
+  until?: string;
+  limit?: number;
+}
+
+export interface SearchHit {
+  field: 'message' | 'conversation_name';
+  conversationId: number;
+  conversationName: string;
+  messageId: number | null;
+  role: Role | null;
+  createdAt: string;
+  snippet: string;
+  // [start, end) ranges of matched terms in `snippet`, usable with `slice`
+  highlights: [number, number][];
+  // Lower is a better match
+  rank: number;
+}
+
+export function searchMessages(query: string, filters?: SearchFilters): Promise<SearchHit[]> {
+  return invoke('search_messages', { query, filters });
+}
+
+// Fired when the backend changes a conversation on its own, e.g. after titling it
+export function onConversationUpdated(
+  handler: (conversation: Conversation) => void,
+): Promise<UnlistenFn> {
+  return listen<Conversation>('conversation-updated', (event) => handler(event.payload));
+}
+
+export interface TitleSettings {
+  enabled: boolean;
+  provider: string;
+  model: string;
+}
+
+export function getTitleSettings(): Promise<TitleSettings> {
+  return invoke('get_title_settings');
+}
+
+export function saveTitleSettings(settings: TitleSettings): Promise<void> {
+  return invoke('save_title_settings', { settings });
+}
+
+export interface DataDir {
+  path: string;
+  // 'env' when PQP_DATA_DIR is set, which also prevents relocating
+  source: 'env' | 'settings' | 'legacy' | 'default';
+}
+
+export function getDataDir(): Promise<DataDir> {
+  return invoke('get_data_dir');
+}
+
+// What to do when the new directory already holds a database
+export type ExistingDatabase = 'use' | 'replace';
+
+// Move the database to another directory; null returns to the platform default.
+// Rejects with kind 'DatabaseExists' when the directory already holds a database
+// and `existing` is not given, so the user can choose.
+export function setDataDir(
+  path: string | null,
+  existing?: ExistingDatabase,
+): Promise<DataDir> {
+  return invoke('set_data_dir', { path, existing: existing ?? null });
+}
+
+// markdown: readable transcript of the active branch
+// json: lossless, every branch and all metadata
+// jsonl: the active branch in OpenAI fine-tuning format
+export type ExportFormat = 'markdown' | 'json' | 'jsonl';
+
+export function exportConversation(conversationId: number, format: ExportFormat): Promise<string> {
+  return invoke('export_conversation', { conversationId, format });
+}
+
+export interface BulkExportSummary {
+  path: string;
+  conversations: number;
+}
+
+// Writes one file per conversation into a directory, or into a zip archive when
+// `path` ends in .zip. Rejects with kind 'Validation', writing nothing, if any of
+// the files already exists in the directory.
+export function exportConversations(format: ExportFormat, path: string): Promise<BulkExportSummary> {
+  return invoke('export_conversations', { format, path });
+}
+
+// 'chatgpt' or 'claude': the conversations.json from the app's data export
+export type ImportSource = 'chatgpt' | 'claude';
+
+export interface SkippedItem {
+  // Title (or id) of the conversation in the source app
+  conversation: string;
+  // Id of the skipped message; null when the whole conversation was skipped
+  message: string | null;
+  reason: string;
+}
+
+export interface ImportSummary {
+  imported: number;
+  messages: number;
+  // Already imported earlier, left unchanged
+  duplicates: number;
+  skipped: SkippedItem[];
+}
+
+export interface ImportProgress {
+  source: ImportSource;
+  processed: number;
+  total: number;
+  imported: number;
+}
+
+// `path` is conversations.json or the export's zip archive; progress is reported
+// through `onImportProgress`
+export function importConversations(source: ImportSource, path: string): Promise<ImportSummary> {
+  return invoke('import_conversations', { source, path });
+}
+
+export function onImportProgress(handler: (progress: ImportProgress) => void): Promise<UnlistenFn> {
+  return listen<ImportProgress>('import-progress', (event) => handler(event.payload));
+}
+
+export interface BackupInfo {
+  path: string;
+  kind: 'automatic' | 'manual' | 'pre_restore';
+  sizeBytes: number;
+  createdAt: string;
+}
+
+export interface BackupSettings {
+  enabled: boolean;
+  intervalHours: number;
+  // Automatic backups kept; older ones are deleted
+  keep: number;
+  // Defaults to `backups` in the data directory
+  directory: string | null;
+}
+
+export interface IntegrityReport {
+  ok: boolean;
+  problems: string[];
+}
+
+export interface RestoreSummary {
+  restoredFrom: string;
+  schemaVersion: number;
+  // The database that was replaced, kept as a pre_restore backup
+  previous: BackupInfo;
+}
+
+// Without a path the backup goes into the backup directory
+export function backupDatabase(path?: string): Promise<BackupInfo> {
+  return invoke('backup_database', { path });
+}
+
+export function listBackups(): Promise<BackupInfo[]> {
+  return invoke('list_backups');
+}
+
+// Conversations should be reloaded afterwards; the whole database is replaced
+export function restoreDatabase(path: string): Promise<RestoreSummary> {
+  return invoke('restore_database', { path });
+}
+
+export function checkDatabaseIntegrity(): Promise<IntegrityReport> {
+  return invoke('check_database_integrity');
+}
+
+export function getBackupSettings(): Promise<BackupSettings> {
+  return invoke('get_backup_settings');
+}
+
+export function saveBackupSettings(settings: BackupSettings): Promise<void> {
+  return invoke('save_backup_settings', { settings });
+}
@@ -1,6 +1,41 @@
 import { invoke } from '@tauri-apps/api/core';
 import { listen } from '@tauri-apps/api/event';
 
+/** Parsed provider failure, mirroring `ProviderError` in the Rust proxy */
+export interface ProviderError {
+  provider: string;
+  status: number | null;
+  errorType: string;
+  message: string;
+  requestId: string | null;
+  retryAfterMs: number | null;
+}
+
+/** Error returned by a Tauri command, tagged with the Rust error variant */
+export interface CommandError {
+  kind: string;
+  message: string;
+  [detail: string]: unknown;
+}
+
+/** Error carrying the provider details, when the failure came from the provider */
+export class ProviderRequestError extends Error {
+  constructor(public readonly details: ProviderError) {
+    super(details.message);
+    this.name = 'ProviderRequestError';
+  }
+}
+
+function toError(payload: string | ProviderError | CommandError): Error {
+  if (typeof payload === 'string') {
+    return new Error(payload);
+  }
+  if ('kind' in payload && payload.kind !== 'Provider') {
+    return new Error(payload.message);
+  }
+  return new ProviderRequestError(payload as ProviderError);
+}
+
 export async function customTauriFetch(
   input: RequestInfo | URL,
   init?: RequestInit
@@ -14,20 +49,20 @@ export async function customTauriFetch(
   }
 
   let provider: string;
-  let apiPayload: string;
+  let chatRequest: unknown;
 
   try {
     const parsedBody = JSON.parse(init.body as string);
     provider = parsedBody.provider;
-    apiPayload = parsedBody.payload;
-    if (!provider || !apiPayload) {
-        throw new Error('Body must contain provider and payload');
+    chatRequest = parsedBody.request;
+    if (!provider || !chatRequest) {
+        throw new Error('Body must contain provider and request');
     }
     console.log(`FE: customTauriFetch: Provider: ${provider}`);
-    console.log('FE: customTauriFetch: API Payload (first 200 chars): ', apiPayload.substring(0, 200));
+    console.log('FE: customTauriFetch: Chat request (first 200 chars): ', JSON.stringify(chatRequest).substring(0, 200));
 
   } catch (e) {
-      console.error('FE: Failed to parse body or extract provider/payload:', e);
+      console.error('FE: Failed to parse body or extract provider/request:', e);
       throw new Error('Invalid body structure for customTauriFetch');
   }
 
@@ -42,9 +77,15 @@ export async function customTauriFetch(
       });
 
       const errorListener = listen('ai-stream-error', (event) => {
-        const errorMessage = event.payload as string;
-        console.error('FE: Stream Error Received from Rust:', errorMessage);
-        controller.error(new Error(errorMessage));
+        // Provider failures arrive as structured details, other failures as plain messages
+        const payload = event.payload as string | ProviderError;
+        console.error('FE: Stream Error Received from Rust:', payload);
+        controller.error(toError(payload));
+      });
+
+      const retryingListener = listen('ai-stream-retrying', (event) => {
+        const notice = event.payload as { reason: string; delayMs: number; attempt: number; maxAttempts: number };
+        console.warn(`FE: ${notice.reason}, retrying in ${Math.round(notice.delayMs / 1000)}s (attempt ${notice.attempt}/${notice.maxAttempts})`);
       });
 
       const endListener = listen('ai-stream-end', () => {
@@ -53,12 +94,13 @@ export async function customTauriFetch(
 
         chunkListener.then(unlisten => { console.log("FE: Unlistening from ai-stream-chunk"); unlisten(); });
         errorListener.then(unlisten => { console.log("FE: Unlistening from ai-stream-error"); unlisten(); });
+        retryingListener.then(unlisten => { console.log("FE: Unlistening from ai-stream-retrying"); unlisten(); });
         endListener.then(unlisten => { console.log("FE: Unlistening from ai-stream-end"); unlisten(); });
       });
 
-      Promise.all([chunkListener, errorListener, endListener]).then(() => {
+      Promise.all([chunkListener, errorListener, retryingListener, endListener]).then(() => {
         console.log('FE: Listeners ready. Invoking Rust command stream_api_request...');
-        invoke('stream_api_request', { provider: provider, payload: apiPayload })
+        invoke('stream_api_request', { provider: provider, request: chatRequest })
           .then(() => {
             console.log('FE: Rust command stream_api_request invoked successfully.');
           })
@@ -66,8 +108,9 @@ export async function customTauriFetch(
             console.error('FE: Error invoking Rust command stream_api_request:', error);
             chunkListener.then(unlisten => unlisten());
             errorListener.then(unlisten => unlisten());
+            retryingListener.then(unlisten => unlisten());
             endListener.then(unlisten => unlisten());
-            controller.error(new Error(`Failed to invoke Tauri command: ${error}`));
+            controller.error(toError(error as CommandError));
           });
       }).catch(error => {
         console.error("FE: Failed to set up listeners:", error);
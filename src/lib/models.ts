@@ -0,0 +1,83 @@
+import { invoke } from '@tauri-apps/api/core';
+
+// Typed wrappers around the provider commands, which own the model lists; the
+// webview does not guess providers from model names
+
+export interface ModelInfo {
+  id: string;
+  displayName: string;
+  // Built-in provider or profile name to send the request to
+  provider: string;
+  contextWindow: number | null;
+  supportsTools: boolean | null;
+  supportsVision: boolean | null;
+  metadata: Record<string, unknown> | null;
+}
+
+export interface ProviderProfile {
+  name: string;
+  base_url: string;
+  api_key_env?: string | null;
+  headers?: Record<string, string>;
+  models?: string[];
+  responses_models?: string[];
+  // Models that take image attachments (and PDFs over the Responses API)
+  vision_models?: string[];
+}
+
+// A model is only unique together with its provider: profiles can serve the
+// same model ids as the built-in providers
+export interface ModelSelection {
+  provider: string;
+  model: string;
+}
+
+export const BUILTIN_PROVIDERS = ['anthropic', 'openai', 'gemini', 'ollama'];
+
+export const defaultSelection: ModelSelection = {
+  provider: 'anthropic',
+  model: 'claude-3-5-sonnet-latest',
+};
+
+// Served from a cache on the Rust side unless refresh is set
+export function listModels(provider: string, refresh = false): Promise<ModelInfo[]> {
+  return invoke('list_models', { provider, refresh });
+}
+
+export function listProviderProfiles(): Promise<ProviderProfile[]> {
+  return invoke('list_provider_profiles');
+}
+
+// Models of every built-in provider and profile. Providers that cannot list
+// theirs, for want of a key or a running server, are left out.
+export async function listAllModels(refresh = false): Promise<ModelInfo[]> {
+  const profiles = await listProviderProfiles().catch(error => {
+    console.error('Failed to list provider profiles:', error);
+    return [] as ProviderProfile[];
+  });
+  const providers = [...BUILTIN_PROVIDERS, ...profiles.map(profile => profile.name)];
+  const lists = await Promise.all(
+    providers.map(provider =>
+      listModels(provider, refresh).catch(error => {
+        console.warn(`No models from ${provider}:`, error);
+        return [] as ModelInfo[];
+      }),
+    ),
+  );
+  return lists.flat();
+}
+
+export type OpenAIApi = 'chat_completions' | 'responses';
+
+export interface OpenAISettings {
+  // Per model id; unlisted reasoning models use the Responses API, others Chat Completions
+  model_apis: Record<string, OpenAIApi>;
+}
+
+export function getOpenAISettings(): Promise<OpenAISettings> {
+  return invoke('get_openai_settings');
+}
+
+export function saveOpenAISettings(settings: OpenAISettings): Promise<void> {
+  return invoke('save_openai_settings', { settings });
+}